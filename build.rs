@@ -308,6 +308,9 @@ fn perms_helper(
             Some("cnotify") => {
                 out.write("\n\t\t\tPerm::Events,")?;
             },
+            Some("write-response") => {
+                out.write("\n\t\t\tPerm::WriteResponse,")?;
+            },
             _ => {},
         }
     }
@@ -375,21 +378,41 @@ fn snake_case_helper(
 
 static CATEGORIES: &'static str = "// THIS FILE IS AUTO-GENERATED\n
 /// HAP Accessory category.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub enum Category {
 {{#each Categories as |c|}}\
 \t{{trim c.Name}} = {{c.Category}},
 {{/each}}\
 }
+
+impl Category {
+    /// Returns the value of the `ci` Bonjour TXT record for the `Category`.
+    pub fn to_txt_value(&self) -> u16 {
+        *self as u16
+    }
+
+    /// Converts the value of a `ci` Bonjour TXT record to the corresponding `Category`.
+    pub fn from_txt_value(v: u16) -> Option<Category> {
+        match v {
+{{#each Categories as |c|}}\
+\t\t\t{{c.Category}} => Some(Category::{{trim c.Name}}),
+{{/each}}\
+\t\t\t_ => None,
+        }
+    }
+}
 ";
 
 static HAP_TYPE: &'static str = "// THIS FILE IS AUTO-GENERATED\n
 use serde::ser::{Serialize, Serializer};
+use uuid::Uuid;
 
 /// HAP Service and Characteristic type.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum HapType {
     Unknown,
+    /// Custom type with a vendor-specific UUID.
+    Custom(Uuid),
 {{#each Characteristics as |c|}}\
 \t{{trim c.Name}},
 {{/each}}\
@@ -398,17 +421,112 @@ pub enum HapType {
 {{/each}}\
 }
 
+/// Suffix shared by the UUIDs of all Apple-defined Service and Characteristic types.
+const APPLE_UUID_SUFFIX: &str = \"-0000-1000-8000-0026BB765291\";
+
+/// Well-known third-party types of the Eve (formerly Elgato Eve) ecosystem.
 impl HapType {
-    /// Converts a `HapType` to its corresponding shortened UUID string.
+    /// Eve History Service.
+    pub const EVE_HISTORY: HapType = HapType::Custom(Uuid::from_u128(0xE863F007_079E_48FF_8F27_9C2605A29F52));
+    /// Eve Voltage Characteristic in V.
+    pub const EVE_VOLTAGE: HapType = HapType::Custom(Uuid::from_u128(0xE863F10A_079E_48FF_8F27_9C2605A29F52));
+    /// Eve Total Consumption Characteristic in kWh.
+    pub const EVE_TOTAL_CONSUMPTION: HapType =
+        HapType::Custom(Uuid::from_u128(0xE863F10C_079E_48FF_8F27_9C2605A29F52));
+    /// Eve Current Consumption Characteristic in W.
+    pub const EVE_CURRENT_CONSUMPTION: HapType =
+        HapType::Custom(Uuid::from_u128(0xE863F10D_079E_48FF_8F27_9C2605A29F52));
+    /// Eve Air Pressure Characteristic in hPa.
+    pub const EVE_AIR_PRESSURE: HapType = HapType::Custom(Uuid::from_u128(0xE863F10F_079E_48FF_8F27_9C2605A29F52));
+    /// Eve Apparent Power Characteristic in VA.
+    pub const EVE_APPARENT_POWER: HapType =
+        HapType::Custom(Uuid::from_u128(0xE863F110_079E_48FF_8F27_9C2605A29F52));
+    /// Eve Times Opened Characteristic.
+    pub const EVE_TIMES_OPENED: HapType = HapType::Custom(Uuid::from_u128(0xE863F129_079E_48FF_8F27_9C2605A29F52));
+    /// Eve History Status Characteristic.
+    pub const EVE_HISTORY_STATUS: HapType =
+        HapType::Custom(Uuid::from_u128(0xE863F116_079E_48FF_8F27_9C2605A29F52));
+    /// Eve History Entries Characteristic.
+    pub const EVE_HISTORY_ENTRIES: HapType =
+        HapType::Custom(Uuid::from_u128(0xE863F117_079E_48FF_8F27_9C2605A29F52));
+    /// Eve Reset Total Characteristic.
+    pub const EVE_RESET_TOTAL: HapType = HapType::Custom(Uuid::from_u128(0xE863F112_079E_48FF_8F27_9C2605A29F52));
+    /// Eve Set Time Characteristic.
+    pub const EVE_SET_TIME: HapType = HapType::Custom(Uuid::from_u128(0xE863F121_079E_48FF_8F27_9C2605A29F52));
+    /// Eve History Request Characteristic.
+    pub const EVE_HISTORY_REQUEST: HapType =
+        HapType::Custom(Uuid::from_u128(0xE863F11C_079E_48FF_8F27_9C2605A29F52));
+    /// Eve Electric Current Characteristic in A.
+    pub const EVE_ELECTRIC_CURRENT: HapType =
+        HapType::Custom(Uuid::from_u128(0xE863F126_079E_48FF_8F27_9C2605A29F52));
+}
+
+impl HapType {
+    /// Converts a `HapType` to its corresponding shortened UUID string. Custom types are converted
+    /// to their full lowercase UUID string.
     pub fn to_string(self) -> String {
         match self {
             HapType::Unknown => \"unknown\".into(),
+            HapType::Custom(uuid) => uuid.to_hyphenated().to_string(),
 {{#each Characteristics as |c|}}\
 \t\t\tHapType::{{trim c.Name}} => \"{{uuid c.UUID}}\".into(),
 {{/each}}\
 {{#each Services as |s|}}\
 \t\t\tHapType::{{trim s.Name}} => \"{{uuid s.UUID}}\".into(),
 {{/each}}\
+\t\t}
+    }
+
+    /// Converts a `HapType` to its full uppercase UUID string.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::HapType;
+    ///
+    /// assert_eq!(HapType::CurrentTemperature.to_uuid(), \"00000011-0000-1000-8000-0026BB765291\");
+    /// ```
+    pub fn to_uuid(&self) -> String {
+        match self {
+            HapType::Unknown => \"unknown\".into(),
+            HapType::Custom(uuid) => uuid.to_hyphenated().to_string().to_uppercase(),
+            hap_type => format!(\"{:0>8}{}\", hap_type.to_string(), APPLE_UUID_SUFFIX),
+        }
+    }
+
+    /// Looks up the `HapType` of a UUID string. Both full and shortened UUIDs of Apple-defined
+    /// types are accepted, case-insensitively. Other full UUIDs are returned as custom types.
+    /// Returns `None` for invalid or unknown shortened UUIDs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::HapType;
+    ///
+    /// assert_eq!(HapType::from_uuid(\"00000011-0000-1000-8000-0026BB765291\"), Some(HapType::CurrentTemperature));
+    /// assert_eq!(HapType::from_uuid(\"11\"), Some(HapType::CurrentTemperature));
+    /// assert_eq!(
+    ///     HapType::from_uuid(\"e863f10d-079e-48ff-8f27-9c2605a29f52\"),
+    ///     Some(HapType::EVE_CURRENT_CONSUMPTION),
+    /// );
+    /// ```
+    pub fn from_uuid(uuid: &str) -> Option<HapType> {
+        let uuid = uuid.to_uppercase();
+        let short = if uuid.ends_with(APPLE_UUID_SUFFIX) {
+            &uuid[..uuid.len() - APPLE_UUID_SUFFIX.len()]
+        } else if uuid.contains('-') {
+            return Uuid::parse_str(&uuid).ok().map(HapType::Custom);
+        } else {
+            &uuid
+        };
+        match short.trim_start_matches('0') {
+{{#each Characteristics as |c|}}\
+\t\t\t\"{{uuid c.UUID}}\" => Some(HapType::{{trim c.Name}}),
+{{/each}}\
+{{#each Services as |s|}}\
+\t\t\t\"{{uuid s.UUID}}\" => Some(HapType::{{trim s.Name}}),
+{{/each}}\
+\t\t\t_ => None,
 \t\t}
     }
 }
@@ -443,7 +561,8 @@ pub fn new() -> {{trim characteristic.Name}} {
         {{#if characteristic.Constraints.StepValue includeZero=true}}\n\t\tstep_value: Some({{characteristic.Constraints.StepValue}}{{float characteristic.Format}}),{{/if}}\
         {{#if characteristic.Constraints.MaximumLength includeZero=true}}\n\t\tmax_len: Some({{characteristic.Constraints.MaximumLength}}{{float characteristic.Format}}),{{/if}}\
         {{#if characteristic.Constraints.MaximumDataLength includeZero=true}}\n\t\tmax_data_len: Some({{characteristic.Constraints.MaximumDataLength}}{{float characteristic.Format}}),{{/if}}\
-        {{#if characteristic.Constraints.ValidValues includeZero=true}}\n\t\tvalid_values: Some({{valid_values characteristic.Constraints.ValidValues}}),{{/if}}
+        {{#if characteristic.Constraints.ValidValues includeZero=true}}\n\t\tvalid_values: Some({{valid_values characteristic.Constraints.ValidValues}}),{{/if}}\
+        {{#if_eq characteristic.Name \"Programmable Switch Event\"}}\n\t\tnotify_unchanged: true,{{/if_eq}}
         ..Default::default()
     })
 }
@@ -466,6 +585,8 @@ use crate::{
 {{/each}}\
 \t},
     HapType,
+    Error,
+    Result,
 };
 
 /// {{service.Name}} Service.
@@ -476,7 +597,7 @@ impl Default for {{trim service.Name}} {
 }
 
 /// Inner type of the {{service.Name}} Service.
-#[derive(Default)]
+#[derive(Debug, Default)]
 pub struct {{trim service.Name}}Inner {
     /// ID of the {{service.Name}} Service.
     id: u64,
@@ -486,6 +607,8 @@ pub struct {{trim service.Name}}Inner {
     hidden: bool,
     /// Specifies if the Service is the primary Service of the Accessory.
     primary: bool,
+    /// IDs of the Services linked to the Service.
+    linked_services: Vec<u64>,
 
 {{#each required_characteristics as |r|}}\
 \t/// {{r.Name}} Characteristic.
@@ -495,6 +618,8 @@ pub struct {{trim service.Name}}Inner {
 \t/// {{r.Name}} Characteristic.
 \tpub {{characteristic_file_name r.Name}}: Option<{{characteristic_file_name r.Name}}::{{characteristic_name r.Name}}>,
 {{/each}}\
+\n    /// Characteristics added to the Service at runtime.
+    added_characteristics: Vec<Box<dyn HapCharacteristic + Send>>,
 }
 
 impl HapService for {{trim service.Name}}Inner {
@@ -526,6 +651,14 @@ impl HapService for {{trim service.Name}}Inner {
         self.primary = primary;
     }
 
+    fn get_linked_services(&self) -> Vec<u64> {
+        self.linked_services.clone()
+    }
+
+    fn set_linked_services(&mut self, linked_services: Vec<u64>) {
+        self.linked_services = linked_services;
+    }
+
     fn get_characteristics(&self) -> Vec<&dyn HapCharacteristic> {
         let mut characteristics: Vec<&dyn HapCharacteristic> = vec![
 {{#each required_characteristics as |r|}}\
@@ -537,7 +670,10 @@ impl HapService for {{trim service.Name}}Inner {
 \t\t    characteristics.push(c);
 \t\t}
 {{/each}}\
-        \t\tcharacteristics
+        \t\tfor c in &self.added_characteristics {
+\t\t    characteristics.push(c.as_ref());
+\t\t}
+\t\tcharacteristics
     }
 
     fn get_mut_characteristics(&mut self) -> Vec<&mut dyn HapCharacteristic> {
@@ -551,7 +687,32 @@ impl HapService for {{trim service.Name}}Inner {
 \t\t    characteristics.push(c);
 \t\t}
 {{/each}}\
-        \t\tcharacteristics
+        \t\tfor c in &mut self.added_characteristics {
+\t\t    characteristics.push(c.as_mut());
+\t\t}
+\t\tcharacteristics
+    }
+
+    fn add_characteristic(&mut self, characteristic: Box<dyn HapCharacteristic + Send>) {
+        self.added_characteristics.push(characteristic);
+    }
+
+    fn remove_characteristic(&mut self, id: u64) -> Result<()> {
+{{#each optional_characteristics as |r|}}\
+\t\tif let Some(c) = &self.{{characteristic_file_name r.Name}} {
+\t\t    if c.get_id()? == id {
+\t\t        self.{{characteristic_file_name r.Name}} = None;
+\t\t        return Ok(());
+\t\t    }
+\t\t}
+{{/each}}\
+        \t\tfor (i, c) in self.added_characteristics.iter().enumerate() {
+\t\t    if c.get_id()? == id {
+\t\t        self.added_characteristics.remove(i);
+\t\t        return Ok(());
+\t\t    }
+\t\t}
+\t\tErr(Error::from_str(\"couldn't find the Characteristic to remove\"))
     }
 }
 
@@ -575,7 +736,6 @@ static ACCESSORY: &'static str = "// THIS FILE IS AUTO-GENERATED\n
 use crate::{
 \taccessory::{HapAccessory, HapAccessoryService, Accessory, Information},
 \tservice::{HapService, accessory_information::AccessoryInformation, {{snake_case service.Name}}},
-\tevent::EventEmitterPtr,
 \tResult,
 };
 
@@ -583,7 +743,7 @@ use crate::{
 pub type {{trim service.Name}} = Accessory<{{trim service.Name}}Inner>;
 
 /// Inner type of the {{service.Name}} Accessory.
-#[derive(Default)]
+#[derive(Debug, Default)]
 pub struct {{trim service.Name}}Inner {
     /// ID of the {{service.Name}} Accessory.
     id: u64,
@@ -592,6 +752,8 @@ pub struct {{trim service.Name}}Inner {
     pub accessory_information: AccessoryInformation,
     /// {{service.Name}} Service.
     pub {{snake_case service.Name}}: {{snake_case service.Name}}::{{trim service.Name}},
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
 }
 
 impl HapAccessory for {{trim service.Name}}Inner {
@@ -604,36 +766,33 @@ impl HapAccessory for {{trim service.Name}}Inner {
     }
 
     fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
-        vec![
+        let mut services: Vec<&dyn HapAccessoryService> = vec![
             &self.accessory_information,
             &self.{{snake_case service.Name}},
-        ]
+        ];
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
     }
 
     fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
-        vec![
+        let mut services: Vec<&mut dyn HapAccessoryService> = vec![
             &mut self.accessory_information,
             &mut self.{{snake_case service.Name}},
-        ]
+        ];
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
     }
 
     fn get_mut_information(&mut self) -> &mut AccessoryInformation {
         &mut self.accessory_information
     }
 
-    fn init_iids(&mut self, accessory_id: u64, event_emitter: EventEmitterPtr) -> Result<()> {
-        let mut next_iid = 1;
-        for service in self.get_mut_services() {
-            service.set_id(next_iid);
-            next_iid += 1;
-            for characteristic in service.get_mut_characteristics() {
-                characteristic.set_id(next_iid)?;
-                characteristic.set_accessory_id(accessory_id)?;
-                characteristic.set_event_emitter(Some(event_emitter.clone()))?;
-                next_iid += 1;
-            }
-        }
-        Ok(())
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) {
+        self.added_services.push(service);
     }
 }
 
@@ -783,14 +942,21 @@ fn main() {
             && s.name != "Doorbell"
             && s.name != "Faucet"
             && s.name != "Filter Maintenance"
+            && s.name != "Heater Cooler"
+            && s.name != "Humidifier Dehumidifier"
+            && s.name != "Humidity Sensor"
             && s.name != "Irrigation System"
+            && s.name != "Light Sensor"
             && s.name != "Lock Management"
             && s.name != "Lock Mechanism"
             && s.name != "Microphone"
             && s.name != "Service Label"
             && s.name != "Slat"
             && s.name != "Speaker"
+            && s.name != "Stateless Programmable Switch"
             && s.name != "Television"
+            && s.name != "Temperature Sensor"
+            && s.name != "Window Covering"
         {
             let accessory = handlebars
                 .render(
@@ -1,7 +1,7 @@
 use std::sync::{Arc, Mutex};
 
 use hap::{
-    accessory::{bridge, door, outlet, security_system, valve, Category, Information},
+    accessory::{door, outlet, security_system, valve, Category, Information},
     characteristic::{Characteristic, Readable, Updatable},
     transport::{IpTransport, Transport},
     Config,
@@ -103,12 +103,6 @@ impl Updatable<u8> for VirtualDoor {
 }
 
 fn main() {
-    let bridge = bridge::new(Information {
-        name: "Bridge".into(),
-        ..Default::default()
-    })
-    .unwrap();
-
     let mut outlet = outlet::new(Information {
         name: "Outlet".into(),
         ..Default::default()
@@ -180,11 +174,10 @@ fn main() {
     };
 
     let mut ip_transport = IpTransport::new(config).unwrap();
-    ip_transport.add_accessory(bridge).unwrap();
-    ip_transport.add_accessory(outlet).unwrap();
-    ip_transport.add_accessory(door).unwrap();
-    ip_transport.add_accessory(security_system).unwrap();
-    ip_transport.add_accessory(valve).unwrap();
+    ip_transport.add_bridged_accessory(outlet).unwrap();
+    ip_transport.add_bridged_accessory(door).unwrap();
+    ip_transport.add_bridged_accessory(security_system).unwrap();
+    ip_transport.add_bridged_accessory(valve).unwrap();
 
     ip_transport.start().unwrap();
 }
@@ -0,0 +1,126 @@
+use hap::{
+    accessory::{outlet, Category, HapAccessory, Information},
+    characteristic::{Characteristic, Format, HapCharacteristic, Perm},
+    service::{HapService, Service},
+    transport::{IpTransport, Transport},
+    Config,
+    Error,
+    HapType,
+    Result,
+};
+use uuid::Uuid;
+
+/// Inner type of a vendor-specific Service reporting the power draw and the consumed energy of
+/// an Accessory.
+pub struct EnergyMonitorServiceInner {
+    id: u64,
+    hap_type: HapType,
+    hidden: bool,
+    primary: bool,
+    linked_services: Vec<u64>,
+
+    pub current_power: Characteristic<f32>,
+    pub total_consumption: Characteristic<f32>,
+    characteristics: Vec<Box<dyn HapCharacteristic + Send>>,
+}
+
+impl EnergyMonitorServiceInner {
+    pub fn new() -> Result<EnergyMonitorServiceInner> {
+        let mut current_power = Characteristic::new_custom(
+            "e863f10d-079e-48ff-8f27-9c2605a29f52",
+            Format::Float,
+            vec![Perm::PairedRead, Perm::Events],
+        )?;
+        current_power.set_min_value(Some(0.0))?;
+        let mut total_consumption = Characteristic::new_custom(
+            "e863f10c-079e-48ff-8f27-9c2605a29f52",
+            Format::Float,
+            vec![Perm::PairedRead, Perm::Events],
+        )?;
+        total_consumption.set_min_value(Some(0.0))?;
+
+        Ok(EnergyMonitorServiceInner {
+            id: 0,
+            hap_type: HapType::Custom(Uuid::parse_str("e863f007-079e-48ff-8f27-9c2605a29f52")?),
+            hidden: false,
+            primary: false,
+            linked_services: vec![],
+            current_power,
+            total_consumption,
+            characteristics: vec![],
+        })
+    }
+}
+
+impl HapService for EnergyMonitorServiceInner {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_type(&self) -> HapType { self.hap_type }
+
+    fn get_hidden(&self) -> bool { self.hidden }
+
+    fn set_hidden(&mut self, hidden: bool) { self.hidden = hidden; }
+
+    fn get_primary(&self) -> bool { self.primary }
+
+    fn set_primary(&mut self, primary: bool) { self.primary = primary; }
+
+    fn get_linked_services(&self) -> Vec<u64> { self.linked_services.clone() }
+
+    fn set_linked_services(&mut self, linked_services: Vec<u64>) { self.linked_services = linked_services; }
+
+    fn get_characteristics(&self) -> Vec<&dyn HapCharacteristic> {
+        let mut characteristics: Vec<&dyn HapCharacteristic> = vec![&self.current_power, &self.total_consumption];
+        for c in &self.characteristics {
+            characteristics.push(c.as_ref());
+        }
+        characteristics
+    }
+
+    fn get_mut_characteristics(&mut self) -> Vec<&mut dyn HapCharacteristic> {
+        let mut characteristics: Vec<&mut dyn HapCharacteristic> =
+            vec![&mut self.current_power, &mut self.total_consumption];
+        for c in &mut self.characteristics {
+            characteristics.push(c.as_mut());
+        }
+        characteristics
+    }
+
+    fn add_characteristic(&mut self, characteristic: Box<dyn HapCharacteristic + Send>) {
+        self.characteristics.push(characteristic);
+    }
+
+    fn remove_characteristic(&mut self, id: u64) -> Result<()> {
+        for (i, c) in self.characteristics.iter().enumerate() {
+            if c.get_id()? == id {
+                self.characteristics.remove(i);
+                return Ok(());
+            }
+        }
+        Err(Error::from_str("couldn't find the Characteristic to remove"))
+    }
+}
+
+fn main() {
+    let mut outlet = outlet::new(Information {
+        name: "Acme Smart Plug".into(),
+        ..Default::default()
+    })
+    .unwrap();
+
+    let mut energy_monitor = Service::new(EnergyMonitorServiceInner::new().unwrap());
+    energy_monitor.inner.current_power.set_value(42.0).unwrap();
+    outlet.add_service(Box::new(energy_monitor));
+
+    let mut ip_transport = IpTransport::new(Config {
+        name: "Acme Smart Plug".into(),
+        category: Category::Outlet,
+        ..Default::default()
+    })
+    .unwrap();
+    ip_transport.add_accessory(outlet).unwrap();
+
+    ip_transport.start().unwrap();
+}
@@ -1,15 +1,22 @@
 use hap::{
-    accessory::{television, Category, Information},
+    accessory::{
+        television::{self, InputSourceType},
+        Category,
+        Information,
+    },
     transport::{IpTransport, Transport},
     Config,
 };
 
 fn main() {
-    let television = television::new(Information {
+    let mut television = television::new(Information {
         name: "Acme TV".into(),
         ..Default::default()
     })
     .unwrap();
+    television.add_input("HDMI 1", InputSourceType::Hdmi).unwrap();
+    television.add_input("HDMI 2", InputSourceType::Hdmi).unwrap();
+    television.add_input("Live TV", InputSourceType::Tuner).unwrap();
 
     let mut ip_transport = IpTransport::new(Config {
         name: "Acme TV".into(),
@@ -1,6 +1,5 @@
 use crate::{
     accessory::{Accessory, HapAccessory, HapAccessoryService, Information},
-    event::EventEmitterPtr,
     service::accessory_information::AccessoryInformation,
     Result,
 };
@@ -9,13 +8,15 @@ use crate::{
 pub type Bridge = Accessory<BridgeInner>;
 
 /// Inner type of the Bridge Accessory.
-#[derive(Default)]
+#[derive(Debug, Default)]
 pub struct BridgeInner {
     /// ID of the Bridge Accessory.
     id: u64,
 
     /// Accessory Information Service.
     pub accessory_information: AccessoryInformation,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
 }
 
 impl HapAccessory for BridgeInner {
@@ -23,26 +24,25 @@ impl HapAccessory for BridgeInner {
 
     fn set_id(&mut self, id: u64) { self.id = id; }
 
-    fn get_services(&self) -> Vec<&dyn HapAccessoryService> { vec![&self.accessory_information] }
+    fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
+        let mut services: Vec<&dyn HapAccessoryService> = vec![&self.accessory_information];
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
+    }
 
-    fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> { vec![&mut self.accessory_information] }
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
+        let mut services: Vec<&mut dyn HapAccessoryService> = vec![&mut self.accessory_information];
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
+    }
 
     fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
 
-    fn init_iids(&mut self, accessory_id: u64, event_emitter: EventEmitterPtr) -> Result<()> {
-        let mut next_iid = 1;
-        for service in self.get_mut_services() {
-            service.set_id(next_iid);
-            next_iid += 1;
-            for characteristic in service.get_mut_characteristics() {
-                characteristic.set_id(next_iid)?;
-                characteristic.set_accessory_id(accessory_id)?;
-                characteristic.set_event_emitter(Some(event_emitter.clone()))?;
-                next_iid += 1;
-            }
-        }
-        Ok(())
-    }
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
 }
 
 /// Creates a new Bridge Accessory.
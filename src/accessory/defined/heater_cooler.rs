@@ -0,0 +1,273 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    accessory::{Accessory, HapAccessory, HapAccessoryService, Information},
+    characteristic::{cooling_threshold_temperature, heating_threshold_temperature, Updatable, Validatable},
+    service::{accessory_information::AccessoryInformation, heater_cooler, HapService},
+    HapType,
+    Result,
+};
+
+/// Heater Cooler Accessory.
+pub type HeaterCooler = Accessory<HeaterCoolerInner>;
+
+/// Inner type of the Heater Cooler Accessory.
+#[derive(Debug, Default)]
+pub struct HeaterCoolerInner {
+    /// ID of the Heater Cooler Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformation,
+    /// Heater Cooler Service.
+    pub heater_cooler: heater_cooler::HeaterCooler,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
+}
+
+impl HapAccessory for HeaterCoolerInner {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
+        let mut services: Vec<&dyn HapAccessoryService> = vec![&self.accessory_information, &self.heater_cooler];
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
+        let mut services: Vec<&mut dyn HapAccessoryService> =
+            vec![&mut self.accessory_information, &mut self.heater_cooler];
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
+    }
+
+    fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
+
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
+}
+
+/// Cooling Threshold Temperature and Heating Threshold Temperature of a Heater Cooler, shared by the
+/// validators of both Characteristics. A write is checked against the other threshold and recorded
+/// under the same lock, so concurrent writes of both thresholds can't pass with each other's old
+/// value. Holding the values rather than the other Characteristic also avoids a reference cycle
+/// between the two Characteristics.
+struct Thresholds {
+    cooling: f32,
+    heating: f32,
+}
+
+/// Rejects Cooling Threshold Temperatures below the Heating Threshold Temperature.
+struct CoolingThresholdValidator {
+    thresholds: Arc<Mutex<Thresholds>>,
+}
+
+impl Validatable<f32> for CoolingThresholdValidator {
+    fn on_validate(&mut self, new_val: &f32, _: HapType) -> bool {
+        match self.thresholds.lock() {
+            Ok(mut thresholds) if *new_val >= thresholds.heating => {
+                thresholds.cooling = *new_val;
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Keeps track of values set by the application, which aren't validated.
+impl Updatable<f32> for CoolingThresholdValidator {
+    fn on_update(&mut self, _: &f32, new_val: &f32, _: HapType) {
+        if let Ok(mut thresholds) = self.thresholds.lock() {
+            thresholds.cooling = *new_val;
+        }
+    }
+}
+
+/// Rejects Heating Threshold Temperatures above the Cooling Threshold Temperature.
+struct HeatingThresholdValidator {
+    thresholds: Arc<Mutex<Thresholds>>,
+}
+
+impl Validatable<f32> for HeatingThresholdValidator {
+    fn on_validate(&mut self, new_val: &f32, _: HapType) -> bool {
+        match self.thresholds.lock() {
+            Ok(mut thresholds) if *new_val <= thresholds.cooling => {
+                thresholds.heating = *new_val;
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+/// Keeps track of values set by the application, which aren't validated.
+impl Updatable<f32> for HeatingThresholdValidator {
+    fn on_update(&mut self, _: &f32, new_val: &f32, _: HapType) {
+        if let Ok(mut thresholds) = self.thresholds.lock() {
+            thresholds.heating = *new_val;
+        }
+    }
+}
+
+/// Creates a new Heater Cooler Accessory, the replacement of the Thermostat Accessory for heaters,
+/// air conditioners and devices doing both.
+///
+/// The Cooling Threshold Temperature and Heating Threshold Temperature Characteristics are added
+/// to the Heater Cooler Service, defaulting to 25 °C and 20 °C. Writes of Controllers that would
+/// set the Cooling Threshold Temperature below the Heating Threshold Temperature are rejected.
+/// Setting a `Validatable` or an `Updatable` on either threshold replaces this behavior. The
+/// optional Swing Mode and Rotation Speed Characteristics can be added as needed.
+///
+/// # Examples
+///
+/// ```
+/// use hap::{
+///     accessory::{heater_cooler, Information},
+///     characteristic::{rotation_speed, swing_mode, HapCharacteristic},
+/// };
+/// use serde_json::json;
+///
+/// let mut air_conditioner = heater_cooler::new(Information {
+///     name: "Air Conditioner".into(),
+///     ..Default::default()
+/// })
+/// .unwrap();
+/// let service = &mut air_conditioner.inner.heater_cooler.inner;
+/// service.swing_mode = Some(swing_mode::new());
+/// service.rotation_speed = Some(rotation_speed::new());
+///
+/// // a remote write of a Cooling Threshold Temperature below the Heating Threshold Temperature
+/// let cooling_threshold = service.cooling_threshold_temperature.as_mut().unwrap();
+/// assert!(HapCharacteristic::set_value(cooling_threshold, json!(18.0)).is_err());
+/// assert!(HapCharacteristic::set_value(cooling_threshold, json!(22.0)).is_ok());
+/// ```
+pub fn new(information: Information) -> Result<HeaterCooler> {
+    let mut heater_cooler = heater_cooler::new();
+    heater_cooler.set_primary(true);
+    {
+        let mut cooling_threshold_temperature = cooling_threshold_temperature::new();
+        let mut heating_threshold_temperature = heating_threshold_temperature::new();
+        cooling_threshold_temperature.set_value(25.0)?;
+        heating_threshold_temperature.set_value(20.0)?;
+        let thresholds = Arc::new(Mutex::new(Thresholds {
+            cooling: cooling_threshold_temperature.get_value()?,
+            heating: heating_threshold_temperature.get_value()?,
+        }));
+        cooling_threshold_temperature.set_validatable(CoolingThresholdValidator {
+            thresholds: thresholds.clone(),
+        })?;
+        cooling_threshold_temperature.set_updatable(CoolingThresholdValidator {
+            thresholds: thresholds.clone(),
+        })?;
+        heating_threshold_temperature.set_validatable(HeatingThresholdValidator {
+            thresholds: thresholds.clone(),
+        })?;
+        heating_threshold_temperature.set_updatable(HeatingThresholdValidator { thresholds })?;
+        let service = &mut heater_cooler.inner;
+        service.cooling_threshold_temperature = Some(cooling_threshold_temperature);
+        service.heating_threshold_temperature = Some(heating_threshold_temperature);
+    }
+    Ok(HeaterCooler::new(HeaterCoolerInner {
+        accessory_information: information.to_service()?,
+        heater_cooler,
+        ..Default::default()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Barrier},
+        thread,
+    };
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::characteristic::HapCharacteristic;
+
+    fn heater_cooler() -> HeaterCooler {
+        new(Information {
+            name: "Air Conditioner".into(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_thresholds_dont_keep_each_other_alive() {
+        let heater_cooler = heater_cooler();
+        let service = &heater_cooler.inner.heater_cooler.inner;
+        let cooling = Arc::downgrade(&service.cooling_threshold_temperature.as_ref().unwrap().inner);
+        let heating = Arc::downgrade(&service.heating_threshold_temperature.as_ref().unwrap().inner);
+
+        drop(heater_cooler);
+        assert!(cooling.upgrade().is_none());
+        assert!(heating.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_writes_validated_before_either_is_set() {
+        let thresholds = Arc::new(Mutex::new(Thresholds {
+            cooling: 25.0,
+            heating: 20.0,
+        }));
+        let mut cooling = CoolingThresholdValidator {
+            thresholds: thresholds.clone(),
+        };
+        let mut heating = HeatingThresholdValidator { thresholds };
+
+        assert!(cooling.on_validate(&21.0, HapType::CoolingThresholdTemperature));
+        assert!(!heating.on_validate(&24.0, HapType::HeatingThresholdTemperature));
+        assert!(heating.on_validate(&21.0, HapType::HeatingThresholdTemperature));
+    }
+
+    #[test]
+    fn test_concurrent_threshold_writes() {
+        for _ in 0..1000 {
+            let mut heater_cooler = heater_cooler();
+            let service = &mut heater_cooler.inner.heater_cooler.inner;
+            let mut cooling = service.cooling_threshold_temperature.clone().unwrap();
+            let mut heating = service.heating_threshold_temperature.clone().unwrap();
+
+            // either write is valid on its own, but not both of them
+            let barrier = Arc::new(Barrier::new(2));
+            let cooling_write = {
+                let (mut cooling, barrier) = (cooling.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    HapCharacteristic::set_value(&mut cooling, json!(21.0)).is_ok()
+                })
+            };
+            let heating_write = {
+                let (mut heating, barrier) = (heating.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    HapCharacteristic::set_value(&mut heating, json!(24.0)).is_ok()
+                })
+            };
+            let accepted = (cooling_write.join().unwrap(), heating_write.join().unwrap());
+
+            assert!(accepted == (true, false) || accepted == (false, true));
+            assert!(cooling.get_value().unwrap() >= heating.get_value().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_thresholds_set_by_the_application_are_checked_against() {
+        let mut heater_cooler = heater_cooler();
+        let service = &mut heater_cooler.inner.heater_cooler.inner;
+        let mut heating = service.heating_threshold_temperature.clone().unwrap();
+        let cooling = service.cooling_threshold_temperature.as_mut().unwrap();
+
+        heating.set_value(10.0).unwrap();
+        assert!(HapCharacteristic::set_value(cooling, json!(15.0)).is_ok());
+        heating.set_value(15.0).unwrap();
+        assert!(HapCharacteristic::set_value(cooling, json!(12.0)).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{
+    accessory::{Accessory, HapAccessory, HapAccessoryService, Information},
+    characteristic::{relative_humidity_dehumidifier_threshold, relative_humidity_humidifier_threshold},
+    service::{accessory_information::AccessoryInformation, humidifier_dehumidifier, HapService},
+    Result,
+};
+
+/// Humidifier Dehumidifier Accessory.
+pub type HumidifierDehumidifier = Accessory<HumidifierDehumidifierInner>;
+
+/// Inner type of the Humidifier Dehumidifier Accessory.
+#[derive(Debug, Default)]
+pub struct HumidifierDehumidifierInner {
+    /// ID of the Humidifier Dehumidifier Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformation,
+    /// Humidifier Dehumidifier Service.
+    pub humidifier_dehumidifier: humidifier_dehumidifier::HumidifierDehumidifier,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
+}
+
+impl HapAccessory for HumidifierDehumidifierInner {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
+        let mut services: Vec<&dyn HapAccessoryService> =
+            vec![&self.accessory_information, &self.humidifier_dehumidifier];
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
+        let mut services: Vec<&mut dyn HapAccessoryService> =
+            vec![&mut self.accessory_information, &mut self.humidifier_dehumidifier];
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
+    }
+
+    fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
+
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
+}
+
+/// Creates a new Humidifier Dehumidifier Accessory.
+///
+/// The Relative Humidity Humidifier Threshold and Relative Humidity Dehumidifier Threshold
+/// Characteristics are added to the Humidifier Dehumidifier Service, defaulting to 40 % and 60 %.
+/// Like the Current Relative Humidity, they range from 0 % to 100 %, and remote writes outside of
+/// that range are rejected. The optional Swing Mode, Water Level, Lock Physical Controls and
+/// Rotation Speed Characteristics can be added as needed.
+///
+/// # Examples
+///
+/// ```
+/// use hap::{
+///     accessory::{humidifier_dehumidifier, Information},
+///     characteristic::{water_level, HapCharacteristic},
+/// };
+/// use serde_json::json;
+///
+/// let mut humidifier = humidifier_dehumidifier::new(Information {
+///     name: "Humidifier".into(),
+///     ..Default::default()
+/// })
+/// .unwrap();
+/// let service = &mut humidifier.inner.humidifier_dehumidifier.inner;
+/// service.water_level = Some(water_level::new());
+///
+/// let humidifier_threshold = service.relative_humidity_humidifier_threshold.as_mut().unwrap();
+/// assert!(HapCharacteristic::set_value(humidifier_threshold, json!(120)).is_err());
+/// assert!(HapCharacteristic::set_value(humidifier_threshold, json!(35)).is_ok());
+/// ```
+pub fn new(information: Information) -> Result<HumidifierDehumidifier> {
+    let mut humidifier_dehumidifier = humidifier_dehumidifier::new();
+    humidifier_dehumidifier.set_primary(true);
+    {
+        let mut humidifier_threshold = relative_humidity_humidifier_threshold::new();
+        let mut dehumidifier_threshold = relative_humidity_dehumidifier_threshold::new();
+        humidifier_threshold.set_value(40.0)?;
+        dehumidifier_threshold.set_value(60.0)?;
+        let service = &mut humidifier_dehumidifier.inner;
+        service.relative_humidity_humidifier_threshold = Some(humidifier_threshold);
+        service.relative_humidity_dehumidifier_threshold = Some(dehumidifier_threshold);
+    }
+    Ok(HumidifierDehumidifier::new(HumidifierDehumidifierInner {
+        accessory_information: information.to_service()?,
+        humidifier_dehumidifier,
+        ..Default::default()
+    }))
+}
@@ -0,0 +1,90 @@
+use crate::{
+    accessory::{defined::set_initial_value, Accessory, HapAccessory, HapAccessoryService, Information},
+    characteristic::{status_active, status_fault},
+    service::{accessory_information::AccessoryInformation, humidity_sensor, HapService},
+    Result,
+};
+
+/// Humidity Sensor Accessory.
+pub type HumiditySensor = Accessory<HumiditySensorInner>;
+
+/// Inner type of the Humidity Sensor Accessory.
+#[derive(Debug, Default)]
+pub struct HumiditySensorInner {
+    /// ID of the Humidity Sensor Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformation,
+    /// Humidity Sensor Service.
+    pub humidity_sensor: humidity_sensor::HumiditySensor,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
+}
+
+impl HapAccessory for HumiditySensorInner {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
+        let mut services: Vec<&dyn HapAccessoryService> = vec![&self.accessory_information, &self.humidity_sensor];
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
+        let mut services: Vec<&mut dyn HapAccessoryService> =
+            vec![&mut self.accessory_information, &mut self.humidity_sensor];
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
+    }
+
+    fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
+
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
+}
+
+/// Creates a new Humidity Sensor Accessory with the given Current Relative Humidity in percent.
+/// Fails if the humidity is outside of the range of 0 % to 100 %.
+///
+/// The Status Active and Status Fault Characteristics are added to the Humidity Sensor Service,
+/// reporting an active sensor without a fault. They can be removed by setting them to `None`.
+///
+/// # Examples
+///
+/// ```
+/// use hap::accessory::{humidity_sensor, Information};
+///
+/// let information = Information {
+///     name: "Hygrometer".into(),
+///     ..Default::default()
+/// };
+/// assert!(humidity_sensor::new(Information::default(), 120.0).is_err());
+///
+/// let mut hygrometer = humidity_sensor::new(information, 45.0).unwrap();
+/// let service = &mut hygrometer.inner.humidity_sensor.inner;
+/// service.current_relative_humidity.set_value(52.0).unwrap();
+/// service.status_fault.as_mut().unwrap().set_value(1).unwrap();
+/// ```
+pub fn new(information: Information, current_relative_humidity: f32) -> Result<HumiditySensor> {
+    let mut humidity_sensor = humidity_sensor::new();
+    humidity_sensor.set_primary(true);
+    {
+        let service = &mut humidity_sensor.inner;
+        set_initial_value(&mut service.current_relative_humidity, current_relative_humidity)?;
+        let mut status_active = status_active::new();
+        status_active.set_value(true)?;
+        service.status_active = Some(status_active);
+        service.status_fault = Some(status_fault::new());
+    }
+    Ok(HumiditySensor::new(HumiditySensorInner {
+        accessory_information: information.to_service()?,
+        humidity_sensor,
+        ..Default::default()
+    }))
+}
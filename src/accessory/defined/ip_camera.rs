@@ -1,7 +1,12 @@
 use crate::{
     accessory::{Accessory, HapAccessory, HapAccessoryService, Information},
-    event::EventEmitterPtr,
-    service::{accessory_information::AccessoryInformation, camera_rtp_stream_management, microphone, HapService},
+    service::{
+        accessory_information::AccessoryInformation,
+        audio,
+        camera_rtp_stream_management,
+        microphone,
+        HapService,
+    },
     Result,
 };
 
@@ -9,7 +14,7 @@ use crate::{
 pub type IpCamera = Accessory<IpCameraInner>;
 
 /// Inner type of the IP Camera Accessory.
-#[derive(Default)]
+#[derive(Debug, Default)]
 pub struct IpCameraInner {
     /// ID of the IP Camera Accessory.
     id: u64,
@@ -20,6 +25,8 @@ pub struct IpCameraInner {
     pub camera_rtp_stream_management: camera_rtp_stream_management::CameraRTPStreamManagement,
     /// Microphone Service.
     pub microphone: microphone::Microphone,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
 }
 
 impl HapAccessory for IpCameraInner {
@@ -28,37 +35,32 @@ impl HapAccessory for IpCameraInner {
     fn set_id(&mut self, id: u64) { self.id = id; }
 
     fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
-        vec![
+        let mut services: Vec<&dyn HapAccessoryService> = vec![
             &self.accessory_information,
             &self.camera_rtp_stream_management,
             &self.microphone,
-        ]
+        ];
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
     }
 
     fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
-        vec![
+        let mut services: Vec<&mut dyn HapAccessoryService> = vec![
             &mut self.accessory_information,
             &mut self.camera_rtp_stream_management,
             &mut self.microphone,
-        ]
+        ];
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
     }
 
     fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
 
-    fn init_iids(&mut self, accessory_id: u64, event_emitter: EventEmitterPtr) -> Result<()> {
-        let mut next_iid = 1;
-        for service in self.get_mut_services() {
-            service.set_id(next_iid);
-            next_iid += 1;
-            for characteristic in service.get_mut_characteristics() {
-                characteristic.set_id(next_iid)?;
-                characteristic.set_accessory_id(accessory_id)?;
-                characteristic.set_event_emitter(Some(event_emitter.clone()))?;
-                next_iid += 1;
-            }
-        }
-        Ok(())
-    }
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
 }
 
 /// Creates a new IP Camera Accessory.
@@ -68,7 +70,7 @@ pub fn new(information: Information) -> Result<IpCamera> {
     Ok(IpCamera::new(IpCameraInner {
         accessory_information: information.to_service()?,
         camera_rtp_stream_management,
-        microphone: microphone::new(),
+        microphone: audio::new_microphone(audio::Options::default()),
         ..Default::default()
     }))
 }
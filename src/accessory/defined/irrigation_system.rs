@@ -0,0 +1,123 @@
+use crate::{
+    accessory::{Accessory, HapAccessory, HapAccessoryService, Information},
+    characteristic::{is_configured, name, remaining_duration, set_duration},
+    service::{accessory_information::AccessoryInformation, irrigation_system, valve, HapService},
+    Result,
+};
+
+/// Valve Type value of an irrigation Valve.
+const VALVE_TYPE_IRRIGATION: u8 = 1;
+/// Is Configured value of a configured Valve.
+const IS_CONFIGURED: u8 = 1;
+
+/// Irrigation System Accessory.
+pub type IrrigationSystem = Accessory<IrrigationSystemInner>;
+
+/// Inner type of the Irrigation System Accessory.
+#[derive(Debug, Default)]
+pub struct IrrigationSystemInner {
+    /// ID of the Irrigation System Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformation,
+    /// Irrigation System Service.
+    pub irrigation_system: irrigation_system::IrrigationSystem,
+    /// Valve Services of the zones of the Irrigation System.
+    pub zones: Vec<valve::Valve>,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
+}
+
+impl HapAccessory for IrrigationSystemInner {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
+        let mut services: Vec<&dyn HapAccessoryService> = vec![&self.accessory_information, &self.irrigation_system];
+        for z in &self.zones {
+            services.push(z);
+        }
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
+        let mut services: Vec<&mut dyn HapAccessoryService> =
+            vec![&mut self.accessory_information, &mut self.irrigation_system];
+        for z in &mut self.zones {
+            services.push(z);
+        }
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
+    }
+
+    fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
+
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
+}
+
+impl IrrigationSystem {
+    /// Adds a zone to the Irrigation System. Each zone is an irrigation Valve Service with the given
+    /// name, linked to the Irrigation System Service, and can be accessed via `inner.zones` in the
+    /// order the zones were added. Like other Services, zones have to be added before the Accessory
+    /// is added to a transport.
+    ///
+    /// The Set Duration and Remaining Duration Characteristics of a zone are in seconds. Counting
+    /// the Remaining Duration down while the zone is watering is up to the application.
+    pub fn add_zone(&mut self, name: &str) -> Result<()> {
+        let mut zone = valve::new();
+        zone.inner.valve_type.set_value(VALVE_TYPE_IRRIGATION)?;
+        let mut zone_name = name::new();
+        zone_name.set_value(name.into())?;
+        zone.inner.name = Some(zone_name);
+        zone.inner.set_duration = Some(set_duration::new());
+        zone.inner.remaining_duration = Some(remaining_duration::new());
+        let mut is_configured = is_configured::new();
+        is_configured.set_value(IS_CONFIGURED)?;
+        zone.inner.is_configured = Some(is_configured);
+
+        // preliminary ID to link the zone by, replaced once the Accessory is added to a transport
+        let zone_id = self.inner.zones.len() as u64 + 1;
+        zone.set_id(zone_id);
+        self.inner.irrigation_system.add_linked_service(zone_id);
+
+        self.inner.zones.push(zone);
+        Ok(())
+    }
+}
+
+/// Creates a new Irrigation System Accessory, e.g. a multi-zone sprinkler controller. Zones are
+/// added with `IrrigationSystem::add_zone`.
+///
+/// # Examples
+///
+/// ```
+/// use hap::accessory::{irrigation_system, Information};
+///
+/// let mut sprinkler = irrigation_system::new(Information {
+///     name: "Sprinkler".into(),
+///     ..Default::default()
+/// })
+/// .unwrap();
+/// sprinkler.add_zone("Front Lawn").unwrap();
+/// sprinkler.add_zone("Back Yard").unwrap();
+///
+/// let front_lawn = &mut sprinkler.inner.zones[0].inner;
+/// front_lawn.remaining_duration.as_mut().unwrap().set_value(300).unwrap();
+/// ```
+pub fn new(information: Information) -> Result<IrrigationSystem> {
+    let mut irrigation_system = irrigation_system::new();
+    irrigation_system.set_primary(true);
+    irrigation_system.inner.remaining_duration = Some(remaining_duration::new());
+    Ok(IrrigationSystem::new(IrrigationSystemInner {
+        accessory_information: information.to_service()?,
+        irrigation_system,
+        ..Default::default()
+    }))
+}
@@ -0,0 +1,90 @@
+use crate::{
+    accessory::{defined::set_initial_value, Accessory, HapAccessory, HapAccessoryService, Information},
+    characteristic::{status_active, status_fault},
+    service::{accessory_information::AccessoryInformation, light_sensor, HapService},
+    Result,
+};
+
+/// Light Sensor Accessory.
+pub type LightSensor = Accessory<LightSensorInner>;
+
+/// Inner type of the Light Sensor Accessory.
+#[derive(Debug, Default)]
+pub struct LightSensorInner {
+    /// ID of the Light Sensor Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformation,
+    /// Light Sensor Service.
+    pub light_sensor: light_sensor::LightSensor,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
+}
+
+impl HapAccessory for LightSensorInner {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
+        let mut services: Vec<&dyn HapAccessoryService> = vec![&self.accessory_information, &self.light_sensor];
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
+        let mut services: Vec<&mut dyn HapAccessoryService> =
+            vec![&mut self.accessory_information, &mut self.light_sensor];
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
+    }
+
+    fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
+
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
+}
+
+/// Creates a new Light Sensor Accessory with the given Current Ambient Light Level in lux. Fails if
+/// the light level is outside of the range of 0.0001 lx to 100000 lx.
+///
+/// The Status Active and Status Fault Characteristics are added to the Light Sensor Service,
+/// reporting an active sensor without a fault. They can be removed by setting them to `None`.
+///
+/// # Examples
+///
+/// ```
+/// use hap::accessory::{light_sensor, Information};
+///
+/// let information = Information {
+///     name: "Light Sensor".into(),
+///     ..Default::default()
+/// };
+/// assert!(light_sensor::new(Information::default(), 0.0).is_err());
+///
+/// let mut light_sensor = light_sensor::new(information, 350.0).unwrap();
+/// let service = &mut light_sensor.inner.light_sensor.inner;
+/// service.current_ambient_light_level.set_value(1200.0).unwrap();
+/// service.status_fault.as_mut().unwrap().set_value(1).unwrap();
+/// ```
+pub fn new(information: Information, current_ambient_light_level: f32) -> Result<LightSensor> {
+    let mut light_sensor = light_sensor::new();
+    light_sensor.set_primary(true);
+    {
+        let service = &mut light_sensor.inner;
+        set_initial_value(&mut service.current_ambient_light_level, current_ambient_light_level)?;
+        let mut status_active = status_active::new();
+        status_active.set_value(true)?;
+        service.status_active = Some(status_active);
+        service.status_fault = Some(status_fault::new());
+    }
+    Ok(LightSensor::new(LightSensorInner {
+        accessory_information: information.to_service()?,
+        light_sensor,
+        ..Default::default()
+    }))
+}
@@ -1,15 +1,21 @@
 use crate::{
     accessory::{Accessory, HapAccessory, HapAccessoryService, Information},
-    event::EventEmitterPtr,
+    characteristic::{administrator_only_access, tlv8::Tlv8},
     service::{accessory_information::AccessoryInformation, lock_management, lock_mechanism, HapService},
+    Error,
     Result,
 };
 
+/// TLV type of the operation item of a Lock Control Point value written by `Lock::clear_all_codes`.
+pub const CONTROL_POINT_OPERATION_TYPE: u8 = 0x00;
+/// Operation value of a Lock Control Point value written by `Lock::clear_all_codes`.
+pub const CONTROL_POINT_CLEAR_ALL_CODES: u8 = 0x01;
+
 /// Lock Accessory.
 pub type Lock = Accessory<LockInner>;
 
 /// Inner type of the Lock Accessory.
-#[derive(Default)]
+#[derive(Debug, Default)]
 pub struct LockInner {
     /// ID of the Lock Accessory.
     id: u64,
@@ -18,8 +24,10 @@ pub struct LockInner {
     pub accessory_information: AccessoryInformation,
     /// Lock Mechanism Service.
     pub lock_mechanism: lock_mechanism::LockMechanism,
-    /// Lock Management Service.
-    pub lock_management: lock_management::LockManagement,
+    /// Optional Lock Management Service.
+    pub lock_management: Option<lock_management::LockManagement>,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
 }
 
 impl HapAccessory for LockInner {
@@ -28,43 +36,104 @@ impl HapAccessory for LockInner {
     fn set_id(&mut self, id: u64) { self.id = id; }
 
     fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
-        vec![&self.accessory_information, &self.lock_mechanism, &self.lock_management]
+        let mut services: Vec<&dyn HapAccessoryService> = vec![&self.accessory_information, &self.lock_mechanism];
+        if let Some(ref s) = self.lock_management {
+            services.push(s);
+        }
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
     }
 
     fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
-        vec![
-            &mut self.accessory_information,
-            &mut self.lock_mechanism,
-            &mut self.lock_management,
-        ]
+        let mut services: Vec<&mut dyn HapAccessoryService> =
+            vec![&mut self.accessory_information, &mut self.lock_mechanism];
+        if let Some(ref mut s) = self.lock_management {
+            services.push(s);
+        }
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
     }
 
     fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
 
-    fn init_iids(&mut self, accessory_id: u64, event_emitter: EventEmitterPtr) -> Result<()> {
-        let mut next_iid = 1;
-        for service in self.get_mut_services() {
-            service.set_id(next_iid);
-            next_iid += 1;
-            for characteristic in service.get_mut_characteristics() {
-                characteristic.set_id(next_iid)?;
-                characteristic.set_accessory_id(accessory_id)?;
-                characteristic.set_event_emitter(Some(event_emitter.clone()))?;
-                next_iid += 1;
-            }
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
+}
+
+impl Lock {
+    /// Clears all access codes of the Lock by writing a clear operation to the Lock Control Point
+    /// Characteristic of the Lock Management Service. HAP leaves the operations of the Lock Control
+    /// Point to the vendor, so the written value is a TLV8 with a single item of the type
+    /// `CONTROL_POINT_OPERATION_TYPE` holding `CONTROL_POINT_CLEAR_ALL_CODES`, and the actual
+    /// clearing is done by an `Updatable` set on the Characteristic. Fails if the Lock has no Lock
+    /// Management Service.
+    pub fn clear_all_codes(&mut self) -> Result<()> {
+        match self.inner.lock_management {
+            Some(ref mut lock_management) => lock_management.inner.lock_control_point.set_value(
+                Tlv8::new()
+                    .with_u8(CONTROL_POINT_OPERATION_TYPE, CONTROL_POINT_CLEAR_ALL_CODES)
+                    .encode(),
+            ),
+            None => Err(Error::from_str("the Lock has no Lock Management Service")),
         }
-        Ok(())
     }
 }
 
-/// Creates a new Lock Accessory.
+/// Creates a new Lock Accessory with a Lock Mechanism Service. Use `with_management` for a Lock
+/// that also has a Lock Management Service.
 pub fn new(information: Information) -> Result<Lock> {
     let mut lock_mechanism = lock_mechanism::new();
     lock_mechanism.set_primary(true);
     Ok(Lock::new(LockInner {
         accessory_information: information.to_service()?,
         lock_mechanism,
-        lock_management: lock_management::new(),
         ..Default::default()
     }))
 }
+
+/// Creates a new Lock Accessory with a Lock Mechanism Service and a Lock Management Service for
+/// administrative operations like clearing the access codes. The optional Administrator Only Access
+/// Characteristic is added to the Lock Management Service, defaulting to `false`.
+///
+/// # Examples
+///
+/// ```
+/// use hap::{
+///     accessory::{lock, Information},
+///     characteristic::{tlv8::Tlv8, Updatable},
+///     HapType,
+/// };
+///
+/// struct ClearCodes;
+///
+/// impl Updatable<Vec<u8>> for ClearCodes {
+///     fn on_update(&mut self, _: &Vec<u8>, new_val: &Vec<u8>, _: HapType) {
+///         let request = Tlv8::decode(new_val).unwrap();
+///         if request.get_u8(lock::CONTROL_POINT_OPERATION_TYPE) == Some(lock::CONTROL_POINT_CLEAR_ALL_CODES) {
+///             // clear the access codes stored on the lock
+///         }
+///     }
+/// }
+///
+/// let mut door_lock = lock::with_management(Information {
+///     name: "Front Door".into(),
+///     ..Default::default()
+/// })
+/// .unwrap();
+/// let lock_management = door_lock.inner.lock_management.as_mut().unwrap();
+/// lock_management.inner.lock_control_point.set_updatable(ClearCodes).unwrap();
+/// door_lock.clear_all_codes().unwrap();
+///
+/// let mut basic_lock = lock::new(Information::default()).unwrap();
+/// assert!(basic_lock.clear_all_codes().is_err());
+/// ```
+pub fn with_management(information: Information) -> Result<Lock> {
+    let mut lock = new(information)?;
+    let mut lock_management = lock_management::new();
+    lock_management.inner.administrator_only_access = Some(administrator_only_access::new());
+    lock.inner.lock_management = Some(lock_management);
+    Ok(lock)
+}
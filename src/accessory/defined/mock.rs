@@ -0,0 +1,229 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use serde::ser::{Serialize, Serializer};
+use serde_json::Value;
+
+use crate::{
+    accessory::{Accessory, HapAccessory, HapAccessoryService, Information},
+    characteristic::{Characteristic, Format, HapCharacteristic, Perm, Unit},
+    event::EventEmitterPtr,
+    service::{accessory_information::AccessoryInformation, custom, HapService},
+    HapType,
+    Result,
+};
+
+/// UUID of the Service holding the Characteristics of a Mock Accessory.
+pub const SERVICE_UUID: &str = "bd1ef292-4919-43d9-9e11-193d026c8694";
+
+/// A scriptable Characteristic for tests. Clones share the same state, so a test can keep a clone
+/// of a `MockCharacteristic` added to a `MockAccessory` to script the values read by controllers
+/// and to check the values written by them.
+#[derive(Clone, Debug)]
+pub struct MockCharacteristic {
+    characteristic: Characteristic<Value>,
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    next_read_value: Option<Value>,
+    last_written_value: Option<Value>,
+}
+
+impl MockCharacteristic {
+    /// Creates a new `MockCharacteristic` with a vendor-specific UUID and the given initial value.
+    pub fn new(uuid: &str, format: Format, perms: Vec<Perm>, value: Value) -> Result<MockCharacteristic> {
+        let mut characteristic = Characteristic::new_custom(uuid, format, perms)?;
+        characteristic.set_value(value)?;
+        Ok(MockCharacteristic {
+            characteristic,
+            state: Arc::new(Mutex::new(MockState::default())),
+        })
+    }
+
+    /// Sets the value returned by the next read of a controller. Following reads return it as
+    /// well, unless it's changed in between.
+    pub fn set_next_read_value(&mut self, value: Value) {
+        self.state.lock().expect("couldn't access mock state").next_read_value = Some(value);
+    }
+
+    /// Returns the value last written by a controller, if any.
+    pub fn last_written_value(&self) -> Option<Value> {
+        self.state.lock().expect("couldn't access mock state").last_written_value.clone()
+    }
+
+    /// Panics if no value was written by a controller or the last written value differs from
+    /// `value`.
+    pub fn assert_last_written_value(&self, value: &Value) {
+        assert_eq!(self.last_written_value().as_ref(), Some(value), "unexpected last written value");
+    }
+}
+
+impl Serialize for MockCharacteristic {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.characteristic.serialize(serializer)
+    }
+}
+
+impl HapCharacteristic for MockCharacteristic {
+    fn get_id(&self) -> Result<u64> { HapCharacteristic::get_id(&self.characteristic) }
+
+    fn set_id(&mut self, id: u64) -> Result<()> { HapCharacteristic::set_id(&mut self.characteristic, id) }
+
+    fn set_accessory_id(&mut self, accessory_id: u64) -> Result<()> {
+        HapCharacteristic::set_accessory_id(&mut self.characteristic, accessory_id)
+    }
+
+    fn get_type(&self) -> Result<HapType> { HapCharacteristic::get_type(&self.characteristic) }
+
+    fn get_format(&self) -> Result<Format> { HapCharacteristic::get_format(&self.characteristic) }
+
+    fn get_perms(&self) -> Result<HashSet<Perm>> { HapCharacteristic::get_perms(&self.characteristic) }
+
+    fn get_description(&self) -> Result<Option<String>> { HapCharacteristic::get_description(&self.characteristic) }
+
+    fn get_event_notifications(&self) -> Result<Option<bool>> {
+        HapCharacteristic::get_event_notifications(&self.characteristic)
+    }
+
+    fn set_event_notifications(&mut self, event_notifications: Option<bool>) -> Result<()> {
+        HapCharacteristic::set_event_notifications(&mut self.characteristic, event_notifications)
+    }
+
+    fn get_value(&mut self) -> Result<Value> {
+        let next_read_value = self
+            .state
+            .lock()
+            .expect("couldn't access mock state")
+            .next_read_value
+            .take();
+        if let Some(value) = next_read_value {
+            self.characteristic.set_value(value)?;
+        }
+        HapCharacteristic::get_value(&mut self.characteristic)
+    }
+
+    fn set_value(&mut self, value: Value) -> Result<()> {
+        HapCharacteristic::set_value(&mut self.characteristic, value.clone())?;
+        self.state.lock().expect("couldn't access mock state").last_written_value = Some(value);
+        Ok(())
+    }
+
+    fn get_unit(&self) -> Result<Option<Unit>> { HapCharacteristic::get_unit(&self.characteristic) }
+
+    fn get_max_value(&self) -> Result<Option<Value>> { HapCharacteristic::get_max_value(&self.characteristic) }
+
+    fn get_min_value(&self) -> Result<Option<Value>> { HapCharacteristic::get_min_value(&self.characteristic) }
+
+    fn get_step_value(&self) -> Result<Option<Value>> { HapCharacteristic::get_step_value(&self.characteristic) }
+
+    fn get_max_len(&self) -> Result<Option<u16>> { HapCharacteristic::get_max_len(&self.characteristic) }
+
+    fn get_max_data_len(&self) -> Result<Option<u32>> { HapCharacteristic::get_max_data_len(&self.characteristic) }
+
+    fn get_valid_values(&self) -> Result<Option<Vec<Value>>> {
+        HapCharacteristic::get_valid_values(&self.characteristic)
+    }
+
+    fn get_valid_values_range(&self) -> Result<Option<[Value; 2]>> {
+        HapCharacteristic::get_valid_values_range(&self.characteristic)
+    }
+
+    fn is_authorized(&mut self, auth_data: Option<&[u8]>) -> Result<bool> {
+        HapCharacteristic::is_authorized(&mut self.characteristic, auth_data)
+    }
+
+    fn set_event_emitter(&mut self, event_emitter: Option<EventEmitterPtr>) -> Result<()> {
+        HapCharacteristic::set_event_emitter(&mut self.characteristic, event_emitter)
+    }
+}
+
+/// Mock Accessory.
+pub type MockAccessory = Accessory<MockAccessoryInner>;
+
+/// Inner type of the Mock Accessory.
+#[derive(Debug)]
+pub struct MockAccessoryInner {
+    /// ID of the Mock Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformation,
+    /// Service holding the `MockCharacteristic`s.
+    pub mock: custom::Custom,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
+}
+
+impl HapAccessory for MockAccessoryInner {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
+        let mut services: Vec<&dyn HapAccessoryService> = vec![&self.accessory_information, &self.mock];
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
+        let mut services: Vec<&mut dyn HapAccessoryService> = vec![&mut self.accessory_information, &mut self.mock];
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
+    }
+
+    fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
+
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
+}
+
+/// Creates a new Mock Accessory for tests, holding the given Characteristics in a primary Service
+/// of the type `SERVICE_UUID`. Only available with the `testing` feature.
+///
+/// # Examples
+///
+/// ```
+/// use hap::{
+///     accessory::{mock, Information},
+///     characteristic::{Format, HapCharacteristic, Perm},
+///     service::HapService,
+/// };
+/// use serde_json::json;
+///
+/// let mut level = mock::MockCharacteristic::new(
+///     "e863f10c-079e-48ff-8f27-9c2605a29f52",
+///     Format::UInt8,
+///     vec![Perm::PairedRead, Perm::PairedWrite],
+///     json!(0),
+/// )
+/// .unwrap();
+/// let mut accessory = mock::new(Information::default(), vec![level.clone()]).unwrap();
+///
+/// // a remote write as performed by the transport
+/// accessory.inner.mock.get_mut_characteristics()[0].set_value(json!(80)).unwrap();
+/// level.assert_last_written_value(&json!(80));
+///
+/// level.set_next_read_value(json!(42));
+/// assert_eq!(accessory.inner.mock.get_mut_characteristics()[0].get_value().unwrap(), json!(42));
+/// ```
+pub fn new(information: Information, characteristics: Vec<MockCharacteristic>) -> Result<MockAccessory> {
+    let characteristics = characteristics
+        .into_iter()
+        .map(|c| Box::new(c) as Box<dyn HapCharacteristic + Send>)
+        .collect();
+    let mut mock = custom::new(SERVICE_UUID, characteristics)?;
+    mock.set_primary(true);
+    Ok(MockAccessory::new(MockAccessoryInner {
+        id: 0,
+        accessory_information: information.to_service()?,
+        mock,
+        added_services: Vec::new(),
+    }))
+}
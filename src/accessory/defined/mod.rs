@@ -1,5 +1,28 @@
 pub mod bridge;
+pub mod heater_cooler;
+pub mod humidifier_dehumidifier;
+pub mod humidity_sensor;
 pub mod ip_camera;
+pub mod irrigation_system;
+pub mod light_sensor;
 pub mod lock;
+#[cfg(feature = "testing")]
+pub mod mock;
+pub mod stateless_programmable_switch;
 pub mod television;
+pub mod temperature_sensor;
 pub mod video_doorbell;
+pub mod window_covering;
+
+use crate::{characteristic::Characteristic, ErrorKind, Result};
+
+/// Sets the initial value of a sensor Characteristic. Fails with `ErrorKind::InvalidValue` if the
+/// value is outside of the minimum and maximum value of the Characteristic.
+pub(crate) fn set_initial_value(characteristic: &mut Characteristic<f32>, value: f32) -> Result<()> {
+    let below_min = characteristic.get_min_value()?.map_or(false, |min| value < min);
+    let above_max = characteristic.get_max_value()?.map_or(false, |max| value > max);
+    if value.is_nan() || below_min || above_max {
+        return Err(ErrorKind::InvalidValue.into());
+    }
+    characteristic.set_value(value)
+}
@@ -0,0 +1,129 @@
+use crate::{
+    accessory::{Accessory, HapAccessory, HapAccessoryService, Information},
+    characteristic::{name, service_label_index},
+    service::{accessory_information::AccessoryInformation, service_label, stateless_programmable_switch, HapService},
+    Error,
+    Result,
+};
+
+/// Service Label Namespace value of buttons labeled with arabic numerals.
+const NAMESPACE_ARABIC_NUMERALS: u8 = 1;
+
+/// Stateless Programmable Switch Accessory.
+pub type StatelessProgrammableSwitch = Accessory<StatelessProgrammableSwitchInner>;
+
+/// Inner type of the Stateless Programmable Switch Accessory.
+#[derive(Debug, Default)]
+pub struct StatelessProgrammableSwitchInner {
+    /// ID of the Stateless Programmable Switch Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformation,
+    /// Service Label Service. Its Service Label Namespace tells controllers how the buttons are
+    /// labeled on the device, defaulting to arabic numerals.
+    pub service_label: service_label::ServiceLabel,
+    /// Stateless Programmable Switch Services, one per button, in the order the buttons were added.
+    pub buttons: Vec<stateless_programmable_switch::StatelessProgrammableSwitch>,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
+}
+
+impl HapAccessory for StatelessProgrammableSwitchInner {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
+        let mut services: Vec<&dyn HapAccessoryService> = vec![&self.accessory_information, &self.service_label];
+        for b in &self.buttons {
+            services.push(b);
+        }
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
+        let mut services: Vec<&mut dyn HapAccessoryService> =
+            vec![&mut self.accessory_information, &mut self.service_label];
+        for b in &mut self.buttons {
+            services.push(b);
+        }
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
+    }
+
+    fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
+
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
+}
+
+impl StatelessProgrammableSwitch {
+    /// Adds a button with the given name to the Stateless Programmable Switch and returns its
+    /// Service Label Index. Indices are assigned in ascending order starting at `1`, matching the
+    /// labels on the device.
+    ///
+    /// Buttons are linked to the Service Label Service once the Accessory is added to a transport
+    /// and can be accessed via `inner.buttons` in the order they were added. Like other Services,
+    /// they have to be added before the Accessory is added to a transport.
+    pub fn add_button(&mut self, name: &str) -> Result<u8> {
+        if self.inner.buttons.len() >= u8::max_value() as usize {
+            return Err(Error::from_str("too many buttons"));
+        }
+        let index = self.inner.buttons.len() as u8 + 1;
+
+        let mut button = stateless_programmable_switch::new();
+        let mut button_name = name::new();
+        button_name.set_value(name.into())?;
+        button.inner.name = Some(button_name);
+        let mut service_label_index = service_label_index::new();
+        service_label_index.set_value(index)?;
+        button.inner.service_label_index = Some(service_label_index);
+        if index == 1 {
+            button.set_primary(true);
+        }
+
+        self.inner.buttons.push(button);
+        Ok(index)
+    }
+}
+
+/// Creates a new Stateless Programmable Switch Accessory with a single button named after the
+/// Accessory. Further buttons are added with `StatelessProgrammableSwitch::add_button`, each
+/// labeled by a Service Label Index.
+///
+/// # Examples
+///
+/// ```
+/// use hap::accessory::{stateless_programmable_switch, Information};
+///
+/// let mut remote = stateless_programmable_switch::new(Information {
+///     name: "Remote".into(),
+///     ..Default::default()
+/// })
+/// .unwrap();
+/// assert_eq!(remote.add_button("Off").unwrap(), 2);
+///
+/// // a single press of the second button
+/// let off_button = &mut remote.inner.buttons[1].inner;
+/// off_button.programmable_switch_event.set_value(0).unwrap();
+/// ```
+pub fn new(information: Information) -> Result<StatelessProgrammableSwitch> {
+    let mut service_label = service_label::new();
+    service_label
+        .inner
+        .service_label_namespace
+        .set_value(NAMESPACE_ARABIC_NUMERALS)?;
+    let name = information.name.clone();
+    let mut stateless_programmable_switch = StatelessProgrammableSwitch::new(StatelessProgrammableSwitchInner {
+        accessory_information: information.to_service()?,
+        service_label,
+        ..Default::default()
+    });
+    stateless_programmable_switch.add_button(&name)?;
+    Ok(stateless_programmable_switch)
+}
@@ -1,15 +1,37 @@
 use crate::{
     accessory::{Accessory, HapAccessory, HapAccessoryService, Information},
-    event::EventEmitterPtr,
-    service::{accessory_information::AccessoryInformation, speaker, television, HapService},
+    characteristic::{identifier, name, remote_key, target_visibility_state},
+    service::{accessory_information::AccessoryInformation, input_source, speaker, television, HapService},
+    Error,
     Result,
 };
 
+/// Is Configured value of a configured Input Source.
+const IS_CONFIGURED: u8 = 1;
+/// Visibility State value of a shown Input Source.
+const VISIBILITY_STATE_SHOWN: u8 = 0;
+
+/// Type of an Input Source of a Television.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputSourceType {
+    Other = 0,
+    HomeScreen = 1,
+    Tuner = 2,
+    Hdmi = 3,
+    CompositeVideo = 4,
+    SVideo = 5,
+    ComponentVideo = 6,
+    Dvi = 7,
+    AirPlay = 8,
+    Usb = 9,
+    Application = 10,
+}
+
 /// Television Accessory.
 pub type Television = Accessory<TelevisionInner>;
 
 /// Inner type of the Television Accessory.
-#[derive(Default)]
+#[derive(Debug, Default)]
 pub struct TelevisionInner {
     /// ID of the Television Accessory.
     id: u64,
@@ -20,6 +42,10 @@ pub struct TelevisionInner {
     pub television: television::Television,
     /// Speaker Service.
     pub speaker: speaker::Speaker,
+    /// Input Source Services of the Television.
+    pub inputs: Vec<input_source::InputSource>,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
 }
 
 impl HapAccessory for TelevisionInner {
@@ -28,35 +54,105 @@ impl HapAccessory for TelevisionInner {
     fn set_id(&mut self, id: u64) { self.id = id; }
 
     fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
-        vec![&self.accessory_information, &self.television, &self.speaker]
+        let mut services: Vec<&dyn HapAccessoryService> =
+            vec![&self.accessory_information, &self.television, &self.speaker];
+        for i in &self.inputs {
+            services.push(i);
+        }
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
     }
 
     fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
-        vec![&mut self.accessory_information, &mut self.television, &mut self.speaker]
+        let mut services: Vec<&mut dyn HapAccessoryService> =
+            vec![&mut self.accessory_information, &mut self.television, &mut self.speaker];
+        for i in &mut self.inputs {
+            services.push(i);
+        }
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
     }
 
     fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
 
-    fn init_iids(&mut self, accessory_id: u64, event_emitter: EventEmitterPtr) -> Result<()> {
-        let mut next_iid = 1;
-        for service in self.get_mut_services() {
-            service.set_id(next_iid);
-            next_iid += 1;
-            for characteristic in service.get_mut_characteristics() {
-                characteristic.set_id(next_iid)?;
-                characteristic.set_accessory_id(accessory_id)?;
-                characteristic.set_event_emitter(Some(event_emitter.clone()))?;
-                next_iid += 1;
-            }
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
+}
+
+impl Television {
+    /// Adds an Input Source with the given name and type to the Television and returns the
+    /// identifier assigned to it. Identifiers are assigned in ascending order starting at `1`, and
+    /// the Active Identifier of the Television Service is the identifier of the selected input. The
+    /// first input added becomes the active one.
+    ///
+    /// Input Sources are linked to the Television Service and can be accessed via `inner.inputs` in
+    /// the order they were added. Like other Services, they have to be added before the Accessory
+    /// is added to a transport.
+    pub fn add_input(&mut self, name: &str, source_type: InputSourceType) -> Result<u8> {
+        if self.inner.inputs.len() >= u8::max_value() as usize {
+            return Err(Error::from_str("too many input sources"));
+        }
+        let input_identifier = self.inner.inputs.len() as u8 + 1;
+
+        let mut input = input_source::new();
+        input.inner.configured_name.set_value(name.into())?;
+        input.inner.input_source_type.set_value(source_type as u8)?;
+        input.inner.is_configured.set_value(IS_CONFIGURED)?;
+        input.inner.current_visibility_state.set_value(VISIBILITY_STATE_SHOWN)?;
+        let mut target_visibility_state = target_visibility_state::new();
+        target_visibility_state.set_value(VISIBILITY_STATE_SHOWN)?;
+        input.inner.target_visibility_state = Some(target_visibility_state);
+        let mut identifier = identifier::new();
+        identifier.set_value(input_identifier as u32)?;
+        input.inner.identifier = Some(identifier);
+        let mut input_name = name::new();
+        input_name.set_value(name.into())?;
+        input.inner.name = Some(input_name);
+
+        // preliminary ID to link the input by, replaced once the Accessory is added to a transport
+        let input_id = input_identifier as u64;
+        input.set_id(input_id);
+        self.inner.television.add_linked_service(input_id);
+
+        if input_identifier == 1 {
+            self.inner.television.inner.active_identifier.set_value(1)?;
         }
-        Ok(())
+        self.inner.inputs.push(input);
+        Ok(input_identifier)
     }
 }
 
-/// Creates a new Television Accessory.
+/// Creates a new Television Accessory. The Configured Name of the Television Service is set to the
+/// name of the Accessory, and the Remote Key Characteristic is added so the TV can be controlled
+/// from the Remote in Control Center. Inputs are added with `Television::add_input`.
+///
+/// # Examples
+///
+/// ```
+/// use hap::accessory::{
+///     television::{self, InputSourceType},
+///     Information,
+/// };
+///
+/// let mut tv = television::new(Information {
+///     name: "TV".into(),
+///     ..Default::default()
+/// })
+/// .unwrap();
+/// let hdmi = tv.add_input("HDMI 1", InputSourceType::Hdmi).unwrap();
+/// let netflix = tv.add_input("Netflix", InputSourceType::Application).unwrap();
+/// assert_eq!((hdmi, netflix), (1, 2));
+///
+/// tv.inner.television.inner.active_identifier.set_value(netflix as u32).unwrap();
+/// ```
 pub fn new(information: Information) -> Result<Television> {
     let mut television = television::new();
     television.set_primary(true);
+    television.inner.configured_name.set_value(information.name.clone())?;
+    television.inner.remote_key = Some(remote_key::new());
     Ok(Television::new(TelevisionInner {
         accessory_information: information.to_service()?,
         television,
@@ -0,0 +1,90 @@
+use crate::{
+    accessory::{defined::set_initial_value, Accessory, HapAccessory, HapAccessoryService, Information},
+    characteristic::{status_active, status_fault},
+    service::{accessory_information::AccessoryInformation, temperature_sensor, HapService},
+    Result,
+};
+
+/// Temperature Sensor Accessory.
+pub type TemperatureSensor = Accessory<TemperatureSensorInner>;
+
+/// Inner type of the Temperature Sensor Accessory.
+#[derive(Debug, Default)]
+pub struct TemperatureSensorInner {
+    /// ID of the Temperature Sensor Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformation,
+    /// Temperature Sensor Service.
+    pub temperature_sensor: temperature_sensor::TemperatureSensor,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
+}
+
+impl HapAccessory for TemperatureSensorInner {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
+        let mut services: Vec<&dyn HapAccessoryService> = vec![&self.accessory_information, &self.temperature_sensor];
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
+        let mut services: Vec<&mut dyn HapAccessoryService> =
+            vec![&mut self.accessory_information, &mut self.temperature_sensor];
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
+    }
+
+    fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
+
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
+}
+
+/// Creates a new Temperature Sensor Accessory with the given Current Temperature in degrees Celsius.
+/// Fails if the temperature is outside of the range of −270 °C to 100 °C.
+///
+/// The Status Active and Status Fault Characteristics are added to the Temperature Sensor Service,
+/// reporting an active sensor without a fault. They can be removed by setting them to `None`.
+///
+/// # Examples
+///
+/// ```
+/// use hap::accessory::{temperature_sensor, Information};
+///
+/// let information = Information {
+///     name: "Thermometer".into(),
+///     ..Default::default()
+/// };
+/// assert!(temperature_sensor::new(Information::default(), -300.0).is_err());
+///
+/// let mut thermometer = temperature_sensor::new(information, -12.5).unwrap();
+/// let service = &mut thermometer.inner.temperature_sensor.inner;
+/// service.current_temperature.set_value(21.3).unwrap();
+/// service.status_fault.as_mut().unwrap().set_value(1).unwrap();
+/// ```
+pub fn new(information: Information, current_temperature: f32) -> Result<TemperatureSensor> {
+    let mut temperature_sensor = temperature_sensor::new();
+    temperature_sensor.set_primary(true);
+    {
+        let service = &mut temperature_sensor.inner;
+        set_initial_value(&mut service.current_temperature, current_temperature)?;
+        let mut status_active = status_active::new();
+        status_active.set_value(true)?;
+        service.status_active = Some(status_active);
+        service.status_fault = Some(status_fault::new());
+    }
+    Ok(TemperatureSensor::new(TemperatureSensorInner {
+        accessory_information: information.to_service()?,
+        temperature_sensor,
+        ..Default::default()
+    }))
+}
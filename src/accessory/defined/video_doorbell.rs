@@ -1,8 +1,8 @@
 use crate::{
     accessory::{Accessory, HapAccessory, HapAccessoryService, Information},
-    event::EventEmitterPtr,
     service::{
         accessory_information::AccessoryInformation,
+        audio,
         camera_rtp_stream_management,
         microphone,
         speaker,
@@ -15,7 +15,7 @@ use crate::{
 pub type VideoDoorbell = Accessory<VideoDoorbellInner>;
 
 /// Inner type of the Video Doorbell Accessory.
-#[derive(Default)]
+#[derive(Debug, Default)]
 pub struct VideoDoorbellInner {
     /// ID of the Video Doorbell Accessory.
     id: u64,
@@ -28,6 +28,8 @@ pub struct VideoDoorbellInner {
     pub speaker: speaker::Speaker,
     /// Microphone Service.
     pub microphone: microphone::Microphone,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
 }
 
 impl HapAccessory for VideoDoorbellInner {
@@ -36,39 +38,34 @@ impl HapAccessory for VideoDoorbellInner {
     fn set_id(&mut self, id: u64) { self.id = id; }
 
     fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
-        vec![
+        let mut services: Vec<&dyn HapAccessoryService> = vec![
             &self.accessory_information,
             &self.camera_rtp_stream_management,
             &self.speaker,
             &self.microphone,
-        ]
+        ];
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
     }
 
     fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
-        vec![
+        let mut services: Vec<&mut dyn HapAccessoryService> = vec![
             &mut self.accessory_information,
             &mut self.camera_rtp_stream_management,
             &mut self.speaker,
             &mut self.microphone,
-        ]
+        ];
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
     }
 
     fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
 
-    fn init_iids(&mut self, accessory_id: u64, event_emitter: EventEmitterPtr) -> Result<()> {
-        let mut next_iid = 1;
-        for service in self.get_mut_services() {
-            service.set_id(next_iid);
-            next_iid += 1;
-            for characteristic in service.get_mut_characteristics() {
-                characteristic.set_id(next_iid)?;
-                characteristic.set_accessory_id(accessory_id)?;
-                characteristic.set_event_emitter(Some(event_emitter.clone()))?;
-                next_iid += 1;
-            }
-        }
-        Ok(())
-    }
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
 }
 
 /// Creates a new Video Doorbell Accessory.
@@ -79,7 +76,7 @@ pub fn new(information: Information) -> Result<VideoDoorbell> {
         accessory_information: information.to_service()?,
         camera_rtp_stream_management,
         speaker: speaker::new(),
-        microphone: microphone::new(),
+        microphone: audio::new_microphone(audio::Options::default()),
         ..Default::default()
     }))
 }
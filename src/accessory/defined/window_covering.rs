@@ -0,0 +1,208 @@
+use std::{
+    cmp::Ordering,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    accessory::{Accessory, HapAccessory, HapAccessoryService, Information},
+    characteristic::{position_state, Updatable},
+    service::{accessory_information::AccessoryInformation, window_covering, HapService},
+    HapType,
+    Result,
+};
+
+/// Position State value of a Window Covering that is closing.
+pub const POSITION_STATE_DECREASING: u8 = 0;
+/// Position State value of a Window Covering that is opening.
+pub const POSITION_STATE_INCREASING: u8 = 1;
+/// Position State value of a Window Covering that isn't moving.
+pub const POSITION_STATE_STOPPED: u8 = 2;
+
+/// Window Covering Accessory.
+pub type WindowCovering = Accessory<WindowCoveringInner>;
+
+/// Inner type of the Window Covering Accessory.
+#[derive(Debug, Default)]
+pub struct WindowCoveringInner {
+    /// ID of the Window Covering Accessory.
+    id: u64,
+
+    /// Accessory Information Service.
+    pub accessory_information: AccessoryInformation,
+    /// Window Covering Service.
+    pub window_covering: window_covering::WindowCovering,
+    /// Services added to the Accessory.
+    added_services: Vec<Box<dyn HapAccessoryService + Send>>,
+}
+
+impl HapAccessory for WindowCoveringInner {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_services(&self) -> Vec<&dyn HapAccessoryService> {
+        let mut services: Vec<&dyn HapAccessoryService> = vec![&self.accessory_information, &self.window_covering];
+        for s in &self.added_services {
+            services.push(s.as_ref());
+        }
+        services
+    }
+
+    fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService> {
+        let mut services: Vec<&mut dyn HapAccessoryService> =
+            vec![&mut self.accessory_information, &mut self.window_covering];
+        for s in &mut self.added_services {
+            services.push(s.as_mut());
+        }
+        services
+    }
+
+    fn get_mut_information(&mut self) -> &mut AccessoryInformation { &mut self.accessory_information }
+
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.added_services.push(service); }
+}
+
+/// Target Position and Current Position of a Window Covering, shared by the `Updatable`s of both
+/// Characteristics. Reading the other Characteristic instead would lock it while the own one is
+/// locked, deadlocking concurrent writes of both positions.
+#[derive(Default)]
+struct Positions {
+    target: u8,
+    current: u8,
+}
+
+/// Sets the Position State to `Increasing` or `Decreasing` when the Target Position changes.
+struct TargetPositionUpdater {
+    positions: Arc<Mutex<Positions>>,
+    position_state: position_state::PositionState,
+}
+
+impl Updatable<u8> for TargetPositionUpdater {
+    fn on_update(&mut self, _: &u8, new_val: &u8, _: HapType) {
+        if let Ok(mut positions) = self.positions.lock() {
+            positions.target = *new_val;
+            let position_state = match positions.target.cmp(&positions.current) {
+                Ordering::Greater => POSITION_STATE_INCREASING,
+                Ordering::Less => POSITION_STATE_DECREASING,
+                Ordering::Equal => POSITION_STATE_STOPPED,
+            };
+            let _ = self.position_state.set_value(position_state);
+        }
+    }
+}
+
+/// Sets the Position State to `Stopped` when the Current Position reaches the Target Position.
+struct CurrentPositionUpdater {
+    positions: Arc<Mutex<Positions>>,
+    position_state: position_state::PositionState,
+}
+
+impl Updatable<u8> for CurrentPositionUpdater {
+    fn on_update(&mut self, _: &u8, new_val: &u8, _: HapType) {
+        if let Ok(mut positions) = self.positions.lock() {
+            positions.current = *new_val;
+            if positions.current == positions.target {
+                let _ = self.position_state.set_value(POSITION_STATE_STOPPED);
+            }
+        }
+    }
+}
+
+/// Creates a new Window Covering Accessory, e.g. a blind, shade or curtain.
+///
+/// The Position State is kept in sync with the positions: it is set to `Increasing` or
+/// `Decreasing` when the Target Position changes, and back to `Stopped` once the Current Position,
+/// as set by the application while the covering moves, reaches the Target Position. Setting an
+/// `Updatable` on the Target Position or the Current Position replaces this behavior for the
+/// respective Characteristic.
+///
+/// # Examples
+///
+/// ```
+/// use hap::accessory::{window_covering, Information};
+///
+/// let mut blind = window_covering::new(Information {
+///     name: "Blind".into(),
+///     ..Default::default()
+/// })
+/// .unwrap();
+/// let service = &mut blind.inner.window_covering.inner;
+///
+/// service.target_position.set_value(100).unwrap();
+/// assert_eq!(service.position_state.get_value().unwrap(), window_covering::POSITION_STATE_INCREASING);
+///
+/// service.current_position.set_value(100).unwrap();
+/// assert_eq!(service.position_state.get_value().unwrap(), window_covering::POSITION_STATE_STOPPED);
+/// ```
+pub fn new(information: Information) -> Result<WindowCovering> {
+    let mut window_covering = window_covering::new();
+    window_covering.set_primary(true);
+    {
+        let service = &mut window_covering.inner;
+        let positions = Arc::new(Mutex::new(Positions {
+            target: service.target_position.get_value()?,
+            current: service.current_position.get_value()?,
+        }));
+        let position_state = service.position_state.clone();
+        service.position_state.set_value(POSITION_STATE_STOPPED)?;
+        service.target_position.set_updatable(TargetPositionUpdater {
+            positions: positions.clone(),
+            position_state: position_state.clone(),
+        })?;
+        service.current_position.set_updatable(CurrentPositionUpdater {
+            positions,
+            position_state,
+        })?;
+    }
+    Ok(WindowCovering::new(WindowCoveringInner {
+        accessory_information: information.to_service()?,
+        window_covering,
+        ..Default::default()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{mpsc, Barrier},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_concurrent_position_writes() {
+        let mut window_covering = new(Information {
+            name: "Blind".into(),
+            ..Default::default()
+        })
+        .unwrap();
+        let service = &mut window_covering.inner.window_covering.inner;
+
+        // both positions are written at the same time, as by a Controller and by the application
+        // reporting the movement of the covering
+        let (sender, receiver) = mpsc::channel();
+        let barrier = Arc::new(Barrier::new(2));
+        for mut position in [service.target_position.clone(), service.current_position.clone()] {
+            let sender = sender.clone();
+            let barrier = barrier.clone();
+            thread::spawn(move || {
+                barrier.wait();
+                for _ in 0..1000 {
+                    for i in 0..=100 {
+                        position.set_value(i).unwrap();
+                    }
+                }
+                sender.send(()).unwrap();
+            });
+        }
+        for _ in 0..2 {
+            receiver
+                .recv_timeout(Duration::from_secs(10))
+                .expect("writing both positions deadlocked");
+        }
+
+        assert_eq!(service.position_state.get_value().unwrap(), POSITION_STATE_STOPPED);
+    }
+}
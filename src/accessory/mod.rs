@@ -1,13 +1,17 @@
+use std::{collections::HashMap, fmt};
+
 use erased_serde::{self, __internal_serialize_trait_object, serialize_trait_object};
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
-    characteristic::{accessory_flags, hardware_revision},
+    characteristic::{accessory_flags, hardware_revision, product_data},
     event::EventEmitterPtr,
     service::{
         accessory_information::{self, AccessoryInformation},
         HapService,
     },
+    Error,
+    HapType,
     Result,
 };
 
@@ -24,6 +28,20 @@ impl<T: HapService + erased_serde::Serialize> HapAccessoryService for T {}
 
 serialize_trait_object!(HapAccessoryService);
 
+impl<'a> fmt::Debug for dyn HapAccessoryService + 'a {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HapAccessoryService")
+            .field("id", &self.get_id())
+            .field("hap_type", &self.get_type())
+            .field("characteristics", &self.get_characteristics())
+            .finish()
+    }
+}
+
+impl<'a> fmt::Debug for dyn HapAccessoryService + Send + 'a {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { (self as &dyn HapAccessoryService).fmt(f) }
+}
+
 /// `HapAccessory` is implemented by the inner type of every `Accessory`.
 pub trait HapAccessory {
     /// Returns the ID of an Accessory.
@@ -36,23 +54,113 @@ pub trait HapAccessory {
     fn get_mut_services(&mut self) -> Vec<&mut dyn HapAccessoryService>;
     /// Returns a mutable reference to the Accessory Information Service of an Accessory.
     fn get_mut_information(&mut self) -> &mut AccessoryInformation;
+    /// Adds a Service, e.g. a `service::custom::Custom` Service, to an Accessory. Services have to
+    /// be added before the Accessory is added to a transport, so they are assigned instance IDs.
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>);
     /// Initializes the Service and Characteristic instance IDs of an Accessory. Service and
     /// Characteristic instance IDs, "iid", are assigned from the same number pool that is unique
     /// within each Accessory object. For example, if the first Service object has an instance ID of
     /// "1" then no other Service or Characteristic objects can have an instance ID of "1" within
     /// the parent Accessory object.
-    fn init_iids(&mut self, accessory_id: u64, event_emitter: EventEmitterPtr) -> Result<()>;
+    ///
+    /// Services that were given unique, non-zero IDs beforehand keep their links to each other, as
+    /// the linked Service IDs are rewritten to the newly assigned IDs.
+    fn init_iids(&mut self, accessory_id: u64, event_emitter: EventEmitterPtr) -> Result<()> {
+        let mut next_iid = 1;
+        let mut reassigned_ids = HashMap::new();
+        for service in self.get_mut_services() {
+            let previous_id = service.get_id();
+            if previous_id != 0 {
+                reassigned_ids.insert(previous_id, next_iid);
+            }
+            service.set_id(next_iid);
+            next_iid += 1;
+            for characteristic in service.get_mut_characteristics() {
+                characteristic.set_id(next_iid)?;
+                characteristic.set_accessory_id(accessory_id)?;
+                characteristic.set_event_emitter(Some(event_emitter.clone()))?;
+                next_iid += 1;
+            }
+        }
+        // Services labeled by a Service Label Index, e.g. the buttons of a multi-button switch, are
+        // linked to the Service Label Service of the Accessory
+        let labeled_services: Vec<u64> = self
+            .get_services()
+            .iter()
+            .filter(|service| {
+                service
+                    .get_characteristics()
+                    .iter()
+                    .any(|c| c.get_type().ok() == Some(HapType::ServiceLabelIndex))
+            })
+            .map(|service| service.get_id())
+            .collect();
+        for service in self.get_mut_services() {
+            let mut linked_services: Vec<u64> = service
+                .get_linked_services()
+                .iter()
+                .map(|id| *reassigned_ids.get(id).unwrap_or(id))
+                .collect();
+            if service.get_type() == HapType::ServiceLabel {
+                for id in &labeled_services {
+                    if !linked_services.contains(id) {
+                        linked_services.push(*id);
+                    }
+                }
+            }
+            service.set_linked_services(linked_services);
+        }
+        Ok(())
+    }
+
+    /// Sets a callback that is called every time a controller asks the Accessory to identify
+    /// itself, e.g. by blinking an LED. This happens on `POST /identify` before the Accessory is
+    /// paired and on writes of `true` to the Identify Characteristic of the Accessory Information
+    /// Service after it is paired. An error returned by the callback is reported to the controller.
+    ///
+    /// The default implementation ignores the callback. `Accessory` stores it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::accessory::{lightbulb, HapAccessory, Information};
+    ///
+    /// let mut lightbulb = lightbulb::new(Information::default()).unwrap();
+    /// lightbulb.set_identify_callback(Box::new(|| {
+    ///     println!("blinking the lightbulb");
+    ///     Ok(())
+    /// }));
+    /// ```
+    fn set_identify_callback(&mut self, _callback: Box<dyn Fn() -> Result<()> + Send + 'static>) {}
+
+    /// Calls the identify callback of the Accessory, if one is set.
+    fn identify(&mut self) -> Result<()> { Ok(()) }
 }
 
 /// An Accessory. Accessories are the outermost data type defined by the HAP. They are comprised of
 /// services and characteristics.
 pub struct Accessory<T: HapAccessory> {
     pub inner: T,
+    identify_callback: Option<Box<dyn Fn() -> Result<()> + Send>>,
 }
 
 impl<T: HapAccessory> Accessory<T> {
     /// Creates a new `Accessory`.
-    fn new(inner: T) -> Accessory<T> { Accessory { inner } }
+    fn new(inner: T) -> Accessory<T> {
+        Accessory {
+            inner,
+            identify_callback: None,
+        }
+    }
+}
+
+impl<T: HapAccessory + fmt::Debug> fmt::Debug for Accessory<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Accessory")
+            .field("inner", &self.inner)
+            .field("identify_callback", &self.identify_callback.is_some())
+            .finish()
+    }
 }
 
 impl<T: HapAccessory> Serialize for Accessory<T> {
@@ -75,9 +183,22 @@ impl<T: HapAccessory> HapAccessory for Accessory<T> {
 
     fn get_mut_information(&mut self) -> &mut AccessoryInformation { self.inner.get_mut_information() }
 
+    fn add_service(&mut self, service: Box<dyn HapAccessoryService + Send>) { self.inner.add_service(service) }
+
     fn init_iids(&mut self, accessory_id: u64, event_emitter: EventEmitterPtr) -> Result<()> {
         self.inner.init_iids(accessory_id, event_emitter)
     }
+
+    fn set_identify_callback(&mut self, callback: Box<dyn Fn() -> Result<()> + Send + 'static>) {
+        self.identify_callback = Some(callback);
+    }
+
+    fn identify(&mut self) -> Result<()> {
+        match self.identify_callback {
+            Some(ref callback) => callback(),
+            None => Ok(()),
+        }
+    }
 }
 
 /// The `Information` struct is used to store metadata about an `Accessory` and is converted to the
@@ -98,6 +219,7 @@ impl<T: HapAccessory> HapAccessory for Accessory<T> {
 ///
 /// let outlet = outlet::new(info).unwrap();
 /// ```
+#[derive(Debug)]
 pub struct Information {
     /// Used to cause the `Accessory` to run its identify routine.
     pub identify: bool,
@@ -137,11 +259,50 @@ pub struct Information {
     /// - Subsequent firmware updates can have a lower <y> version only if <x> is incremented
     /// - Subsequent firmware updates can have a lower <z> version only if <x> or <y> is incremented
     ///
-    /// The value must change after every hardware update.
+    /// The value must change after every hardware update. `to_service` fails if the string isn't
+    /// of that format.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::accessory::{outlet, Information};
+    ///
+    /// let v2_board = Information {
+    ///     hardware_revision: Some("2.1".into()),
+    ///     ..Default::default()
+    /// };
+    /// assert!(v2_board.to_service().is_ok());
+    ///
+    /// let invalid = Information {
+    ///     hardware_revision: Some("v2 PCB".into()),
+    ///     ..Default::default()
+    /// };
+    /// assert!(outlet::new(invalid).is_err());
+    /// ```
     pub hardware_revision: Option<String>,
     /// When set indicates accessory requires additional setup. Use of Accessory Flags requires
     /// written approval by Apple in advance.
     pub accessory_flags: Option<u32>,
+    /// Manufacturer-specific opaque identifier of the certified product the `Accessory` is, e.g.
+    /// checked by HomeKit certification tooling. Transported as a Base64 string.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::accessory::{outlet, Information};
+    ///
+    /// let outlet = outlet::new(Information {
+    ///     product_data: Some([0x03, 0xd8, 0xa2, 0x75, 0x31, 0xe2, 0x2c, 0xb2]),
+    ///     ..Default::default()
+    /// })
+    /// .unwrap();
+    ///
+    /// let attribute_db = serde_json::to_value(&outlet).unwrap();
+    /// let characteristics = attribute_db["services"][0]["characteristics"].as_array().unwrap();
+    /// let product_data = characteristics.iter().find(|c| c["type"] == "220").unwrap();
+    /// assert_eq!(product_data["value"], "A9iidTHiLLI=");
+    /// ```
+    pub product_data: Option<[u8; 8]>,
 }
 
 impl Information {
@@ -155,6 +316,9 @@ impl Information {
         i.inner.serial_number.set_value(self.serial_number)?;
         i.inner.firmware_revision.set_value(self.firmware_revision)?;
         if let Some(v) = self.hardware_revision {
+            if !is_revision(&v) {
+                return Err(Error::from_str("the hardware revision isn't of the format x[.y[.z]]"));
+            }
             let mut hr = hardware_revision::new();
             hr.set_value(v)?;
             i.inner.hardware_revision = Some(hr);
@@ -164,10 +328,21 @@ impl Information {
             af.set_value(v)?;
             i.inner.accessory_flags = Some(af);
         }
+        if let Some(v) = self.product_data {
+            let mut pd = product_data::new();
+            pd.set_value(v.to_vec())?;
+            i.inner.product_data = Some(pd);
+        }
         Ok(i)
     }
 }
 
+/// Whether `revision` is a revision string of the format `x[.y[.z]]`, e.g. `"100.1.1"`.
+fn is_revision(revision: &str) -> bool {
+    let parts: Vec<&str> = revision.split('.').collect();
+    parts.len() <= 3 && parts.iter().all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+}
+
 impl Default for Information {
     fn default() -> Information {
         Information {
@@ -179,6 +354,7 @@ impl Default for Information {
             firmware_revision: "undefined".into(),
             hardware_revision: None,
             accessory_flags: None,
+            product_data: None,
         }
     }
 }
@@ -1,24 +1,38 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::HashSet,
+    fmt,
+    sync::{Arc, Mutex},
+};
 
 use erased_serde::{self, __internal_serialize_trait_object, serialize_trait_object};
 use serde::{
-    ser::{SerializeStruct, Serializer},
+    ser::{self, SerializeStruct, Serializer},
     Deserialize,
     Serialize,
 };
 use serde_json::{self, json};
+use uuid::Uuid;
 
 use crate::{
     event::{Event, EventEmitterPtr},
     Error,
+    ErrorKind,
     HapType,
     Result,
 };
 
 mod generated;
 
+pub mod tlv8;
+
 pub use crate::characteristic::generated::*;
 
+/// Default maximum length of `string` Characteristics.
+const DEFAULT_MAX_LEN: u16 = 64;
+
+/// Default maximum data length of `data` Characteristics.
+const DEFAULT_MAX_DATA_LEN: u32 = 2_097_152;
+
 /// Inner type of a `Characteristic`.
 #[derive(Default)]
 pub struct Inner<T: Default + Clone + Serialize> {
@@ -40,17 +54,61 @@ pub struct Inner<T: Default + Clone + Serialize> {
     max_data_len: Option<u32>,
     valid_values: Option<Vec<T>>,
     valid_values_range: Option<[T; 2]>,
+    clamp_values: bool,
+    notify_unchanged: bool,
+    bools_as_numbers: bool,
 
     readable: Option<Box<dyn Readable<T> + Send>>,
     updatable: Option<Box<dyn Updatable<T> + Send>>,
+    authorizable: Option<Box<dyn Authorizable + Send>>,
+    validatable: Option<Arc<Mutex<Box<dyn Validatable<T> + Send>>>>,
 
     event_emitter: Option<EventEmitterPtr>,
 }
 
+impl<T: Default + Clone + Serialize> Inner<T> {
+    /// Converts a value to the JSON representation it is transported as. `tlv8` and `data` values
+    /// are transported as base64 strings and `bool` values optionally as `0` or `1`.
+    fn to_transport_value(&self, value: &T) -> Result<serde_json::Value> {
+        let value = json!(value);
+        match self.format {
+            Format::Tlv8 | Format::Data => to_base64_value(value),
+            Format::Bool if self.bools_as_numbers => Ok(match value.as_bool() {
+                Some(b) => json!(b as u8),
+                None => value,
+            }),
+            _ => Ok(value),
+        }
+    }
+}
+
+impl<T: Default + Clone + Serialize + fmt::Debug> fmt::Debug for Inner<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Inner")
+            .field("id", &self.id)
+            .field("accessory_id", &self.accessory_id)
+            .field("hap_type", &self.hap_type)
+            .field("format", &self.format)
+            .field("perms", &self.perms)
+            .field("description", &self.description)
+            .field("event_notifications", &self.event_notifications)
+            .field("value", &self.value)
+            .field("unit", &self.unit)
+            .field("max_value", &self.max_value)
+            .field("min_value", &self.min_value)
+            .field("step_value", &self.step_value)
+            .field("max_len", &self.max_len)
+            .field("max_data_len", &self.max_data_len)
+            .field("valid_values", &self.valid_values)
+            .field("valid_values_range", &self.valid_values_range)
+            .finish_non_exhaustive()
+    }
+}
+
 /// A Characteristic. A characteristic is a feature that represents data or an associated behavior
 /// of a service. The characteristic is defined by a universally unique type, and has additional
 /// properties that determine how the value of the characteristic can be accessed
-#[derive(Clone, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct Characteristic<T: Default + Clone + Serialize> {
     pub inner: Arc<Mutex<Inner<T>>>,
 }
@@ -66,6 +124,40 @@ where
         }
     }
 
+    /// Creates a new custom `Characteristic` with a vendor-specific UUID like
+    /// `"e863f10c-079e-48ff-8f27-9c2605a29f52"`. A unit, minimum, maximum or step value can be
+    /// set on the returned `Characteristic` using the regular setters.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::{
+    ///     accessory::{outlet, Information},
+    ///     characteristic::{Characteristic, Format, Perm},
+    ///     service::HapService,
+    /// };
+    ///
+    /// let mut total_consumption = Characteristic::<f32>::new_custom(
+    ///     "e863f10c-079e-48ff-8f27-9c2605a29f52",
+    ///     Format::Float,
+    ///     vec![Perm::PairedRead, Perm::Events],
+    /// )
+    /// .unwrap();
+    /// total_consumption.set_min_value(Some(0.0)).unwrap();
+    /// total_consumption.set_step_value(Some(0.01)).unwrap();
+    ///
+    /// let mut outlet = outlet::new(Information::default()).unwrap();
+    /// outlet.inner.outlet.add_characteristic(Box::new(total_consumption));
+    /// ```
+    pub fn new_custom(uuid: &str, format: Format, perms: Vec<Perm>) -> Result<Characteristic<T>> {
+        Ok(Characteristic::new(Inner {
+            hap_type: HapType::Custom(Uuid::parse_str(uuid)?),
+            format,
+            perms,
+            ..Default::default()
+        }))
+    }
+
     /// Returns the ID of a Characteristic.
     pub fn get_id(&self) -> Result<u64> { Ok(self.inner.lock().expect("couldn't access characteristic").id) }
 
@@ -90,17 +182,47 @@ where
     pub fn get_format(&self) -> Result<Format> { Ok(self.inner.lock().expect("couldn't access characteristic").format) }
 
     /// Returns the `Perm`s of a Characteristic.
-    pub fn get_perms(&self) -> Result<Vec<Perm>> {
-        Ok(self.inner.lock().expect("couldn't access characteristic").perms.clone())
+    pub fn get_perms(&self) -> Result<HashSet<Perm>> {
+        Ok(self
+            .inner
+            .lock()
+            .expect("couldn't access characteristic")
+            .perms
+            .iter()
+            .copied()
+            .collect())
     }
 
-    /// Sets the description of a Characteristic.
+    /// Sets the `Perm`s of a Characteristic, e.g. to require `Perm::AdditionalAuthorization`.
+    pub fn set_perms(&mut self, perms: Vec<Perm>) -> Result<()> {
+        self.inner.lock().expect("couldn't access characteristic").perms = perms;
+        Ok(())
+    }
+
+    /// Returns the description of a Characteristic.
+    pub fn get_description(&self) -> Result<Option<String>> {
+        Ok(self.inner.lock().expect("couldn't access characteristic").description.clone())
+    }
+
+    /// Sets the description of a Characteristic. A description is useful for custom Characteristics,
+    /// as it is shown to the user instead of the UUID.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::characteristic::{Characteristic, Inner};
+    ///
+    /// let mut characteristic = Characteristic::<u8>::new(Inner::default());
+    /// characteristic.set_description(Some("Scene".into())).unwrap();
+    /// assert_eq!(characteristic.get_description().unwrap(), Some("Scene".into()));
+    /// ```
     pub fn set_description(&mut self, description: Option<String>) -> Result<()> {
         self.inner.lock().expect("couldn't access characteristic").description = description;
         Ok(())
     }
 
-    /// Returns the event notifications value of a Characteristic.
+    /// Returns the event notifications value of a Characteristic. This is the value reported in the
+    /// Accessory database; the subscriptions of the controllers are tracked per connection.
     pub fn get_event_notifications(&self) -> Result<Option<bool>> {
         Ok(self
             .inner
@@ -160,13 +282,16 @@ where
 
         {
             let inner = self.inner.lock().expect("couldn't access characteristic");
-            if inner.event_notifications == Some(true) {
+            let changed = inner.notify_unchanged || !is_same_value(&json!(&inner.value), &json!(&val));
+            if changed {
                 if let Some(ref event_emitter) = inner.event_emitter {
+                    let value = inner.to_transport_value(&val)?;
                     event_emitter.lock().expect("couldn't access event_emitter").emit(
                         &Event::CharacteristicValueChanged {
                             aid: inner.accessory_id,
                             iid: inner.id,
-                            value: json!(&val),
+                            hap_type: inner.hap_type,
+                            value,
                         },
                     );
                 }
@@ -183,6 +308,12 @@ where
         Ok(self.inner.lock().expect("couldn't access characteristic").unit)
     }
 
+    /// Sets the `Unit` of a Characteristic.
+    pub fn set_unit(&mut self, unit: Option<Unit>) -> Result<()> {
+        self.inner.lock().expect("couldn't access characteristic").unit = unit;
+        Ok(())
+    }
+
     /// Returns the maximum value of a Characteristic.
     pub fn get_max_value(&self) -> Result<Option<T>> {
         Ok(self
@@ -225,17 +356,128 @@ where
             .clone())
     }
 
-    /// Returns the step value of a Characteristic.
+    /// Sets the step value of a Characteristic. Remotely written values have to be a multiple of
+    /// the step value away from the minimum value, or from zero if there is none.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::characteristic::{brightness, HapCharacteristic};
+    /// use serde_json::json;
+    ///
+    /// let mut brightness = brightness::new();
+    /// brightness.set_min_value(Some(0)).unwrap();
+    /// brightness.set_max_value(Some(100)).unwrap();
+    /// brightness.set_step_value(Some(5)).unwrap();
+    ///
+    /// assert!(HapCharacteristic::set_value(&mut brightness, json!(37)).is_err());
+    /// assert!(HapCharacteristic::set_value(&mut brightness, json!(35)).is_ok());
+    /// ```
     pub fn set_step_value(&mut self, val: Option<T>) -> Result<()> {
         self.inner.lock().expect("couldn't access characteristic").step_value = val;
         Ok(())
     }
 
+    /// Returns the valid values of a Characteristic.
+    pub fn get_valid_values(&self) -> Result<Option<Vec<T>>> {
+        Ok(self
+            .inner
+            .lock()
+            .expect("couldn't access characteristic")
+            .valid_values
+            .clone())
+    }
+
+    /// Sets the valid values of a Characteristic. Remotely written values that aren't one of the
+    /// valid values are rejected.
+    pub fn set_valid_values(&mut self, valid_values: Option<Vec<T>>) -> Result<()> {
+        self.inner.lock().expect("couldn't access characteristic").valid_values = valid_values;
+        Ok(())
+    }
+
+    /// Returns the inclusive valid values range of a Characteristic.
+    pub fn get_valid_values_range(&self) -> Result<Option<[T; 2]>> {
+        Ok(self
+            .inner
+            .lock()
+            .expect("couldn't access characteristic")
+            .valid_values_range
+            .clone())
+    }
+
+    /// Sets the inclusive valid values range of a Characteristic in the format `[start, end]`.
+    pub fn set_valid_values_range(&mut self, valid_values_range: Option<[T; 2]>) -> Result<()> {
+        self.inner.lock().expect("couldn't access characteristic").valid_values_range = valid_values_range;
+        Ok(())
+    }
+
+    /// Returns whether remotely written values outside of the min/max/step constraints of a
+    /// Characteristic are clamped instead of rejected.
+    pub fn get_clamp_values(&self) -> Result<bool> {
+        Ok(self.inner.lock().expect("couldn't access characteristic").clamp_values)
+    }
+
+    /// Sets whether remotely written values outside of the min/max/step constraints of a
+    /// Characteristic are clamped to the nearest valid value instead of rejected. Defaults to
+    /// `false`.
+    pub fn set_clamp_values(&mut self, clamp_values: bool) -> Result<()> {
+        self.inner.lock().expect("couldn't access characteristic").clamp_values = clamp_values;
+        Ok(())
+    }
+
+    /// Returns whether the value of a `bool` Characteristic is read as `0` or `1` instead of
+    /// `false` or `true`.
+    pub fn get_bools_as_numbers(&self) -> Result<bool> {
+        Ok(self.inner.lock().expect("couldn't access characteristic").bools_as_numbers)
+    }
+
+    /// Sets whether the value of a `bool` Characteristic is read as `0` or `1` instead of `false`
+    /// or `true`, for Controllers that expect the numeric form. Writes are accepted in both forms
+    /// either way. Defaults to `false`.
+    pub fn set_bools_as_numbers(&mut self, bools_as_numbers: bool) -> Result<()> {
+        self.inner.lock().expect("couldn't access characteristic").bools_as_numbers = bools_as_numbers;
+        Ok(())
+    }
+
+    /// Returns whether setting a Characteristic to its current value notifies subscribed
+    /// Controllers.
+    pub fn get_notify_unchanged(&self) -> Result<bool> {
+        Ok(self.inner.lock().expect("couldn't access characteristic").notify_unchanged)
+    }
+
+    /// Sets whether setting a Characteristic to its current value notifies subscribed Controllers.
+    /// Defaults to `false`, except for Characteristics like the Programmable Switch Event, where
+    /// every value set is meaningful.
+    pub fn set_notify_unchanged(&mut self, notify_unchanged: bool) -> Result<()> {
+        self.inner.lock().expect("couldn't access characteristic").notify_unchanged = notify_unchanged;
+        Ok(())
+    }
+
     /// Returns the maximum length of a Characteristic.
     pub fn get_max_len(&self) -> Result<Option<u16>> {
         Ok(self.inner.lock().expect("couldn't access characteristic").max_len)
     }
 
+    /// Sets the maximum length of a Characteristic. Only applies to `string` Characteristics. If no
+    /// maximum length is set, the default of 64 characters is used.
+    pub fn set_max_len(&mut self, max_len: Option<u16>) -> Result<()> {
+        self.inner.lock().expect("couldn't access characteristic").max_len = max_len;
+        Ok(())
+    }
+
+    /// Returns the maximum data length of a Characteristic.
+    pub fn get_max_data_len(&self) -> Result<Option<u32>> {
+        Ok(self.inner.lock().expect("couldn't access characteristic").max_data_len)
+    }
+
+    /// Sets the maximum data length of a Characteristic in Bytes. Only applies to `tlv8` and `data`
+    /// Characteristics. If no maximum data length is set on a `data` Characteristic, the default of
+    /// 2097152 Bytes is used.
+    pub fn set_max_data_len(&mut self, max_data_len: Option<u32>) -> Result<()> {
+        self.inner.lock().expect("couldn't access characteristic").max_data_len = max_data_len;
+        Ok(())
+    }
+
     /// Sets a `Readable` on the Characteristic.
     pub fn set_readable(&mut self, readable: impl Readable<T> + 'static + Send) -> Result<()> {
         self.inner.lock().expect("couldn't access characteristic").readable = Some(Box::new(readable));
@@ -248,11 +490,129 @@ where
         Ok(())
     }
 
+    /// Sets an `Authorizable` on the Characteristic.
+    pub fn set_authorizable(&mut self, authorizable: impl Authorizable + 'static + Send) -> Result<()> {
+        self.inner.lock().expect("couldn't access characteristic").authorizable = Some(Box::new(authorizable));
+        Ok(())
+    }
+
+    /// Sets a `Validatable` on the Characteristic.
+    pub fn set_validatable(&mut self, validatable: impl Validatable<T> + 'static + Send) -> Result<()> {
+        self.inner.lock().expect("couldn't access characteristic").validatable =
+            Some(Arc::new(Mutex::new(Box::new(validatable))));
+        Ok(())
+    }
+
+    /// Checks a value a Controller attempts to write against the `Validatable` set on the
+    /// Characteristic. Values are accepted if no `Validatable` is set.
+    fn is_valid(&mut self, val: &T) -> bool {
+        // the `Validatable` is called without holding the lock of the Characteristic, so it can inspect
+        // other Characteristics without risking a deadlock with their own `Validatable`s. It has a
+        // lock of its own, so concurrent writes are validated one after another
+        let (validatable, hap_type) = {
+            let inner = self.inner.lock().expect("couldn't access characteristic");
+            (inner.validatable.clone(), inner.hap_type)
+        };
+        match validatable {
+            Some(validatable) => validatable
+                .lock()
+                .expect("couldn't access validatable")
+                .on_validate(val, hap_type),
+            None => true,
+        }
+    }
+
+    /// Checks the additional authorization data of a remote write. Characteristics without the
+    /// `Perm::AdditionalAuthorization` permission accept every write. If no `Authorizable` is set
+    /// on the Characteristic, writes are accepted as long as they carry authorization data.
+    pub fn is_authorized(&mut self, auth_data: Option<&[u8]>) -> Result<bool> {
+        let mut inner = self.inner.lock().expect("couldn't access characteristic");
+        if !inner.perms.contains(&Perm::AdditionalAuthorization) {
+            return Ok(true);
+        }
+        let hap_type = inner.hap_type;
+        match inner.authorizable {
+            Some(ref mut authorizable) => Ok(authorizable.on_authorize(auth_data, hap_type)),
+            None => Ok(auth_data.is_some()),
+        }
+    }
+
     /// Sets a `hap::event::EventEmitterPtr` on the Characteristic.
     pub fn set_event_emitter(&mut self, event_emitter: Option<EventEmitterPtr>) -> Result<()> {
         self.inner.lock().expect("couldn't access characteristic").event_emitter = event_emitter;
         Ok(())
     }
+
+    /// Checks a remotely written numeric value against the min/max/step and valid values range
+    /// constraints of the Characteristic. Depending on `clamp_values`, invalid values are either
+    /// clamped to the nearest valid value or rejected with `ErrorKind::InvalidValue`. Values that
+    /// aren't one of the valid values of the Characteristic are always rejected.
+    fn check_numeric_value(&self, value: serde_json::Value) -> Result<serde_json::Value> {
+        let number = match value.as_f64() {
+            Some(number) => number,
+            None => return Ok(value),
+        };
+        let inner = self.inner.lock().expect("couldn't access characteristic");
+        let min = inner.min_value.as_ref().and_then(|v| json!(v).as_f64());
+        let max = inner.max_value.as_ref().and_then(|v| json!(v).as_f64());
+        let step = inner.step_value.as_ref().and_then(|v| json!(v).as_f64());
+
+        let mut checked = number;
+        if let Some(min) = min {
+            if checked < min {
+                checked = min;
+            }
+        }
+        if let Some(max) = max {
+            if checked > max {
+                checked = max;
+            }
+        }
+        if let Some(ref range) = inner.valid_values_range {
+            if let (Some(start), Some(end)) = (json!(&range[0]).as_f64(), json!(&range[1]).as_f64()) {
+                if checked < start {
+                    checked = start;
+                }
+                if checked > end {
+                    checked = end;
+                }
+            }
+        }
+        if let Some(step) = step {
+            if step > 0.0 {
+                // tolerate floating point inaccuracies of the controller and of `f32` step values,
+                // which aren't exact once widened to `f64`
+                let tolerance = step * 1e-3;
+                let base = min.unwrap_or(0.0);
+                let mut snapped = base + ((checked - base) / step).round() * step;
+                if let Some(max) = max {
+                    if snapped > max + tolerance {
+                        snapped -= step;
+                    }
+                }
+                if (snapped - checked).abs() > tolerance {
+                    checked = snapped;
+                }
+            }
+        }
+
+        if let Some(ref valid_values) = inner.valid_values {
+            if !valid_values.iter().any(|v| json!(v).as_f64() == Some(number)) {
+                return Err(ErrorKind::InvalidValue.into());
+            }
+        }
+
+        if checked == number {
+            return Ok(value);
+        }
+        if !inner.clamp_values {
+            return Err(ErrorKind::InvalidValue.into());
+        }
+        match inner.format {
+            Format::Float => Ok(json!(checked)),
+            _ => Ok(json!(checked.round() as i64)),
+        }
+    }
 }
 
 impl<T: Default + Clone + Serialize> Serialize for Characteristic<T> {
@@ -271,7 +631,8 @@ impl<T: Default + Clone + Serialize> Serialize for Characteristic<T> {
         }
 
         if inner.perms.contains(&Perm::PairedRead) {
-            state.serialize_field("value", &inner.value)?;
+            let value = inner.to_transport_value(&inner.value).map_err(ser::Error::custom)?;
+            state.serialize_field("value", &value)?;
         }
         if let Some(ref unit) = inner.unit {
             state.serialize_field("unit", unit)?;
@@ -301,7 +662,31 @@ impl<T: Default + Clone + Serialize> Serialize for Characteristic<T> {
     }
 }
 
-/// `HapCharacteristic` is implemented by the inner type of every `Characteristic`.
+/// `HapCharacteristic` is implemented by every `Characteristic` and allows working with
+/// characteristics regardless of their value type, e.g. on the characteristics returned by
+/// `HapService::get_characteristics`. Values are passed as `serde_json::Value`s.
+///
+/// # Examples
+///
+/// ```
+/// use hap::{
+///     characteristic::{Format, HapCharacteristic},
+///     service::{outlet, HapService},
+/// };
+///
+/// fn turn_off(characteristics: Vec<&mut dyn HapCharacteristic>) {
+///     for characteristic in characteristics {
+///         if characteristic.get_format().unwrap() == Format::Bool {
+///             characteristic.set_value(serde_json::Value::Bool(false)).unwrap();
+///         }
+///     }
+/// }
+///
+/// let mut outlet = outlet::new();
+/// outlet.inner.on.set_value(true).unwrap();
+/// turn_off(outlet.get_mut_characteristics());
+/// assert_eq!(outlet.inner.on.get_value().unwrap(), false);
+/// ```
 pub trait HapCharacteristic: erased_serde::Serialize {
     /// Returns the ID of a Characteristic.
     fn get_id(&self) -> Result<u64>;
@@ -314,7 +699,9 @@ pub trait HapCharacteristic: erased_serde::Serialize {
     /// Returns the `Format` of a Characteristic.
     fn get_format(&self) -> Result<Format>;
     /// Returns the `Perm`s of a Characteristic.
-    fn get_perms(&self) -> Result<Vec<Perm>>;
+    fn get_perms(&self) -> Result<HashSet<Perm>>;
+    /// Returns the description of a Characteristic.
+    fn get_description(&self) -> Result<Option<String>>;
     /// Returns the event notifications value of a Characteristic.
     fn get_event_notifications(&self) -> Result<Option<bool>>;
     /// Sets the event notifications value of a Characteristic.
@@ -333,12 +720,38 @@ pub trait HapCharacteristic: erased_serde::Serialize {
     fn get_step_value(&self) -> Result<Option<serde_json::Value>>;
     /// Returns the maximum length of a Characteristic.
     fn get_max_len(&self) -> Result<Option<u16>>;
+    /// Returns the maximum data length of a Characteristic.
+    fn get_max_data_len(&self) -> Result<Option<u32>>;
+    /// Returns the valid values of a Characteristic.
+    fn get_valid_values(&self) -> Result<Option<Vec<serde_json::Value>>>;
+    /// Returns the inclusive valid values range of a Characteristic.
+    fn get_valid_values_range(&self) -> Result<Option<[serde_json::Value; 2]>>;
+    /// Checks the additional authorization data of a remote write.
+    fn is_authorized(&mut self, auth_data: Option<&[u8]>) -> Result<bool>;
     /// Sets a `hap::event::EventEmitterPtr` on the Characteristic.
     fn set_event_emitter(&mut self, event_emitter: Option<EventEmitterPtr>) -> Result<()>;
 }
 
 serialize_trait_object!(HapCharacteristic);
 
+impl<'a> fmt::Debug for dyn HapCharacteristic + 'a {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.get_id(), self.get_type(), self.get_format()) {
+            (Ok(id), Ok(hap_type), Ok(format)) => f
+                .debug_struct("HapCharacteristic")
+                .field("id", &id)
+                .field("hap_type", &hap_type)
+                .field("format", &format)
+                .finish(),
+            _ => f.debug_struct("HapCharacteristic").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for dyn HapCharacteristic + Send + 'a {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { (self as &dyn HapCharacteristic).fmt(f) }
+}
+
 impl<T: Default + Clone + Serialize> HapCharacteristic for Characteristic<T>
 where
     for<'de> T: Deserialize<'de>,
@@ -353,7 +766,9 @@ where
 
     fn get_format(&self) -> Result<Format> { self.get_format() }
 
-    fn get_perms(&self) -> Result<Vec<Perm>> { self.get_perms() }
+    fn get_perms(&self) -> Result<HashSet<Perm>> { self.get_perms() }
+
+    fn get_description(&self) -> Result<Option<String>> { self.get_description() }
 
     fn get_event_notifications(&self) -> Result<Option<bool>> { self.get_event_notifications() }
 
@@ -361,24 +776,50 @@ where
         self.set_event_notifications(event_notifications)
     }
 
-    fn get_value(&mut self) -> Result<serde_json::Value> { Ok(json!(self.get_value()?)) }
+    fn get_value(&mut self) -> Result<serde_json::Value> {
+        let value = self.get_value()?;
+        self.inner
+            .lock()
+            .expect("couldn't access characteristic")
+            .to_transport_value(&value)
+    }
 
     fn set_value(&mut self, value: serde_json::Value) -> Result<()> {
         let v;
-        // the controller is setting boolean values
-        // either as a boolean or as an integer
-        if self.inner.lock().expect("couldn't access characteristic").format == Format::Bool && value.is_number() {
-            let num_v: u8 = serde_json::from_value(value)?;
-            if num_v == 0 {
-                v = serde_json::from_value(json!(false))?;
-            } else if num_v == 1 {
-                v = serde_json::from_value(json!(true))?;
-            } else {
-                return Err(Error::from_str("invalid value for bool characteristic"));
+        let (format, max_data_len) = {
+            let inner = self.inner.lock().expect("couldn't access characteristic");
+            (inner.format, inner.max_data_len)
+        };
+        let value = normalize_value(format, value)?;
+        if format == Format::Tlv8 || format == Format::Data {
+            // tlv8 and data values are transported as base64 strings
+            let bytes = from_base64_value(value).map_err(|_| ErrorKind::InvalidValue)?;
+            let max_data_len = match format {
+                Format::Data => Some(max_data_len.unwrap_or(DEFAULT_MAX_DATA_LEN)),
+                _ => max_data_len,
+            };
+            if let Some(max_data_len) = max_data_len {
+                if bytes.len() > max_data_len as usize {
+                    return Err(ErrorKind::InvalidValue.into());
+                }
+            }
+            v = serde_json::from_value(json!(bytes))?;
+        } else if format == Format::String {
+            let max_len = self.inner.lock().expect("couldn't access characteristic").max_len;
+            let len = value.as_str().ok_or(ErrorKind::InvalidValue)?.chars().count();
+            if len > max_len.unwrap_or(DEFAULT_MAX_LEN) as usize {
+                return Err(ErrorKind::InvalidValue.into());
             }
+            v = serde_json::from_value(value)?;
+        } else if format.is_numeric() {
+            // values out of range of the type, e.g. negative values for unsigned formats
+            v = serde_json::from_value(self.check_numeric_value(value)?).map_err(|_| ErrorKind::InvalidValue)?;
         } else {
             v = serde_json::from_value(value)?;
         }
+        if !self.is_valid(&v) {
+            return Err(ErrorKind::InvalidValue.into());
+        }
         self.set_value(v)
     }
 
@@ -407,11 +848,71 @@ where
 
     fn get_max_len(&self) -> Result<Option<u16>> { self.get_max_len() }
 
+    fn get_max_data_len(&self) -> Result<Option<u32>> { self.get_max_data_len() }
+
+    fn get_valid_values(&self) -> Result<Option<Vec<serde_json::Value>>> {
+        Ok(self.get_valid_values()?.map(|v| v.iter().map(|v| json!(v)).collect()))
+    }
+
+    fn get_valid_values_range(&self) -> Result<Option<[serde_json::Value; 2]>> {
+        Ok(self.get_valid_values_range()?.map(|[start, end]| [json!(start), json!(end)]))
+    }
+
+    fn is_authorized(&mut self, auth_data: Option<&[u8]>) -> Result<bool> { self.is_authorized(auth_data) }
+
     fn set_event_emitter(&mut self, event_emitter: Option<EventEmitterPtr>) -> Result<()> {
         self.set_event_emitter(event_emitter)
     }
 }
 
+/// Normalizes a remotely written value to the canonical JSON type of the given `Format`.
+/// Controllers write e.g. `1` for `bool`, `50` for `float` or `50.0` for `uint8` Characteristics.
+/// Values of incompatible types are rejected with `ErrorKind::InvalidValue`.
+fn normalize_value(format: Format, value: serde_json::Value) -> Result<serde_json::Value> {
+    let number = value.as_f64();
+    match format {
+        Format::Bool => match (&value, number) {
+            (serde_json::Value::Bool(_), _) => Ok(value),
+            (_, Some(n)) if n == 0.0 => Ok(json!(false)),
+            (_, Some(n)) if n == 1.0 => Ok(json!(true)),
+            _ => Err(ErrorKind::InvalidValue.into()),
+        },
+        Format::Float => match number {
+            Some(n) => Ok(json!(n)),
+            None => Err(ErrorKind::InvalidValue.into()),
+        },
+        Format::UInt8 | Format::UInt16 | Format::UInt32 | Format::UInt64 | Format::Int32 => match number {
+            Some(_) if value.is_i64() || value.is_u64() => Ok(value),
+            Some(n) if n.fract() == 0.0 => Ok(json!(n as i64)),
+            _ => Err(ErrorKind::InvalidValue.into()),
+        },
+        Format::String | Format::Tlv8 | Format::Data => match value {
+            serde_json::Value::String(_) => Ok(value),
+            _ => Err(ErrorKind::InvalidValue.into()),
+        },
+    }
+}
+
+/// Compares two Characteristic values, tolerating floating point inaccuracies.
+fn is_same_value(a: &serde_json::Value, b: &serde_json::Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => (a - b).abs() <= f64::from(std::f32::EPSILON) * a.abs().max(b.abs()).max(1.0),
+        _ => a == b,
+    }
+}
+
+/// Converts a JSON array of Bytes to its base64 string representation.
+fn to_base64_value(value: serde_json::Value) -> Result<serde_json::Value> {
+    let bytes: Vec<u8> = serde_json::from_value(value)?;
+    Ok(json!(base64::encode(&bytes)))
+}
+
+/// Decodes a base64 string to its Bytes.
+fn from_base64_value(value: serde_json::Value) -> Result<Vec<u8>> {
+    let encoded = value.as_str().ok_or_else(|| Error::from_str("expected base64 string"))?;
+    base64::decode(encoded).map_err(|_| Error::from_str("invalid base64 string"))
+}
+
 /// `Readable` can be implemented to react to the remote read of a `Characteristic`.
 pub trait Readable<T: Default + Serialize> {
     /// This function is called every time a Controller attempts to read the value of a
@@ -429,8 +930,28 @@ pub trait Updatable<T: Default + Serialize> {
     fn on_update(&mut self, old_val: &T, new_val: &T, hap_type: HapType);
 }
 
+/// `Validatable` can be implemented to validate the values Controllers attempt to write to a
+/// `Characteristic`, e.g. against the values of other `Characteristic`s.
+pub trait Validatable<T: Default + Serialize> {
+    /// This function is called every time a Controller attempts to update the value of a
+    /// `Characteristic`, before the `Updatable` is called. `new_val` is a reference to the value the
+    /// Controller attempts to change the `Characteristic`'s to. Returning `false` rejects the write
+    /// with the status `InvalidValueInRequest`.
+    fn on_validate(&mut self, new_val: &T, hap_type: HapType) -> bool;
+}
+
+/// `Authorizable` can be implemented to validate the additional authorization data of remote
+/// writes to a `Characteristic` with the `Perm::AdditionalAuthorization` permission.
+pub trait Authorizable {
+    /// This function is called every time a Controller attempts to update the value of a
+    /// `Characteristic` requiring additional authorization. `auth_data` is the decoded
+    /// authorization data sent along with the write, if any. Returning `false` rejects the write
+    /// with the status `InsufficientAuthorization`.
+    fn on_authorize(&mut self, auth_data: Option<&[u8]>, hap_type: HapType) -> bool;
+}
+
 /// Permission of a `Characteristic`.
-#[derive(Debug, Copy, Clone, Serialize, PartialEq)]
+#[derive(Debug, Copy, Clone, Serialize, PartialEq, Eq, Hash)]
 pub enum Perm {
     #[serde(rename = "pr")]
     PairedRead,
@@ -444,10 +965,56 @@ pub enum Perm {
     TimedWrite,
     #[serde(rename = "hd")]
     Hidden,
+    /// The value of the Characteristic is returned in the response to a write. The value is read
+    /// after the write, so a `Readable` can provide the response to the written request.
+    #[serde(rename = "wr")]
+    WriteResponse,
+}
+
+impl Characteristic<f32> {
+    /// Returns the value of a `Unit::Celsius` Characteristic converted to degrees Fahrenheit.
+    pub fn get_value_fahrenheit(&mut self) -> Result<f32> {
+        self.check_celsius()?;
+        Ok(celsius_to_fahrenheit(self.get_value()?))
+    }
+
+    /// Sets the value of a `Unit::Celsius` Characteristic from degrees Fahrenheit. The value is
+    /// still stored and transported in degrees Celsius.
+    pub fn set_value_fahrenheit(&mut self, val: f32) -> Result<()> {
+        self.check_celsius()?;
+        self.set_value(fahrenheit_to_celsius(val))
+    }
+
+    fn check_celsius(&self) -> Result<()> {
+        match self.get_unit()? {
+            Some(Unit::Celsius) => Ok(()),
+            _ => Err(Error::from_str("characteristic unit is not celsius")),
+        }
+    }
+}
+
+/// A Characteristic of the `data` format. Its value is transported as a base64 string.
+pub type DataCharacteristic = Characteristic<Vec<u8>>;
+
+impl DataCharacteristic {
+    /// Creates a new custom `data` Characteristic with a vendor-specific UUID and a maximum data
+    /// length of 2097152 Bytes.
+    pub fn new_data(uuid: &str, perms: Vec<Perm>) -> Result<DataCharacteristic> {
+        let mut characteristic = DataCharacteristic::new_custom(uuid, Format::Data, perms)?;
+        characteristic.set_max_data_len(Some(DEFAULT_MAX_DATA_LEN))?;
+        Ok(characteristic)
+    }
 }
 
-/// Unit of a `Characteristic`.
-#[derive(Debug, Copy, Clone, Serialize)]
+/// Converts a temperature from degrees Celsius to degrees Fahrenheit.
+pub fn celsius_to_fahrenheit(celsius: f32) -> f32 { celsius * 1.8 + 32.0 }
+
+/// Converts a temperature from degrees Fahrenheit to degrees Celsius.
+pub fn fahrenheit_to_celsius(fahrenheit: f32) -> f32 { (fahrenheit - 32.0) / 1.8 }
+
+/// Unit of a `Characteristic`. HAP always transports values in these canonical units, e.g.
+/// temperatures are in degrees Celsius regardless of the Temperature Display Units.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
 pub enum Unit {
     #[serde(rename = "percentage")]
     Percentage,
@@ -486,6 +1053,99 @@ pub enum Format {
     Data,
 }
 
+impl Format {
+    /// Returns whether the `Format` is one of the numeric formats.
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Format::Float | Format::UInt8 | Format::UInt16 | Format::UInt32 | Format::UInt64 | Format::Int32
+        )
+    }
+}
+
 impl Default for Format {
     fn default() -> Format { Format::String }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::mpsc::{self, Receiver, Sender},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    /// Rejects values above 50, after signaling that it was called and waiting to be released.
+    struct BlockingValidator {
+        called: Sender<()>,
+        release: Receiver<()>,
+    }
+
+    impl Validatable<u8> for BlockingValidator {
+        fn on_validate(&mut self, new_val: &u8, _: HapType) -> bool {
+            self.called.send(()).unwrap();
+            self.release.recv_timeout(Duration::from_secs(10)).unwrap();
+            *new_val <= 50
+        }
+    }
+
+    #[test]
+    fn test_concurrent_writes_are_validated() {
+        let (called_sender, called) = mpsc::channel();
+        let (release, release_receiver) = mpsc::channel();
+        let mut target_position = target_position::new();
+        target_position
+            .set_validatable(BlockingValidator {
+                called: called_sender,
+                release: release_receiver,
+            })
+            .unwrap();
+
+        let mut first = target_position.clone();
+        let first = thread::spawn(move || HapCharacteristic::set_value(&mut first, json!(10)).is_ok());
+        called.recv_timeout(Duration::from_secs(10)).unwrap();
+
+        // a second write while the first one is being validated
+        let mut second = target_position.clone();
+        let second = thread::spawn(move || HapCharacteristic::set_value(&mut second, json!(100)).is_ok());
+        thread::sleep(Duration::from_millis(100));
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+
+        assert!(first.join().unwrap());
+        assert!(!second.join().unwrap());
+        assert_eq!(target_position.get_value().unwrap(), 10);
+    }
+
+    #[test]
+    fn test_step_value() {
+        let mut brightness = brightness::new();
+        brightness.set_min_value(Some(0)).unwrap();
+        brightness.set_max_value(Some(100)).unwrap();
+        brightness.set_step_value(Some(5)).unwrap();
+
+        let err = HapCharacteristic::set_value(&mut brightness, json!(37)).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::InvalidValue));
+        HapCharacteristic::set_value(&mut brightness, json!(35)).unwrap();
+        assert_eq!(brightness.get_value().unwrap(), 35);
+
+        brightness.set_clamp_values(true).unwrap();
+        HapCharacteristic::set_value(&mut brightness, json!(37)).unwrap();
+        assert_eq!(brightness.get_value().unwrap(), 35);
+    }
+
+    #[test]
+    fn test_float_step_value_tolerance() {
+        // min 10, max 38 and step 0.1, which isn't exact as `f32`
+        let mut target_temperature = target_temperature::new();
+
+        for &value in &[10.0, 20.1, 20.10005, 37.9, 38.0] {
+            assert!(HapCharacteristic::set_value(&mut target_temperature, json!(value)).is_ok());
+        }
+        for &value in &[20.1002, 20.15] {
+            assert!(HapCharacteristic::set_value(&mut target_temperature, json!(value)).is_err());
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::{Error, Result};
+
+/// A TLV8 value as used by Characteristics of the `tlv8` format. Items keep their order and may
+/// repeat, so nested structures like the Selected RTP Stream Configuration can be expressed.
+///
+/// # Examples
+///
+/// ```
+/// use hap::characteristic::tlv8::Tlv8;
+///
+/// let session_control = Tlv8::new().with(0x01, vec![0xAA; 16]).with_u8(0x02, 1);
+/// let bytes = Tlv8::new().with_tlv(0x01, session_control).encode();
+///
+/// let decoded = Tlv8::decode(&bytes).unwrap();
+/// let session_control = decoded.get_tlv(0x01).unwrap().unwrap();
+/// assert_eq!(session_control.get_u8(0x02), Some(1));
+/// ```
+///
+/// The TLV types of the pairing protocol are available as `protocol::TlvType`:
+///
+/// ```
+/// use hap::{
+///     characteristic::tlv8::Tlv8,
+///     protocol::{PairingMethod, TlvType},
+/// };
+///
+/// let public_key = vec![0x42; 384];
+/// let bytes = Tlv8::from(vec![
+///     (TlvType::State as u8, vec![3]),
+///     (TlvType::Method as u8, vec![PairingMethod::PairSetup as u8]),
+///     (TlvType::PublicKey as u8, public_key.clone()),
+/// ])
+/// .encode();
+///
+/// // the 384 Byte public key is fragmented across two items and merged again when decoding
+/// let decoded = Tlv8::decode(&bytes).unwrap();
+/// assert_eq!(decoded.get(TlvType::PublicKey as u8), Some(&public_key[..]));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tlv8 {
+    items: Vec<(u8, Vec<u8>)>,
+}
+
+impl Tlv8 {
+    /// Creates a new, empty `Tlv8`.
+    pub fn new() -> Tlv8 { Tlv8 { items: Vec::new() } }
+
+    /// Decodes a `&[u8]` of concatenated TLVs to a `Tlv8`. Values longer than 255 Bytes that were
+    /// fragmented across consecutive items of the same type are merged.
+    pub fn decode(bytes: &[u8]) -> Result<Tlv8> {
+        let mut items: Vec<(u8, Vec<u8>)> = Vec::new();
+        let mut p = 0;
+        let mut last_len = 0;
+        while p < bytes.len() {
+            if p + 2 > bytes.len() {
+                return Err(Error::from_str("invalid TLV8 data"));
+            }
+            let t = bytes[p];
+            let l = bytes[p + 1] as usize;
+            if p + 2 + l > bytes.len() {
+                return Err(Error::from_str("invalid TLV8 data"));
+            }
+            let v = &bytes[p + 2..p + 2 + l];
+            match items.last_mut() {
+                Some((last_t, last_v)) if *last_t == t && last_len == 255 => last_v.extend_from_slice(v),
+                _ => items.push((t, v.to_vec())),
+            }
+            last_len = l;
+            p += 2 + l;
+        }
+        Ok(Tlv8 { items })
+    }
+
+    /// Encodes the `Tlv8` to a `Vec<u8>` of concatenated TLVs. Values longer than 255 Bytes are
+    /// fragmented across consecutive items of the same type.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut vec = Vec::new();
+        for (t, v) in &self.items {
+            if v.is_empty() {
+                vec.push(*t);
+                vec.push(0);
+            }
+            for chunk in v.chunks(255) {
+                vec.push(*t);
+                vec.push(chunk.len() as u8);
+                vec.extend_from_slice(chunk);
+            }
+        }
+        vec
+    }
+
+    /// Adds an item with the given type and value.
+    pub fn with(mut self, t: u8, value: Vec<u8>) -> Tlv8 {
+        self.items.push((t, value));
+        self
+    }
+
+    /// Adds an item with the given type and a `u8` value.
+    pub fn with_u8(self, t: u8, value: u8) -> Tlv8 { self.with(t, vec![value]) }
+
+    /// Adds an item with the given type and a little-endian `u16` value.
+    pub fn with_u16(self, t: u8, value: u16) -> Tlv8 {
+        let mut buf = vec![0; 2];
+        LittleEndian::write_u16(&mut buf, value);
+        self.with(t, buf)
+    }
+
+    /// Adds an item with the given type and a little-endian `u32` value.
+    pub fn with_u32(self, t: u8, value: u32) -> Tlv8 {
+        let mut buf = vec![0; 4];
+        LittleEndian::write_u32(&mut buf, value);
+        self.with(t, buf)
+    }
+
+    /// Adds an item with the given type and a nested `Tlv8` as its value.
+    pub fn with_tlv(self, t: u8, value: Tlv8) -> Tlv8 { self.with(t, value.encode()) }
+
+    /// Returns all items in the format `(Type, Value)`.
+    pub fn items(&self) -> &[(u8, Vec<u8>)] { &self.items }
+
+    /// Consumes the `Tlv8` and returns all items in the format `(Type, Value)`.
+    pub fn into_items(self) -> Vec<(u8, Vec<u8>)> { self.items }
+
+    /// Returns the value of the first item of the given type.
+    pub fn get(&self, t: u8) -> Option<&[u8]> {
+        self.items.iter().find(|(i_t, _)| *i_t == t).map(|(_, v)| v.as_slice())
+    }
+
+    /// Returns the values of all items of the given type.
+    pub fn get_all(&self, t: u8) -> Vec<&[u8]> {
+        self.items
+            .iter()
+            .filter(|(i_t, _)| *i_t == t)
+            .map(|(_, v)| v.as_slice())
+            .collect()
+    }
+
+    /// Returns the value of the first item of the given type as a `u8`.
+    pub fn get_u8(&self, t: u8) -> Option<u8> {
+        match self.get(t) {
+            Some(v) if v.len() == 1 => Some(v[0]),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the first item of the given type as a little-endian `u16`.
+    pub fn get_u16(&self, t: u8) -> Option<u16> {
+        match self.get(t) {
+            Some(v) if v.len() == 2 => Some(LittleEndian::read_u16(v)),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the first item of the given type as a little-endian `u32`.
+    pub fn get_u32(&self, t: u8) -> Option<u32> {
+        match self.get(t) {
+            Some(v) if v.len() == 4 => Some(LittleEndian::read_u32(v)),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the first item of the given type decoded as a nested `Tlv8`.
+    pub fn get_tlv(&self, t: u8) -> Option<Result<Tlv8>> { self.get(t).map(Tlv8::decode) }
+}
+
+impl From<Vec<(u8, Vec<u8>)>> for Tlv8 {
+    fn from(items: Vec<(u8, Vec<u8>)>) -> Tlv8 { Tlv8 { items } }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::characteristic::{selected_rtp_stream_configuration, HapCharacteristic};
+
+    /// A Selected RTP Stream Configuration starting an H.264 video and an AAC-ELD audio stream.
+    fn stream_configuration() -> Tlv8 {
+        let session_control = Tlv8::new().with(0x01, vec![0x5A; 16]).with_u8(0x02, 1);
+        let video_parameters = Tlv8::new()
+            .with_u8(0x01, 0)
+            .with_tlv(0x02, Tlv8::new().with_u8(0x01, 1).with_u8(0x02, 2).with_u8(0x03, 0))
+            .with_tlv(0x03, Tlv8::new().with_u16(0x01, 1920).with_u16(0x02, 1080).with_u8(0x03, 30))
+            .with_tlv(
+                0x04,
+                Tlv8::new()
+                    .with_u8(0x01, 99)
+                    .with_u32(0x02, 0x1234_5678)
+                    .with_u16(0x03, 299)
+                    .with(0x04, 0.5f32.to_le_bytes().to_vec())
+                    .with_u16(0x05, 1378),
+            );
+        let audio_parameters = Tlv8::new()
+            .with_u8(0x01, 3)
+            .with_tlv(0x02, Tlv8::new().with_u8(0x01, 1).with_u8(0x02, 0).with_u8(0x03, 1))
+            .with_tlv(0x03, Tlv8::new().with_u8(0x01, 110).with_u32(0x02, 0x8765_4321).with_u16(0x03, 24))
+            .with_u8(0x04, 0);
+
+        Tlv8::new()
+            .with_tlv(0x01, session_control)
+            .with_tlv(0x02, video_parameters)
+            .with_tlv(0x03, audio_parameters)
+    }
+
+    #[test]
+    fn test_selected_rtp_stream_configuration_round_trip() {
+        let bytes = stream_configuration().encode();
+        let mut characteristic = selected_rtp_stream_configuration::new();
+
+        HapCharacteristic::set_value(&mut characteristic, json!(base64::encode(&bytes))).unwrap();
+        assert_eq!(characteristic.get_value().unwrap(), bytes);
+
+        let value = HapCharacteristic::get_value(&mut characteristic).unwrap();
+        let read_bytes = base64::decode(value.as_str().unwrap()).unwrap();
+        assert_eq!(read_bytes, bytes);
+
+        let configuration = Tlv8::decode(&read_bytes).unwrap();
+        assert_eq!(configuration, Tlv8::decode(&bytes).unwrap());
+
+        let session_control = configuration.get_tlv(0x01).unwrap().unwrap();
+        assert_eq!(session_control.get(0x01), Some(&[0x5A; 16][..]));
+        assert_eq!(session_control.get_u8(0x02), Some(1));
+
+        let video_parameters = configuration.get_tlv(0x02).unwrap().unwrap();
+        let video_attributes = video_parameters.get_tlv(0x03).unwrap().unwrap();
+        assert_eq!(video_attributes.get_u16(0x01), Some(1920));
+        assert_eq!(video_attributes.get_u16(0x02), Some(1080));
+        assert_eq!(video_attributes.get_u8(0x03), Some(30));
+        let video_rtp_parameters = video_parameters.get_tlv(0x04).unwrap().unwrap();
+        assert_eq!(video_rtp_parameters.get_u32(0x02), Some(0x1234_5678));
+        assert_eq!(video_rtp_parameters.get_u16(0x05), Some(1378));
+
+        let audio_parameters = configuration.get_tlv(0x03).unwrap().unwrap();
+        assert_eq!(audio_parameters.get_u8(0x01), Some(3));
+        let audio_rtp_parameters = audio_parameters.get_tlv(0x03).unwrap().unwrap();
+        assert_eq!(audio_rtp_parameters.get_u32(0x02), Some(0x8765_4321));
+        assert_eq!(audio_parameters.get_u8(0x04), Some(0));
+    }
+
+    #[test]
+    fn test_round_trip_of_fragmented_value() {
+        let value = (0..600).map(|i| i as u8).collect::<Vec<_>>();
+        let bytes = Tlv8::new().with(0x01, value.clone()).with_u8(0x02, 7).encode();
+        assert_eq!(bytes.len(), 600 + 3 * 2 + 3);
+
+        let decoded = Tlv8::decode(&bytes).unwrap();
+        assert_eq!(decoded.get(0x01), Some(&value[..]));
+        assert_eq!(decoded.get_u8(0x02), Some(7));
+        assert_eq!(decoded.encode(), bytes);
+    }
+}
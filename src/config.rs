@@ -1,20 +1,27 @@
 use std::{
     collections::hash_map::DefaultHasher,
     env::current_dir,
+    fmt,
     hash::{Hash, Hasher},
-    net::IpAddr,
+    net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6},
     str,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use eui48::MacAddress;
 use pnet::datalink;
 use rand::{self, Rng};
+use sha2::{Digest, Sha512};
 
 use crate::{
     accessory::Category,
     db::Storage,
-    transport::bonjour::{FeatureFlag, StatusFlag},
+    transport::{
+        bonjour::{FeatureFlag, StatusFlag},
+        mdns::MdnsInterface,
+    },
+    Error,
     Result,
 };
 
@@ -37,14 +44,36 @@ pub type ConfigPtr = Arc<Mutex<Config>>;
 ///     ..Default::default()
 /// };
 /// ```
+#[derive(Clone)]
 pub struct Config {
     /// Storage path for the persisted data. If no path is specified, the current working directory
     /// is used.
     pub storage_path: String,
-    /// IP address to serve on.
+    /// IP address to serve on. Defaults to the first non-loopback IPv4 address of the host. Set it to
+    /// `0.0.0.0` or `::` to serve on all interfaces, e.g. on multi-homed hosts. IPv6 addresses are
+    /// served on exclusively, i.e. `::` doesn't cover IPv4.
     pub ip: IpAddr,
+    /// Name of the network interface to serve on, e.g. `"eth0"`. If set, all IPv4 and IPv6
+    /// addresses of the interface are served on instead of `ip`, including IPv6 link-local ones.
+    ///
+    /// The addresses announced via mDNS aren't affected. The A and AAAA records always contain the
+    /// addresses of all non-loopback interfaces, so the server should be reachable on each of them.
+    pub interface: Option<String>,
+    /// Network interface to announce the accessory on via mDNS, given by its name, e.g. `"eth0"`,
+    /// or by one of its IP addresses. If set, the built-in mDNS responder only joins the mDNS
+    /// multicast group on that interface and only announces addresses of it the server is
+    /// reachable on. Defaults to `None`, which announces on all interfaces.
+    ///
+    /// The interface needs an IPv4 address, and the server has to be reachable on it, i.e.
+    /// `interface` has to be the same interface if set, and `ip` has to be one of its addresses or
+    /// a wildcard address otherwise. Other combinations are rejected on start.
+    pub mdns_interface: Option<String>,
     /// Port to serve on. Defaults to `32000`.
     pub port: u16,
+    /// Whether to additionally serve on the IPv6 wildcard address on the same port. The IPv6
+    /// addresses are announced in AAAA records alongside the A records, also if the announcement is
+    /// restricted to `mdns_interface`. Defaults to `false`.
+    pub bind_ipv6: bool,
     /// 8 digit pin used for pairing. Defaults to `"11122333"`.
     ///
     /// The following pins are considered too easy and are therefore not allowed:
@@ -66,6 +95,11 @@ pub struct Config {
     /// Device ID of the accessory. Generated randomly if not specified. This value is also used as
     /// the accessory's Pairing Identifier.
     pub device_id: MacAddress, // id
+    /// Setup ID of the accessory, 4 characters from `0`-`9` and `A`-`Z`, e.g. `"7OSX"`. Generated
+    /// randomly if not specified and persisted like the device ID. It is part of the setup payload
+    /// encoded in the QR code or NFC tag used for pairing, and controllers identify the accessory to
+    /// pair with by the setup hash derived from it and the device ID, see `setup_hash`.
+    pub setup_id: String,
     /// Current configuration number. Is updated when an accessory, service, or characteristic is
     /// added or removed on the accessory server. Accessories must increment the config number after
     /// a firmware update.
@@ -85,33 +119,128 @@ pub struct Config {
     pub feature_flag: FeatureFlag, // ff
     /// Optional maximum number of paired controllers.
     pub max_peers: Option<usize>,
+    /// Minimum interval between two event notifications of the same Characteristic on a
+    /// connection. Value changes within the interval are coalesced to the most recent value.
+    /// Programmable Switch Events are always sent right away. Defaults to 1 second, a zero
+    /// interval disables coalescing.
+    pub event_interval: Duration,
+    /// Maximum number of simultaneous controller connections. Further connections are reset right
+    /// away. HAP requires Accessories to support at least 8 connections. Defaults to `8`.
+    pub max_connections: usize,
+    /// Maximum number of requests per second accepted from a single IP address, with bursts of up
+    /// to one second worth of requests. Further requests are answered with `429 Too Many
+    /// Requests`. Defaults to `100`, `0` disables the limit.
+    pub max_requests_per_second_per_ip: u32,
+    /// Time a verified session can be resumed for via Pair Resume, skipping the full Pair Verify on
+    /// reconnects. Defaults to 1 hour, a zero TTL disables Pair Resume.
+    pub session_cache_ttl: Duration,
+    /// Maximum number of resumable sessions. When it's reached, the oldest session is dropped.
+    /// Defaults to `16`, `0` disables Pair Resume.
+    pub session_cache_capacity: usize,
+    /// Time after which a connection without any traffic in either direction, including event
+    /// notifications, is closed. Defaults to `0`, which keeps idle connections open.
+    pub connection_idle_timeout: Duration,
+    /// Maximum size of a request body in Bytes. Requests with larger bodies are answered with
+    /// `413 Payload Too Large` without reading the rest of the body. Defaults to 1 MiB.
+    pub max_request_body_size: usize,
+    /// Instance name announced via mDNS. Set by the transport on start, which appends a numeric
+    /// suffix to `name`, e.g. `Acme Outlet (2)`, if another device on the network already
+    /// announces it. The chosen name is persisted, so it stays the same across restarts. Use
+    /// `effective_name` to get the announced name.
+    pub mdns_name: Option<String>,
     pub version: u64,
     pub config_hash: Option<u64>,
 }
 
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Config")
+            .field("storage_path", &self.storage_path)
+            .field("ip", &self.ip)
+            .field("interface", &self.interface)
+            .field("mdns_interface", &self.mdns_interface)
+            .field("port", &self.port)
+            .field("bind_ipv6", &self.bind_ipv6)
+            // the setup code lets anyone in reach pair with the accessory
+            .field("pin", &"<redacted>")
+            .field("name", &self.name)
+            .field("device_id", &self.device_id)
+            .field("setup_id", &self.setup_id)
+            .field("configuration_number", &self.configuration_number)
+            .field("state_number", &self.state_number)
+            .field("category", &self.category)
+            .field("protocol_version", &self.protocol_version)
+            .field("status_flag", &self.status_flag)
+            .field("feature_flag", &self.feature_flag)
+            .field("max_peers", &self.max_peers)
+            .field("event_interval", &self.event_interval)
+            .field("max_connections", &self.max_connections)
+            .field("max_requests_per_second_per_ip", &self.max_requests_per_second_per_ip)
+            .field("session_cache_ttl", &self.session_cache_ttl)
+            .field("session_cache_capacity", &self.session_cache_capacity)
+            .field("connection_idle_timeout", &self.connection_idle_timeout)
+            .field("max_request_body_size", &self.max_request_body_size)
+            .field("mdns_name", &self.mdns_name)
+            .field("version", &self.version)
+            .field("config_hash", &self.config_hash)
+            .finish()
+    }
+}
+
 impl Config {
     pub(crate) fn load_from(&mut self, storage: &dyn Storage) -> Result<()> {
         if let Some(device_id) = storage.get_bytes("device_id").ok() {
             self.device_id = MacAddress::parse_str(str::from_utf8(&device_id)?)?;
         }
+        if let Ok(setup_id) = storage.get_bytes("setup_id") {
+            self.setup_id = str::from_utf8(&setup_id)?.into();
+        }
+        if !is_setup_id(&self.setup_id) {
+            return Err(Error::from_str("the setup ID must be 4 characters from 0-9 and A-Z"));
+        }
         if let Some(version) = storage.get_u64("version").ok() {
             self.version = version;
         }
         if let Some(config_hash) = storage.get_u64("config_hash").ok() {
             self.config_hash = Some(config_hash);
         }
+        // a configured number above the persisted one, e.g. after a firmware update, takes precedence
+        if let Ok(configuration_number) = storage.get_u64("configuration_number") {
+            self.configuration_number = self.configuration_number.max(configuration_number);
+        }
+        if let Ok(mdns_name) = storage.get_bytes("mdns_name") {
+            self.mdns_name = String::from_utf8(mdns_name).ok();
+        }
         Ok(())
     }
 
     pub(crate) fn save_to(&self, storage: &dyn Storage) -> Result<()> {
         storage.set_bytes("device_id", self.device_id.to_hex_string().as_bytes().to_vec())?;
+        storage.set_bytes("setup_id", self.setup_id.as_bytes().to_vec())?;
         storage.set_u64("version", self.version)?;
         if let Some(config_hash) = self.config_hash {
             storage.set_u64("config_hash", config_hash)?;
         }
+        storage.set_u64("configuration_number", self.configuration_number)?;
+        if let Some(ref mdns_name) = self.mdns_name {
+            storage.set_bytes("mdns_name", mdns_name.as_bytes().to_vec())?;
+        }
         Ok(())
     }
 
+    /// Returns the instance name announced via mDNS, which is `name` unless it had to be changed
+    /// due to a name conflict on the network.
+    pub fn effective_name(&self) -> &str { self.mdns_name.as_ref().unwrap_or(&self.name) }
+
+    /// Increments the configuration number, wrapping around to `1` after `65535` as the `c#` TXT
+    /// record only holds values from `1` to `65535`.
+    pub(crate) fn increment_configuration_number(&mut self) {
+        self.configuration_number = match self.configuration_number {
+            n if n >= 65535 => 1,
+            n => n + 1,
+        };
+    }
+
     fn calculate_hash(&self) -> u64 {
         let mut s = DefaultHasher::new();
         self.hash(&mut s);
@@ -130,16 +259,40 @@ impl Config {
         self.set_hash(hash);
     }
 
-    pub(crate) fn txt_records(&self) -> [String; 8] {
+    /// Returns the setup hash announced as the `sh` TXT record, the Base64 encoded first 4 Bytes of
+    /// the SHA-512 hash of the setup ID followed by the device ID.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use eui48::MacAddress;
+    /// use hap::Config;
+    ///
+    /// let config = Config {
+    ///     device_id: MacAddress::parse_str("CC:22:3D:E3:CE:30").unwrap(),
+    ///     setup_id: "7OSX".into(),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(config.setup_hash(), "neO6bA==");
+    /// ```
+    pub fn setup_hash(&self) -> String {
+        let mut hasher = Sha512::new();
+        hasher.input(self.setup_id.as_bytes());
+        hasher.input(self.device_id.to_hex_string().as_bytes());
+        base64::encode(&hasher.result()[..4])
+    }
+
+    pub(crate) fn txt_records(&self) -> [String; 9] {
         [
             format!("md={}", self.name),
             format!("id={}", self.device_id.to_hex_string()),
             format!("c#={}", self.configuration_number),
             format!("s#={}", self.state_number),
-            format!("ci={}", self.category as u8),
+            format!("ci={}", self.category.to_txt_value()),
             format!("pv={}", self.protocol_version),
             format!("sf={}", self.status_flag as u8),
             format!("ff={}", self.feature_flag as u8),
+            format!("sh={}", self.setup_hash()),
         ]
     }
 }
@@ -172,10 +325,14 @@ impl Default for Config {
                     .expect("couldn't stringify current directory")
             ),
             ip: current_ip().expect("couldn't determine local IP address"),
+            interface: None,
+            mdns_interface: None,
             port: 32000,
+            bind_ipv6: false,
             pin: "11122333".into(),
             name: "Accessory".into(),
             device_id: random_mac_address(),
+            setup_id: random_setup_id(),
             configuration_number: 1,
             state_number: 1,
             category: Category::Unknown,
@@ -183,6 +340,14 @@ impl Default for Config {
             status_flag: StatusFlag::NotPaired,
             feature_flag: FeatureFlag::Zero,
             max_peers: None,
+            event_interval: Duration::from_secs(1),
+            max_connections: 8,
+            max_requests_per_second_per_ip: 100,
+            session_cache_ttl: Duration::from_secs(60 * 60),
+            session_cache_capacity: 16,
+            connection_idle_timeout: Duration::from_secs(0),
+            max_request_body_size: 1024 * 1024,
+            mdns_name: None,
             version: 0,
             config_hash: None,
         };
@@ -191,6 +356,96 @@ impl Default for Config {
     }
 }
 
+impl Config {
+    /// Returns the socket addresses to serve on. All of them use `port`, so if it is `0`, the port
+    /// assigned by the OS for the first one is to be used for the others.
+    pub(crate) fn serve_addresses(&self) -> Result<Vec<SocketAddr>> {
+        let mut addresses = match self.interface {
+            Some(ref name) => {
+                let iface = datalink::interfaces()
+                    .into_iter()
+                    .find(|iface| &iface.name == name)
+                    .ok_or_else(|| Error::from_str("couldn't find the configured network interface"))?;
+                let mut addresses = iface
+                    .ips
+                    .iter()
+                    .map(|ip_network| match ip_network.ip() {
+                        // link-local addresses are only unique together with their interface
+                        IpAddr::V6(ip) if is_unicast_link_local(&ip) => {
+                            SocketAddr::V6(SocketAddrV6::new(ip, self.port, 0, iface.index))
+                        },
+                        ip => SocketAddr::new(ip, self.port),
+                    })
+                    .collect::<Vec<_>>();
+                // the IPv4 address goes first so it's the one reported as local address
+                addresses.sort_by_key(|a| a.is_ipv6());
+                addresses
+            },
+            None => vec![SocketAddr::new(self.ip, self.port)],
+        };
+        if addresses.is_empty() {
+            return Err(Error::from_str("the configured network interface has no address"));
+        }
+        if self.bind_ipv6 && !addresses.iter().any(SocketAddr::is_ipv6) {
+            addresses.push(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), self.port));
+        }
+        Ok(addresses)
+    }
+
+    /// Returns the interface to restrict the mDNS announcement to, or `None` to announce on all
+    /// interfaces. Only the addresses of the interface the server is reachable on are announced.
+    pub(crate) fn mdns_interface(&self) -> Result<Option<MdnsInterface>> {
+        let wanted = match self.mdns_interface {
+            Some(ref wanted) => wanted,
+            None => return Ok(None),
+        };
+        let wanted_ip = wanted.parse::<IpAddr>().ok();
+        let iface = datalink::interfaces()
+            .into_iter()
+            .find(|iface| &iface.name == wanted || iface.ips.iter().any(|n| Some(n.ip()) == wanted_ip))
+            .ok_or_else(|| Error::from_str("couldn't find the configured mDNS interface"))?;
+        let multicast_ip = iface
+            .ips
+            .iter()
+            .find_map(|n| match n.ip() {
+                IpAddr::V4(ip) => Some(ip),
+                IpAddr::V6(_) => None,
+            })
+            .ok_or_else(|| Error::from_str("the configured mDNS interface has no IPv4 address"))?;
+
+        let iface_addresses = iface.ips.iter().map(|n| n.ip());
+        let addresses: Vec<IpAddr> = match self.interface {
+            Some(ref name) if name == &iface.name => iface_addresses.collect(),
+            Some(_) => {
+                return Err(Error::from_str(
+                    "the server and the mDNS announcement are bound to different interfaces",
+                ))
+            },
+            // a wildcard address serves either IPv4 or IPv6, and IPv6 additionally if `bind_ipv6` is set
+            None if self.ip.is_unspecified() => iface_addresses
+                .filter(|ip| ip.is_ipv4() == self.ip.is_ipv4() || (ip.is_ipv6() && self.bind_ipv6))
+                .collect(),
+            None => iface_addresses
+                .filter(|ip| ip == &self.ip || (ip.is_ipv6() && self.bind_ipv6))
+                .collect(),
+        };
+        if !addresses.contains(&self.ip) && self.interface.is_none() && !self.ip.is_unspecified() {
+            return Err(Error::from_str("the server address isn't an address of the mDNS interface"));
+        }
+        if addresses.is_empty() {
+            return Err(Error::from_str("the server isn't reachable on the mDNS interface"));
+        }
+
+        Ok(Some(MdnsInterface {
+            name: iface.name,
+            multicast_ip,
+            addresses,
+        }))
+    }
+}
+
+fn is_unicast_link_local(ip: &Ipv6Addr) -> bool { ip.segments()[0] & 0xffc0 == 0xfe80 }
+
 fn current_ip() -> Option<IpAddr> {
     for iface in datalink::interfaces() {
         for ip_network in iface.ips {
@@ -205,8 +460,87 @@ fn current_ip() -> Option<IpAddr> {
     None
 }
 
+/// Characters a setup ID is made of.
+const SETUP_ID_CHARS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+fn is_setup_id(setup_id: &str) -> bool { setup_id.len() == 4 && setup_id.bytes().all(|c| SETUP_ID_CHARS.contains(&c)) }
+
+fn random_setup_id() -> String {
+    let mut rng = rand::thread_rng();
+    (0..4)
+        .map(|_| SETUP_ID_CHARS[rng.gen_range(0, SETUP_ID_CHARS.len())] as char)
+        .collect()
+}
+
 fn random_mac_address() -> MacAddress {
     let mut rng = rand::thread_rng();
     let eui = rng.gen::<[u8; 6]>();
     MacAddress::new(eui)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn unspecified_config(bind_ipv6: bool) -> Config {
+        Config {
+            ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 0,
+            bind_ipv6,
+            ..Default::default()
+        }
+    }
+
+    /// Returns the name of the loopback interface, or `None` if the host has none.
+    fn loopback_interface() -> Option<String> {
+        datalink::interfaces()
+            .into_iter()
+            .find(|iface| iface.is_loopback())
+            .map(|iface| iface.name)
+    }
+
+    #[test]
+    fn test_serve_addresses_with_bind_ipv6() {
+        assert_eq!(unspecified_config(false).serve_addresses().unwrap(), vec![
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+        ]);
+        assert_eq!(unspecified_config(true).serve_addresses().unwrap(), vec![
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+        ]);
+    }
+
+    #[test]
+    fn test_mdns_interface_announces_ipv6_addresses_with_bind_ipv6() {
+        // skipped on hosts without a loopback interface
+        let loopback = match loopback_interface() {
+            Some(loopback) => loopback,
+            None => return,
+        };
+        let loopback_config = |bind_ipv6| Config {
+            mdns_interface: Some(loopback.clone()),
+            ..unspecified_config(bind_ipv6)
+        };
+
+        let iface = loopback_config(false).mdns_interface().unwrap().unwrap();
+        assert_eq!(iface.multicast_ip, Ipv4Addr::LOCALHOST);
+        assert_eq!(iface.addresses, vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]);
+
+        let iface = loopback_config(true).mdns_interface().unwrap().unwrap();
+        assert!(iface.addresses.contains(&IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert!(iface.addresses.contains(&IpAddr::V6(Ipv6Addr::LOCALHOST)));
+    }
+
+    #[test]
+    fn test_debug_redacts_pin() {
+        let config = Config {
+            pin: "11122333".into(),
+            ..Default::default()
+        };
+        let debug = format!("{:?}", config);
+        assert!(debug.contains("pin: \"<redacted>\""));
+        assert!(!debug.contains("11122333"));
+    }
+}
@@ -0,0 +1,154 @@
+use ring::{digest, hkdf, hmac};
+
+pub const PAIR_SETUP_ENCRYPT_SALT: &[u8] = b"Pair-Setup-Encrypt-Salt";
+pub const PAIR_SETUP_ENCRYPT_INFO: &[u8] = b"Pair-Setup-Encrypt-Info";
+pub const PAIR_SETUP_CONTROLLER_SIGN_SALT: &[u8] = b"Pair-Setup-Controller-Sign-Salt";
+pub const PAIR_SETUP_CONTROLLER_SIGN_INFO: &[u8] = b"Pair-Setup-Controller-Sign-Info";
+pub const PAIR_SETUP_ACCESSORY_SIGN_SALT: &[u8] = b"Pair-Setup-Accessory-Sign-Salt";
+pub const PAIR_SETUP_ACCESSORY_SIGN_INFO: &[u8] = b"Pair-Setup-Accessory-Sign-Info";
+
+pub const PAIR_VERIFY_ENCRYPT_SALT: &[u8] = b"Pair-Verify-Encrypt-Salt";
+pub const PAIR_VERIFY_ENCRYPT_INFO: &[u8] = b"Pair-Verify-Encrypt-Info";
+pub const PAIR_VERIFY_RESUME_SESSION_ID_SALT: &[u8] = b"Pair-Verify-ResumeSessionID-Salt";
+pub const PAIR_VERIFY_RESUME_SESSION_ID_INFO: &[u8] = b"Pair-Verify-ResumeSessionID-Info";
+
+pub const PAIR_RESUME_REQUEST_INFO: &[u8] = b"Pair-Resume-Request-Info";
+pub const PAIR_RESUME_RESPONSE_INFO: &[u8] = b"Pair-Resume-Response-Info";
+pub const PAIR_RESUME_SHARED_SECRET_INFO: &[u8] = b"Pair-Resume-Shared-Secret-Info";
+
+pub const CONTROL_SALT: &[u8] = b"Control-Salt";
+pub const CONTROL_READ_ENCRYPTION_KEY_INFO: &[u8] = b"Control-Read-Encryption-Key";
+pub const CONTROL_WRITE_ENCRYPTION_KEY_INFO: &[u8] = b"Control-Write-Encryption-Key";
+
+/// Derives key material from the input key material `ikm` via HKDF-SHA-512, filling `out`.
+pub fn derive(ikm: &[u8], salt: &[u8], info: &[u8], out: &mut [u8]) {
+    let salt = hmac::SigningKey::new(&digest::SHA512, salt);
+    hkdf::extract_and_expand(&salt, ikm, info, out);
+}
+
+/// Derives a 32 byte key from the input key material `ikm` via HKDF-SHA-512.
+pub fn derive_key(ikm: &[u8], salt: &[u8], info: &[u8]) -> [u8; 32] {
+    let mut key = [0; 32];
+    derive(ikm, salt, info, &mut key);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::from_hex;
+
+    fn range(start: u8, len: usize) -> Vec<u8> { (0..len).map(|i| start + i as u8).collect() }
+
+    // The inputs of the test cases of RFC 5869, appendix A, with SHA-512 instead of SHA-256.
+    #[test]
+    fn test_derive_rfc_5869_vectors() {
+        let cases = vec![
+            (
+                vec![0x0b; 22],
+                range(0x00, 13),
+                range(0xf0, 10),
+                "832390086cda71fb47625bb5ceb168e4c8e26a1a16ed34d9fc7fe92c1481579338da362cb8d9f925d7cb",
+            ),
+            (
+                range(0x00, 80),
+                range(0x60, 80),
+                range(0xb0, 80),
+                concat!(
+                    "ce6c97192805b346e6161e821ed165673b84f400a2b514b2fe23d84cd189ddf1b695b48cbd1c8388441137b3ce28f16a",
+                    "a64ba33ba466b24df6cfcb021ecff235f6a2056ce3af1de44d572097a8505d9e7a93",
+                ),
+            ),
+            (
+                vec![0x0b; 22],
+                vec![],
+                vec![],
+                "f5fa02b18298a72a8c23898a8703472c6eb179dc204c03425c970e3b164bf90fff22d04836d0e2343bac",
+            ),
+        ];
+
+        for (ikm, salt, info, okm) in cases {
+            let okm = from_hex(okm);
+            let mut out = vec![0; okm.len()];
+            derive(&ikm, &salt, &info, &mut out);
+            assert_eq!(out, okm);
+        }
+    }
+
+    #[test]
+    fn test_derive_key_hap_salts_and_infos() {
+        let ikm = range(0x00, 32);
+        let cases = vec![
+            (
+                PAIR_SETUP_ENCRYPT_SALT,
+                PAIR_SETUP_ENCRYPT_INFO,
+                "52890146745a52e57b82b859a7a3679c7f3d40bb295b055a0c8fa8af92a3746d",
+            ),
+            (
+                PAIR_SETUP_CONTROLLER_SIGN_SALT,
+                PAIR_SETUP_CONTROLLER_SIGN_INFO,
+                "acaad01277164b90d790f2e3f6535370bf1fde16c9a0d5001514c43517d3aeba",
+            ),
+            (
+                PAIR_SETUP_ACCESSORY_SIGN_SALT,
+                PAIR_SETUP_ACCESSORY_SIGN_INFO,
+                "b1c3f46160df05f83758c67ba18f6225a188b3221bd09ca3d69c1a65b7d7dcd4",
+            ),
+            (
+                PAIR_VERIFY_ENCRYPT_SALT,
+                PAIR_VERIFY_ENCRYPT_INFO,
+                "faf9f3558a8ed1e45219bd94fb6d27e5b43a1bc861157fc2a0d291d8e3df410a",
+            ),
+            (
+                CONTROL_SALT,
+                CONTROL_READ_ENCRYPTION_KEY_INFO,
+                "c09403ef8aa6c5045cbd8cf9bf3e665b2caed623af2be0e87c8f80f519914d3d",
+            ),
+            (
+                CONTROL_SALT,
+                CONTROL_WRITE_ENCRYPTION_KEY_INFO,
+                "c3ca130c7033dbe5e7ff7f91d117ead869bac476994c7a48ca170c111136ed96",
+            ),
+        ];
+
+        for (salt, info, key) in cases {
+            assert_eq!(derive_key(&ikm, salt, info).to_vec(), from_hex(key));
+        }
+    }
+
+    #[test]
+    fn test_derive_resume_session_id() {
+        let mut session_id = [0; 8];
+        derive(
+            &range(0x00, 32),
+            PAIR_VERIFY_RESUME_SESSION_ID_SALT,
+            PAIR_VERIFY_RESUME_SESSION_ID_INFO,
+            &mut session_id,
+        );
+        assert_eq!(session_id.to_vec(), from_hex("6ad7e215bad6aa8a"));
+    }
+
+    // the salt of Pair Resume is the public key of the controller followed by the session ID
+    #[test]
+    fn test_derive_key_pair_resume_infos() {
+        let (ikm, salt) = (range(0x00, 32), range(0x20, 32));
+        let cases = vec![
+            (
+                PAIR_RESUME_REQUEST_INFO,
+                "0fd1e06725573ce97cd827a64e52794c10a6e50a189b979f18a584b2ff72bffa",
+            ),
+            (
+                PAIR_RESUME_RESPONSE_INFO,
+                "e169c2a25a7c6897f8812b8766b9799b4551c49e34470e09e303ac9f90358b18",
+            ),
+            (
+                PAIR_RESUME_SHARED_SECRET_INFO,
+                "172a2ab683e1dc3f17d448fb0828e86532ef9116e2b0b080137c7c7cd2572b5f",
+            ),
+        ];
+
+        for (info, key) in cases {
+            assert_eq!(derive_key(&ikm, &salt, info).to_vec(), from_hex(key));
+        }
+    }
+}
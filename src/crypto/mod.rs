@@ -0,0 +1,2 @@
+pub(crate) mod hkdf;
+pub(crate) mod x25519;
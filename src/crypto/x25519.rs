@@ -0,0 +1,87 @@
+use ::crypto::curve25519;
+use rand::{self, Rng};
+
+/// Ephemeral X25519 secret key. It's consumed by the key exchange, so every key pair is used for
+/// a single exchange only.
+pub struct EphemeralSecret([u8; 32]);
+
+/// X25519 public key.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey([u8; 32]);
+
+impl PublicKey {
+    /// Creates a `PublicKey` from its 32 bytes. Returns `None` for a slice of another length.
+    pub fn from_slice(bytes: &[u8]) -> Option<PublicKey> {
+        if bytes.len() != 32 {
+            return None;
+        }
+        let mut key = [0; 32];
+        key.copy_from_slice(bytes);
+        Some(PublicKey(key))
+    }
+
+    /// Returns the bytes of the `PublicKey`.
+    pub fn as_bytes(&self) -> &[u8; 32] { &self.0 }
+}
+
+/// Shared secret resulting from an X25519 key exchange.
+pub type SharedSecret = [u8; 32];
+
+/// Generates a random ephemeral key pair.
+pub fn generate_keypair() -> (EphemeralSecret, PublicKey) {
+    let secret = rand::thread_rng().gen::<[u8; 32]>();
+    let public = curve25519::curve25519_base(&secret);
+    (EphemeralSecret(secret), PublicKey(public))
+}
+
+/// Computes the shared secret of an ephemeral secret key and the public key of the peer. Returns
+/// `None` if the shared secret is all zeros, i.e. the peer sent a public key of low order, which
+/// would make the shared secret independent of the secret key.
+pub fn diffie_hellman(secret: EphemeralSecret, peer_public: &PublicKey) -> Option<SharedSecret> {
+    let shared_secret = curve25519::curve25519(&secret.0, &peer_public.0);
+    if shared_secret.iter().all(|&b| b == 0) {
+        return None;
+    }
+    Some(shared_secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::from_hex;
+
+    fn key(hex: &str) -> [u8; 32] {
+        let mut key = [0; 32];
+        key.copy_from_slice(&from_hex(hex));
+        key
+    }
+
+    // RFC 7748, section 6.1
+    const ALICE_SECRET: &str = "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a";
+    const ALICE_PUBLIC: &str = "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a";
+    const BOB_SECRET: &str = "5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb";
+    const BOB_PUBLIC: &str = "de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f";
+    const SHARED_SECRET: &str = "4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742";
+
+    #[test]
+    fn test_diffie_hellman_rfc_7748_vector() {
+        assert_eq!(curve25519::curve25519_base(&key(ALICE_SECRET)), key(ALICE_PUBLIC));
+        assert_eq!(curve25519::curve25519_base(&key(BOB_SECRET)), key(BOB_PUBLIC));
+
+        let alice_shared = diffie_hellman(EphemeralSecret(key(ALICE_SECRET)), &PublicKey(key(BOB_PUBLIC)));
+        let bob_shared = diffie_hellman(EphemeralSecret(key(BOB_SECRET)), &PublicKey(key(ALICE_PUBLIC)));
+        assert_eq!(alice_shared, Some(key(SHARED_SECRET)));
+        assert_eq!(bob_shared, Some(key(SHARED_SECRET)));
+    }
+
+    #[test]
+    fn test_diffie_hellman_rejects_low_order_public_keys() {
+        let (secret, _) = generate_keypair();
+        assert_eq!(diffie_hellman(secret, &PublicKey([0; 32])), None);
+
+        // the point of order 8, see the list of low order points of Curve25519
+        let (secret, _) = generate_keypair();
+        let low_order = key("e0eb7a7c3b41b8ae1656e3faf19fc46ada098deb9c32b1fd866205165f49b800");
+        assert_eq!(diffie_hellman(secret, &PublicKey(low_order)), None);
+    }
+}
@@ -1,19 +1,27 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt,
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
 
 use erased_serde::{self, __internal_serialize_trait_object, serialize_trait_object};
+use log::warn;
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
 use crate::{
     accessory::HapAccessory,
-    characteristic::Perm,
-    event::EventEmitterPtr,
+    characteristic::{Format, HapCharacteristic, Perm},
+    event::{Event, EventEmitterPtr},
     transport::http::{server::EventSubscriptions, ReadResponseObject, Status, WriteObject, WriteResponseObject},
     Error,
+    ErrorKind,
+    HapType,
     Result,
 };
 
 /// `AccessoryList` is a wrapper type holding an `Arc<Mutex>` with a `Vec` of boxed Accessories.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct AccessoryList {
     pub accessories: Arc<Mutex<Vec<AccessoryListPtr>>>,
     event_emitter: EventEmitterPtr,
@@ -44,6 +52,26 @@ impl AccessoryList {
         Ok(a_ptr)
     }
 
+    /// Returns a hash of the Accessories, Services and Characteristics of the `AccessoryList`, i.e.
+    /// of the structure of the attribute database regardless of the Characteristic values. It
+    /// changes whenever the configuration number has to be incremented.
+    pub(crate) fn topology_hash(&self) -> Result<u64> {
+        let mut s = DefaultHasher::new();
+        for accessory in self.accessories.lock().expect("couldn't access accessories").iter() {
+            let a = accessory.lock().expect("couldn't access accessory");
+            a.get_id().hash(&mut s);
+            for service in a.get_services() {
+                service.get_id().hash(&mut s);
+                service.get_type().to_uuid().hash(&mut s);
+                for characteristic in service.get_characteristics() {
+                    characteristic.get_id()?.hash(&mut s);
+                    characteristic.get_type()?.to_uuid().hash(&mut s);
+                }
+            }
+        }
+        Ok(s.finish())
+    }
+
     /// Takes a pointer to an Accessory and removes the Accessory from the `AccessoryList`.
     pub fn remove_accessory(&mut self, accessory: &AccessoryListPtr) -> Result<()> {
         let accessory = accessory.lock().expect("couldn't access accessory");
@@ -64,7 +92,90 @@ impl AccessoryList {
             self.accessories.lock().expect("couldn't access accessories").remove(i);
             return Ok(());
         }
-        Err(Error::from_str("couldn't find the Accessory to remove"))
+        Err(ErrorKind::AccessoryNotFound { aid: accessory.get_id() }.into())
+    }
+
+    /// Adds a Characteristic to the Service with the given ID of an Accessory. The Characteristic is
+    /// assigned the next free instance ID of the Accessory, which is returned. Fails if the Service
+    /// already has a Characteristic of the same type.
+    pub fn add_characteristic(
+        &mut self,
+        accessory: &AccessoryListPtr,
+        service_id: u64,
+        characteristic: Box<dyn HapCharacteristic + Send>,
+    ) -> Result<u64> {
+        let mut characteristic = characteristic;
+        let mut a = accessory.lock().expect("couldn't access accessory");
+        let accessory_id = a.get_id();
+
+        let mut next_iid = 1;
+        for service in a.get_services() {
+            next_iid = next_iid.max(service.get_id() + 1);
+            for c in service.get_characteristics() {
+                next_iid = next_iid.max(c.get_id()? + 1);
+            }
+        }
+        characteristic.set_id(next_iid)?;
+        characteristic.set_accessory_id(accessory_id)?;
+        characteristic.set_event_emitter(Some(self.event_emitter.clone()))?;
+
+        let hap_type = characteristic.get_type()?;
+        for service in a.get_mut_services() {
+            if service.get_id() == service_id {
+                for c in service.get_characteristics() {
+                    if c.get_type()? == hap_type {
+                        return Err(Error::from_str("the Service already has a Characteristic of this type"));
+                    }
+                }
+                service.add_characteristic(characteristic);
+                return Ok(next_iid);
+            }
+        }
+        Err(ErrorKind::ServiceNotFound {
+            aid: accessory_id,
+            iid: service_id,
+        }
+        .into())
+    }
+
+    /// Removes the Characteristic with the given ID from an Accessory. Event subscriptions of
+    /// controllers to the Characteristic are dropped. Fails if the Characteristic is a required one
+    /// of its Service.
+    pub fn remove_characteristic(&mut self, accessory: &AccessoryListPtr, characteristic_id: u64) -> Result<()> {
+        let mut a = accessory.lock().expect("couldn't access accessory");
+        let accessory_id = a.get_id();
+
+        let mut removed = false;
+        for service in a.get_mut_services() {
+            let mut contains = false;
+            for c in service.get_characteristics() {
+                if c.get_id()? == characteristic_id {
+                    contains = true;
+                    break;
+                }
+            }
+            if contains {
+                service.remove_characteristic(characteristic_id)?;
+                removed = true;
+                break;
+            }
+        }
+        if !removed {
+            return Err(ErrorKind::CharacteristicNotFound {
+                aid: accessory_id,
+                iid: characteristic_id,
+            }
+            .into());
+        }
+
+        self.event_emitter
+            .lock()
+            .expect("couldn't access event_emitter")
+            .emit(&Event::CharacteristicRemoved {
+                aid: accessory_id,
+                iid: characteristic_id,
+            });
+        Ok(())
     }
 
     pub(crate) fn read_characteristic(
@@ -74,7 +185,7 @@ impl AccessoryList {
         meta: bool,
         perms: bool,
         hap_type: bool,
-        ev: bool,
+        ev: Option<&EventSubscriptions>,
     ) -> Result<ReadResponseObject> {
         let mut result_object = ReadResponseObject {
             iid,
@@ -82,6 +193,7 @@ impl AccessoryList {
             hap_type: None,
             format: None,
             perms: None,
+            description: None,
             ev: None,
             value: None,
             unit: None,
@@ -89,25 +201,36 @@ impl AccessoryList {
             min_value: None,
             step_value: None,
             max_len: None,
-            status: Some(0),
+            max_data_len: None,
+            valid_values: None,
+            valid_values_range: None,
+            status: Some(Status::ResourceDoesNotExist as i32),
         };
 
+        #[cfg(feature = "tracing")]
+        tracing::event!(tracing::Level::TRACE, aid, iid, "characteristic read");
+
         'l: for accessory in self.accessories.lock().expect("couldn't access accessories").iter_mut() {
             let mut a = accessory.lock().expect("couldn't access accessory");
             if a.get_id() == aid {
                 for service in a.get_mut_services() {
                     for characteristic in service.get_mut_characteristics() {
                         if characteristic.get_id()? == iid {
+                            result_object.status = Some(Status::Success as i32);
                             let characteristic_perms = characteristic.get_perms()?;
                             if characteristic_perms.contains(&Perm::PairedRead) {
                                 result_object.value = Some(characteristic.get_value()?);
                                 if meta {
                                     result_object.format = Some(characteristic.get_format()?);
+                                    result_object.description = characteristic.get_description()?;
                                     result_object.unit = characteristic.get_unit()?;
                                     result_object.max_value = characteristic.get_max_value()?;
                                     result_object.min_value = characteristic.get_min_value()?;
                                     result_object.step_value = characteristic.get_step_value()?;
                                     result_object.max_len = characteristic.get_max_len()?;
+                                    result_object.max_data_len = characteristic.get_max_data_len()?;
+                                    result_object.valid_values = characteristic.get_valid_values()?;
+                                    result_object.valid_values_range = characteristic.get_valid_values_range()?;
                                 }
                                 if perms {
                                     result_object.perms = Some(characteristic_perms);
@@ -115,8 +238,13 @@ impl AccessoryList {
                                 if hap_type {
                                     result_object.hap_type = Some(characteristic.get_type()?);
                                 }
-                                if ev {
-                                    result_object.ev = characteristic.get_event_notifications()?;
+                                if let Some(event_subscriptions) = ev {
+                                    result_object.ev = Some(
+                                        event_subscriptions
+                                            .lock()
+                                            .expect("couldn't access event_subscriptions")
+                                            .contains(&(aid, iid)),
+                                    );
                                 }
                             } else {
                                 result_object.status = Some(Status::WriteOnlyCharacteristic as i32);
@@ -131,17 +259,96 @@ impl AccessoryList {
         Ok(result_object)
     }
 
+    /// Restores the persisted `values` of the Characteristics of an Accessory of the `AccessoryList`
+    /// and returns the keys under which the values of its Characteristics are persisted, by their
+    /// Accessory and instance ID.
+    ///
+    /// Only readable and writable Characteristics are persisted, i.e. the state set by controllers,
+    /// except for TLV8 control points. Their keys are made of the serial number of the Accessory and
+    /// the types of the Service and the Characteristic, so they don't depend on the order in which
+    /// Accessories are added. The state of an Accessory sharing its serial number with another one
+    /// isn't persisted. Values failing validation are skipped and the Characteristic keeps its
+    /// default value.
+    ///
+    /// If a `characteristic_id` is given, only the value of that Characteristic is restored and only
+    /// its key is returned, e.g. for a Characteristic added to an Accessory that was added before.
+    pub(crate) fn restore_characteristic_values(
+        &self,
+        accessory: &AccessoryListPtr,
+        characteristic_id: Option<u64>,
+        values: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Vec<((u64, u64), String)>> {
+        let serial_number = accessory
+            .lock()
+            .expect("couldn't access accessory")
+            .get_mut_information()
+            .inner
+            .serial_number
+            .get_value()?;
+        for other in self.accessories.lock().expect("couldn't access accessories").iter() {
+            if Arc::ptr_eq(other, accessory) {
+                continue;
+            }
+            let mut other = other.lock().expect("couldn't access accessory");
+            if other.get_mut_information().inner.serial_number.get_value()? == serial_number {
+                warn!(
+                    "the state of accessory {} isn't persisted, as its serial number {} isn't unique",
+                    other.get_id(),
+                    serial_number,
+                );
+                return Ok(vec![]);
+            }
+        }
+
+        let mut a = accessory.lock().expect("couldn't access accessory");
+        let accessory_id = a.get_id();
+        let mut keys = Vec::new();
+        let mut type_counts = HashMap::new();
+        for service in a.get_mut_services() {
+            let service_type = service.get_type().to_uuid();
+            for characteristic in service.get_mut_characteristics() {
+                let perms = characteristic.get_perms()?;
+                if !perms.contains(&Perm::PairedRead)
+                    || !perms.contains(&Perm::PairedWrite)
+                    || characteristic.get_format()? == Format::Tlv8
+                {
+                    continue;
+                }
+                // Services and Characteristics of the same type are told apart by their order
+                let key = format!("{}/{}/{}", serial_number, service_type, characteristic.get_type()?.to_uuid());
+                let count = type_counts.entry(key.clone()).or_insert(0);
+                let key = format!("{}/{}", key, count);
+                *count += 1;
+                let id = characteristic.get_id()?;
+                if characteristic_id.is_some() && characteristic_id != Some(id) {
+                    continue;
+                }
+                if let Some(value) = values.get(&key) {
+                    if let Err(e) = characteristic.set_value(value.clone()) {
+                        warn!("couldn't restore the persisted value of {}: {}", key, e);
+                    }
+                }
+                keys.push(((accessory_id, id), key));
+            }
+        }
+        Ok(keys)
+    }
+
     pub(crate) fn write_characteristic(
         &self,
         write_object: WriteObject,
         event_subscriptions: &EventSubscriptions,
+        timed_write: Status,
     ) -> Result<WriteResponseObject> {
         let mut result_object = WriteResponseObject {
             aid: write_object.aid,
             iid: write_object.iid,
-            status: 0,
+            status: Status::ResourceDoesNotExist as i32,
+            value: None,
         };
 
+        // an Accessory whose Identify Characteristic is written identifies itself after the loop
+        let mut identifying = None;
         let mut a = self.accessories.lock().expect("couldn't access accessories");
         'l: for accessory in a.iter_mut() {
             let mut a = accessory.lock().expect("couldn't access accessory");
@@ -149,10 +356,10 @@ impl AccessoryList {
                 for service in a.get_mut_services() {
                     for characteristic in service.get_mut_characteristics() {
                         if characteristic.get_id()? == write_object.iid {
+                            result_object.status = Status::Success as i32;
                             let characteristic_perms = characteristic.get_perms()?;
                             if let Some(ev) = write_object.ev {
                                 if characteristic_perms.contains(&Perm::Events) {
-                                    characteristic.set_event_notifications(Some(ev))?;
                                     let subscription = (write_object.aid, write_object.iid);
                                     let mut es =
                                         event_subscriptions.lock().expect("couldn't access event_subscriptions");
@@ -171,10 +378,45 @@ impl AccessoryList {
                                 }
                             }
                             if let Some(value) = write_object.value {
-                                if characteristic_perms.contains(&Perm::PairedWrite) {
-                                    characteristic.set_value(value)?;
-                                } else {
+                                let auth_data = match write_object.auth_data {
+                                    Some(ref auth_data) => match base64::decode(auth_data) {
+                                        Ok(auth_data) => Some(auth_data),
+                                        Err(_) => {
+                                            result_object.status = Status::InsufficientAuthorization as i32;
+                                            break 'l;
+                                        },
+                                    },
+                                    None => None,
+                                };
+                                if !characteristic_perms.contains(&Perm::PairedWrite) {
                                     result_object.status = Status::ReadOnlyCharacteristic as i32;
+                                } else if characteristic_perms.contains(&Perm::TimedWrite)
+                                    && timed_write != Status::Success
+                                {
+                                    result_object.status = timed_write as i32;
+                                } else if !characteristic.is_authorized(auth_data.as_deref())? {
+                                    result_object.status = Status::InsufficientAuthorization as i32;
+                                } else {
+                                    match characteristic.set_value(value) {
+                                        Ok(()) => {
+                                            if write_object.response == Some(true)
+                                                || characteristic_perms.contains(&Perm::WriteResponse)
+                                            {
+                                                result_object.value = Some(characteristic.get_value()?);
+                                            }
+                                            if characteristic.get_type()? == HapType::Identify
+                                                && characteristic.get_value()? == serde_json::Value::Bool(true)
+                                            {
+                                                identifying = Some(accessory.clone());
+                                            }
+                                        },
+                                        Err(e) => match e.kind() {
+                                            ErrorKind::InvalidValue => {
+                                                result_object.status = Status::InvalidValueInRequest as i32;
+                                            },
+                                            _ => return Err(e),
+                                        },
+                                    }
                                 }
                             }
                             break 'l;
@@ -183,6 +425,11 @@ impl AccessoryList {
                 }
             }
         }
+        if let Some(accessory) = identifying {
+            if accessory.lock().expect("couldn't access accessory").identify().is_err() {
+                result_object.status = Status::ServiceCommunicationFailure as i32;
+            }
+        }
 
         Ok(result_object)
     }
@@ -203,4 +450,90 @@ impl<T: HapAccessory + erased_serde::Serialize> AccessoryListMember for T {}
 
 serialize_trait_object!(AccessoryListMember);
 
+impl<'a> fmt::Debug for dyn AccessoryListMember + Send + 'a {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AccessoryListMember")
+            .field("id", &self.get_id())
+            .field("services", &self.get_services())
+            .finish()
+    }
+}
+
 pub type AccessoryListPtr = Arc<Mutex<Box<dyn AccessoryListMember + Send>>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        accessory::{outlet, Information},
+        characteristic::{name, on},
+        event::EventEmitter,
+    };
+
+    /// Returns an `AccessoryList` with an Outlet and the ID of its Outlet Service.
+    fn accessory_list() -> (AccessoryList, AccessoryListPtr, u64) {
+        let mut accessory_list = AccessoryList::new(Arc::new(Mutex::new(EventEmitter::new())));
+        let accessory = accessory_list
+            .add_accessory(Box::new(outlet::new(Information::default()).unwrap()))
+            .unwrap();
+        let service_id = accessory
+            .lock()
+            .unwrap()
+            .get_services()
+            .into_iter()
+            .find(|s| s.get_type() == HapType::Outlet)
+            .unwrap()
+            .get_id();
+        (accessory_list, accessory, service_id)
+    }
+
+    #[test]
+    fn test_add_and_remove_characteristic() {
+        let (mut accessory_list, accessory, service_id) = accessory_list();
+
+        let iid = accessory_list
+            .add_characteristic(&accessory, service_id, Box::new(name::new()))
+            .unwrap();
+        accessory_list.remove_characteristic(&accessory, iid).unwrap();
+
+        let err = accessory_list.remove_characteristic(&accessory, iid).unwrap_err();
+        assert!(matches!(err.kind(), ErrorKind::CharacteristicNotFound { .. }));
+    }
+
+    #[test]
+    fn test_add_characteristic_of_existing_type() {
+        let (mut accessory_list, accessory, service_id) = accessory_list();
+
+        assert!(accessory_list
+            .add_characteristic(&accessory, service_id, Box::new(on::new()))
+            .is_err());
+
+        accessory_list
+            .add_characteristic(&accessory, service_id, Box::new(name::new()))
+            .unwrap();
+        assert!(accessory_list
+            .add_characteristic(&accessory, service_id, Box::new(name::new()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_remove_required_characteristic() {
+        let (mut accessory_list, accessory, service_id) = accessory_list();
+        let on_id = accessory
+            .lock()
+            .unwrap()
+            .get_services()
+            .into_iter()
+            .find(|s| s.get_id() == service_id)
+            .unwrap()
+            .get_characteristics()
+            .into_iter()
+            .find(|c| c.get_type().unwrap() == HapType::On)
+            .unwrap()
+            .get_id()
+            .unwrap();
+
+        let err = accessory_list.remove_characteristic(&accessory, on_id).unwrap_err();
+        assert!(!matches!(err.kind(), ErrorKind::CharacteristicNotFound { .. }));
+    }
+}
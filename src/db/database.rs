@@ -1,9 +1,12 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
 
 use uuid::Uuid;
 
 use crate::{
-    db::{file_storage, storage::Storage},
+    db::{file_storage, memory_storage, storage::Storage},
     protocol::{Device, Pairing},
 };
 
@@ -17,6 +20,10 @@ pub struct Database {
     storage: Box<dyn Storage + Send>,
 }
 
+impl fmt::Debug for Database {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { f.debug_struct("Database").finish_non_exhaustive() }
+}
+
 impl Database {
     /// Creates a new `Database`.
     pub fn new(storage: Box<dyn Storage + Send>) -> Database { Database { storage } }
@@ -27,6 +34,20 @@ impl Database {
         Ok(Database::new(Box::new(storage)))
     }
 
+    /// Creates a new `Database` with an empty `MemoryStorage` as its `Storage`. Nothing is
+    /// persisted, e.g. for tests.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::db::Database;
+    ///
+    /// let database = Database::new_with_memory_storage();
+    /// assert!(database.get_device().is_err());
+    /// assert_eq!(database.count_pairings().unwrap(), 0);
+    /// ```
+    pub fn new_with_memory_storage() -> Database { Database::new(Box::new(memory_storage::MemoryStorage::new())) }
+
     /// Returns the stored value for a given key as a `Vec<u8>`.
     pub fn get_bytes(&self, key: &str) -> Result<Vec<u8>> {
         let k = format!("{}.entity", key);
@@ -86,6 +107,22 @@ impl Database {
         Ok(pairings)
     }
 
+    /// Returns the number of unsuccessful pair setup attempts and the UNIX timestamp in seconds of
+    /// the last one.
+    pub fn get_unsuccessful_setup_attempts(&self) -> Result<(u64, u64)> {
+        let attempts = self.storage.get_u64("setup_attempts").unwrap_or(0);
+        let last_attempt = self.storage.get_u64("last_setup_attempt").unwrap_or(0);
+        Ok((attempts, last_attempt))
+    }
+
+    /// Stores the number of unsuccessful pair setup attempts and the UNIX timestamp in seconds of
+    /// the last one.
+    pub fn set_unsuccessful_setup_attempts(&self, attempts: u64, last_attempt: u64) -> Result<()> {
+        self.storage.set_u64("setup_attempts", attempts)?;
+        self.storage.set_u64("last_setup_attempt", last_attempt)?;
+        Ok(())
+    }
+
     /// Returns the number of stored pairings.
     pub fn count_pairings(&self) -> Result<usize> {
         let mut count = 0;
@@ -1,20 +1,17 @@
 use std::{
     ffi::OsStr,
     fs,
-    io::{BufReader, BufWriter, Read, Write},
+    io::{BufRead, BufReader, BufWriter, Write},
     os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
-    str,
 };
 
-use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
-use uuid::Uuid;
-
 use crate::db::storage::Storage;
 
-use crate::{Error, Result};
+use crate::{ErrorKind, Result};
 
 /// `FileStorage` is an implementor of the `Storage` trait that stores data to the file system.
+#[derive(Clone, Debug)]
 pub struct FileStorage {
     dir_path: PathBuf,
 }
@@ -41,10 +38,26 @@ impl FileStorage {
     /// Returns a writable `File` for the given file name.
     fn file_for_write(&self, file: &str) -> Result<fs::File> {
         let file_path = self.path_to_file(file);
-        let file = fs::OpenOptions::new().write(true).create(true).open(file_path)?;
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(file_path)?;
         Ok(file)
     }
 
+    /// Replaces the contents of the given file. The value is written to a temporary file that's
+    /// renamed to the file name once it's complete, so the previous value stays intact if writing
+    /// fails halfway.
+    fn write_file(&self, file: &str, value: &[u8]) -> Result<()> {
+        let tmp_file = format!("{}.tmp", file);
+        let mut writer = self.file_for_write(&tmp_file)?;
+        writer.write_all(value)?;
+        writer.sync_all()?;
+        fs::rename(self.path_to_file(&tmp_file), self.path_to_file(file))?;
+        Ok(())
+    }
+
     /// Returns the full storage path for the given file name.
     fn path_to_file(&self, file: &str) -> PathBuf {
         let mut file_path = self.dir_path.clone();
@@ -54,61 +67,14 @@ impl FileStorage {
 }
 
 impl Storage for FileStorage {
-    fn get_reader(&self, key: &str) -> Result<BufReader<fs::File>> {
+    fn get_reader(&self, key: &str) -> Result<Box<dyn BufRead>> {
         let file = self.file_for_read(key)?;
-        let reader = BufReader::new(file);
-        Ok(reader)
+        Ok(Box::new(BufReader::new(file)))
     }
 
-    fn get_writer(&self, key: &str) -> Result<BufWriter<fs::File>> {
+    fn get_writer(&self, key: &str) -> Result<Box<dyn Write>> {
         let file = self.file_for_write(key)?;
-        let writer = BufWriter::new(file);
-        Ok(writer)
-    }
-
-    fn get_bytes(&self, key: &str) -> Result<Vec<u8>> {
-        let mut reader = self.get_reader(key)?;
-        let mut value = Vec::new();
-        reader.read_to_end(&mut value)?;
-        Ok(value)
-    }
-
-    fn set_bytes(&self, key: &str, value: Vec<u8>) -> Result<()> {
-        let mut writer = self.get_writer(key)?;
-        writer.write_all(&value)?;
-        Ok(())
-    }
-
-    fn get_u64(&self, key: &str) -> Result<u64> {
-        let mut reader = self.get_reader(key)?;
-        let value = reader.read_u64::<BigEndian>()?;
-        Ok(value)
-    }
-
-    fn set_u64(&self, key: &str, value: u64) -> Result<()> {
-        let mut buf = [0; 8];
-        BigEndian::write_u64(&mut buf, value);
-        self.set_bytes(key, buf.to_vec())?;
-        Ok(())
-    }
-
-    fn get_uuid(&self, key: &str) -> Result<Uuid> {
-        let mut reader = self.get_reader(key)?;
-        let mut buf = Vec::new();
-        reader.read_to_end(&mut buf)?;
-        match str::from_utf8(&buf) {
-            Ok(uuid_str) => match Uuid::parse_str(uuid_str) {
-                Ok(value) => Ok(value),
-                _ => Err(Error::from_str("couldn't parse UUID")),
-            },
-            _ => Err(Error::from_str("couldn't read UUID")),
-        }
-    }
-
-    fn set_uuid(&self, key: &str, value: Uuid) -> Result<()> {
-        let mut writer = self.get_writer(key)?;
-        writer.write_all(value.to_hyphenated().to_string().as_bytes())?;
-        Ok(())
+        Ok(Box::new(BufWriter::new(file)))
     }
 
     fn keys_with_suffix(&self, suffix: &str) -> Result<Vec<String>> {
@@ -120,10 +86,10 @@ impl Storage for FileStorage {
             if path.extension() == extension {
                 let key = path
                     .file_stem()
-                    .ok_or(Error::from_str("invalid file name"))?
+                    .ok_or_else(|| ErrorKind::Storage("invalid file name".into()))?
                     .to_os_string()
                     .into_string()
-                    .or(Err(Error::from_str("invalid file name")))?;
+                    .map_err(|_| ErrorKind::Storage("invalid file name".into()))?;
                 keys.push(key);
             }
         }
@@ -135,4 +101,6 @@ impl Storage for FileStorage {
         fs::remove_file(file_path)?;
         Ok(())
     }
+
+    fn set_bytes(&self, key: &str, value: Vec<u8>) -> Result<()> { self.write_file(key, &value) }
 }
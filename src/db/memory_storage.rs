@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Cursor, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{db::storage::Storage, ErrorKind, Result};
+
+/// `MemoryStorage` is an implementor of the `Storage` trait that keeps data in memory, e.g. for
+/// tests or ephemeral Accessories. Clones of a `MemoryStorage` share the same data. Missing keys
+/// fail with the same errors as with a `FileStorage`.
+///
+/// # Examples
+///
+/// ```
+/// use hap::db::{MemoryStorage, Storage};
+///
+/// let storage = MemoryStorage::new();
+/// assert!(storage.get_u64("setup_attempts").is_err());
+///
+/// storage.set_u64("setup_attempts", 3).unwrap();
+/// storage.set_bytes("device.entity", vec![1, 2, 3]).unwrap();
+/// assert_eq!(storage.get_u64("setup_attempts").unwrap(), 3);
+/// assert_eq!(storage.keys_with_suffix("entity").unwrap(), vec!["device".to_string()]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    values: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+}
+
+impl MemoryStorage {
+    /// Creates a new, empty `MemoryStorage`.
+    pub fn new() -> MemoryStorage { MemoryStorage::default() }
+}
+
+/// Writer appending to the value of a key of a `MemoryStorage`.
+struct MemoryWriter {
+    values: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+    key: String,
+}
+
+impl Write for MemoryWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.values
+            .lock()
+            .expect("couldn't access values")
+            .entry(self.key.clone())
+            .or_insert_with(Vec::new)
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+impl Storage for MemoryStorage {
+    fn get_reader(&self, key: &str) -> Result<Box<dyn BufRead>> {
+        let value = self
+            .values
+            .lock()
+            .expect("couldn't access values")
+            .get(key)
+            .cloned()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        Ok(Box::new(Cursor::new(value)))
+    }
+
+    fn get_writer(&self, key: &str) -> Result<Box<dyn Write>> {
+        // like a file opened for writing, the previous value is truncated right away
+        self.values
+            .lock()
+            .expect("couldn't access values")
+            .insert(key.to_string(), Vec::new());
+        Ok(Box::new(MemoryWriter {
+            values: self.values.clone(),
+            key: key.to_string(),
+        }))
+    }
+
+    fn keys_with_suffix(&self, suffix: &str) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for key in self.values.lock().expect("couldn't access values").keys() {
+            // the keys are matched like the file names of a `FileStorage`
+            let path = Path::new(key);
+            if path.extension().map_or(false, |e| e == suffix) {
+                let stem = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .ok_or_else(|| ErrorKind::Storage("invalid key".into()))?;
+                keys.push(stem.to_string());
+            }
+        }
+        Ok(keys)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.values
+            .lock()
+            .expect("couldn't access values")
+            .remove(key)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        Ok(())
+    }
+}
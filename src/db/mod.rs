@@ -1,11 +1,13 @@
 mod accessory_list;
 mod database;
 mod file_storage;
+mod memory_storage;
 mod storage;
 
 pub use self::{
     accessory_list::{AccessoryList, AccessoryListMember, AccessoryListPtr},
     database::{Database, DatabasePtr},
     file_storage::FileStorage,
+    memory_storage::MemoryStorage,
     storage::Storage,
 };
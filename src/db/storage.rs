@@ -1,33 +1,71 @@
 use std::{
-    fs::File,
-    io::{BufReader, BufWriter},
+    io::{BufRead, Read, Write},
+    str,
 };
 
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
 use uuid::Uuid;
 
-use crate::Result;
+use crate::{ErrorKind, Result};
 
-/// `Storage` is implemented by the data storage methods HAP supports. Currently, that's just
-/// `FileStorage`.
+/// `Storage` is implemented by the data storage methods HAP supports. Currently, that's
+/// `FileStorage` and `MemoryStorage`. Implementors provide readers and writers for the stored
+/// values, the typed getters and setters are built on top of them.
 pub trait Storage {
-    /// Returns a `BufReader` to the `File` stored for the given key.
-    fn get_reader(&self, key: &str) -> Result<BufReader<File>>;
-    /// Returns a `BufWriter` to the `File` stored for the given key.
-    fn get_writer(&self, key: &str) -> Result<BufWriter<File>>;
+    /// Returns a reader of the value stored for the given key. Fails if there is no value stored.
+    fn get_reader(&self, key: &str) -> Result<Box<dyn BufRead>>;
+    /// Returns a writer of the value for the given key, replacing the value stored before.
+    fn get_writer(&self, key: &str) -> Result<Box<dyn Write>>;
+    /// Returns all keys with a given suffix as a `Vec<String>`.
+    fn keys_with_suffix(&self, suffix: &str) -> Result<Vec<String>>;
+    /// Deletes the stored value for a given key.
+    fn delete(&self, key: &str) -> Result<()>;
+
     /// Returns the stored value for a given key as a `Vec<u8>`.
-    fn get_bytes(&self, key: &str) -> Result<Vec<u8>>;
+    fn get_bytes(&self, key: &str) -> Result<Vec<u8>> {
+        let mut reader = self.get_reader(key)?;
+        let mut value = Vec::new();
+        reader.read_to_end(&mut value)?;
+        Ok(value)
+    }
+
     /// Stores a given `Vec<u8>` as the value for a given key.
-    fn set_bytes(&self, key: &str, value: Vec<u8>) -> Result<()>;
+    fn set_bytes(&self, key: &str, value: Vec<u8>) -> Result<()> {
+        let mut writer = self.get_writer(key)?;
+        writer.write_all(&value)?;
+        writer.flush()?;
+        Ok(())
+    }
+
     /// Returns the stored value for a given key as a `u64`.
-    fn get_u64(&self, key: &str) -> Result<u64>;
+    fn get_u64(&self, key: &str) -> Result<u64> {
+        let mut reader = self.get_reader(key)?;
+        let value = reader.read_u64::<BigEndian>()?;
+        Ok(value)
+    }
+
     /// Stores a given `u64` as the value for a given key.
-    fn set_u64(&self, key: &str, value: u64) -> Result<()>;
+    fn set_u64(&self, key: &str, value: u64) -> Result<()> {
+        let mut buf = [0; 8];
+        BigEndian::write_u64(&mut buf, value);
+        self.set_bytes(key, buf.to_vec())?;
+        Ok(())
+    }
+
     /// Returns the stored value for a given key as a `Uuid`.
-    fn get_uuid(&self, key: &str) -> Result<Uuid>;
+    fn get_uuid(&self, key: &str) -> Result<Uuid> {
+        let buf = self.get_bytes(key)?;
+        match str::from_utf8(&buf) {
+            Ok(uuid_str) => match Uuid::parse_str(uuid_str) {
+                Ok(value) => Ok(value),
+                _ => Err(ErrorKind::Storage(format!("couldn't parse UUID {}", key)).into()),
+            },
+            _ => Err(ErrorKind::Storage(format!("couldn't read UUID {}", key)).into()),
+        }
+    }
+
     /// Stores a given `Uuid` as the value for a given key.
-    fn set_uuid(&self, key: &str, value: Uuid) -> Result<()>;
-    /// Returns all keys with a given suffix as a `Vec<String>`.
-    fn keys_with_suffix(&self, suffix: &str) -> Result<Vec<String>>;
-    /// Deletes the stored value for a given key.
-    fn delete(&self, key: &str) -> Result<()>;
+    fn set_uuid(&self, key: &str, value: Uuid) -> Result<()> {
+        self.set_bytes(key, value.to_hyphenated().to_string().into_bytes())
+    }
 }
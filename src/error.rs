@@ -1,4 +1,4 @@
-use std::{fmt, io, num, str, sync::mpsc};
+use std::{cell, fmt, io, num, str, sync::mpsc};
 
 use chacha20_poly1305_aead;
 use eui48;
@@ -8,30 +8,64 @@ use hyper::{self, http};
 /// ErrorKind wrapper type.
 #[derive(Debug, Fail)]
 pub enum ErrorKind {
-    #[fail(display = "IO Error {}", _0)]
+    #[fail(display = "IO Error")]
     Io(#[cause] io::Error),
-    #[fail(display = "JSON Error {}", _0)]
+    #[fail(display = "JSON Error")]
     Json(#[cause] serde_json::Error),
     #[fail(display = "HTTP Status Code {}", _0)]
     HttpStatus(hyper::StatusCode),
-    #[fail(display = "HTTP Error {}", _0)]
+    #[fail(display = "HTTP Error")]
     Http(#[cause] http::Error),
-    #[fail(display = "Hyper Error {}", _0)]
+    #[fail(display = "Hyper Error")]
     Hyper(#[cause] hyper::error::Error),
-    #[fail(display = "ChaCha20-Poly1305-AEAD Error {}", _0)]
+    #[fail(display = "ChaCha20-Poly1305-AEAD Error")]
     ChaCha20Poly1305Aead(#[cause] chacha20_poly1305_aead::DecryptError),
-    #[fail(display = "UTF-8 Error {}", _0)]
+    #[fail(display = "UTF-8 Error")]
     Utf8(#[cause] str::Utf8Error),
-    #[fail(display = "MAC Address Parse Error {}", _0)]
+    #[fail(display = "MAC Address Parse Error")]
     MacAddressParse(#[cause] eui48::ParseError),
-    #[fail(display = "Parse Int Error {}", _0)]
+    #[fail(display = "Parse Int Error")]
     ParseInt(#[cause] num::ParseIntError),
-    #[fail(display = "MPSC Send Error {}", _0)]
+    #[fail(display = "UUID Parse Error")]
+    UuidParse(#[cause] uuid::Error),
+    #[fail(display = "MPSC Send Error")]
     MpscSend(#[cause] mpsc::SendError<()>),
+    #[fail(display = "Borrow Error")]
+    Borrow(#[cause] cell::BorrowError),
+    #[fail(display = "Borrow Mut Error")]
+    BorrowMut(#[cause] cell::BorrowMutError),
+    #[fail(display = "Invalid Value")]
+    InvalidValue,
+    #[fail(display = "Accessory {} not found", aid)]
+    AccessoryNotFound { aid: u64 },
+    #[fail(display = "Service {}.{} not found", aid, iid)]
+    ServiceNotFound { aid: u64, iid: u64 },
+    #[fail(display = "Characteristic {}.{} not found", aid, iid)]
+    CharacteristicNotFound { aid: u64, iid: u64 },
+    #[fail(display = "Invalid Pin: {}", _0)]
+    InvalidPin(String),
+    #[fail(display = "Pairing Error: {}", _0)]
+    Pairing(String),
+    #[fail(display = "Storage Error: {}", _0)]
+    Storage(String),
     #[fail(display = "Error {}", _0)]
     Other(failure::Error),
 }
 
+/// Error type of the crate. The underlying error, if any, is available via
+/// `std::error::Error::source` and isn't repeated in the message, so it's printed once when the
+/// whole chain of errors is printed.
+///
+/// # Examples
+///
+/// ```
+/// use std::{error::Error as _, io};
+///
+/// let err = hap::Error::from(io::Error::new(io::ErrorKind::Other, "disk full"));
+///
+/// assert_eq!(err.to_string(), "hap error: IO Error");
+/// assert_eq!(err.source().unwrap().to_string(), "disk full");
+/// ```
 #[derive(Debug)]
 pub struct Error {
     kind: Context<ErrorKind>,
@@ -58,10 +92,28 @@ impl Error {
 //     fn backtrace(&self) -> Option<&Backtrace> { self.kind.backtrace() }
 // }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self.kind() {
+            ErrorKind::Io(err) => Some(err),
+            ErrorKind::Json(err) => Some(err),
+            ErrorKind::Http(err) => Some(err),
+            ErrorKind::Hyper(err) => Some(err),
+            ErrorKind::ChaCha20Poly1305Aead(err) => Some(err),
+            ErrorKind::Utf8(err) => Some(err),
+            ErrorKind::MacAddressParse(err) => Some(err),
+            ErrorKind::ParseInt(err) => Some(err),
+            ErrorKind::UuidParse(err) => Some(err),
+            ErrorKind::MpscSend(err) => Some(err),
+            ErrorKind::Borrow(err) => Some(err),
+            ErrorKind::BorrowMut(err) => Some(err),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::Display::fmt(&self.kind, f) }
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "hap error: {}", self.kind) }
 }
 
 impl From<ErrorKind> for Error {
@@ -115,6 +167,84 @@ impl From<num::ParseIntError> for Error {
     fn from(err: num::ParseIntError) -> Error { ErrorKind::ParseInt(err).into() }
 }
 
+impl From<uuid::Error> for Error {
+    fn from(err: uuid::Error) -> Error { ErrorKind::UuidParse(err).into() }
+}
+
+impl From<cell::BorrowError> for Error {
+    fn from(err: cell::BorrowError) -> Error { ErrorKind::Borrow(err).into() }
+}
+
+impl From<cell::BorrowMutError> for Error {
+    fn from(err: cell::BorrowMutError) -> Error { ErrorKind::BorrowMut(err).into() }
+}
+
 impl From<mpsc::SendError<()>> for Error {
     fn from(err: mpsc::SendError<()>) -> Error { ErrorKind::MpscSend(err).into() }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, error::Error as _};
+
+    use futures::{stream, Future, Stream};
+    use hyper::{Body, Chunk, Response, StatusCode};
+
+    use super::*;
+
+    fn hyper_error() -> hyper::error::Error {
+        let body = stream::once::<Chunk, io::Error>(Err(io::Error::new(io::ErrorKind::Other, "reset")));
+        Body::wrap_stream(body).concat2().wait().unwrap_err()
+    }
+
+    fn decrypt_error() -> chacha20_poly1305_aead::DecryptError {
+        chacha20_poly1305_aead::decrypt(&[0; 32], &[0; 12], &[], &[], &[0; 16], &mut Vec::new()).unwrap_err()
+    }
+
+    #[test]
+    fn test_display() {
+        let cell = RefCell::new(());
+        let _borrow = cell.borrow_mut();
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+
+        let errors: Vec<(Error, &str)> = vec![
+            (io::Error::new(io::ErrorKind::Other, "disk full").into(), "IO Error"),
+            (serde_json::from_str::<u8>("x").unwrap_err().into(), "JSON Error"),
+            (ErrorKind::HttpStatus(StatusCode::NOT_FOUND).into(), "HTTP Status Code 404 Not Found"),
+            (Response::builder().status(1000).body(()).unwrap_err().into(), "HTTP Error"),
+            (hyper_error().into(), "Hyper Error"),
+            (decrypt_error().into(), "ChaCha20-Poly1305-AEAD Error"),
+            (String::from_utf8(vec![0xff]).unwrap_err().utf8_error().into(), "UTF-8 Error"),
+            (eui48::MacAddress::parse_str("x").unwrap_err().into(), "MAC Address Parse Error"),
+            ("x".parse::<u8>().unwrap_err().into(), "Parse Int Error"),
+            (uuid::Uuid::parse_str("x").unwrap_err().into(), "UUID Parse Error"),
+            (sender.send(()).unwrap_err().into(), "MPSC Send Error"),
+            (cell.try_borrow().unwrap_err().into(), "Borrow Error"),
+            (cell.try_borrow_mut().unwrap_err().into(), "Borrow Mut Error"),
+            (ErrorKind::InvalidValue.into(), "Invalid Value"),
+            (ErrorKind::AccessoryNotFound { aid: 1 }.into(), "Accessory 1 not found"),
+            (ErrorKind::ServiceNotFound { aid: 1, iid: 2 }.into(), "Service 1.2 not found"),
+            (ErrorKind::CharacteristicNotFound { aid: 1, iid: 3 }.into(), "Characteristic 1.3 not found"),
+            (ErrorKind::InvalidPin("12345678".into()).into(), "Invalid Pin: 12345678"),
+            (ErrorKind::Pairing("no admin".into()).into(), "Pairing Error: no admin"),
+            (ErrorKind::Storage("disk full".into()).into(), "Storage Error: disk full"),
+            (Error::from_str("unknown"), "Error unknown"),
+        ];
+
+        for (err, message) in errors {
+            assert_eq!(err.to_string(), format!("hap error: {}", message));
+            if let Some(source) = err.source() {
+                assert!(!err.to_string().contains(&source.to_string()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_source() {
+        let err = Error::from(io::Error::new(io::ErrorKind::Other, "disk full"));
+        assert_eq!(err.source().unwrap().to_string(), "disk full");
+
+        assert!(Error::from(ErrorKind::InvalidValue).source().is_none());
+    }
+}
@@ -1,25 +1,73 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    fmt,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
 
 use serde_json::Value;
+use uuid::Uuid;
 
+use crate::{transport::bonjour::StatusFlag, HapType};
+
+/// Events emitted by a transport, e.g. on pairing changes or value changes of characteristics.
+#[derive(Debug)]
 pub enum Event {
     DevicePaired,
     DeviceUnpaired,
-    CharacteristicValueChanged { aid: u64, iid: u64, value: Value },
+    ControllerUnpaired { id: Uuid },
+    FactoryReset,
+    /// A pair setup attempt failed due to a wrong setup code or signature. Once `attempts` reaches
+    /// 100, pair setup is refused until the accessory is factory reset.
+    PairSetupFailed { attempts: u64 },
+    /// A connection was closed after being idle for `Config::connection_idle_timeout`.
+    ConnectionIdleTimeout { remote_addr: SocketAddr },
+    /// The instance name announced via mDNS changed to `name`, because another device on the
+    /// network announces the previous one. See `Config::mdns_name`.
+    MdnsNameChanged { name: String },
+    /// The status flag announced via mDNS changed, e.g. to `StatusFlag::Zero` after the first
+    /// controller was paired or to `StatusFlag::NotPaired` after the last one was removed.
+    StatusFlagChanged { status_flag: StatusFlag },
+    /// The TXT records announced via mDNS were updated, e.g. after a change of the status flag or
+    /// the configuration number.
+    TxtRecordsUpdated { txt_records: [String; 9] },
+    CharacteristicValueChanged { aid: u64, iid: u64, hap_type: HapType, value: Value },
+    CharacteristicRemoved { aid: u64, iid: u64 },
 }
 
+/// ID of a listener added to an `EventEmitter`, to remove it again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ListenerId(u64);
+
 #[derive(Default)]
 pub struct EventEmitter {
-    listeners: Vec<Box<dyn Fn(&Event) + Send>>,
+    listeners: Vec<(ListenerId, Box<dyn Fn(&Event) + Send>)>,
+    next_id: u64,
+}
+
+impl fmt::Debug for EventEmitter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventEmitter")
+            .field("listeners", &self.listeners.len())
+            .finish()
+    }
 }
 
 impl EventEmitter {
-    pub fn new() -> EventEmitter { EventEmitter { listeners: vec![] } }
+    pub fn new() -> EventEmitter { EventEmitter::default() }
+
+    /// Adds a listener and returns its ID, e.g. to remove the listener of a closed connection.
+    pub fn add_listener(&mut self, listener: Box<dyn Fn(&Event) + Send>) -> ListenerId {
+        let id = ListenerId(self.next_id);
+        self.next_id += 1;
+        self.listeners.push((id, listener));
+        id
+    }
 
-    pub fn add_listener(&mut self, listener: Box<dyn Fn(&Event) + Send>) { self.listeners.push(listener); }
+    /// Removes the listener with the given ID. Removing a listener twice does nothing.
+    pub fn remove_listener(&mut self, id: ListenerId) { self.listeners.retain(|(i, _)| *i != id); }
 
     pub fn emit(&self, event: &Event) {
-        for listener in &self.listeners {
+        for (_, listener) in &self.listeners {
             listener(&event);
         }
     }
@@ -30,3 +78,32 @@ pub type EventEmitterPtr = Arc<Mutex<EventEmitter>>;
 
 /// Pointer to a list of event subscriptions.
 pub type EventSubscriptions = Arc<Mutex<Vec<(u64, u64)>>>;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_remove_listener() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut event_emitter = EventEmitter::new();
+        let ids: Vec<ListenerId> = (0..2)
+            .map(|_| {
+                let calls = calls.clone();
+                event_emitter.add_listener(Box::new(move |_| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                }))
+            })
+            .collect();
+
+        event_emitter.emit(&Event::DevicePaired);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        event_emitter.remove_listener(ids[0]);
+        event_emitter.remove_listener(ids[0]);
+        event_emitter.emit(&Event::DevicePaired);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -7,14 +7,18 @@ pub mod protocol;
 pub mod transport;
 
 mod config;
+mod crypto;
 mod error;
 mod event;
 mod hap_type;
 mod pin;
+#[cfg(test)]
+mod test_util;
 
 pub use crate::{
     config::Config,
     error::{Error, ErrorKind},
+    event::Event,
     hap_type::HapType,
 };
 
@@ -1,4 +1,4 @@
-use crate::{Error, Result};
+use crate::{ErrorKind, Result};
 
 pub type Pin = String;
 
@@ -9,14 +9,14 @@ const INVALID_PINS: [&'static str; 12] = [
 
 pub fn new(input: &str) -> Result<Pin> {
     if INVALID_PINS.contains(&input) {
-        return Err(Error::from_str("invalid pin - too easy"));
+        return Err(ErrorKind::InvalidPin("too easy".into()).into());
     }
     if input.chars().count() != 8 {
-        return Err(Error::from_str("pin must be 8 characters long"));
+        return Err(ErrorKind::InvalidPin("must be 8 characters long".into()).into());
     }
     for digit in input.chars() {
         if digit < '0' || digit > '9' {
-            return Err(Error::from_str("pin must only contain numbers"));
+            return Err(ErrorKind::InvalidPin("must only contain numbers".into()).into());
         }
     }
 
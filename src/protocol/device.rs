@@ -8,6 +8,7 @@ use serde::{
     Deserialize,
     Serialize,
 };
+use uuid::Uuid;
 
 use crate::{
     db::{Database, DatabasePtr},
@@ -25,6 +26,15 @@ pub struct Device {
     pub public_key: [u8; 32],
 }
 
+impl fmt::Debug for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Device")
+            .field("id", &self.id)
+            .field("public_key", &self.public_key)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Device {
     /// Creates a new `Device` with a given key pair.
     pub fn new(id: String, pin: Pin, private_key: [u8; 64], public_key: [u8; 32]) -> Device {
@@ -38,7 +48,7 @@ impl Device {
 
     /// Creates a new `Device` generating a random key pair.
     pub fn new_random(id: String, pin: Pin) -> Device {
-        let (private_key, public_key) = generate_key_pair();
+        let (private_key, public_key) = Device::generate_keypair();
         Device {
             id,
             pin,
@@ -71,6 +81,34 @@ impl Device {
         Ok(())
     }
 
+    /// Generates a random Ed25519 key pair in the format `(private_key, public_key)`.
+    pub fn generate_keypair() -> ([u8; 64], [u8; 32]) {
+        let mut rng = rand::thread_rng();
+        let seed = rng.gen::<[u8; 32]>();
+        ed25519::keypair(&seed)
+    }
+
+    /// Replaces the long-term key pair of the `Device` with a newly generated one, saves it to a
+    /// database and removes all existing pairings, as they're bound to the previous key pair.
+    /// Returns the IDs of the removed pairings.
+    pub fn rotate_keypair(&mut self, database: &DatabasePtr) -> Result<Vec<Uuid>> {
+        let (private_key, public_key) = Device::generate_keypair();
+        self.private_key = private_key;
+        self.public_key = public_key;
+
+        let database = database.lock().expect("couldn't access database");
+        database.set_device(self)?;
+        let mut removed = Vec::new();
+        for pairing in database.list_pairings()? {
+            database.delete_pairing(&pairing.id)?;
+            removed.push(pairing.id);
+        }
+        Ok(removed)
+    }
+
+    /// Returns the long-term public key of the `Device`.
+    pub fn public_key(&self) -> &[u8; 32] { &self.public_key }
+
     /// Serializes a `Device` to a `Vec<u8>`.
     pub fn as_bytes(&self) -> Result<Vec<u8>> {
         let value = serde_json::to_vec(&self)?;
@@ -84,12 +122,6 @@ impl Device {
     }
 }
 
-fn generate_key_pair() -> ([u8; 64], [u8; 32]) {
-    let mut rng = rand::thread_rng();
-    let seed = rng.gen::<[u8; 32]>();
-    ed25519::keypair(&seed)
-}
-
 // see https://github.com/serde-rs/serde/issues/631
 trait BigArray<'de>: Sized {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
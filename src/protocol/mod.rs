@@ -5,7 +5,8 @@ mod pairing;
 
 pub use self::{
     device::Device,
-    pairing::{Pairing, Permissions},
+    pairing::{ControllerInfo, Pairing, Permissions},
+    tlv::{Method as PairingMethod, Type as TlvType},
 };
 
 pub(crate) use self::pairing::IdPtr;
@@ -3,7 +3,7 @@ use std::sync::{Arc, Mutex};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{db::DatabasePtr, Error, Result};
+use crate::{db::DatabasePtr, ErrorKind, Result};
 
 /// `Pairing` represents paired controllers.
 #[derive(Debug, Serialize, Deserialize)]
@@ -47,6 +47,27 @@ impl Pairing {
     }
 }
 
+/// Information about a paired controller.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControllerInfo {
+    /// Pairing identifier of the controller.
+    pub id: String,
+    /// Long-term public key of the controller.
+    pub public_key: Vec<u8>,
+    /// Specifies if the controller has admin permissions.
+    pub is_admin: bool,
+}
+
+impl From<Pairing> for ControllerInfo {
+    fn from(pairing: Pairing) -> ControllerInfo {
+        ControllerInfo {
+            id: pairing.id.to_hyphenated().to_string(),
+            public_key: pairing.public_key.to_vec(),
+            is_admin: pairing.permissions == Permissions::Admin,
+        }
+    }
+}
+
 /// The permissions of a paired controller.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum Permissions {
@@ -62,7 +83,7 @@ impl Permissions {
         match u {
             0x00 => Ok(Permissions::User),
             0x01 => Ok(Permissions::Admin),
-            _ => Err(Error::from_str("invalid permission Byte")),
+            _ => Err(ErrorKind::Pairing("invalid permission Byte".into()).into()),
         }
     }
 
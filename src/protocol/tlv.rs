@@ -81,8 +81,10 @@ pub trait Encodable {
     fn encode(self) -> Vec<u8>;
 }
 
-/// `Type` represents the TLV types defined by the protocol.
-#[derive(Copy, Clone)]
+/// `Type` represents the TLV types of the pairing protocol, i.e. of Pair Setup, Pair Verify and
+/// the Pairings requests. Casting a variant `as u8` gives the type byte, e.g. to look up items of
+/// a `characteristic::tlv8::Tlv8`. Re-exported as `protocol::TlvType`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Type {
     Method = 0x00,
     Identifier = 0x01,
@@ -98,6 +100,7 @@ pub enum Type {
     Permissions = 0x0B,
     FragmentData = 0x0C,
     FragmentLast = 0x0D,
+    SessionId = 0x0E,
     Separator = 0xFF,
 }
 
@@ -118,6 +121,7 @@ pub enum Value {
     Permissions(Permissions),
     FragmentData(Vec<u8>),
     FragmentLast(Vec<u8>),
+    SessionId(Vec<u8>),
     Separator,
 }
 
@@ -144,6 +148,7 @@ impl Value {
             Value::Permissions(permissions) => (Type::Permissions as u8, vec![permissions.as_u8()]),
             Value::FragmentData(fragment_data) => (Type::FragmentData as u8, fragment_data),
             Value::FragmentLast(fragment_last) => (Type::FragmentLast as u8, fragment_last),
+            Value::SessionId(session_id) => (Type::SessionId as u8, session_id),
             Value::Separator => (Type::Separator as u8, vec![0x00]),
         }
     }
@@ -156,14 +161,17 @@ impl Value {
     }
 }
 
+/// `Method` represents the values of the `Type::Method` item of the pairing protocol. Re-exported
+/// as `protocol::PairingMethod`.
 #[allow(dead_code)]
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Method {
     PairSetup = 1,
     PairVerify = 2,
     AddPairing = 3,
     RemovePairing = 4,
     ListPairings = 5,
+    PairResume = 6,
 }
 
 #[allow(dead_code)]
@@ -232,10 +240,27 @@ impl Encodable for Container {
 pub struct ErrorContainer {
     step: u8,
     error: Error,
+    retry_delay: Option<usize>,
 }
 
 impl ErrorContainer {
-    pub fn new(step: u8, error: Error) -> ErrorContainer { ErrorContainer { step, error } }
+    pub fn new(step: u8, error: Error) -> ErrorContainer {
+        ErrorContainer {
+            step,
+            error,
+            retry_delay: None,
+        }
+    }
+
+    /// Creates a new `ErrorContainer` holding a `Backoff` error and the number of seconds the client
+    /// has to wait before retrying.
+    pub fn backoff(step: u8, retry_delay: usize) -> ErrorContainer {
+        ErrorContainer {
+            step,
+            error: Error::Backoff,
+            retry_delay: Some(retry_delay),
+        }
+    }
 }
 
 impl Encodable for ErrorContainer {
@@ -243,6 +268,9 @@ impl Encodable for ErrorContainer {
         let mut map = HashMap::new();
         Value::State(self.step).into_map(&mut map);
         Value::Error(self.error).into_map(&mut map);
+        if let Some(retry_delay) = self.retry_delay {
+            Value::RetryDelay(retry_delay).into_map(&mut map);
+        }
         encode(map)
     }
 }
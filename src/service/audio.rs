@@ -0,0 +1,82 @@
+use crate::{
+    characteristic::volume::{self, Volume},
+    service::{microphone::Microphone, speaker::Speaker},
+    Error,
+    ErrorKind,
+    Result,
+};
+
+/// Maximum value of the Volume Characteristic.
+const MAX_VOLUME: u8 = 100;
+
+/// Options of the audio Services.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Options {
+    /// Whether the optional Volume Characteristic is added to the Service. Without it, the Service
+    /// can only be muted and unmuted. Defaults to `true`.
+    pub with_volume: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options { Options { with_volume: true } }
+}
+
+/// Creates a new Speaker Service with the given `Options`.
+///
+/// # Examples
+///
+/// ```
+/// use hap::service::audio;
+///
+/// let mut speaker = audio::new_speaker(audio::Options::default());
+/// speaker.set_volume(40).unwrap();
+/// speaker.set_muted(true).unwrap();
+/// assert!(speaker.set_volume(120).is_err());
+///
+/// let mut mute_only = audio::new_speaker(audio::Options { with_volume: false });
+/// assert!(mute_only.set_volume(40).is_err());
+/// ```
+pub fn new_speaker(options: Options) -> Speaker {
+    let mut speaker = Speaker::default();
+    if options.with_volume {
+        speaker.inner.volume = Some(volume::new());
+    }
+    speaker
+}
+
+/// Creates a new Microphone Service with the given `Options`.
+pub fn new_microphone(options: Options) -> Microphone {
+    let mut microphone = Microphone::default();
+    if options.with_volume {
+        microphone.inner.volume = Some(volume::new());
+    }
+    microphone
+}
+
+impl Speaker {
+    /// Sets the Mute Characteristic of the Speaker.
+    pub fn set_muted(&mut self, muted: bool) -> Result<()> { self.inner.mute.set_value(muted) }
+
+    /// Sets the Volume Characteristic of the Speaker to a value from `0` to `100`. Fails if the
+    /// value is out of range or the Speaker has no Volume Characteristic.
+    pub fn set_volume(&mut self, volume: u8) -> Result<()> { set_volume(self.inner.volume.as_mut(), volume) }
+}
+
+impl Microphone {
+    /// Sets the Mute Characteristic of the Microphone.
+    pub fn set_muted(&mut self, muted: bool) -> Result<()> { self.inner.mute.set_value(muted) }
+
+    /// Sets the Volume Characteristic of the Microphone to a value from `0` to `100`. Fails if the
+    /// value is out of range or the Microphone has no Volume Characteristic.
+    pub fn set_volume(&mut self, volume: u8) -> Result<()> { set_volume(self.inner.volume.as_mut(), volume) }
+}
+
+fn set_volume(characteristic: Option<&mut Volume>, volume: u8) -> Result<()> {
+    if volume > MAX_VOLUME {
+        return Err(ErrorKind::InvalidValue.into());
+    }
+    match characteristic {
+        Some(characteristic) => characteristic.set_value(volume),
+        None => Err(Error::from_str("the Service has no Volume Characteristic")),
+    }
+}
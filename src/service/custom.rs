@@ -0,0 +1,112 @@
+use uuid::Uuid;
+
+use crate::{
+    characteristic::HapCharacteristic,
+    service::{HapService, Service},
+    Error,
+    HapType,
+    Result,
+};
+
+/// Custom Service.
+pub type Custom = Service<CustomInner>;
+
+/// Inner type of a Custom Service.
+#[derive(Debug, Default)]
+pub struct CustomInner {
+    /// ID of the Custom Service.
+    id: u64,
+    /// `HapType` of the Custom Service.
+    hap_type: HapType,
+    /// Specifies if the Service is hidden.
+    hidden: bool,
+    /// Specifies if the Service is the primary Service of the Accessory.
+    primary: bool,
+    /// IDs of the Services linked to the Service.
+    linked_services: Vec<u64>,
+
+    /// Characteristics of the Custom Service.
+    characteristics: Vec<Box<dyn HapCharacteristic + Send>>,
+}
+
+impl HapService for CustomInner {
+    fn get_id(&self) -> u64 { self.id }
+
+    fn set_id(&mut self, id: u64) { self.id = id; }
+
+    fn get_type(&self) -> HapType { self.hap_type }
+
+    fn get_hidden(&self) -> bool { self.hidden }
+
+    fn set_hidden(&mut self, hidden: bool) { self.hidden = hidden; }
+
+    fn get_primary(&self) -> bool { self.primary }
+
+    fn set_primary(&mut self, primary: bool) { self.primary = primary; }
+
+    fn get_linked_services(&self) -> Vec<u64> { self.linked_services.clone() }
+
+    fn set_linked_services(&mut self, linked_services: Vec<u64>) { self.linked_services = linked_services; }
+
+    fn get_characteristics(&self) -> Vec<&dyn HapCharacteristic> {
+        let mut characteristics: Vec<&dyn HapCharacteristic> = Vec::new();
+        for c in &self.characteristics {
+            characteristics.push(c.as_ref());
+        }
+        characteristics
+    }
+
+    fn get_mut_characteristics(&mut self) -> Vec<&mut dyn HapCharacteristic> {
+        let mut characteristics: Vec<&mut dyn HapCharacteristic> = Vec::new();
+        for c in &mut self.characteristics {
+            characteristics.push(c.as_mut());
+        }
+        characteristics
+    }
+
+    fn add_characteristic(&mut self, characteristic: Box<dyn HapCharacteristic + Send>) {
+        self.characteristics.push(characteristic);
+    }
+
+    fn remove_characteristic(&mut self, id: u64) -> Result<()> {
+        for (i, c) in self.characteristics.iter().enumerate() {
+            if c.get_id()? == id {
+                self.characteristics.remove(i);
+                return Ok(());
+            }
+        }
+        Err(Error::from_str("couldn't find the Characteristic to remove"))
+    }
+}
+
+/// Creates a new Custom Service with a vendor-specific UUID like
+/// `"e863f007-079e-48ff-8f27-9c2605a29f52"` and the given Characteristics.
+///
+/// # Examples
+///
+/// ```
+/// use hap::{
+///     accessory::{outlet, HapAccessory, Information},
+///     characteristic::{Characteristic, Format, Perm},
+///     service::custom,
+/// };
+///
+/// let total_consumption = Characteristic::<f32>::new_custom(
+///     "e863f10c-079e-48ff-8f27-9c2605a29f52",
+///     Format::Float,
+///     vec![Perm::PairedRead, Perm::Events],
+/// )
+/// .unwrap();
+/// let energy_metering =
+///     custom::new("e863f007-079e-48ff-8f27-9c2605a29f52", vec![Box::new(total_consumption)]).unwrap();
+///
+/// let mut outlet = outlet::new(Information::default()).unwrap();
+/// outlet.add_service(Box::new(energy_metering));
+/// ```
+pub fn new(uuid: &str, characteristics: Vec<Box<dyn HapCharacteristic + Send>>) -> Result<Custom> {
+    Ok(Custom::new(CustomInner {
+        hap_type: HapType::Custom(Uuid::parse_str(uuid)?),
+        characteristics,
+        ..Default::default()
+    }))
+}
@@ -1,12 +1,18 @@
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 
-use crate::{characteristic::HapCharacteristic, HapType};
+use crate::{characteristic::HapCharacteristic, HapType, Result};
 
 mod generated;
 
+pub mod audio;
+pub mod custom;
+
 pub use crate::service::generated::*;
 
-/// `HapService` is implemented by the inner type of every `Service`.
+/// `HapService` is implemented by the inner type of every `Service`. Implementing it for a type of
+/// your own and wrapping that in a `Service` defines a custom Service, e.g. a vendor-specific one,
+/// that can be added to an Accessory via `HapAccessory::add_service`. See the `energy_monitor`
+/// example.
 pub trait HapService {
     /// Returns the ID of a Service.
     fn get_id(&self) -> u64;
@@ -16,38 +22,89 @@ pub trait HapService {
     fn get_type(&self) -> HapType;
     /// Returns the hidden value of a Service.
     fn get_hidden(&self) -> bool;
-    /// Sets the hidden value of a Service.
+    /// Sets the hidden value of a Service. Hidden Services are still readable and writable via
+    /// `/characteristics`, but aren't presented to the user.
     fn set_hidden(&mut self, hidden: bool);
     /// Returns the primary value of a Service.
     fn get_primary(&self) -> bool;
-    /// Sets the primary value of a Service.
+    /// Sets the primary value of a Service. The primary Service determines how a multi-Service
+    /// Accessory is presented to the user.
     fn set_primary(&mut self, primary: bool);
+    /// Returns the IDs of the Services linked to a Service.
+    fn get_linked_services(&self) -> Vec<u64>;
+    /// Sets the IDs of the Services linked to a Service.
+    fn set_linked_services(&mut self, linked_services: Vec<u64>);
+    /// Links the Service with the given ID to a Service, e.g. an Input Source to a Television. The
+    /// links are part of the attribute database as the `linked` array of the Service.
+    ///
+    /// IDs of Services not yet added to a transport are preliminary. If they are unique and
+    /// non-zero, links to them are rewritten to the assigned IDs once the Accessory is added.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::{
+    ///     accessory::{television, Information},
+    ///     service::{input_source, HapService},
+    /// };
+    /// use serde_json::json;
+    ///
+    /// let mut tv = television::new(Information::default()).unwrap();
+    /// let mut input = input_source::new();
+    /// input.set_id(1);
+    /// tv.inner.television.add_linked_service(1);
+    /// tv.inner.inputs.push(input);
+    ///
+    /// let attribute_db = serde_json::to_value(&tv).unwrap();
+    /// assert_eq!(attribute_db["services"][1]["linked"], json!([1]));
+    /// ```
+    fn add_linked_service(&mut self, id: u64) {
+        let mut linked_services = self.get_linked_services();
+        if !linked_services.contains(&id) {
+            linked_services.push(id);
+            self.set_linked_services(linked_services);
+        }
+    }
     /// Returns references to the Characteristics of a Service.
     fn get_characteristics(&self) -> Vec<&dyn HapCharacteristic>;
     /// Returns mutable references to the Characteristics of a Service.
     fn get_mut_characteristics(&mut self) -> Vec<&mut dyn HapCharacteristic>;
+    /// Adds a Characteristic to a Service. Characteristics added to a Service that is already
+    /// part of a running transport should be added via `Transport::add_characteristic` instead, so
+    /// they are assigned an instance ID.
+    fn add_characteristic(&mut self, characteristic: Box<dyn HapCharacteristic + Send>);
+    /// Removes the optional or added Characteristic with the given ID from a Service.
+    fn remove_characteristic(&mut self, id: u64) -> Result<()>;
 }
 
 /// A Service. Services group functionality in order to provide context. They are comprised of
 /// characteristics.
+#[derive(Debug)]
 pub struct Service<T: HapService> {
     pub inner: T,
 }
 
 impl<T: HapService> Service<T> {
-    /// Creates a new `Service`.
-    fn new(inner: T) -> Service<T> { Service { inner } }
+    /// Creates a new `Service` from its inner type.
+    pub fn new(inner: T) -> Service<T> { Service { inner } }
 }
 
 impl<T: HapService> Serialize for Service<T> {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
-        let mut state = serializer.serialize_struct("HapService", 5)?;
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("HapService", 6)?;
         state.serialize_field("iid", &self.get_id())?;
         state.serialize_field("type", &self.get_type())?;
-        state.serialize_field("hidden", &self.get_hidden())?;
-        state.serialize_field("primary", &self.get_primary())?;
+        if self.get_hidden() {
+            state.serialize_field("hidden", &true)?;
+        }
+        if self.get_primary() {
+            state.serialize_field("primary", &true)?;
+        }
         state.serialize_field("characteristics", &self.get_characteristics())?;
-        // linked services left out for now
+        let linked_services = self.get_linked_services();
+        if !linked_services.is_empty() {
+            state.serialize_field("linked", &linked_services)?;
+        }
         state.end()
     }
 }
@@ -67,7 +124,17 @@ impl<T: HapService> HapService for Service<T> {
 
     fn set_primary(&mut self, primary: bool) { self.inner.set_primary(primary) }
 
+    fn get_linked_services(&self) -> Vec<u64> { self.inner.get_linked_services() }
+
+    fn set_linked_services(&mut self, linked_services: Vec<u64>) { self.inner.set_linked_services(linked_services) }
+
     fn get_characteristics(&self) -> Vec<&dyn HapCharacteristic> { self.inner.get_characteristics() }
 
     fn get_mut_characteristics(&mut self) -> Vec<&mut dyn HapCharacteristic> { self.inner.get_mut_characteristics() }
+
+    fn add_characteristic(&mut self, characteristic: Box<dyn HapCharacteristic + Send>) {
+        self.inner.add_characteristic(characteristic)
+    }
+
+    fn remove_characteristic(&mut self, id: u64) -> Result<()> { self.inner.remove_characteristic(id) }
 }
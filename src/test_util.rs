@@ -0,0 +1,24 @@
+use std::{env, fs, path::PathBuf};
+
+use uuid::Uuid;
+
+/// Decodes a hex string, e.g. of a test vector.
+pub(crate) fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+/// A unique storage path in the temp dir, removed with its contents on drop.
+pub(crate) struct TempStorage(PathBuf);
+
+impl TempStorage {
+    pub fn new() -> TempStorage { TempStorage(env::temp_dir().join(format!("hap-test-{}", Uuid::new_v4()))) }
+
+    pub fn path(&self) -> &str { self.0.to_str().unwrap() }
+}
+
+impl Drop for TempStorage {
+    fn drop(&mut self) { let _ = fs::remove_dir_all(&self.0); }
+}
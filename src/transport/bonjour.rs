@@ -1,12 +1,12 @@
 /// Bonjour Feature Flag.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub enum FeatureFlag {
     Zero = 0,
     MfiCompliant = 1,
 }
 
 /// Bonjour Status Flag.
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum StatusFlag {
     Zero = 0,
     NotPaired = 1,
@@ -12,15 +12,16 @@ use crate::{
         handler::JsonHandler,
         json_response,
         server::EventSubscriptions,
+        status_json_response,
         status_response,
         CharacteristicResponseBody,
         ReadResponseObject,
         Status,
-        WriteObject,
+        TimedWrite,
+        TimedWritePtr,
+        WriteRequestBody,
         WriteResponseObject,
     },
-    Error,
-    ErrorKind,
     Result,
 };
 
@@ -36,7 +37,7 @@ impl JsonHandler for GetCharacteristics {
         uri: Uri,
         _: Vec<u8>,
         _: &IdPtr,
-        _: &EventSubscriptions,
+        event_subscriptions: &EventSubscriptions,
         _: &ConfigPtr,
         _: &DatabasePtr,
         accessories: &AccessoryList,
@@ -54,19 +55,22 @@ impl JsonHandler for GetCharacteristics {
                 queries.insert(key.into(), val.into());
             }
             let (f_meta, f_perms, f_type, f_ev) = check_flags(&queries);
-            let q_id = queries
-                .get("id")
-                .ok_or(Error::new(ErrorKind::HttpStatus(StatusCode::BAD_REQUEST)))?;
-            let ids = q_id.split(',').collect::<Vec<&str>>();
-            for id in ids {
-                let id_pair = id.split('.').collect::<Vec<&str>>();
-                if id_pair.len() != 2 {
-                    return Err(ErrorKind::HttpStatus(StatusCode::BAD_REQUEST).into());
-                }
-                let aid = id_pair[0].parse::<u64>()?;
-                let iid = id_pair[1].parse::<u64>()?;
-
-                let res_object = match accessories.read_characteristic(aid, iid, f_meta, f_perms, f_type, f_ev) {
+            let ids = match queries.get("id").map(|q_id| parse_ids(q_id)) {
+                Some(Some(ids)) => ids,
+                _ => {
+                    return status_json_response(Status::InvalidValueInRequest, StatusCode::BAD_REQUEST);
+                },
+            };
+            for (aid, iid) in ids {
+
+                let res_object = match accessories.read_characteristic(
+                    aid,
+                    iid,
+                    f_meta,
+                    f_perms,
+                    f_type,
+                    if f_ev { Some(event_subscriptions) } else { None },
+                ) {
                     Ok(mut res_object) => {
                         if res_object.status != Some(0) {
                             some_err = true;
@@ -104,6 +108,19 @@ impl JsonHandler for GetCharacteristics {
     }
 }
 
+/// Parses a list of IDs like `1.4,1.5,2.8` into pairs of Accessory and Characteristic instance IDs.
+/// Returns `None` if any of the IDs is malformed.
+fn parse_ids(q_id: &str) -> Option<Vec<(u64, u64)>> {
+    q_id.split(',')
+        .map(|id| {
+            let mut id_pair = id.splitn(2, '.');
+            let aid = id_pair.next()?.parse::<u64>().ok()?;
+            let iid = id_pair.next()?.parse::<u64>().ok()?;
+            Some((aid, iid))
+        })
+        .collect()
+}
+
 fn check_flags(flags: &HashMap<String, String>) -> (bool, bool, bool, bool) {
     let true_val = "1".to_string();
     (
@@ -114,10 +131,12 @@ fn check_flags(flags: &HashMap<String, String>) -> (bool, bool, bool, bool) {
     )
 }
 
-pub struct UpdateCharacteristics {}
+pub struct UpdateCharacteristics {
+    timed_write: TimedWritePtr,
+}
 
 impl UpdateCharacteristics {
-    pub fn new() -> UpdateCharacteristics { UpdateCharacteristics {} }
+    pub fn new(timed_write: TimedWritePtr) -> UpdateCharacteristics { UpdateCharacteristics { timed_write } }
 }
 
 impl JsonHandler for UpdateCharacteristics {
@@ -132,31 +151,46 @@ impl JsonHandler for UpdateCharacteristics {
         accessories: &AccessoryList,
         _: &EventEmitterPtr,
     ) -> Result<Response<Body>> {
-        let write_body: CharacteristicResponseBody<WriteObject> = serde_json::from_slice(&body)?;
+        let write_body: WriteRequestBody = match serde_json::from_slice(&body) {
+            Ok(write_body) => write_body,
+            Err(_) => return status_json_response(Status::InvalidValueInRequest, StatusCode::BAD_REQUEST),
+        };
+        // a prepared timed write is only valid for the next write request carrying its pid
+        let timed_write = if write_body.pid.is_some() || write_body.characteristics.iter().any(|c| c.pid.is_some()) {
+            self.timed_write.lock().expect("couldn't access timed_write").take()
+        } else {
+            None
+        };
         let mut resp_body = CharacteristicResponseBody::<WriteResponseObject> {
             characteristics: Vec::new(),
         };
         let mut some_err = false;
-        let mut all_err = true;
+        let mut some_value = false;
 
         for c in write_body.characteristics {
             let iid = c.iid;
             let aid = c.aid;
-            let res_object = match accessories.write_characteristic(c, event_subscriptions) {
+            let timed_write_status = TimedWrite::check(timed_write.as_ref(), c.pid.or(write_body.pid));
+            let res_object = match accessories.write_characteristic(c, event_subscriptions, timed_write_status) {
                 Ok(res_object) => {
-                    if res_object.status != 0 {
+                    if res_object.status != Status::Success as i32 {
                         some_err = true;
-                    } else {
-                        all_err = false;
+                    }
+                    if res_object.value.is_some() {
+                        some_value = true;
                     }
                     res_object
                 },
-                Err(_) => {
+                Err(_err) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::event!(tracing::Level::WARN, aid, iid, error = %_err, "characteristic write failed");
+
                     some_err = true;
                     WriteResponseObject {
                         iid,
                         aid,
                         status: Status::ServiceCommunicationFailure as i32,
+                        value: None,
                     }
                 },
             };
@@ -164,10 +198,11 @@ impl JsonHandler for UpdateCharacteristics {
             resp_body.characteristics.push(res_object);
         }
 
-        if all_err {
-            let res = serde_json::to_vec(&resp_body)?;
-            json_response(res, StatusCode::BAD_REQUEST)
-        } else if some_err {
+        // a batch with any failed write is answered with the status of every write, including the
+        // successful ones
+        if resp_body.characteristics.is_empty() {
+            status_response(StatusCode::BAD_REQUEST)
+        } else if some_err || some_value {
             let res = serde_json::to_vec(&resp_body)?;
             json_response(res, StatusCode::MULTI_STATUS)
         } else {
@@ -175,3 +210,122 @@ impl JsonHandler for UpdateCharacteristics {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::{Future, Stream};
+    use serde_json::{json, Value as JsonValue};
+
+    use super::*;
+    use crate::{
+        accessory::{outlet, Information},
+        db::Database,
+        event::EventEmitter,
+        HapType,
+    };
+
+    struct Fixture {
+        accessories: AccessoryList,
+        on_iid: u64,
+        outlet_in_use_iid: u64,
+    }
+
+    fn fixture() -> Fixture {
+        let mut accessories = AccessoryList::new(Arc::new(Mutex::new(EventEmitter::new())));
+        let outlet = accessories
+            .add_accessory(Box::new(outlet::new(Information::default()).unwrap()))
+            .unwrap();
+        let iid = |hap_type| {
+            outlet
+                .lock()
+                .unwrap()
+                .get_services()
+                .into_iter()
+                .flat_map(|s| s.get_characteristics())
+                .find(|c| c.get_type().unwrap() == hap_type)
+                .unwrap()
+                .get_id()
+                .unwrap()
+        };
+        let (on_iid, outlet_in_use_iid) = (iid(HapType::On), iid(HapType::OutletInUse));
+        Fixture {
+            accessories,
+            on_iid,
+            outlet_in_use_iid,
+        }
+    }
+
+    /// Writes the given values to the Characteristics of the Outlet and returns the status code and
+    /// the parsed body of the response.
+    fn write(fixture: &Fixture, values: Vec<(u64, JsonValue)>) -> (StatusCode, JsonValue) {
+        let characteristics = values
+            .into_iter()
+            .map(|(iid, value)| json!({ "aid": 1, "iid": iid, "value": value }))
+            .collect::<Vec<_>>();
+        let body = serde_json::to_vec(&json!({ "characteristics": characteristics })).unwrap();
+
+        let response = UpdateCharacteristics::new(Arc::new(Mutex::new(None)))
+            .handle(
+                Uri::default(),
+                body,
+                &Arc::new(Mutex::new(None)),
+                &Arc::new(Mutex::new(Vec::new())),
+                &Arc::new(Mutex::new(Default::default())),
+                &Arc::new(Mutex::new(Database::new_with_memory_storage())),
+                &fixture.accessories,
+                &Arc::new(Mutex::new(EventEmitter::new())),
+            )
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().concat2().wait().unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(JsonValue::Null))
+    }
+
+    /// Returns the status of every write in a multi-status response body by instance ID.
+    fn statuses(body: &JsonValue) -> HashMap<u64, i64> {
+        body["characteristics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| (c["iid"].as_u64().unwrap(), c["status"].as_i64().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_update_characteristics_all_succeeding() {
+        let fixture = fixture();
+
+        let (status, body) = write(&fixture, vec![(fixture.on_iid, json!(true))]);
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert_eq!(body, JsonValue::Null);
+    }
+
+    #[test]
+    fn test_update_characteristics_all_failing() {
+        let fixture = fixture();
+
+        let (status, body) = write(&fixture, vec![(fixture.outlet_in_use_iid, json!(true)), (1000, json!(true))]);
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        let statuses = statuses(&body);
+        assert_eq!(statuses.len(), 2);
+        assert_ne!(statuses[&fixture.outlet_in_use_iid], Status::Success as i64);
+        assert_ne!(statuses[&1000], Status::Success as i64);
+    }
+
+    #[test]
+    fn test_update_characteristics_partially_failing() {
+        let fixture = fixture();
+
+        let (status, body) = write(&fixture, vec![
+            (fixture.on_iid, json!(true)),
+            (fixture.outlet_in_use_iid, json!(true)),
+        ]);
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        let statuses = statuses(&body);
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[&fixture.on_iid], Status::Success as i64);
+        assert_ne!(statuses[&fixture.outlet_in_use_iid], Status::Success as i64);
+    }
+}
@@ -0,0 +1,45 @@
+use hyper::{Body, Response, StatusCode, Uri};
+use serde_json::{self, json};
+
+use crate::{
+    config::ConfigPtr,
+    db::{AccessoryList, DatabasePtr},
+    event::EventEmitterPtr,
+    protocol::IdPtr,
+    transport::http::{handler::JsonHandler, json_response, server::EventSubscriptions},
+    Result,
+};
+
+pub struct Health;
+
+impl Health {
+    pub fn new() -> Health { Health }
+}
+
+impl JsonHandler for Health {
+    fn handle(
+        &mut self,
+        _: Uri,
+        _: Vec<u8>,
+        _: &IdPtr,
+        _: &EventSubscriptions,
+        _: &ConfigPtr,
+        database: &DatabasePtr,
+        accessory_list: &AccessoryList,
+        _: &EventEmitterPtr,
+    ) -> Result<Response<Body>> {
+        let paired = database.lock().expect("couldn't access database").count_pairings()? > 0;
+        let accessories = accessory_list
+            .accessories
+            .lock()
+            .expect("couldn't access accessory_list")
+            .len();
+
+        let body = serde_json::to_vec(&json!({
+            "status": "ok",
+            "paired": paired,
+            "accessories": accessories,
+        }))?;
+        json_response(body, StatusCode::OK)
+    }
+}
@@ -1,12 +1,17 @@
 use hyper::{Body, Response, StatusCode, Uri};
-use serde_json::{self, json};
 
 use crate::{
     config::ConfigPtr,
     db::{AccessoryList, DatabasePtr},
     event::EventEmitterPtr,
     protocol::IdPtr,
-    transport::http::{handler::JsonHandler, json_response, server::EventSubscriptions, status_response, Status},
+    transport::http::{
+        handler::JsonHandler,
+        server::EventSubscriptions,
+        status_json_response,
+        status_response,
+        Status,
+    },
     Result,
 };
 
@@ -29,23 +34,23 @@ impl JsonHandler for Identify {
         _: &EventEmitterPtr,
     ) -> Result<Response<Body>> {
         if database.lock().expect("couldn't access database").count_pairings()? > 0 {
-            let body = serde_json::to_vec(&json!({ "status": Status::InsufficientPrivileges as i32 }))?;
-            return json_response(body, StatusCode::BAD_REQUEST);
+            return status_json_response(Status::InsufficientPrivileges, StatusCode::BAD_REQUEST);
         }
 
+        // every Accessory identifies itself, even if the identify routine of another one failed
+        let mut failed = false;
         for accessory in accessory_list
             .accessories
             .lock()
             .expect("couldn't access accessory_list")
             .iter_mut()
         {
-            accessory
-                .lock()
-                .expect("couldn't access accessory")
-                .get_mut_information()
-                .inner
-                .identify
-                .set_value(true)?;
+            let mut accessory = accessory.lock().expect("couldn't access accessory");
+            accessory.get_mut_information().inner.identify.set_value(true)?;
+            failed |= accessory.identify().is_err();
+        }
+        if failed {
+            return status_json_response(Status::ServiceCommunicationFailure, StatusCode::INTERNAL_SERVER_ERROR);
         }
 
         status_response(StatusCode::NO_CONTENT)
@@ -17,10 +17,13 @@ use crate::{
 
 pub mod accessories;
 pub mod characteristics;
+pub mod health;
 pub mod identify;
 pub mod pair_setup;
 pub mod pair_verify;
 pub mod pairings;
+pub mod prepare;
+pub mod resource;
 
 pub trait Handler {
     fn handle(
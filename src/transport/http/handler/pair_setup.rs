@@ -1,22 +1,26 @@
-use std::{collections::HashMap, ops::BitXor, str};
+use std::{
+    collections::HashMap,
+    str,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use chacha20_poly1305_aead;
 use crypto::ed25519;
-use log::debug;
-use num::BigUint;
+use log::{debug, error, warn};
+use num::{BigUint, Zero};
 use rand::{self, distributions::Standard, Rng};
-use ring::{digest, hkdf, hmac};
 use sha2::{Digest, Sha512};
 use srp::{
     client::{srp_private_key, SrpClient},
     groups::G_3072,
-    server::{SrpServer, UserRecord},
     types::SrpGroup,
 };
 use uuid::Uuid;
 
 use crate::{
     config::ConfigPtr,
+    crypto::hkdf,
     db::DatabasePtr,
     event::{Event, EventEmitterPtr},
     protocol::{
@@ -37,18 +41,76 @@ struct Session {
     shared_secret: Option<Vec<u8>>,
 }
 
+/// Number of unsuccessful pair setup attempts after which pair setup is refused until the accessory
+/// is factory reset.
+const MAX_TRIES: u64 = 100;
+/// Number of unsuccessful pair setup attempts that are allowed before the client has to back off.
+const BACKOFF_THRESHOLD: u64 = 3;
+/// Maximum number of seconds a client has to back off before the next pair setup attempt.
+const MAX_BACKOFF: u64 = 60;
+
+/// Time after which a pair setup that is in progress can be taken over by another connection.
+const PAIRING_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Tracks the connection that is currently performing pair setup, so that concurrent attempts of
+/// other controllers are refused. The lock is released once the pair setup finishes or fails, its
+/// connection closes or it times out.
+#[derive(Default)]
+pub struct PairingLock {
+    owner: Option<(Weak<()>, Instant)>,
+}
+
+impl PairingLock {
+    /// Acquires the lock for a given connection token. Returns `false` if another connection holds
+    /// it.
+    fn acquire(&mut self, token: &Arc<()>) -> bool {
+        if let Some((ref owner, acquired_at)) = self.owner {
+            if let Some(owner) = owner.upgrade() {
+                if !Arc::ptr_eq(&owner, token) && acquired_at.elapsed() < PAIRING_LOCK_TIMEOUT {
+                    return false;
+                }
+            }
+        }
+        self.owner = Some((Arc::downgrade(token), Instant::now()));
+        true
+    }
+
+    /// Releases the lock if it's held by a given connection token.
+    fn release(&mut self, token: &Arc<()>) {
+        let owned = match self.owner {
+            Some((ref owner, _)) => owner.upgrade().map_or(true, |owner| Arc::ptr_eq(&owner, token)),
+            None => false,
+        };
+        if owned {
+            self.owner = None;
+        }
+    }
+}
+
+/// Pointer to the `PairingLock` shared by all connections.
+pub type PairingLockPtr = Arc<Mutex<PairingLock>>;
+
 pub struct PairSetup {
     session: Option<Session>,
-    unsuccessful_tries: u8,
+    pairing_lock: PairingLockPtr,
+    token: Arc<()>,
 }
 
 impl PairSetup {
-    pub fn new() -> PairSetup {
+    pub fn new(pairing_lock: PairingLockPtr) -> PairSetup {
         PairSetup {
             session: None,
-            unsuccessful_tries: 0,
+            pairing_lock,
+            token: Arc::new(()),
         }
     }
+
+    fn release_pairing_lock(&self) {
+        self.pairing_lock
+            .lock()
+            .expect("couldn't access pairing lock")
+            .release(&self.token);
+    }
 }
 
 enum StepNumber {
@@ -112,48 +174,118 @@ impl TlvHandler for PairSetup {
         database: &DatabasePtr,
         event_emitter: &EventEmitterPtr,
     ) -> Result<tlv::Container, tlv::ErrorContainer> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::span!(
+            tracing::Level::DEBUG,
+            "pair_setup",
+            step = match step {
+                Step::Start => "start",
+                Step::Verify { .. } => "verify",
+                Step::Exchange { .. } => "exchange",
+            }
+        );
+        #[cfg(feature = "tracing")]
+        let _enter = span.enter();
+
         match step {
-            Step::Start => match handle_start(self, database) {
-                Ok(res) => {
-                    self.unsuccessful_tries = 0;
-                    Ok(res)
-                },
-                Err(err) => {
-                    self.unsuccessful_tries += 1;
-                    Err(tlv::ErrorContainer::new(StepNumber::StartRes as u8, err))
-                },
-            },
+            Step::Start => handle_start(self, database),
             Step::Verify { a_pub, a_proof } => match handle_verify(self, &a_pub, &a_proof) {
-                Ok(res) => {
-                    self.unsuccessful_tries = 0;
-                    Ok(res)
-                },
+                Ok(res) => Ok(res),
                 Err(err) => {
-                    self.unsuccessful_tries += 1;
+                    self.release_pairing_lock();
+                    register_failure(database, event_emitter, err);
                     Err(tlv::ErrorContainer::new(StepNumber::VerifyRes as u8, err))
                 },
             },
-            Step::Exchange { data } => match handle_exchange(self, config, database, event_emitter, &data) {
-                Ok(res) => {
-                    self.unsuccessful_tries = 0;
-                    Ok(res)
-                },
-                Err(err) => {
-                    self.unsuccessful_tries += 1;
-                    Err(tlv::ErrorContainer::new(StepNumber::ExchangeRes as u8, err))
-                },
+            Step::Exchange { data } => {
+                let res = handle_exchange(self, config, database, event_emitter, &data);
+                self.release_pairing_lock();
+                res.map_err(|err| {
+                    register_failure(database, event_emitter, err);
+                    tlv::ErrorContainer::new(StepNumber::ExchangeRes as u8, err)
+                })
             },
         }
     }
 }
 
-fn handle_start(handler: &mut PairSetup, database: &DatabasePtr) -> Result<tlv::Container, tlv::Error> {
+/// Returns the current UNIX timestamp in seconds.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Returns the number of seconds a client has to wait after a given number of unsuccessful pair
+/// setup attempts before it may try again.
+fn backoff_delay(attempts: u64) -> u64 {
+    if attempts < BACKOFF_THRESHOLD {
+        return 0;
+    }
+    (1 << (attempts - BACKOFF_THRESHOLD).min(6)).min(MAX_BACKOFF)
+}
+
+/// Persists an unsuccessful pair setup attempt if the error is an authentication failure, i.e. a
+/// wrong setup code or an invalid signature, and notifies the application about it.
+fn register_failure(database: &DatabasePtr, event_emitter: &EventEmitterPtr, err: tlv::Error) {
+    if let tlv::Error::Authentication = err {
+        let attempts = {
+            let database = database.lock().expect("couldn't access database");
+            let (attempts, _) = database.get_unsuccessful_setup_attempts().unwrap_or((0, 0));
+            let attempts = attempts + 1;
+            if let Err(err) = database.set_unsuccessful_setup_attempts(attempts, now()) {
+                error!("couldn't store unsuccessful pair setup attempts: {}", err);
+            }
+            attempts
+        };
+
+        warn!("unsuccessful pair setup attempt ({}/{})", attempts, MAX_TRIES);
+
+        event_emitter
+            .lock()
+            .expect("couldn't access event_emitter")
+            .emit(&Event::PairSetupFailed { attempts });
+    }
+}
+
+fn handle_start(handler: &mut PairSetup, database: &DatabasePtr) -> Result<tlv::Container, tlv::ErrorContainer> {
     debug!("M1: Got SRP Start Request");
 
-    if handler.unsuccessful_tries > 99 {
-        return Err(tlv::Error::MaxTries);
+    let (attempts, last_attempt) = database
+        .lock()
+        .expect("couldn't access database")
+        .get_unsuccessful_setup_attempts()
+        .map_err(|_| tlv::ErrorContainer::new(StepNumber::StartRes as u8, tlv::Error::Unknown))?;
+    if attempts >= MAX_TRIES {
+        return Err(tlv::ErrorContainer::new(StepNumber::StartRes as u8, tlv::Error::MaxTries));
+    }
+    let retry_at = last_attempt + backoff_delay(attempts);
+    let now = now();
+    if retry_at > now {
+        return Err(tlv::ErrorContainer::backoff(
+            StepNumber::StartRes as u8,
+            (retry_at - now) as usize,
+        ));
+    }
+
+    if !handler
+        .pairing_lock
+        .lock()
+        .expect("couldn't access pairing lock")
+        .acquire(&handler.token)
+    {
+        debug!("refusing pair setup, another pair setup is in progress");
+        return Err(tlv::ErrorContainer::new(StepNumber::StartRes as u8, tlv::Error::Busy));
     }
 
+    start_session(handler, database).map_err(|err| {
+        handler.release_pairing_lock();
+        tlv::ErrorContainer::new(StepNumber::StartRes as u8, err)
+    })
+}
+
+fn start_session(handler: &mut PairSetup, database: &DatabasePtr) -> Result<tlv::Container, tlv::Error> {
     let accessory = Device::load_from(database)?;
 
     let rng = rand::thread_rng();
@@ -164,13 +296,7 @@ fn handle_start(handler: &mut PairSetup, database: &DatabasePtr) -> Result<tlv::
     let srp_client = SrpClient::<Sha512>::new(&private_key, &G_3072);
     let verifier = srp_client.get_password_verifier(&private_key); // v = g^x
 
-    let user = UserRecord {
-        username: b"Pair-Setup",
-        salt: &salt,
-        verifier: &verifier,
-    };
-    let srp_server = SrpServer::<Sha512>::new(&user, b"foo", &b, &G_3072)?;
-    let b_pub = srp_server.get_b_pub();
+    let b_pub = compute_b_pub::<Sha512>(&verifier, &b, &G_3072);
 
     handler.session = Some(Session {
         salt: salt.clone(),
@@ -193,20 +319,15 @@ fn handle_verify(handler: &mut PairSetup, a_pub: &[u8], a_proof: &[u8]) -> Resul
     debug!("M3: Got SRP Verify Request");
 
     if let Some(ref mut session) = handler.session {
-        let user = UserRecord {
-            username: b"Pair-Setup",
-            salt: &session.salt,
-            verifier: &session.verifier,
-        };
-        let srp_server = SrpServer::<Sha512>::new(&user, &a_pub, &session.b, &G_3072)?;
-        let shared_secret = srp_server.get_key();
-        session.shared_secret = Some(shared_secret.as_slice().to_vec());
+        let shared_secret =
+            compute_shared_secret::<Sha512>(&session.verifier, &session.b, a_pub, &session.b_pub, &G_3072)?;
+        session.shared_secret = Some(shared_secret.clone());
         let b_proof = verify_client_proof::<Sha512>(
             &session.b_pub,
             a_pub,
             a_proof,
             &session.salt,
-            &shared_secret.as_slice().to_vec(),
+            &shared_secret,
             &G_3072,
         )?;
 
@@ -232,9 +353,8 @@ fn handle_exchange(
             let encrypted_data = Vec::from(&data[..data.len() - 16]);
             let auth_tag = Vec::from(&data[data.len() - 16..]);
 
-            let mut encryption_key = [0; 32];
-            let salt = hmac::SigningKey::new(&digest::SHA512, b"Pair-Setup-Encrypt-Salt");
-            hkdf::extract_and_expand(&salt, &shared_secret, b"Pair-Setup-Encrypt-Info", &mut encryption_key);
+            let encryption_key =
+                hkdf::derive_key(shared_secret, hkdf::PAIR_SETUP_ENCRYPT_SALT, hkdf::PAIR_SETUP_ENCRYPT_INFO);
 
             let mut decrypted_data = Vec::new();
             let mut nonce = vec![0; 4];
@@ -253,9 +373,11 @@ fn handle_exchange(
             let device_ltpk = sub_tlv.get(&(Type::PublicKey as u8)).ok_or(tlv::Error::Unknown)?;
             let device_signature = sub_tlv.get(&(Type::Signature as u8)).ok_or(tlv::Error::Unknown)?;
 
-            let mut device_x = [0; 32];
-            let salt = hmac::SigningKey::new(&digest::SHA512, b"Pair-Setup-Controller-Sign-Salt");
-            hkdf::extract_and_expand(&salt, &shared_secret, b"Pair-Setup-Controller-Sign-Info", &mut device_x);
+            let device_x = hkdf::derive_key(
+                shared_secret,
+                hkdf::PAIR_SETUP_CONTROLLER_SIGN_SALT,
+                hkdf::PAIR_SETUP_CONTROLLER_SIGN_INFO,
+            );
 
             let mut device_info: Vec<u8> = Vec::new();
             device_info.extend(&device_x);
@@ -278,14 +400,15 @@ fn handle_exchange(
 
             let pairing = Pairing::new(pairing_uuid, Permissions::Admin, pairing_ltpk);
             pairing.save_to(database)?;
+            database
+                .lock()
+                .expect("couldn't access database")
+                .set_unsuccessful_setup_attempts(0, 0)?;
 
-            let mut accessory_x = [0; 32];
-            let salt = hmac::SigningKey::new(&digest::SHA512, b"Pair-Setup-Accessory-Sign-Salt");
-            hkdf::extract_and_expand(
-                &salt,
-                &shared_secret,
-                b"Pair-Setup-Accessory-Sign-Info",
-                &mut accessory_x,
+            let accessory_x = hkdf::derive_key(
+                shared_secret,
+                hkdf::PAIR_SETUP_ACCESSORY_SIGN_SALT,
+                hkdf::PAIR_SETUP_ACCESSORY_SIGN_INFO,
             );
 
             let accessory = Device::load_from(database)?;
@@ -313,6 +436,9 @@ fn handle_exchange(
                 .expect("couldn't access event_emitter")
                 .emit(&Event::DevicePaired);
 
+            #[cfg(feature = "tracing")]
+            tracing::event!(tracing::Level::INFO, id = %pairing_uuid, "controller paired");
+
             debug!("M6: Sending SRP Exchange Response");
 
             Ok(vec![
@@ -327,6 +453,72 @@ fn handle_exchange(
     }
 }
 
+/// Left-pads a `BigUint` with zeros to the byte length of the group's modulus `N`.
+fn pad(n: &BigUint, group: &SrpGroup) -> Vec<u8> {
+    let len = group.n.to_bytes_be().len();
+    let bytes = n.to_bytes_be();
+    let mut padded = vec![0; len.saturating_sub(bytes.len())];
+    padded.extend(bytes);
+    padded
+}
+
+/// Computes the SRP-6a multiplier parameter `k = H(N | PAD(g))`.
+fn compute_k<D: Digest>(group: &SrpGroup) -> BigUint {
+    let mut d = D::new();
+    d.input(&group.n.to_bytes_be());
+    d.input(&pad(&group.g, group));
+    BigUint::from_bytes_be(&d.result())
+}
+
+/// Computes the server's public ephemeral value `B = k*v + g^b % N`, padded to the length of `N`.
+fn compute_b_pub<D: Digest>(verifier: &[u8], b: &[u8], group: &SrpGroup) -> Vec<u8> {
+    let v = BigUint::from_bytes_be(verifier);
+    let b = BigUint::from_bytes_be(b) % &group.n;
+    let k = compute_k::<D>(group);
+    let b_pub = (k * v + group.g.modpow(&b, &group.n)) % &group.n;
+    pad(&b_pub, group)
+}
+
+/// Computes the random scrambling parameter `u = H(PAD(A) | PAD(B))`.
+fn compute_u<D: Digest>(a_pub: &BigUint, b_pub: &BigUint, group: &SrpGroup) -> BigUint {
+    let mut d = D::new();
+    d.input(&pad(a_pub, group));
+    d.input(&pad(b_pub, group));
+    BigUint::from_bytes_be(&d.result())
+}
+
+/// Computes the premaster secret `S = (A * v^u) ^ b % N`.
+fn compute_premaster_secret(v: &BigUint, b: &BigUint, a_pub: &BigUint, u: &BigUint, group: &SrpGroup) -> BigUint {
+    (a_pub * v.modpow(u, &group.n) % &group.n).modpow(&(b % &group.n), &group.n)
+}
+
+/// Computes the session key `K = H(S)` from the premaster secret `S = (A * v^u) ^ b % N`, with
+/// `u = H(PAD(A) | PAD(B))`.
+fn compute_shared_secret<D: Digest>(
+    verifier: &[u8],
+    b: &[u8],
+    a_pub: &[u8],
+    b_pub: &[u8],
+    group: &SrpGroup,
+) -> Result<Vec<u8>, tlv::Error> {
+    let a_pub = BigUint::from_bytes_be(a_pub);
+    // safeguard against a malicious A
+    if (&a_pub % &group.n).is_zero() {
+        return Err(tlv::Error::Authentication);
+    }
+    let b_pub = BigUint::from_bytes_be(b_pub);
+    let v = BigUint::from_bytes_be(verifier);
+    let b = BigUint::from_bytes_be(b);
+
+    let u = compute_u::<D>(&a_pub, &b_pub, group);
+    if u.is_zero() {
+        return Err(tlv::Error::Authentication);
+    }
+
+    let s = compute_premaster_secret(&v, &b, &a_pub, &u, group);
+    Ok(D::digest(&s.to_bytes_be()).as_slice().to_vec())
+}
+
 fn verify_client_proof<D: Digest>(
     b_pub: &[u8],
     a_pub: &[u8],
@@ -335,15 +527,9 @@ fn verify_client_proof<D: Digest>(
     key: &[u8],
     group: &SrpGroup,
 ) -> Result<Vec<u8>, tlv::Error> {
-    let mut dhn = D::new();
-    dhn.input(&group.n.to_bytes_be());
-    let hn = BigUint::from_bytes_be(&dhn.result());
-
-    let mut dhg = D::new();
-    dhg.input(&group.g.to_bytes_be());
-    let hg = BigUint::from_bytes_be(&dhg.result());
-
-    let hng = hn.bitxor(hg);
+    let hn = D::digest(&group.n.to_bytes_be());
+    let hg = D::digest(&group.g.to_bytes_be());
+    let hng = hn.iter().zip(hg.iter()).map(|(n, g)| n ^ g).collect::<Vec<u8>>();
 
     let mut dhi = D::new();
     dhi.input(b"Pair-Setup");
@@ -351,7 +537,7 @@ fn verify_client_proof<D: Digest>(
 
     let mut d = D::new();
     // M = H(H(N) xor H(g), H(I), s, A, B, K)
-    d.input(&hng.to_bytes_be());
+    d.input(&hng);
     d.input(&hi);
     d.input(salt);
     d.input(a_pub);
@@ -369,3 +555,185 @@ fn verify_client_proof<D: Digest>(
         Err(tlv::Error::Authentication)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::from_hex;
+
+    // The salt and the private values `a` and `b` of the test vector in RFC 5054, appendix B, which
+    // the HAP specification reuses for SRP-3072 with SHA-512. The username and password are the
+    // ones of Pair Setup though, as `verify_client_proof` always hashes `Pair-Setup` as `I`.
+    const SETUP_CODE: &[u8] = b"111-22-333";
+    const SALT: &str = "beb25379d1a8581eb5a727673a2441ee";
+    const A: &str = "60975527035cf2ad1989806f0407210bc81edc04e2762a56afd529ddda2d4393";
+    const B: &str = "e487cb59d31ac550471e81f00f6928e01dda08e974a004f49e61f5d105284d20";
+
+    /// `A = g^a % N`
+    const A_PUB: &str = concat!(
+        "fab6f5d2615d1e323512e7991cc37443f487da604ca8c9230fcb04e541dce6280b27ca4680b0374f179dc3bdc7553fe6",
+        "2459798c701ad864a91390a28c93b644adbf9c00745b942b79f9012a21b9b78782319d83a1f8362866fbd6f46bfc0ddb",
+        "2e1ab6e4b45a9906b82e37f05d6f97f6a3eb6e182079759c4f6847837b62321ac1b4fa68641fcb4bb98dd697a0c73641",
+        "385f4bab25b793584cc39fc8d48d4bd867a9a3c10f8ea12170268e34fe3bbe6ff89998d60da2f3e4283cbec1393d52af",
+        "724a57230c604e9fbce583d7613e6bffd67596ad121a8707eec46944957033686a155f644d5c5863b48f61bdbf19a53e",
+        "ab6dad0a186b8c152e5f5d8cad4b0ef8aa4ea5008834c3cd342e5e0f167ad04592cd8bd279639398ef9e114dfaaab919",
+        "e14e850989224ddd98576d79385d2210902e9f9b1f2d86cfa47ee244635465f71058421a0184be51dd10cc9d079e6f16",
+        "04e7aa9b7cf7883c7d4ce12b06ebe16081e23f27a231d18432d7d1bb55c28ae21ffcf005f57528d15a88881bb3bbb7fe",
+    );
+
+    /// `B = k*v + g^b % N`
+    const B_PUB: &str = concat!(
+        "0d948f8add88658bfb2f58b2a2580674aba5e03685d39a8a433a7414c83c7d3ab7626ed9633fb12f8424d0826c493e35",
+        "08273637f0d1c9831554a3cab1e6ef3ef09fa3caa039d2151deb113e97cb59150623ad0b1f9cea013aa1228afe715e8e",
+        "90f27003f4a7aca1807efad4e879a7d5269e65c12d1c60b79a2106904847026cd19a62dd7d43c3b56e58f097bebc5621",
+        "453b6098130157e8a9b4b0e1be0570215eda49ad4863c6d30181d4211ee8227844147786915026b83c87af3e462fff1e",
+        "9eb53db966780886011122ee9f3e7706e8898461fa60df894fbfa718116583318ae8e219592a8956b0b66060d087b38f",
+        "a7146eaf5718d1917749d5d49785006ca75e97e413f8788e5788df27775cfba9f2a893b474bf725b017a6afbaf19c918",
+        "0de934b5ba320a24978913b51e78b3215968efeafd14e269c70e1d3277c37ff662eedf58a8858e9dd40fc9f06ee8b7f9",
+        "1623970fa28c6fe6fdd693d9913783efcd4aa66188bd8c321076165d9225ca650e5950a6a50b3165b32fa4a85e30a6b0",
+    );
+
+    /// `u = H(PAD(A) | PAD(B))`
+    const U: &str = concat!(
+        "8642d17647f8f523425cd53f30dfd10df72afb9ef62ce15fbf263f32983c0f3ab97a44bfc2ed966a944f7a7d70e2251a",
+        "b9853a1f39f801df2c7ff972aa7fe666",
+    );
+
+    /// `S = (A * v^u) ^ b % N`
+    const PREMASTER_SECRET: &str = concat!(
+        "01f4b9303098e8ca9e13168f90040ef056a25ad620ee4d7a70ee66c0c4ea368ba3c8b2faf1caa025007b547279f7ee13",
+        "8418f63352c67494a3865e851eba4d5006319f148037ccac6a7803238ddff04568b93652083f67e82d56725244b5f33f",
+        "6112fb6b06e84c806eb06e06f56f209db1dafd8c5d6a0a2b0bde32a6790557ecf5254c413f759dd62bcd99a7de55d64e",
+        "2f8b04862daa508762e888e77c10d36d21730e221d50dbe4f8e1577748fef6d77b81b63100c5f8770c0c4a3679c0022b",
+        "141fcf7a16f36cf15eb4fc9fa300a7e6cb67efd2f95b8a939cd749397b533825b612a41e927a2fe2ea915ea89abb8db7",
+        "dc4e00e148b8d2c2398ede66f2ad74bfe57b30d0646147937eb3950bdd753747037344370c03233e12dd4a1c13bbad8d",
+        "1af13b8fe9efa9587d73129f5c679cc4f5d55824c832a0dfee31e19f9564828a4a606c948fd7424ddf808de96cc43579",
+        "2b89f05b6c6ce3d5d32b853d522a56b9d885a757c36bafc44274de55780f6b7160874c5af4445087f44f42e484c3fd50",
+    );
+
+    /// `K = H(S)`
+    const SESSION_KEY: &str = concat!(
+        "c85cbc9a1b876a5a6c43397c3441a99750fbdb1db9ac794cb00ec8fdd2e2dec2d07f4830250d67d63f9f7d3dfca1fa9f",
+        "b9ee86316c5a7e9fa39ff6a8cbc98388",
+    );
+
+    /// `M1 = H(H(N) xor H(g) | H(I) | s | A | B | K)`
+    const CLIENT_PROOF: &str = concat!(
+        "f67168e094bcabc208cdaa79628c82ccb1ecebb92eaa7fc2d5e56eff80dfb51cdf347c6680502c2b3b34617f0f975a6b",
+        "d7e3757918e3b0a21624e244bc6c0f2d",
+    );
+
+    /// `M2 = H(A | M1 | K)`
+    const SERVER_PROOF: &str = concat!(
+        "674bda7a714c0fead16487feec76086be293e3483154a52110675e3f2696d87044ceff24be5297285ec16cea9eddee9d",
+        "98084248a68716bc00a3603b267a1541",
+    );
+
+    fn verifier() -> Vec<u8> {
+        let private_key = srp_private_key::<Sha512>(b"Pair-Setup", SETUP_CODE, &from_hex(SALT));
+        SrpClient::<Sha512>::new(&private_key, &G_3072).get_password_verifier(&private_key)
+    }
+
+    #[test]
+    fn test_compute_b_pub() {
+        let a_pub = G_3072.g.modpow(&BigUint::from_bytes_be(&from_hex(A)), &G_3072.n);
+        assert_eq!(pad(&a_pub, &G_3072), from_hex(A_PUB));
+        assert_eq!(compute_b_pub::<Sha512>(&verifier(), &from_hex(B), &G_3072), from_hex(B_PUB));
+    }
+
+    #[test]
+    fn test_compute_u_and_premaster_secret() {
+        let a_pub = BigUint::from_bytes_be(&from_hex(A_PUB));
+        let u = compute_u::<Sha512>(&a_pub, &BigUint::from_bytes_be(&from_hex(B_PUB)), &G_3072);
+        assert_eq!(u.to_bytes_be(), from_hex(U));
+
+        let v = BigUint::from_bytes_be(&verifier());
+        let b = BigUint::from_bytes_be(&from_hex(B));
+        let s = compute_premaster_secret(&v, &b, &a_pub, &u, &G_3072);
+        assert_eq!(pad(&s, &G_3072), from_hex(PREMASTER_SECRET));
+    }
+
+    #[test]
+    fn test_compute_shared_secret() {
+        let shared_secret =
+            compute_shared_secret::<Sha512>(&verifier(), &from_hex(B), &from_hex(A_PUB), &from_hex(B_PUB), &G_3072)
+                .unwrap();
+        assert_eq!(shared_secret, from_hex(SESSION_KEY));
+    }
+
+    #[test]
+    fn test_compute_shared_secret_rejects_zero_a_pub() {
+        let n = G_3072.n.to_bytes_be();
+        for a_pub in &[vec![0], n] {
+            let err = compute_shared_secret::<Sha512>(&verifier(), &from_hex(B), a_pub, &from_hex(B_PUB), &G_3072);
+            assert!(matches!(err, Err(tlv::Error::Authentication)));
+        }
+    }
+
+    #[test]
+    fn test_verify_client_proof() {
+        let b_proof = verify_client_proof::<Sha512>(
+            &from_hex(B_PUB),
+            &from_hex(A_PUB),
+            &from_hex(CLIENT_PROOF),
+            &from_hex(SALT),
+            &from_hex(SESSION_KEY),
+            &G_3072,
+        )
+        .unwrap();
+        assert_eq!(b_proof, from_hex(SERVER_PROOF));
+
+        let mut a_proof = from_hex(CLIENT_PROOF);
+        a_proof[0] ^= 1;
+        let err = verify_client_proof::<Sha512>(
+            &from_hex(B_PUB),
+            &from_hex(A_PUB),
+            &a_proof,
+            &from_hex(SALT),
+            &from_hex(SESSION_KEY),
+            &G_3072,
+        );
+        assert!(matches!(err, Err(tlv::Error::Authentication)));
+    }
+
+    #[test]
+    fn test_pairing_lock_refuses_other_connections() {
+        let mut lock = PairingLock::default();
+        let (first, second) = (Arc::new(()), Arc::new(()));
+
+        assert!(lock.acquire(&first));
+        assert!(!lock.acquire(&second));
+        // the owner may restart its own pair setup
+        assert!(lock.acquire(&first));
+
+        // releasing with a foreign token leaves the lock untouched
+        lock.release(&second);
+        assert!(!lock.acquire(&second));
+
+        lock.release(&first);
+        assert!(lock.acquire(&second));
+        assert!(!lock.acquire(&first));
+    }
+
+    #[test]
+    fn test_pairing_lock_is_freed_when_its_connection_closes() {
+        let mut lock = PairingLock::default();
+        let first = Arc::new(());
+        let second = Arc::new(());
+
+        assert!(lock.acquire(&first));
+        drop(first);
+        assert!(lock.acquire(&second));
+    }
+
+    #[test]
+    fn test_pairing_lock_times_out() {
+        let mut lock = PairingLock::default();
+        let (first, second) = (Arc::new(()), Arc::new(()));
+
+        assert!(lock.acquire(&first));
+        lock.owner = Some((Arc::downgrade(&first), Instant::now() - PAIRING_LOCK_TIMEOUT));
+        assert!(lock.acquire(&second));
+        assert!(!lock.acquire(&first));
+    }
+}
@@ -1,19 +1,24 @@
-use std::{collections::HashMap, str};
+use std::{
+    collections::HashMap,
+    str,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use chacha20_poly1305_aead;
-use crypto::{curve25519, ed25519};
+use crypto::ed25519;
 use futures::sync::oneshot;
 use log::debug;
 use rand::{self, Rng};
-use ring::{digest, hkdf, hmac};
 use uuid::Uuid;
 
 use crate::{
     config::ConfigPtr,
+    crypto::{hkdf, x25519},
     db::DatabasePtr,
     event::EventEmitterPtr,
     protocol::{
-        tlv::{self, Type, Value},
+        tlv::{self, Method, Type, Value},
         Device,
         IdPtr,
         Pairing,
@@ -28,16 +33,90 @@ struct Session {
     session_key: [u8; 32],
 }
 
+struct CachedSession {
+    controller_id: Uuid,
+    shared_secret: [u8; 32],
+    created_at: Instant,
+}
+
+/// Sessions of controllers that can be resumed via Pair Resume instead of running the full
+/// Pair Verify again. Every controller has at most one resumable session.
+pub struct SessionCache {
+    sessions: HashMap<[u8; 8], CachedSession>,
+    ttl: Duration,
+    capacity: usize,
+}
+
+impl SessionCache {
+    /// Creates a new `SessionCache` keeping sessions resumable for `ttl` and holding at most
+    /// `capacity` sessions, evicting the oldest one first.
+    pub fn new(ttl: Duration, capacity: usize) -> SessionCache {
+        SessionCache {
+            sessions: HashMap::new(),
+            ttl,
+            capacity,
+        }
+    }
+
+    fn insert(&mut self, session_id: [u8; 8], controller_id: Uuid, shared_secret: [u8; 32]) {
+        if self.capacity == 0 || self.ttl == Duration::from_secs(0) {
+            return;
+        }
+        let now = Instant::now();
+        let ttl = self.ttl;
+        self.sessions.retain(|_, s| s.controller_id != controller_id && now.duration_since(s.created_at) < ttl);
+        while self.sessions.len() >= self.capacity {
+            let oldest = self
+                .sessions
+                .iter()
+                .min_by_key(|(_, s)| s.created_at)
+                .map(|(id, _)| *id)
+                .expect("session cache is not empty");
+            self.sessions.remove(&oldest);
+        }
+        self.sessions.insert(session_id, CachedSession {
+            controller_id,
+            shared_secret,
+            created_at: now,
+        });
+    }
+
+    /// Removes the session with the given ID and returns its controller ID and shared secret if it
+    /// hasn't expired. A session can only be resumed once.
+    fn take(&mut self, session_id: &[u8]) -> Option<(Uuid, [u8; 32])> {
+        if session_id.len() != 8 {
+            return None;
+        }
+        let mut id = [0; 8];
+        id.copy_from_slice(session_id);
+        let ttl = self.ttl;
+        self.sessions
+            .remove(&id)
+            .filter(|s| s.created_at.elapsed() < ttl)
+            .map(|s| (s.controller_id, s.shared_secret))
+    }
+
+    /// Removes the session of a controller, e.g. after its pairing was removed.
+    pub fn remove_controller(&mut self, controller_id: &Uuid) {
+        self.sessions.retain(|_, s| s.controller_id != *controller_id);
+    }
+}
+
+/// Pointer to the `SessionCache` shared by all connections.
+pub type SessionCachePtr = Arc<Mutex<SessionCache>>;
+
 pub struct PairVerify {
     session: Option<Session>,
     session_sender: Option<oneshot::Sender<tcp::Session>>,
+    session_cache: SessionCachePtr,
 }
 
 impl PairVerify {
-    pub fn new(session_sender: oneshot::Sender<tcp::Session>) -> PairVerify {
+    pub fn new(session_sender: oneshot::Sender<tcp::Session>, session_cache: SessionCachePtr) -> PairVerify {
         PairVerify {
             session: None,
             session_sender: Some(session_sender),
+            session_cache,
         }
     }
 }
@@ -52,6 +131,11 @@ enum StepNumber {
 
 pub enum Step {
     Start { a_pub: Vec<u8> },
+    Resume {
+        a_pub: Vec<u8>,
+        session_id: Vec<u8>,
+        data: Vec<u8>,
+    },
     Finish { data: Vec<u8> },
 }
 
@@ -68,7 +152,18 @@ impl TlvHandler for PairVerify {
                         StepNumber::StartRes as u8,
                         tlv::Error::Unknown,
                     ))?;
-                    Ok(Step::Start { a_pub: a_pub.clone() })
+                    let resume = decoded.get(&(Type::Method as u8)) == Some(&vec![Method::PairResume as u8]);
+                    match (
+                        decoded.get(&(Type::SessionId as u8)),
+                        decoded.get(&(Type::EncryptedData as u8)),
+                    ) {
+                        (Some(session_id), Some(data)) if resume => Ok(Step::Resume {
+                            a_pub: a_pub.clone(),
+                            session_id: session_id.clone(),
+                            data: data.clone(),
+                        }),
+                        _ => Ok(Step::Start { a_pub: a_pub.clone() }),
+                    }
                 },
                 x if x == StepNumber::FinishReq as u8 => {
                     let data = decoded
@@ -98,6 +193,14 @@ impl TlvHandler for PairVerify {
                 Ok(res) => Ok(res),
                 Err(err) => Err(tlv::ErrorContainer::new(StepNumber::StartRes as u8, err)),
             },
+            Step::Resume {
+                a_pub,
+                session_id,
+                data,
+            } => match handle_resume(self, database, a_pub, &session_id, &data) {
+                Ok(res) => Ok(res),
+                Err(err) => Err(tlv::ErrorContainer::new(StepNumber::StartRes as u8, err)),
+            },
             Step::Finish { data } => match handle_finish(self, database, &data) {
                 Ok(res) => Ok(res),
                 Err(err) => Err(tlv::ErrorContainer::new(StepNumber::FinishRes as u8, err)),
@@ -113,10 +216,11 @@ fn handle_start(
 ) -> Result<tlv::Container, tlv::Error> {
     debug!("M1: Got Verify Start Request");
 
-    let mut rng = rand::thread_rng();
-    let b = rng.gen::<[u8; 32]>();
-    let b_pub = curve25519::curve25519_base(&b);
-    let shared_secret = curve25519::curve25519(&b, &a_pub);
+    let a_pub_key = x25519::PublicKey::from_slice(&a_pub).ok_or(tlv::Error::Unknown)?;
+    let (b, b_pub_key) = x25519::generate_keypair();
+    let b_pub = *b_pub_key.as_bytes();
+    // a public key of low order yields a shared secret known to anyone
+    let shared_secret = x25519::diffie_hellman(b, &a_pub_key).ok_or(tlv::Error::Authentication)?;
 
     let accessory = Device::load_from(database)?;
     let mut accessory_info: Vec<u8> = Vec::new();
@@ -132,9 +236,7 @@ fn handle_start(
     sub_tlv.insert(t, v);
     let encoded_sub_tlv = tlv::encode(sub_tlv);
 
-    let mut session_key = [0; 32];
-    let salt = hmac::SigningKey::new(&digest::SHA512, b"Pair-Verify-Encrypt-Salt");
-    hkdf::extract_and_expand(&salt, &shared_secret, b"Pair-Verify-Encrypt-Info", &mut session_key);
+    let session_key = hkdf::derive_key(&shared_secret, hkdf::PAIR_VERIFY_ENCRYPT_SALT, hkdf::PAIR_VERIFY_ENCRYPT_INFO);
 
     handler.session = Some(Session {
         b_pub,
@@ -183,7 +285,8 @@ fn handle_finish(handler: &mut PairVerify, database: &DatabasePtr, data: &[u8])
 
         let uuid_str = str::from_utf8(device_pairing_id)?;
         let pairing_uuid = Uuid::parse_str(uuid_str)?;
-        let pairing = Pairing::load_from(pairing_uuid, database)?;
+        // unknown Controllers fail like Controllers with an invalid signature
+        let pairing = Pairing::load_from(pairing_uuid, database).map_err(|_| tlv::Error::Authentication)?;
 
         let mut device_info: Vec<u8> = Vec::new();
         device_info.extend(&session.a_pub);
@@ -203,6 +306,19 @@ fn handle_finish(handler: &mut PairVerify, database: &DatabasePtr, data: &[u8])
             return Err(tlv::Error::Unknown);
         }
 
+        let mut session_id = [0; 8];
+        hkdf::derive(
+            &session.shared_secret,
+            hkdf::PAIR_VERIFY_RESUME_SESSION_ID_SALT,
+            hkdf::PAIR_VERIFY_RESUME_SESSION_ID_INFO,
+            &mut session_id,
+        );
+        handler
+            .session_cache
+            .lock()
+            .expect("couldn't access session cache")
+            .insert(session_id, pairing_uuid, session.shared_secret);
+
         debug!("M4: Sending Verify Finish Response");
 
         Ok(vec![Value::State(StepNumber::FinishRes as u8)])
@@ -210,3 +326,70 @@ fn handle_finish(handler: &mut PairVerify, database: &DatabasePtr, data: &[u8])
         Err(tlv::Error::Unknown)
     }
 }
+
+fn handle_resume(
+    handler: &mut PairVerify,
+    database: &DatabasePtr,
+    a_pub: Vec<u8>,
+    session_id: &[u8],
+    data: &[u8],
+) -> Result<tlv::Container, tlv::Error> {
+    debug!("M1: Got Resume Request");
+
+    let cached_session = handler
+        .session_cache
+        .lock()
+        .expect("couldn't access session cache")
+        .take(session_id);
+    // sessions of removed controllers can't be resumed
+    let cached_session =
+        cached_session.filter(|&(controller_id, _)| Pairing::load_from(controller_id, database).is_ok());
+    let (controller_id, shared_secret) = match cached_session {
+        Some(cached_session) => cached_session,
+        None => {
+            debug!("Unknown session, falling back to Verify");
+            return handle_start(handler, database, a_pub);
+        },
+    };
+
+    let mut salt = a_pub.clone();
+    salt.extend(session_id);
+    let request_key = hkdf::derive_key(&shared_secret, &salt, hkdf::PAIR_RESUME_REQUEST_INFO);
+    let mut nonce = vec![0; 4];
+    nonce.extend(b"PR-Msg01");
+    if data.len() != 16
+        || chacha20_poly1305_aead::decrypt(&request_key, &nonce, &[], &[], data, &mut Vec::new()).is_err()
+    {
+        debug!("Resume Request failed authentication, falling back to Verify");
+        return handle_start(handler, database, a_pub);
+    }
+
+    let new_session_id = rand::thread_rng().gen::<[u8; 8]>();
+    let mut salt = a_pub;
+    salt.extend(&new_session_id);
+    let response_key = hkdf::derive_key(&shared_secret, &salt, hkdf::PAIR_RESUME_RESPONSE_INFO);
+    let mut nonce = vec![0; 4];
+    nonce.extend(b"PR-Msg02");
+    let auth_tag = chacha20_poly1305_aead::encrypt(&response_key, &nonce, &[], &[], &mut Vec::new())?;
+    let new_shared_secret = hkdf::derive_key(&shared_secret, &salt, hkdf::PAIR_RESUME_SHARED_SECRET_INFO);
+
+    let sender = handler.session_sender.take().ok_or(tlv::Error::Unknown)?;
+    let _session = sender.send(tcp::Session {
+        controller_id,
+        shared_secret: new_shared_secret,
+    });
+    handler
+        .session_cache
+        .lock()
+        .expect("couldn't access session cache")
+        .insert(new_session_id, controller_id, new_shared_secret);
+
+    debug!("M2: Sending Resume Response");
+
+    Ok(vec![
+        Value::State(StepNumber::StartRes as u8),
+        Value::Method(Method::PairResume),
+        Value::SessionId(new_session_id.to_vec()),
+        Value::EncryptedData(auth_tag.to_vec()),
+    ])
+}
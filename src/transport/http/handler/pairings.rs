@@ -199,14 +199,33 @@ fn handle_remove(
 
     let uuid_str = str::from_utf8(&pairing_id)?;
     let pairing_uuid = Uuid::parse_str(uuid_str)?;
+
+    let mut removed = vec![];
     let d = database.lock().expect("couldn't access database");
-    d.delete_pairing(&d.get_pairing(pairing_uuid)?.id)?;
+    // removing a pairing that doesn't exist succeeds, so a controller can retry a removal
+    if d.get_pairing(pairing_uuid).is_ok() {
+        d.delete_pairing(&pairing_uuid)?;
+        removed.push(pairing_uuid);
+    }
+    // without an admin left, the remaining pairings can't be managed anymore, so they're removed as
+    // well and the Accessory is unpaired
+    let remaining = d.list_pairings()?;
+    let unpaired = !remaining.iter().any(|p| p.permissions == Permissions::Admin);
+    if unpaired {
+        for pairing in remaining {
+            d.delete_pairing(&pairing.id)?;
+            removed.push(pairing.id);
+        }
+    }
     drop(d);
 
-    event_emitter
-        .lock()
-        .expect("couldn't access event_emitter")
-        .emit(&Event::DeviceUnpaired);
+    let event_emitter = event_emitter.lock().expect("couldn't access event_emitter");
+    for id in removed {
+        event_emitter.emit(&Event::ControllerUnpaired { id });
+    }
+    if unpaired {
+        event_emitter.emit(&Event::DeviceUnpaired);
+    }
 
     debug!("M2: Sending Remove Pairing Response");
 
@@ -224,7 +243,7 @@ fn handle_list(database: &DatabasePtr, controller_id: &IdPtr) -> Result<tlv::Con
         list.push(Value::Identifier(pairing.id.to_hyphenated().to_string()));
         list.push(Value::PublicKey(pairing.public_key.to_vec()));
         list.push(Value::Permissions(pairing.permissions.clone()));
-        if i < pairings.len() {
+        if i + 1 < pairings.len() {
             list.push(Value::Separator);
         }
     }
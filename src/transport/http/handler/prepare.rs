@@ -0,0 +1,54 @@
+use std::time::{Duration, Instant};
+
+use hyper::{Body, Response, StatusCode, Uri};
+
+use crate::{
+    config::ConfigPtr,
+    db::{AccessoryList, DatabasePtr},
+    event::EventEmitterPtr,
+    protocol::IdPtr,
+    transport::http::{
+        handler::JsonHandler,
+        server::EventSubscriptions,
+        status_json_response,
+        PrepareObject,
+        Status,
+        TimedWrite,
+        TimedWritePtr,
+    },
+    Result,
+};
+
+pub struct Prepare {
+    timed_write: TimedWritePtr,
+}
+
+impl Prepare {
+    pub fn new(timed_write: TimedWritePtr) -> Prepare { Prepare { timed_write } }
+}
+
+impl JsonHandler for Prepare {
+    fn handle(
+        &mut self,
+        _: Uri,
+        body: Vec<u8>,
+        _: &IdPtr,
+        _: &EventSubscriptions,
+        _: &ConfigPtr,
+        _: &DatabasePtr,
+        _: &AccessoryList,
+        _: &EventEmitterPtr,
+    ) -> Result<Response<Body>> {
+        let prepare_object: PrepareObject = match serde_json::from_slice(&body) {
+            Ok(prepare_object) => prepare_object,
+            Err(_) => return status_json_response(Status::InvalidValueInRequest, StatusCode::BAD_REQUEST),
+        };
+
+        *self.timed_write.lock().expect("couldn't access timed_write") = Some(TimedWrite {
+            pid: prepare_object.pid,
+            expires_at: Instant::now() + Duration::from_millis(prepare_object.ttl),
+        });
+
+        status_json_response(Status::Success, StatusCode::OK)
+    }
+}
@@ -0,0 +1,56 @@
+use hyper::{Body, Response, StatusCode, Uri};
+
+use crate::{
+    config::ConfigPtr,
+    db::{AccessoryList, DatabasePtr},
+    event::EventEmitterPtr,
+    protocol::IdPtr,
+    transport::http::{
+        handler::JsonHandler,
+        jpeg_response,
+        server::EventSubscriptions,
+        status_json_response,
+        ResourceHandlerPtr,
+        ResourceRequest,
+        Status,
+    },
+    Result,
+};
+
+pub struct Resource {
+    resource_handler: ResourceHandlerPtr,
+}
+
+impl Resource {
+    pub fn new(resource_handler: ResourceHandlerPtr) -> Resource { Resource { resource_handler } }
+}
+
+impl JsonHandler for Resource {
+    fn handle(
+        &mut self,
+        _: Uri,
+        body: Vec<u8>,
+        _: &IdPtr,
+        _: &EventSubscriptions,
+        _: &ConfigPtr,
+        _: &DatabasePtr,
+        _: &AccessoryList,
+        _: &EventEmitterPtr,
+    ) -> Result<Response<Body>> {
+        let resource_request: ResourceRequest = match serde_json::from_slice(&body) {
+            Ok(resource_request) => resource_request,
+            Err(_) => return status_json_response(Status::InvalidValueInRequest, StatusCode::BAD_REQUEST),
+        };
+        if resource_request.resource_type != "image" {
+            return status_json_response(Status::InvalidValueInRequest, StatusCode::BAD_REQUEST);
+        }
+
+        match *self.resource_handler.lock().expect("couldn't access resource_handler") {
+            Some(ref resource_handler) => match resource_handler(resource_request) {
+                Ok(image) => jpeg_response(image, StatusCode::OK),
+                Err(_) => status_json_response(Status::ServiceCommunicationFailure, StatusCode::INTERNAL_SERVER_ERROR),
+            },
+            None => status_json_response(Status::ResourceDoesNotExist, StatusCode::NOT_FOUND),
+        }
+    }
+}
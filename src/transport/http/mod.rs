@@ -1,13 +1,28 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+        Condvar,
+        Mutex,
+    },
+    time::Instant,
+};
+
 use hyper::{
-    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    header::{CONNECTION, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER},
     Body,
     Response,
     StatusCode,
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
 
 use crate::{
     characteristic::{Format, Perm, Unit},
+    protocol::IdPtr,
     Error,
     HapType,
     Result,
@@ -16,7 +31,11 @@ use crate::{
 pub(crate) mod handler;
 pub(crate) mod server;
 
+#[cfg(test)]
+pub(crate) mod tests;
+
 #[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Status {
     Success = 0,
     InsufficientPrivileges = -70401,
@@ -29,11 +48,14 @@ pub enum Status {
     OperationTimedOut = -70408,
     ResourceDoesNotExist = -70409,
     InvalidValueInRequest = -70410,
+    InsufficientAuthorization = -70411,
+    NotAllowedInCurrentState = -70412,
 }
 
 enum ContentType {
     PairingTLV8,
     HapJson,
+    ImageJpeg,
 }
 
 impl ContentType {
@@ -41,6 +63,7 @@ impl ContentType {
         match self {
             ContentType::PairingTLV8 => "application/pairing+tlv8".into(),
             ContentType::HapJson => "application/hap+json".into(),
+            ContentType::ImageJpeg => "image/jpeg".into(),
         }
     }
 }
@@ -59,7 +82,9 @@ pub struct ReadResponseObject {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub format: Option<Format>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub perms: Option<Vec<Perm>>,
+    pub perms: Option<HashSet<Perm>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ev: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -74,10 +99,22 @@ pub struct ReadResponseObject {
     pub step_value: Option<serde_json::Value>,
     #[serde(rename = "maxLen", skip_serializing_if = "Option::is_none")]
     pub max_len: Option<u16>,
+    #[serde(rename = "maxDataLen", skip_serializing_if = "Option::is_none")]
+    pub max_data_len: Option<u32>,
+    #[serde(rename = "valid-values", skip_serializing_if = "Option::is_none")]
+    pub valid_values: Option<Vec<serde_json::Value>>,
+    #[serde(rename = "valid-values-range", skip_serializing_if = "Option::is_none")]
+    pub valid_values_range: Option<[serde_json::Value; 2]>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub status: Option<i32>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct WriteRequestBody {
+    characteristics: Vec<WriteObject>,
+    pid: Option<u64>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct WriteObject {
     pub iid: u64,
@@ -87,6 +124,172 @@ pub struct WriteObject {
     #[serde(rename = "authData")]
     pub auth_data: Option<String>,
     pub remote: Option<bool>,
+    #[serde(rename = "r")]
+    pub response: Option<bool>,
+    pub pid: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrepareObject {
+    pub ttl: u64,
+    pub pid: u64,
+}
+
+/// A timed write prepared by a controller via `/prepare`, valid until `expires_at`.
+#[derive(Debug)]
+pub struct TimedWrite {
+    pub pid: u64,
+    pub expires_at: Instant,
+}
+
+impl TimedWrite {
+    /// Returns the `Status` a write carrying the given `pid` gets against this prepared timed
+    /// write. Writes without a `pid` aren't allowed, writes with an unknown or expired `pid` are
+    /// rejected as invalid.
+    pub fn check(timed_write: Option<&TimedWrite>, pid: Option<u64>) -> Status {
+        match (timed_write, pid) {
+            (_, None) => Status::NotAllowedInCurrentState,
+            (Some(t), Some(pid)) if t.pid == pid && Instant::now() <= t.expires_at => Status::Success,
+            _ => Status::InvalidValueInRequest,
+        }
+    }
+}
+
+/// Per-connection state of a prepared timed write.
+pub type TimedWritePtr = Arc<Mutex<Option<TimedWrite>>>;
+
+/// A request of a controller for a resource of an Accessory, e.g. a snapshot of a camera.
+#[derive(Debug, Deserialize)]
+pub struct ResourceRequest {
+    /// ID of the Accessory the resource is requested from. Only sent to bridges.
+    pub aid: Option<u64>,
+    /// Type of the requested resource. Currently always `"image"`.
+    #[serde(rename = "resource-type")]
+    pub resource_type: String,
+    /// Requested width of the image in pixels.
+    #[serde(rename = "image-width")]
+    pub image_width: Option<u32>,
+    /// Requested height of the image in pixels.
+    #[serde(rename = "image-height")]
+    pub image_height: Option<u32>,
+}
+
+/// Handler returning the JPEG encoded image for a `ResourceRequest`.
+pub type ResourceHandler = Box<dyn Fn(ResourceRequest) -> Result<Vec<u8>> + Send>;
+
+/// Pointer to the optional `ResourceHandler` of a transport.
+pub type ResourceHandlerPtr = Arc<Mutex<Option<ResourceHandler>>>;
+
+/// Handle to shut down the server of a running transport. Calling `shutdown` makes the server stop
+/// accepting connections and close the open sessions, after which `start` returns. Clones of a
+/// `ShutdownHandle` control the same server.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownHandle {
+    shutdown: Arc<AtomicBool>,
+    serving: Arc<(Mutex<bool>, Condvar)>,
+    connections: Arc<Mutex<HashMap<u64, OpenConnection>>>,
+    next_connection_id: Arc<AtomicU64>,
+}
+
+/// State of an open connection as tracked by the server.
+#[derive(Debug)]
+struct OpenConnection {
+    peer_addr: SocketAddr,
+    controller_id: IdPtr,
+    event_subscriptions: Arc<Mutex<Vec<(u64, u64)>>>,
+}
+
+/// Information about an open controller connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// Address of the remote end of the connection.
+    pub peer_addr: SocketAddr,
+    /// ID of the controller that secured the connection via pair-verify, if it did so yet.
+    pub controller_id: Option<Uuid>,
+    /// Whether the connection is secured, i.e. encrypted.
+    pub secured: bool,
+    /// Characteristics the controller subscribed to events of on the connection, as pairs of
+    /// Accessory ID and Characteristic instance ID.
+    pub subscriptions: Vec<(u64, u64)>,
+}
+
+impl ShutdownHandle {
+    /// Signals the server to shut down.
+    pub fn shutdown(&self) { self.shutdown.store(true, Ordering::SeqCst); }
+
+    /// Returns `true` if the server was signaled to shut down.
+    pub fn is_shutdown(&self) -> bool { self.shutdown.load(Ordering::SeqCst) }
+
+    /// Blocks until the server isn't serving anymore. Returns right away if it isn't started.
+    pub fn wait(&self) {
+        let (serving, stopped) = &*self.serving;
+        let mut serving = serving.lock().expect("couldn't access serving state");
+        while *serving {
+            serving = stopped.wait(serving).expect("couldn't access serving state");
+        }
+    }
+
+    pub(crate) fn reset(&self) { self.shutdown.store(false, Ordering::SeqCst); }
+
+    /// Returns the number of open controller connections of the server.
+    pub(crate) fn connection_count(&self) -> usize {
+        self.connections.lock().expect("couldn't access connections").len()
+    }
+
+    /// Returns information about the open controller connections of the server.
+    pub(crate) fn connections(&self) -> Vec<ConnectionInfo> {
+        let connections = self.connections.lock().expect("couldn't access connections");
+        let mut infos = connections
+            .iter()
+            .map(|(id, c)| {
+                let controller_id = *c.controller_id.lock().expect("couldn't access controller_id");
+                let info = ConnectionInfo {
+                    peer_addr: c.peer_addr,
+                    controller_id,
+                    secured: controller_id.is_some(),
+                    subscriptions: c
+                        .event_subscriptions
+                        .lock()
+                        .expect("couldn't access event subscriptions")
+                        .clone(),
+                };
+                (*id, info)
+            })
+            .collect::<Vec<_>>();
+        infos.sort_by_key(|(id, _)| *id);
+        infos.into_iter().map(|(_, info)| info).collect()
+    }
+
+    /// Registers a newly opened connection and returns the ID to unregister it with.
+    pub(crate) fn connection_opened(
+        &self,
+        peer_addr: SocketAddr,
+        controller_id: IdPtr,
+        event_subscriptions: Arc<Mutex<Vec<(u64, u64)>>>,
+    ) -> u64 {
+        let id = self.next_connection_id.fetch_add(1, Ordering::SeqCst);
+        self.connections.lock().expect("couldn't access connections").insert(id, OpenConnection {
+            peer_addr,
+            controller_id,
+            event_subscriptions,
+        });
+        id
+    }
+
+    pub(crate) fn connection_closed(&self, id: u64) {
+        self.connections.lock().expect("couldn't access connections").remove(&id);
+    }
+
+    /// Returns `true` while the server is serving.
+    pub(crate) fn is_serving(&self) -> bool { *self.serving.0.lock().expect("couldn't access serving state") }
+
+    pub(crate) fn set_serving(&self, serving: bool) {
+        let (state, stopped) = &*self.serving;
+        *state.lock().expect("couldn't access serving state") = serving;
+        if !serving {
+            stopped.notify_all();
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -94,6 +297,8 @@ pub struct WriteResponseObject {
     pub iid: u64,
     pub aid: u64,
     pub status: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Serialize)]
@@ -111,19 +316,65 @@ pub fn json_response(body: Vec<u8>, status: StatusCode) -> Result<Response<Body>
     response(body, status, ContentType::HapJson)
 }
 
+/// Builds a JSON response carrying only a HAP status, e.g. `{"status":-70410}`.
+pub fn status_json_response(status: Status, status_code: StatusCode) -> Result<Response<Body>> {
+    let body = serde_json::to_vec(&json!({ "status": status as i32 }))?;
+    json_response(body, status_code)
+}
+
+pub fn jpeg_response(body: Vec<u8>, status: StatusCode) -> Result<Response<Body>> {
+    response(body, status, ContentType::ImageJpeg)
+}
+
 pub fn status_response(status: StatusCode) -> Result<Response<Body>> {
     Response::builder()
         .status(status)
+        .header(CONNECTION, "keep-alive")
+        .body(Body::empty())
+        .map_err(Error::from)
+}
+
+/// Builds a `470 Connection Authorization Required` response, asking the controller to secure the
+/// connection via pair-verify first.
+pub fn connection_authorization_required_response() -> Result<Response<Body>> {
+    let body = serde_json::to_vec(&json!({ "status": Status::InsufficientPrivileges as i32 }))?;
+    let status = StatusCode::from_u16(470).map_err(hyper::http::Error::from)?;
+    json_response(body, status)
+}
+
+/// Builds a `429 Too Many Requests` response asking the controller to retry after the given number
+/// of seconds.
+pub fn too_many_requests_response(retry_after: u64) -> Result<Response<Body>> {
+    Response::builder()
+        .status(StatusCode::TOO_MANY_REQUESTS)
+        .header(CONNECTION, "keep-alive")
+        .header(RETRY_AFTER, retry_after)
         .body(Body::empty())
         .map_err(Error::from)
 }
 
+/// Builds a `413 Payload Too Large` response for a request with a body exceeding
+/// `Config::max_request_body_size`. The connection is closed afterwards, as the rest of the body
+/// isn't read.
+pub fn payload_too_large_response() -> Result<Response<Body>> {
+    let body = serde_json::to_vec(&json!({ "status": Status::InvalidValueInRequest as i32 }))?;
+    Response::builder()
+        .status(StatusCode::PAYLOAD_TOO_LARGE)
+        .header(CONNECTION, "close")
+        .header(CONTENT_TYPE, ContentType::HapJson.to_string())
+        .header(CONTENT_LENGTH, body.len() as u64)
+        .body(body.into())
+        .map_err(Error::from)
+}
+
+/// Builds an out-of-band `EVENT/1.0` message notifying a subscribed controller of changed
+/// Characteristic values.
 pub fn event_response(event_objects: Vec<EventObject>) -> Result<Vec<u8>> {
     let body = serde_json::to_string(&CharacteristicResponseBody {
         characteristics: event_objects,
     })?;
     let response = format!(
-        "EVENT/1.0 200 OK\nContent-Type: application/hap+json\nContent-Length: {}\n\n{}",
+        "EVENT/1.0 200 OK\r\nContent-Type: application/hap+json\r\nContent-Length: {}\r\n\r\n{}",
         body.len(),
         body,
     );
@@ -133,6 +384,7 @@ pub fn event_response(event_objects: Vec<EventObject>) -> Result<Vec<u8>> {
 fn response(body: Vec<u8>, status: StatusCode, content_type: ContentType) -> Result<Response<Body>> {
     Response::builder()
         .status(status)
+        .header(CONNECTION, "keep-alive")
         .header(CONTENT_TYPE, content_type.to_string())
         .header(CONTENT_LENGTH, body.len() as u64)
         .body(body.into())
@@ -1,13 +1,40 @@
 use std::{
-    net::SocketAddr,
-    sync::{Arc, Mutex},
+    collections::HashMap,
+    io,
+    net::{self, IpAddr, SocketAddr},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
 };
 
-use futures::{future, stream::Stream, sync::oneshot, Future};
-use hyper::{self, server::conn::Http, service::Service, Body, Method, Request, Response, StatusCode};
-use log::error;
+use futures::{
+    future,
+    stream::Stream,
+    sync::{
+        mpsc::{self, UnboundedSender},
+        oneshot,
+    },
+    Future,
+};
+use hyper::{
+    self,
+    header::CONTENT_LENGTH,
+    server::conn::Http,
+    service::Service,
+    Body,
+    Method,
+    Request,
+    Response,
+    StatusCode,
+};
+use log::{debug, error};
+use net2::TcpBuilder;
 use route_recognizer::Router;
-use tokio::net::TcpListener;
+use serde_json::Value;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    reactor::Handle,
+    timer::{Delay, Interval},
+};
 
 use crate::{
     config::ConfigPtr,
@@ -16,26 +43,49 @@ use crate::{
     protocol::IdPtr,
     transport::{
         http::{
+            connection_authorization_required_response,
             event_response,
-            handler::{self, accessories, characteristics, identify, pair_setup, pair_verify, pairings},
+            handler::{
+                self,
+                accessories,
+                characteristics,
+                health,
+                identify,
+                pair_setup::{self, PairingLockPtr},
+                pair_verify::{self, SessionCache, SessionCachePtr},
+                pairings,
+                prepare,
+                resource,
+            },
+            payload_too_large_response,
             status_response,
+            too_many_requests_response,
             EventObject,
+            ResourceHandlerPtr,
+            ShutdownHandle,
+            TimedWritePtr,
         },
-        tcp::{EncryptedStream, Session, StreamWrapper},
+        tcp::{EncryptedStream, Outgoing, Session},
     },
     Error,
+    ErrorKind,
+    HapType,
     Result,
 };
 
 enum Route {
     Get(Box<Mutex<dyn handler::Handler + Send>>),
     Post(Box<Mutex<dyn handler::Handler + Send>>),
+    Put(Box<Mutex<dyn handler::Handler + Send>>),
     GetPut {
         _get: Box<Mutex<dyn handler::Handler + Send>>,
         _put: Box<Mutex<dyn handler::Handler + Send>>,
     },
 }
 
+/// Paths only served on sessions secured via pair-verify.
+const SECURED_PATHS: [&str; 5] = ["/accessories", "/characteristics", "/prepare", "/pairings", "/resource"];
+
 struct Api {
     controller_id: IdPtr,
     event_subscriptions: EventSubscriptions,
@@ -46,6 +96,73 @@ struct Api {
     router: Arc<Router<Route>>,
 }
 
+/// Creates the `Router` of a connection, routing to handlers holding its per-connection state.
+fn router(
+    session_sender: oneshot::Sender<Session>,
+    pairing_lock: PairingLockPtr,
+    session_cache: SessionCachePtr,
+    resource_handler: ResourceHandlerPtr,
+) -> Router<Route> {
+    let timed_write: TimedWritePtr = Arc::new(Mutex::new(None));
+    let mut router = Router::new();
+    router.add(
+        "/pair-setup",
+        Route::Post(Box::new(Mutex::new(handler::TlvHandlerType::from(
+            pair_setup::PairSetup::new(pairing_lock),
+        )))),
+    );
+    router.add(
+        "/pair-verify",
+        Route::Post(Box::new(Mutex::new(handler::TlvHandlerType::from(
+            pair_verify::PairVerify::new(session_sender, session_cache),
+        )))),
+    );
+    router.add(
+        "/accessories",
+        Route::Get(Box::new(Mutex::new(handler::JsonHandlerType::from(
+            accessories::Accessories::new(),
+        )))),
+    );
+    router.add("/characteristics", Route::GetPut {
+        _get: Box::new(Mutex::new(handler::JsonHandlerType::from(
+            characteristics::GetCharacteristics::new(),
+        ))),
+        _put: Box::new(Mutex::new(handler::JsonHandlerType::from(
+            characteristics::UpdateCharacteristics::new(timed_write.clone()),
+        ))),
+    });
+    router.add(
+        "/prepare",
+        Route::Put(Box::new(Mutex::new(handler::JsonHandlerType::from(
+            prepare::Prepare::new(timed_write),
+        )))),
+    );
+    router.add(
+        "/pairings",
+        Route::Post(Box::new(Mutex::new(handler::TlvHandlerType::from(
+            pairings::Pairings::new(),
+        )))),
+    );
+    router.add(
+        "/identify",
+        Route::Post(Box::new(Mutex::new(handler::JsonHandlerType::from(
+            identify::Identify::new(),
+        )))),
+    );
+    router.add(
+        "/resource",
+        Route::Post(Box::new(Mutex::new(handler::JsonHandlerType::from(
+            resource::Resource::new(resource_handler),
+        )))),
+    );
+    router.add(
+        "/.well-known/hap/health",
+        Route::Get(Box::new(Mutex::new(handler::JsonHandlerType::from(health::Health::new())))),
+    );
+
+    router
+}
+
 impl Api {
     fn new(
         controller_id: IdPtr,
@@ -54,48 +171,8 @@ impl Api {
         database: DatabasePtr,
         accessories: AccessoryList,
         event_emitter: EventEmitterPtr,
-        session_sender: oneshot::Sender<Session>,
+        router: Router<Route>,
     ) -> Api {
-        let mut router = Router::new();
-        router.add(
-            "/pair-setup",
-            Route::Post(Box::new(Mutex::new(handler::TlvHandlerType::from(
-                pair_setup::PairSetup::new(),
-            )))),
-        );
-        router.add(
-            "/pair-verify",
-            Route::Post(Box::new(Mutex::new(handler::TlvHandlerType::from(
-                pair_verify::PairVerify::new(session_sender),
-            )))),
-        );
-        router.add(
-            "/accessories",
-            Route::Get(Box::new(Mutex::new(handler::JsonHandlerType::from(
-                accessories::Accessories::new(),
-            )))),
-        );
-        router.add("/characteristics", Route::GetPut {
-            _get: Box::new(Mutex::new(handler::JsonHandlerType::from(
-                characteristics::GetCharacteristics::new(),
-            ))),
-            _put: Box::new(Mutex::new(handler::JsonHandlerType::from(
-                characteristics::UpdateCharacteristics::new(),
-            ))),
-        });
-        router.add(
-            "/pairings",
-            Route::Post(Box::new(Mutex::new(handler::TlvHandlerType::from(
-                pairings::Pairings::new(),
-            )))),
-        );
-        router.add(
-            "/identify",
-            Route::Post(Box::new(Mutex::new(handler::JsonHandlerType::from(
-                identify::Identify::new(),
-            )))),
-        );
-
         Api {
             controller_id,
             event_subscriptions,
@@ -115,6 +192,30 @@ impl Service for Api {
     type ResBody = Body;
 
     fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
+        // the attribute database and pairings are only served on verified sessions, otherwise the
+        // controller is asked to authorize the connection
+        if SECURED_PATHS.contains(&req.uri().path())
+            && self.controller_id.lock().expect("couldn't access controller_id").is_none()
+        {
+            return Box::new(future::result(connection_authorization_required_response()));
+        }
+
+        // oversized bodies are refused before reading them, or as soon as they exceed the limit
+        // when their length isn't announced
+        let max_body_size = self
+            .config
+            .lock()
+            .expect("couldn't access config")
+            .max_request_body_size;
+        let content_length = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|l| l.to_str().ok())
+            .and_then(|l| l.parse::<usize>().ok());
+        if content_length.map_or(false, |l| l > max_body_size) {
+            return Box::new(future::result(payload_too_large_response()));
+        }
+
         let (parts, body) = req.into_parts();
         let router = self.router.clone();
         let controller_id = self.controller_id.clone();
@@ -125,86 +226,447 @@ impl Service for Api {
         let event_emitter = self.event_emitter.clone();
 
         Box::new(
-            body.fold(vec![], |mut v, c| {
-                v.extend(c.to_vec());
-                future::ok::<Vec<u8>, hyper::Error>(v)
-            })
-            .map_err(|e| e.into())
-            .and_then(move |body| {
-                if let Ok(route_match) = router.recognize(parts.uri.path()) {
-                    match (route_match.handler, parts.method) {
-                        (&Route::Get(ref handler), Method::GET) => handler.lock().unwrap().handle(
-                            parts.uri,
-                            body.into(),
-                            &controller_id,
-                            &event_subscriptions,
-                            &config,
-                            &database,
-                            &accessories,
-                            &event_emitter,
-                        ),
-                        (&Route::Post(ref handler), Method::POST) => handler.lock().unwrap().handle(
-                            parts.uri,
-                            body.into(),
-                            &controller_id,
-                            &event_subscriptions,
-                            &config,
-                            &database,
-                            &accessories,
-                            &event_emitter,
-                        ),
-                        (&Route::GetPut { ref _get, ref _put }, Method::GET) => _get.lock().unwrap().handle(
-                            parts.uri,
-                            body.into(),
-                            &controller_id,
-                            &event_subscriptions,
-                            &config,
-                            &database,
-                            &accessories,
-                            &event_emitter,
-                        ),
-                        (&Route::GetPut { ref _get, ref _put }, Method::PUT) => _put.lock().unwrap().handle(
-                            parts.uri,
-                            body.into(),
-                            &controller_id,
-                            &event_subscriptions,
-                            &config,
-                            &database,
-                            &accessories,
-                            &event_emitter,
-                        ),
-                        _ => Box::new(future::result(status_response(StatusCode::BAD_REQUEST))),
+            body.map_err(Error::from)
+                .fold(vec![], move |mut v, c| {
+                    if v.len() + c.len() > max_body_size {
+                        return Err(Error::from(ErrorKind::HttpStatus(StatusCode::PAYLOAD_TOO_LARGE)));
                     }
-                } else {
-                    Box::new(future::result(status_response(StatusCode::NOT_FOUND)))
-                }
-            }),
+                    v.extend(c.to_vec());
+                    Ok(v)
+                })
+                .then(move |body| -> Box<dyn Future<Item = Response<Body>, Error = Error> + Send> {
+                    let body = match body {
+                        Ok(body) => body,
+                        Err(e) => match e.kind() {
+                            ErrorKind::HttpStatus(StatusCode::PAYLOAD_TOO_LARGE) => {
+                                return Box::new(future::result(payload_too_large_response()));
+                            },
+                            _ => return Box::new(future::err(e)),
+                        },
+                    };
+                    if let Ok(route_match) = router.recognize(parts.uri.path()) {
+                        match (route_match.handler, parts.method) {
+                            (&Route::Get(ref handler), Method::GET) => handler.lock().unwrap().handle(
+                                parts.uri,
+                                body.into(),
+                                &controller_id,
+                                &event_subscriptions,
+                                &config,
+                                &database,
+                                &accessories,
+                                &event_emitter,
+                            ),
+                            (&Route::Post(ref handler), Method::POST) => handler.lock().unwrap().handle(
+                                parts.uri,
+                                body.into(),
+                                &controller_id,
+                                &event_subscriptions,
+                                &config,
+                                &database,
+                                &accessories,
+                                &event_emitter,
+                            ),
+                            (Route::Put(handler), Method::PUT) => handler.lock().unwrap().handle(
+                                parts.uri,
+                                body,
+                                &controller_id,
+                                &event_subscriptions,
+                                &config,
+                                &database,
+                                &accessories,
+                                &event_emitter,
+                            ),
+                            (&Route::GetPut { ref _get, ref _put }, Method::GET) => _get.lock().unwrap().handle(
+                                parts.uri,
+                                body.into(),
+                                &controller_id,
+                                &event_subscriptions,
+                                &config,
+                                &database,
+                                &accessories,
+                                &event_emitter,
+                            ),
+                            (&Route::GetPut { ref _get, ref _put }, Method::PUT) => _put.lock().unwrap().handle(
+                                parts.uri,
+                                body.into(),
+                                &controller_id,
+                                &event_subscriptions,
+                                &config,
+                                &database,
+                                &accessories,
+                                &event_emitter,
+                            ),
+                            _ => Box::new(future::result(status_response(StatusCode::BAD_REQUEST))),
+                        }
+                    } else {
+                        Box::new(future::result(status_response(StatusCode::NOT_FOUND)))
+                    }
+                }),
         )
     }
 }
 
 pub type EventSubscriptions = Arc<Mutex<Vec<(u64, u64)>>>;
 
+/// `Api` of a connection limited to the requests per second the `RateLimiter` grants its remote IP
+/// address.
+struct RateLimited {
+    api: Api,
+    remote_ip: IpAddr,
+    rate_limiter: Arc<Mutex<RateLimiter>>,
+}
+
+impl Service for RateLimited {
+    type Error = Error;
+    type Future = Box<dyn Future<Item = Response<Self::ResBody>, Error = Self::Error> + Send>;
+    type ReqBody = Body;
+    type ResBody = Body;
+
+    fn call(&mut self, req: Request<Self::ReqBody>) -> Self::Future {
+        let rate = self
+            .api
+            .config
+            .lock()
+            .expect("couldn't access config")
+            .max_requests_per_second_per_ip;
+        let wait = self
+            .rate_limiter
+            .lock()
+            .expect("couldn't access rate limiter")
+            .take(self.remote_ip, rate);
+        match wait {
+            Some(wait) => {
+                Box::new(future::result(too_many_requests_response(wait.as_secs_f64().ceil() as u64)))
+            },
+            None => self.api.call(req),
+        }
+    }
+}
+
+/// Token buckets of the remote IP addresses. Every bucket holds up to one second worth of requests
+/// and is refilled continuously.
+struct RateLimiter {
+    buckets: HashMap<IpAddr, (f64, Instant)>,
+}
+
+impl RateLimiter {
+    fn new() -> RateLimiter { RateLimiter { buckets: HashMap::new() } }
+
+    /// Takes a token for a request from `ip`, allowing `rate` requests per second. Returns the time
+    /// to wait for the next token if the bucket is empty. A `rate` of `0` allows any request.
+    fn take(&mut self, ip: IpAddr, rate: u32) -> Option<Duration> {
+        if rate == 0 {
+            return None;
+        }
+        let rate = f64::from(rate);
+        let now = Instant::now();
+        if !self.buckets.contains_key(&ip) {
+            // buckets not touched for a second are full again, so they can be dropped
+            self.buckets
+                .retain(|_, &mut (_, updated)| now.duration_since(updated) < Duration::from_secs(1));
+        }
+        let (tokens, updated) = self.buckets.entry(ip).or_insert((rate, now));
+        *tokens = (*tokens + now.duration_since(*updated).as_secs_f64() * rate).min(rate);
+        *updated = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - *tokens) / rate))
+        }
+    }
+}
+
+/// Coalesces the Characteristic value change events of a connection, so every Characteristic
+/// notifies at most once per interval with its most recent value.
+struct EventCoalescer {
+    interval: Duration,
+    last_sent: HashMap<(u64, u64), Instant>,
+    pending: HashMap<(u64, u64), Value>,
+}
+
+impl EventCoalescer {
+    fn new(interval: Duration) -> EventCoalescer {
+        EventCoalescer {
+            interval,
+            last_sent: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Returns the event to send right away if the Characteristic didn't notify within the
+    /// interval or the event is `immediate`. Otherwise the value is kept until the interval passed,
+    /// replacing any value pending before.
+    fn push(&mut self, aid: u64, iid: u64, value: Value, immediate: bool) -> Option<EventObject> {
+        let now = Instant::now();
+        let due = match self.last_sent.get(&(aid, iid)) {
+            Some(&last_sent) => now.duration_since(last_sent) >= self.interval,
+            None => true,
+        };
+        if immediate || due {
+            self.last_sent.insert((aid, iid), now);
+            self.pending.remove(&(aid, iid));
+            Some(EventObject { aid, iid, value })
+        } else {
+            self.pending.insert((aid, iid), value);
+            None
+        }
+    }
+
+    /// Takes the pending events whose interval has passed.
+    fn take_due(&mut self) -> Vec<EventObject> {
+        let now = Instant::now();
+        let interval = self.interval;
+        let last_sent = &mut self.last_sent;
+        let mut events = vec![];
+        self.pending.retain(|&(aid, iid), value| {
+            let due = match last_sent.get(&(aid, iid)) {
+                Some(&sent) => now.duration_since(sent) >= interval,
+                None => true,
+            };
+            if due {
+                last_sent.insert((aid, iid), now);
+                events.push(EventObject {
+                    aid,
+                    iid,
+                    value: value.take(),
+                });
+            }
+            !due
+        });
+        events
+    }
+
+    /// Drops the state kept for a removed Characteristic.
+    fn remove(&mut self, aid: u64, iid: u64) {
+        self.last_sent.remove(&(aid, iid));
+        self.pending.remove(&(aid, iid));
+    }
+}
+
+/// Sends events on a connection. The receiving half is gone once the connection is closed, so
+/// there's no one left to notify on it and its subscriptions are dropped.
+fn send_events(
+    stream_outgoing: &UnboundedSender<Outgoing>,
+    mut event_subscriptions: MutexGuard<Vec<(u64, u64)>>,
+    events: Vec<EventObject>,
+) -> Result<()> {
+    let event_res = event_response(events)?;
+    if stream_outgoing.unbounded_send(Outgoing::Data(event_res)).is_err() {
+        event_subscriptions.clear();
+    }
+    Ok(())
+}
+
+/// Handles an event on a connection. Value changes of subscribed Characteristics are sent as events
+/// or coalesced, the state kept for removed Characteristics is dropped, and the connection is
+/// notified via `unpaired_sender` once its controller is unpaired.
+fn forward_event(
+    event: &Event,
+    controller_id: &IdPtr,
+    event_subscriptions: &EventSubscriptions,
+    coalescer: &Mutex<EventCoalescer>,
+    stream_outgoing: &UnboundedSender<Outgoing>,
+    unpaired_sender: &UnboundedSender<()>,
+) -> Result<()> {
+    match *event {
+        Event::CharacteristicValueChanged {
+            aid,
+            iid,
+            hap_type,
+            ref value,
+        } => {
+            let es = event_subscriptions
+                .lock()
+                .map_err(|_| Error::from_str("couldn't access event subscriptions"))?;
+            if es.contains(&(aid, iid)) {
+                let immediate = matches!(hap_type, HapType::ProgrammableSwitchEvent);
+                let event = coalescer
+                    .lock()
+                    .map_err(|_| Error::from_str("couldn't access event coalescer"))?
+                    .push(aid, iid, value.clone(), immediate);
+                if let Some(event) = event {
+                    send_events(stream_outgoing, es, vec![event])?;
+                }
+            }
+        },
+        Event::CharacteristicRemoved { aid, iid } => {
+            event_subscriptions
+                .lock()
+                .map_err(|_| Error::from_str("couldn't access event subscriptions"))?
+                .retain(|&s| s != (aid, iid));
+            coalescer
+                .lock()
+                .map_err(|_| Error::from_str("couldn't access event coalescer"))?
+                .remove(aid, iid);
+        },
+        Event::ControllerUnpaired { id } => {
+            let controller_id = *controller_id
+                .lock()
+                .map_err(|_| Error::from_str("couldn't access controller_id"))?;
+            if controller_id == Some(id) {
+                let _ = unpaired_sender.unbounded_send(());
+            }
+        },
+        _ => {},
+    }
+    Ok(())
+}
+
+/// Sends the coalesced events that are due on a connection.
+fn flush_events(
+    event_subscriptions: &EventSubscriptions,
+    coalescer: &Mutex<EventCoalescer>,
+    stream_outgoing: &UnboundedSender<Outgoing>,
+) -> Result<()> {
+    let es = event_subscriptions
+        .lock()
+        .map_err(|_| Error::from_str("couldn't access event subscriptions"))?;
+    let events = coalescer
+        .lock()
+        .map_err(|_| Error::from_str("couldn't access event coalescer"))?
+        .take_due()
+        .into_iter()
+        .filter(|e| es.contains(&(e.aid, e.iid)))
+        .collect::<Vec<_>>();
+    if !events.is_empty() {
+        send_events(stream_outgoing, es, events)?;
+    }
+    Ok(())
+}
+
+/// Binds a `TcpListener` to the given `SocketAddr`. IPv6 listeners are bound IPv6-only, so they
+/// don't collide with an IPv4 listener on the same port.
+pub fn bind(socket_addr: &SocketAddr) -> Result<TcpListener> {
+    let listener = match socket_addr {
+        SocketAddr::V4(_) => net::TcpListener::bind(socket_addr)?,
+        SocketAddr::V6(_) => {
+            let builder = TcpBuilder::new_v6()?;
+            builder.only_v6(true)?;
+            builder.reuse_address(true)?;
+            builder.bind(socket_addr)?.listen(1024)?
+        },
+    };
+    Ok(TcpListener::from_std(listener, &Handle::default())?)
+}
+
+/// Returns a future resolving once the `ShutdownHandle` is signaled. The flag is polled, so it
+/// resolves within 100 ms of the signal.
+fn shutdown_signal(shutdown_handle: &ShutdownHandle) -> impl Future<Item = (), Error = ()> {
+    let shutdown_handle = shutdown_handle.clone();
+    Interval::new_interval(Duration::from_millis(100))
+        .take_while(move |_| Ok(!shutdown_handle.is_shutdown()))
+        .for_each(|_| Ok(()))
+        .map_err(|e| error!("{}", e))
+}
+
+/// Returns a future resolving once a connection has been idle for the given timeout, emitting an
+/// `Event::ConnectionIdleTimeout`. A zero timeout never resolves.
+fn idle_timeout(
+    last_activity: Arc<Mutex<Instant>>,
+    timeout: Duration,
+    remote_addr: SocketAddr,
+    event_emitter: EventEmitterPtr,
+) -> impl Future<Item = (), Error = ()> {
+    if timeout == Duration::from_secs(0) {
+        return future::Either::B(future::empty());
+    }
+    future::Either::A(
+        Interval::new_interval((timeout / 4).min(Duration::from_secs(1)))
+            .take_while(move |_| {
+                let last_activity = *last_activity.lock().expect("couldn't access last_activity");
+                Ok(last_activity.elapsed() < timeout)
+            })
+            .for_each(|_| Ok(()))
+            .map_err(|e| error!("{}", e))
+            .map(move |_| {
+                debug!("closing connection of {} after being idle for {:?}", remote_addr, timeout);
+                event_emitter
+                    .lock()
+                    .expect("couldn't access event_emitter")
+                    .emit(&Event::ConnectionIdleTimeout { remote_addr });
+            }),
+    )
+}
+
 pub fn serve(
-    socket_addr: &SocketAddr,
+    listeners: Vec<TcpListener>,
     config: &ConfigPtr,
     database: &DatabasePtr,
     accessories: &AccessoryList,
     event_emitter: &EventEmitterPtr,
+    resource_handler: &ResourceHandlerPtr,
+    shutdown_handle: &ShutdownHandle,
 ) -> Result<()> {
-    let listener = TcpListener::bind(socket_addr)?;
+    let mut incoming: Option<Box<dyn Stream<Item = TcpStream, Error = io::Error> + Send>> = None;
+    for listener in listeners {
+        let listener_incoming = listener.incoming();
+        incoming = Some(match incoming {
+            Some(incoming) => Box::new(incoming.select(listener_incoming)),
+            None => Box::new(listener_incoming),
+        });
+    }
+    let incoming = incoming.ok_or_else(|| Error::from_str("no listener to serve on"))?;
 
     let config = config.clone();
     let database = database.clone();
     let accessories = accessories.clone();
     let event_emitter = event_emitter.clone();
+    let resource_handler = resource_handler.clone();
+    let shutdown_handle = shutdown_handle.clone();
+    let rate_limiter = Arc::new(Mutex::new(RateLimiter::new()));
+    let pairing_lock: PairingLockPtr = Arc::default();
+    let session_cache: SessionCachePtr = {
+        let c = config.lock().expect("couldn't access config");
+        Arc::new(Mutex::new(SessionCache::new(c.session_cache_ttl, c.session_cache_capacity)))
+    };
+    {
+        let session_cache = session_cache.clone();
+        event_emitter
+            .lock()
+            .expect("couldn't add listener for controller unpaired events")
+            .add_listener(Box::new(move |event| {
+                if let Event::ControllerUnpaired { id } = *event {
+                    match session_cache.lock() {
+                        Ok(mut session_cache) => session_cache.remove_controller(&id),
+                        Err(_) => error!("couldn't access session cache"),
+                    }
+                }
+            }));
+    }
 
-    let server = listener
-        .incoming()
+    let accept_shutdown_handle = shutdown_handle.clone();
+    let server = incoming
         .for_each(move |stream| {
-            let (encrypted_stream, stream_incoming, stream_outgoing, session_sender) = EncryptedStream::new(stream);
-            let stream_wrapper = StreamWrapper::new(stream_incoming, stream_outgoing.clone());
-            let event_subscriptions = Arc::new(Mutex::new(vec![]));
+            let max_connections = config.lock().expect("couldn't access config").max_connections;
+            if accept_shutdown_handle.connection_count() >= max_connections {
+                error!("refusing connection, {} connections are already open", max_connections);
+                // a zero linger time resets the connection on close
+                if let Err(e) = stream.set_linger(Some(Duration::from_secs(0))) {
+                    error!("couldn't reset the refused connection: {}", e);
+                }
+                return Ok(());
+            }
+            // errors of a single connection don't stop accepting further ones
+            let remote_addr = match stream.peer_addr() {
+                Ok(remote_addr) => remote_addr,
+                Err(e) => {
+                    error!("couldn't get the address of an accepted connection: {}", e);
+                    return Ok(());
+                },
+            };
+            let remote_ip = remote_addr.ip();
+
+            let (encrypted_stream, stream_wrapper, stream_outgoing, session_sender) = EncryptedStream::new(stream);
+            let event_subscriptions: EventSubscriptions = Arc::new(Mutex::new(vec![]));
+            let connection_id = accept_shutdown_handle.connection_opened(
+                remote_addr,
+                encrypted_stream.controller_id.clone(),
+                event_subscriptions.clone(),
+            );
+            let closed_shutdown_handle = accept_shutdown_handle.clone();
+            let closed_event_subscriptions = event_subscriptions.clone();
+            let closed_event_emitter = event_emitter.clone();
             let api = Api::new(
                 encrypted_stream.controller_id.clone(),
                 event_subscriptions.clone(),
@@ -212,51 +674,226 @@ pub fn serve(
                 database.clone(),
                 accessories.clone(),
                 event_emitter.clone(),
-                session_sender,
+                router(
+                    session_sender,
+                    pairing_lock.clone(),
+                    session_cache.clone(),
+                    resource_handler.clone(),
+                ),
             );
-            let http = Http::new();
+            // connections are persistent, events are sent on the socket the subscription was made on,
+            // and pipelined requests are answered in order
+            let mut http = Http::new();
+            http.keep_alive(true).pipeline_flush(true);
+
+            let (event_interval, idle) = {
+                let c = config.lock().expect("couldn't access config");
+                let idle = idle_timeout(
+                    encrypted_stream.last_activity.clone(),
+                    c.connection_idle_timeout,
+                    remote_addr,
+                    event_emitter.clone(),
+                );
+                (c.event_interval, idle)
+            };
+            let coalescer = Arc::new(Mutex::new(EventCoalescer::new(event_interval)));
 
-            event_emitter
+            let (unpaired_sender, unpaired_receiver) = mpsc::unbounded();
+            let listener_controller_id = encrypted_stream.controller_id.clone();
+            let listener_event_subscriptions = event_subscriptions.clone();
+            let listener_stream_outgoing = stream_outgoing.clone();
+            let listener_coalescer = coalescer.clone();
+            let listener_id = event_emitter
                 .lock()
                 .expect("couldn't add listener for characteristic value change events")
-                .add_listener(Box::new(move |event| match *event {
-                    Event::CharacteristicValueChanged { aid, iid, ref value } => {
-                        let mut dropped_subscriptions = vec![];
-                        for (i, &(s_aid, s_iid)) in event_subscriptions
-                            .lock()
-                            .expect("couldn't read event subscriptions")
-                            .iter()
-                            .enumerate()
-                        {
-                            if s_aid == aid && s_iid == iid {
-                                let event = EventObject {
-                                    aid,
-                                    iid,
-                                    value: value.clone(),
-                                };
-                                let event_res = event_response(vec![event]).expect("couldn't create event response");
-                                if stream_outgoing.unbounded_send(event_res).is_err() {
-                                    dropped_subscriptions.push(i);
-                                }
+                .add_listener(Box::new(move |event| {
+                    if let Err(e) = forward_event(
+                        event,
+                        &listener_controller_id,
+                        &listener_event_subscriptions,
+                        &listener_coalescer,
+                        &listener_stream_outgoing,
+                        &unpaired_sender,
+                    ) {
+                        error!("couldn't forward event to {}: {}", remote_addr, e);
+                    }
+                }));
+
+            // the session of a removed controller is closed after a short delay, so the response to
+            // the removal request still goes out on it
+            let unpaired = unpaired_receiver
+                .into_future()
+                .map_err(|_| ())
+                .and_then(|_| Delay::new(Instant::now() + Duration::from_millis(500)).map_err(|e| error!("{}", e)));
+
+            let flusher = if event_interval > Duration::from_secs(0) {
+                future::Either::A(
+                    Interval::new_interval(event_interval / 4)
+                        .for_each(move |_| {
+                            if let Err(e) = flush_events(&event_subscriptions, &coalescer, &stream_outgoing) {
+                                error!("couldn't send events to {}: {}", remote_addr, e);
                             }
+                            Ok(())
+                        })
+                        .map_err(|e| error!("{}", e)),
+                )
+            } else {
+                future::Either::B(future::empty())
+            };
+
+            // every connection is served by its own task, so a slow controller doesn't hold up the
+            // others
+            tokio::spawn(
+                encrypted_stream
+                    .map_err(|e| error!("{}", e))
+                    .join(
+                        http.serve_connection(stream_wrapper, RateLimited {
+                            api,
+                            remote_ip,
+                            rate_limiter: rate_limiter.clone(),
+                        })
+                        .map_err(|e| error!("{}", e)),
+                    )
+                    .map(|_| ())
+                    .select(flusher)
+                    .map(|_| ())
+                    .map_err(|_| ())
+                    .select(unpaired)
+                    .map(|_| ())
+                    .map_err(|_| ())
+                    .select(idle)
+                    .map(|_| ())
+                    .map_err(|_| ())
+                    .select(shutdown_signal(&accept_shutdown_handle))
+                    .then(move |_| {
+                        // subscriptions don't outlive the connection they were made on
+                        if let Ok(mut es) = closed_event_subscriptions.lock() {
+                            es.clear();
                         }
-                        let mut ev = event_subscriptions.lock().expect("couldn't modify event subscriptions");
-                        for s in dropped_subscriptions {
-                            ev.remove(s);
+                        // neither do the event listener and the state it holds
+                        if let Ok(mut event_emitter) = closed_event_emitter.lock() {
+                            event_emitter.remove_listener(listener_id);
                         }
-                    },
-                    _ => {},
-                }));
+                        closed_shutdown_handle.connection_closed(connection_id);
+                        Ok(())
+                    }),
+            );
 
-            encrypted_stream
-                .map_err(|e| error!("{}", e))
-                .join(http.serve_connection(stream_wrapper, api).map_err(|e| error!("{}", e)))
-                .map(|_| ())
-                .then(|_| Ok(()))
+            Ok(())
         })
         .map_err(|e| error!("{}", e));
 
-    tokio::run(server);
+    // on shutdown, the server stops accepting connections and the connection tasks close their
+    // sessions
+    tokio::run(server.select(shutdown_signal(&shutdown_handle)).map(|_| ()).map_err(|_| ()));
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::*;
+
+    struct Connection {
+        controller_id: IdPtr,
+        event_subscriptions: EventSubscriptions,
+        coalescer: Mutex<EventCoalescer>,
+        stream_outgoing: UnboundedSender<Outgoing>,
+        unpaired_sender: UnboundedSender<()>,
+    }
+
+    impl Connection {
+        fn forward(&self, event: &Event) -> Result<()> {
+            forward_event(
+                event,
+                &self.controller_id,
+                &self.event_subscriptions,
+                &self.coalescer,
+                &self.stream_outgoing,
+                &self.unpaired_sender,
+            )
+        }
+    }
+
+    /// Returns a connection of a controller subscribed to the Characteristic 1.10, along with the
+    /// receiving halves of the data sent on it and of its unpaired notification.
+    fn connection(controller_id: Uuid) -> (Connection, mpsc::UnboundedReceiver<Outgoing>, mpsc::UnboundedReceiver<()>) {
+        let (stream_outgoing, outgoing_receiver) = mpsc::unbounded();
+        let (unpaired_sender, unpaired_receiver) = mpsc::unbounded();
+        let connection = Connection {
+            controller_id: Arc::new(Mutex::new(Some(controller_id))),
+            event_subscriptions: Arc::new(Mutex::new(vec![(1, 10)])),
+            coalescer: Mutex::new(EventCoalescer::new(Duration::from_secs(0))),
+            stream_outgoing,
+            unpaired_sender,
+        };
+        (connection, outgoing_receiver, unpaired_receiver)
+    }
+
+    fn value_changed(aid: u64, iid: u64) -> Event {
+        Event::CharacteristicValueChanged {
+            aid,
+            iid,
+            hap_type: HapType::On,
+            value: json!(true),
+        }
+    }
+
+    /// Returns the data sent on a connection once it's dropped.
+    fn sent_data(connection: Connection, outgoing_receiver: mpsc::UnboundedReceiver<Outgoing>) -> Vec<String> {
+        drop(connection);
+        outgoing_receiver
+            .wait()
+            .map(|outgoing| match outgoing.unwrap() {
+                Outgoing::Data(data) => String::from_utf8(data).unwrap(),
+                Outgoing::Upgrade(..) => panic!("unexpected upgrade"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_forward_event() {
+        let controller_id = Uuid::new_v4();
+        let (connection, outgoing_receiver, unpaired_receiver) = connection(controller_id);
+
+        connection.forward(&value_changed(1, 10)).unwrap();
+        connection.forward(&value_changed(1, 11)).unwrap();
+        connection.forward(&Event::CharacteristicRemoved { aid: 1, iid: 10 }).unwrap();
+        connection.forward(&value_changed(1, 10)).unwrap();
+        connection
+            .forward(&Event::ControllerUnpaired { id: Uuid::new_v4() })
+            .unwrap();
+        connection.forward(&Event::ControllerUnpaired { id: controller_id }).unwrap();
+
+        let unpaired_sender = connection.unpaired_sender.clone();
+        let data = sent_data(connection, outgoing_receiver);
+        assert_eq!(data.len(), 1);
+        assert!(data[0].starts_with("EVENT/1.0 200 OK\r\n"));
+        assert!(data[0].ends_with(r#"{"characteristics":[{"iid":10,"aid":1,"value":true}]}"#));
+
+        drop(unpaired_sender);
+        assert_eq!(unpaired_receiver.wait().count(), 1);
+    }
+
+    #[test]
+    fn test_forward_event_with_poisoned_lock() {
+        let (connection, outgoing_receiver, _) = connection(Uuid::new_v4());
+        let event_subscriptions = connection.event_subscriptions.clone();
+        let _ = thread::spawn(move || {
+            let _es = event_subscriptions.lock().unwrap();
+            panic!("poisoning the event subscriptions");
+        })
+        .join();
+
+        // the poisoned lock is reported instead of panicking in the emitting thread
+        assert!(connection.forward(&value_changed(1, 10)).is_err());
+        let flushed = flush_events(&connection.event_subscriptions, &connection.coalescer, &connection.stream_outgoing);
+        assert!(flushed.is_err());
+        assert!(sent_data(connection, outgoing_receiver).is_empty());
+    }
+}
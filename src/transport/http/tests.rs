@@ -0,0 +1,752 @@
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::{self, SocketAddr},
+    str,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use byteorder::{ByteOrder, LittleEndian};
+use chacha20_poly1305_aead;
+use crypto::{curve25519, ed25519};
+use net2::TcpStreamExt;
+use num::BigUint;
+use rand::{self, Rng};
+use ring::{digest, hkdf, hmac};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha512};
+use srp::groups::G_3072;
+use uuid::Uuid;
+
+use crate::{
+    accessory::{outlet, HapAccessory, Information},
+    config::Config,
+    db::{AccessoryList, AccessoryListMember, Database},
+    event::EventEmitter,
+    pin,
+    protocol::{
+        tlv::{self, Type},
+        Device,
+    },
+    test_util::TempStorage,
+    transport::http::{server, ShutdownHandle, Status},
+    Error,
+};
+
+const PIN: &str = "11122333";
+/// The setup code of `PIN`, as entered on a Controller.
+pub(crate) const SETUP_CODE: &str = "111-22-333";
+
+/// An Accessory served on a local port. The server is shut down and its storage removed on drop.
+pub(crate) struct TestServer {
+    pub addr: SocketAddr,
+    shutdown_handle: ShutdownHandle,
+    thread: Option<JoinHandle<()>>,
+    _storage: TempStorage,
+}
+
+impl TestServer {
+    /// Serves an Outlet Accessory on a free local port.
+    pub fn start() -> TestServer {
+        let outlet = outlet::new(Information {
+            name: "Outlet".into(),
+            ..Default::default()
+        })
+        .unwrap();
+        TestServer::start_with(Box::new(outlet))
+    }
+
+    /// Serves `accessory` on a free local port.
+    pub fn start_with(accessory: Box<dyn AccessoryListMember + Send>) -> TestServer {
+        let listener = server::bind(&SocketAddr::new([127, 0, 0, 1].into(), 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let storage = TempStorage::new();
+        let database = Database::new_with_file_storage(storage.path()).unwrap();
+        Device::load_or_new("11:22:33:44:55:66".into(), pin::new(PIN).unwrap(), &database).unwrap();
+        let database = Arc::new(Mutex::new(database));
+        let config = Arc::new(Mutex::new(Config {
+            pin: PIN.into(),
+            ..Default::default()
+        }));
+        let event_emitter = Arc::new(Mutex::new(EventEmitter::new()));
+        let mut accessories = AccessoryList::new(event_emitter.clone());
+        accessories.add_accessory(accessory).unwrap();
+
+        let resource_handler = Arc::new(Mutex::new(None));
+        let shutdown_handle = ShutdownHandle::default();
+
+        let thread = {
+            let shutdown_handle = shutdown_handle.clone();
+            thread::spawn(move || {
+                server::serve(
+                    vec![listener],
+                    &config,
+                    &database,
+                    &accessories,
+                    &event_emitter,
+                    &resource_handler,
+                    &shutdown_handle,
+                )
+                .unwrap()
+            })
+        };
+
+        TestServer {
+            addr,
+            shutdown_handle,
+            thread: Some(thread),
+            _storage: storage,
+        }
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.shutdown_handle.shutdown();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// A Controller with its long-term key pair.
+pub(crate) struct Controller {
+    pub id: Uuid,
+    pub private_key: [u8; 64],
+    pub public_key: [u8; 32],
+}
+
+impl Controller {
+    pub fn new() -> Controller {
+        let (private_key, public_key) = ed25519::keypair(&rand::thread_rng().gen::<[u8; 32]>());
+        Controller {
+            id: Uuid::new_v4(),
+            private_key,
+            public_key,
+        }
+    }
+}
+
+struct SessionKeys {
+    write_key: [u8; 32],
+    read_key: [u8; 32],
+    write_count: u64,
+    read_count: u64,
+}
+
+/// A connection of a Controller to the server, speaking HTTP and, after Pair Verify, the
+/// encrypted framing.
+pub(crate) struct Connection {
+    stream: net::TcpStream,
+    session: Option<SessionKeys>,
+    frames: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+impl Connection {
+    pub fn connect(addr: SocketAddr) -> Connection {
+        let stream = net::TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        Connection {
+            stream,
+            session: None,
+            frames: Vec::new(),
+            buf: Vec::new(),
+        }
+    }
+
+    /// Sends a request and returns the status code and the body of the response.
+    pub fn request(&mut self, method: &str, path: &str, content_type: &str, body: &[u8]) -> (u16, Vec<u8>) {
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: hap\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+            method,
+            path,
+            content_type,
+            body.len()
+        )
+        .into_bytes();
+        request.extend_from_slice(body);
+        self.send(&request);
+        self.read_response()
+    }
+
+    /// Sends a pairing request and returns the decoded TLVs of the response.
+    pub fn tlv_request(&mut self, path: &str, items: Vec<(Type, Vec<u8>)>) -> HashMap<u8, Vec<u8>> {
+        let body = tlv::encode(items.into_iter().map(|(t, v)| (t as u8, v)).collect());
+        let (status, body) = self.request("POST", path, "application/pairing+tlv8", &body);
+        assert_eq!(status, 200);
+        tlv::decode(body)
+    }
+
+    /// Sends a GET request and returns the status code and the parsed JSON body of the response.
+    pub fn get_json(&mut self, path: &str) -> (u16, JsonValue) {
+        let (status, body) = self.request("GET", path, "application/hap+json", &[]);
+        (status, serde_json::from_slice(&body).unwrap_or(JsonValue::Null))
+    }
+
+    /// Sends a PUT request with a JSON body and returns the status code and the parsed JSON body of
+    /// the response.
+    pub fn put_json(&mut self, path: &str, body: &JsonValue) -> (u16, JsonValue) {
+        let body = serde_json::to_vec(body).unwrap();
+        let (status, body) = self.request("PUT", path, "application/hap+json", &body);
+        (status, serde_json::from_slice(&body).unwrap_or(JsonValue::Null))
+    }
+
+    /// Adds a pairing of `controller` with the given permissions, on a session of an admin.
+    pub fn add_pairing(&mut self, controller: &Controller, permissions: u8) {
+        let res = self.tlv_request("/pairings", vec![
+            (Type::State, vec![1]),
+            (Type::Method, vec![3]),
+            (Type::Identifier, controller.id.to_hyphenated().to_string().into_bytes()),
+            (Type::PublicKey, controller.public_key.to_vec()),
+            (Type::Permissions, vec![permissions]),
+        ]);
+        assert_eq!(res.get(&(Type::Error as u8)), None);
+    }
+
+    fn send(&mut self, data: &[u8]) {
+        let data = match self.session {
+            Some(ref mut session) => {
+                let mut frames = Vec::new();
+                for chunk in data.chunks(1024) {
+                    let mut aad = [0; 2];
+                    LittleEndian::write_u16(&mut aad, chunk.len() as u16);
+                    let mut encrypted = Vec::new();
+                    let auth_tag = chacha20_poly1305_aead::encrypt(
+                        &session.write_key,
+                        &frame_nonce(&mut session.write_count),
+                        &aad,
+                        chunk,
+                        &mut encrypted,
+                    )
+                    .unwrap();
+                    frames.extend_from_slice(&aad);
+                    frames.extend(encrypted);
+                    frames.extend_from_slice(&auth_tag);
+                }
+                frames
+            },
+            None => data.to_vec(),
+        };
+        self.stream.write_all(&data).unwrap();
+    }
+
+    /// Reads from the stream, decrypting complete frames once the session is encrypted.
+    fn receive(&mut self) {
+        let mut buf = [0; 4096];
+        let len = self.stream.read(&mut buf).unwrap();
+        assert!(len > 0, "connection closed");
+        match self.session {
+            Some(ref mut session) => {
+                self.frames.extend_from_slice(&buf[..len]);
+                while self.frames.len() >= 2 {
+                    let len = LittleEndian::read_u16(&self.frames) as usize;
+                    if self.frames.len() < len + 18 {
+                        break;
+                    }
+                    chacha20_poly1305_aead::decrypt(
+                        &session.read_key,
+                        &frame_nonce(&mut session.read_count),
+                        &self.frames[..2],
+                        &self.frames[2..len + 2],
+                        &self.frames[len + 2..len + 18],
+                        &mut self.buf,
+                    )
+                    .unwrap();
+                    self.frames.drain(..len + 18);
+                }
+            },
+            None => self.buf.extend_from_slice(&buf[..len]),
+        }
+    }
+
+    fn read_response(&mut self) -> (u16, Vec<u8>) {
+        let header_len = loop {
+            if let Some(pos) = self.buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+            self.receive();
+        };
+        let head = str::from_utf8(&self.buf[..header_len]).unwrap().to_string();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        let content_length = head
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(2, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(name), Some(value)) if name.eq_ignore_ascii_case("content-length") => {
+                        value.trim().parse::<usize>().ok()
+                    },
+                    _ => None,
+                }
+            })
+            .next()
+            .unwrap_or(0);
+        while self.buf.len() < header_len + content_length {
+            self.receive();
+        }
+        let body = self.buf[header_len..header_len + content_length].to_vec();
+        self.buf.drain(..header_len + content_length);
+        (status, body)
+    }
+
+    /// Runs Pair Setup with the given setup code, pairing `controller` as an admin.
+    pub fn pair_setup(&mut self, controller: &Controller, setup_code: &str) -> Result<(), u8> {
+        let m2 = self.tlv_request("/pair-setup", vec![(Type::State, vec![1]), (Type::Method, vec![0])]);
+        tlv_error(&m2)?;
+        let salt = m2.get(&(Type::Salt as u8)).unwrap();
+        let b_pub = m2.get(&(Type::PublicKey as u8)).unwrap();
+
+        let srp = SrpClient::new(setup_code, salt, b_pub);
+        let m4 = self.tlv_request("/pair-setup", vec![
+            (Type::State, vec![3]),
+            (Type::PublicKey, srp.a_pub.clone()),
+            (Type::Proof, srp.proof.clone()),
+        ]);
+        tlv_error(&m4)?;
+        assert_eq!(m4.get(&(Type::Proof as u8)), Some(&srp.server_proof));
+
+        let encryption_key = derive_key(&srp.key, b"Pair-Setup-Encrypt-Salt", b"Pair-Setup-Encrypt-Info");
+        let controller_x = derive_key(
+            &srp.key,
+            b"Pair-Setup-Controller-Sign-Salt",
+            b"Pair-Setup-Controller-Sign-Info",
+        );
+        let controller_id = controller.id.to_hyphenated().to_string();
+        let mut controller_info = controller_x.to_vec();
+        controller_info.extend(controller_id.as_bytes());
+        controller_info.extend(&controller.public_key);
+        let mut sub_tlv = HashMap::new();
+        sub_tlv.insert(Type::Identifier as u8, controller_id.into_bytes());
+        sub_tlv.insert(Type::PublicKey as u8, controller.public_key.to_vec());
+        sub_tlv.insert(
+            Type::Signature as u8,
+            ed25519::signature(&controller_info, &controller.private_key).to_vec(),
+        );
+        let m6 = self.tlv_request("/pair-setup", vec![
+            (Type::State, vec![5]),
+            (
+                Type::EncryptedData,
+                encrypt(&encryption_key, b"PS-Msg05", &tlv::encode(sub_tlv)),
+            ),
+        ]);
+        tlv_error(&m6)?;
+
+        // the accessory proves its identity with its long-term key
+        let sub_tlv = tlv::decode(decrypt(
+            &encryption_key,
+            b"PS-Msg06",
+            m6.get(&(Type::EncryptedData as u8)).unwrap(),
+        ));
+        let accessory_x = derive_key(
+            &srp.key,
+            b"Pair-Setup-Accessory-Sign-Salt",
+            b"Pair-Setup-Accessory-Sign-Info",
+        );
+        let accessory_ltpk = sub_tlv.get(&(Type::PublicKey as u8)).unwrap();
+        let mut accessory_info = accessory_x.to_vec();
+        accessory_info.extend(sub_tlv.get(&(Type::Identifier as u8)).unwrap());
+        accessory_info.extend(accessory_ltpk);
+        assert!(ed25519::verify(
+            &accessory_info,
+            accessory_ltpk,
+            sub_tlv.get(&(Type::Signature as u8)).unwrap(),
+        ));
+
+        Ok(())
+    }
+
+    /// Runs Pair Verify as `controller`, signing with `signing_key`. Switches the connection to the
+    /// encrypted framing on success.
+    pub fn pair_verify(&mut self, controller: &Controller, signing_key: &[u8; 64]) -> Result<(), u8> {
+        let a = rand::thread_rng().gen::<[u8; 32]>();
+        let a_pub = curve25519::curve25519_base(&a).to_vec();
+        let m2 = self.tlv_request("/pair-verify", vec![
+            (Type::State, vec![1]),
+            (Type::PublicKey, a_pub.clone()),
+        ]);
+        tlv_error(&m2)?;
+        let b_pub = m2.get(&(Type::PublicKey as u8)).unwrap().clone();
+        let shared_secret = curve25519::curve25519(&a, &b_pub);
+        let session_key = derive_key(&shared_secret, b"Pair-Verify-Encrypt-Salt", b"Pair-Verify-Encrypt-Info");
+
+        let sub_tlv = tlv::decode(decrypt(
+            &session_key,
+            b"PV-Msg02",
+            m2.get(&(Type::EncryptedData as u8)).unwrap(),
+        ));
+        assert!(sub_tlv.get(&(Type::Identifier as u8)).is_some());
+        assert!(sub_tlv.get(&(Type::Signature as u8)).is_some());
+
+        let controller_id = controller.id.to_hyphenated().to_string();
+        let mut controller_info = a_pub;
+        controller_info.extend(controller_id.as_bytes());
+        controller_info.extend(&b_pub);
+        let mut sub_tlv = HashMap::new();
+        sub_tlv.insert(Type::Identifier as u8, controller_id.into_bytes());
+        sub_tlv.insert(
+            Type::Signature as u8,
+            ed25519::signature(&controller_info, signing_key).to_vec(),
+        );
+        let m4 = self.tlv_request("/pair-verify", vec![
+            (Type::State, vec![3]),
+            (
+                Type::EncryptedData,
+                encrypt(&session_key, b"PV-Msg03", &tlv::encode(sub_tlv)),
+            ),
+        ]);
+        tlv_error(&m4)?;
+
+        self.session = Some(SessionKeys {
+            write_key: derive_key(&shared_secret, b"Control-Salt", b"Control-Write-Encryption-Key"),
+            read_key: derive_key(&shared_secret, b"Control-Salt", b"Control-Read-Encryption-Key"),
+            write_count: 0,
+            read_count: 0,
+        });
+
+        Ok(())
+    }
+}
+
+/// The client side of SRP-6a with the 3072-bit group and SHA-512, as used by Pair Setup.
+struct SrpClient {
+    a_pub: Vec<u8>,
+    proof: Vec<u8>,
+    server_proof: Vec<u8>,
+    key: Vec<u8>,
+}
+
+impl SrpClient {
+    fn new(setup_code: &str, salt: &[u8], b_pub: &[u8]) -> SrpClient {
+        let group = &G_3072;
+        let pad = |n: &BigUint| {
+            let bytes = n.to_bytes_be();
+            let mut padded = vec![0; 384 - bytes.len()];
+            padded.extend(bytes);
+            padded
+        };
+
+        let a = BigUint::from_bytes_be(&rand::thread_rng().gen::<[u8; 32]>());
+        let a_pub = group.g.modpow(&a, &group.n);
+        let b_pub = BigUint::from_bytes_be(b_pub);
+
+        let mut d = Sha512::new();
+        d.input(b"Pair-Setup:");
+        d.input(setup_code.as_bytes());
+        let identity_hash = d.result();
+        let mut d = Sha512::new();
+        d.input(salt);
+        d.input(&identity_hash);
+        let x = BigUint::from_bytes_be(&d.result());
+
+        let mut d = Sha512::new();
+        d.input(&group.n.to_bytes_be());
+        d.input(&pad(&group.g));
+        let k = BigUint::from_bytes_be(&d.result());
+
+        let mut d = Sha512::new();
+        d.input(&pad(&a_pub));
+        d.input(&pad(&b_pub));
+        let u = BigUint::from_bytes_be(&d.result());
+
+        // S = (B - k * g^x) ^ (a + u * x) % N
+        let kgx = k * group.g.modpow(&x, &group.n) % &group.n;
+        let base = (&b_pub + &group.n - kgx) % &group.n;
+        let s = base.modpow(&(a + u * x), &group.n);
+        let key = Sha512::digest(&s.to_bytes_be()).to_vec();
+
+        let hn = Sha512::digest(&group.n.to_bytes_be());
+        let hg = Sha512::digest(&group.g.to_bytes_be());
+        let hng = hn.iter().zip(hg.iter()).map(|(n, g)| n ^ g).collect::<Vec<u8>>();
+        let a_pub = pad(&a_pub);
+        let mut d = Sha512::new();
+        d.input(&hng);
+        d.input(&Sha512::digest(b"Pair-Setup"));
+        d.input(salt);
+        d.input(&a_pub);
+        d.input(&pad(&b_pub));
+        d.input(&key);
+        let proof = d.result().to_vec();
+
+        let mut d = Sha512::new();
+        d.input(&a_pub);
+        d.input(&proof);
+        d.input(&key);
+        let server_proof = d.result().to_vec();
+
+        SrpClient {
+            a_pub,
+            proof,
+            server_proof,
+            key,
+        }
+    }
+}
+
+fn tlv_error(response: &HashMap<u8, Vec<u8>>) -> Result<(), u8> {
+    match response.get(&(Type::Error as u8)) {
+        Some(error) => Err(error[0]),
+        None => Ok(()),
+    }
+}
+
+fn derive_key(secret: &[u8], salt: &[u8], info: &[u8]) -> [u8; 32] {
+    let mut key = [0; 32];
+    hkdf::extract_and_expand(&hmac::SigningKey::new(&digest::SHA512, salt), secret, info, &mut key);
+    key
+}
+
+fn frame_nonce(count: &mut u64) -> Vec<u8> {
+    let mut nonce = vec![0; 12];
+    LittleEndian::write_u64(&mut nonce[4..], *count);
+    *count += 1;
+    nonce
+}
+
+fn encrypt(key: &[u8; 32], nonce: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut nonce_bytes = vec![0; 4];
+    nonce_bytes.extend(nonce);
+    let mut encrypted = Vec::new();
+    let auth_tag = chacha20_poly1305_aead::encrypt(key, &nonce_bytes, &[], data, &mut encrypted).unwrap();
+    encrypted.extend(&auth_tag);
+    encrypted
+}
+
+fn decrypt(key: &[u8; 32], nonce: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut nonce_bytes = vec![0; 4];
+    nonce_bytes.extend(nonce);
+    let mut decrypted = Vec::new();
+    chacha20_poly1305_aead::decrypt(
+        key,
+        &nonce_bytes,
+        &[],
+        &data[..data.len() - 16],
+        &data[data.len() - 16..],
+        &mut decrypted,
+    )
+    .unwrap();
+    decrypted
+}
+
+#[test]
+fn test_pair_setup_pair_verify_and_get_accessories() {
+    let server = TestServer::start();
+    let controller = Controller::new();
+
+    assert_eq!(
+        Connection::connect(server.addr).pair_setup(&controller, SETUP_CODE),
+        Ok(())
+    );
+
+    let mut connection = Connection::connect(server.addr);
+    assert_eq!(connection.pair_verify(&controller, &controller.private_key), Ok(()));
+    let (status, body) = connection.get_json("/accessories");
+    assert_eq!(status, 200);
+    assert_eq!(body["accessories"][0]["aid"], 1);
+    assert_eq!(body["accessories"][0]["services"][0]["type"], "3E");
+
+    // the encrypted session is kept alive for further requests
+    let (status, _) = connection.get_json("/accessories");
+    assert_eq!(status, 200);
+}
+
+#[test]
+fn test_pair_setup_with_wrong_pin() {
+    let server = TestServer::start();
+    let controller = Controller::new();
+
+    let mut connection = Connection::connect(server.addr);
+    assert_eq!(
+        connection.pair_setup(&controller, "111-22-334"),
+        Err(tlv::Error::Authentication as u8)
+    );
+}
+
+#[test]
+fn test_pair_verify_with_wrong_controller_key() {
+    let server = TestServer::start();
+    let controller = Controller::new();
+    Connection::connect(server.addr).pair_setup(&controller, SETUP_CODE).unwrap();
+
+    let other_controller = Controller::new();
+    let mut connection = Connection::connect(server.addr);
+    assert_eq!(
+        connection.pair_verify(&controller, &other_controller.private_key),
+        Err(tlv::Error::Authentication as u8)
+    );
+}
+
+#[test]
+fn test_pair_verify_with_unknown_controller() {
+    let server = TestServer::start();
+    Connection::connect(server.addr)
+        .pair_setup(&Controller::new(), SETUP_CODE)
+        .unwrap();
+
+    let unknown_controller = Controller::new();
+    let mut connection = Connection::connect(server.addr);
+    assert_eq!(
+        connection.pair_verify(&unknown_controller, &unknown_controller.private_key),
+        Err(tlv::Error::Authentication as u8)
+    );
+}
+
+/// Returns the instance ID of the first Characteristic of the given (short) type.
+fn characteristic_iid(accessories: &JsonValue, hap_type: &str) -> u64 {
+    accessories["accessories"][0]["services"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .flat_map(|s| s["characteristics"].as_array().unwrap())
+        .find(|c| c["type"] == hap_type)
+        .unwrap()["iid"]
+        .as_u64()
+        .unwrap()
+}
+
+#[test]
+fn test_interleaved_requests_of_two_controllers() {
+    let server = TestServer::start();
+    let (admin, user) = (Controller::new(), Controller::new());
+    Connection::connect(server.addr).pair_setup(&admin, SETUP_CODE).unwrap();
+
+    let mut a = Connection::connect(server.addr);
+    a.pair_verify(&admin, &admin.private_key).unwrap();
+    a.add_pairing(&user, 0);
+    let mut b = Connection::connect(server.addr);
+    b.pair_verify(&user, &user.private_key).unwrap();
+
+    let (_, accessories) = a.get_json("/accessories");
+    let on = characteristic_iid(&accessories, "25");
+    let read_path = format!("/characteristics?id=1.{}", on);
+    let write = |value| serde_json::json!({ "characteristics": [{ "aid": 1, "iid": on, "value": value }] });
+
+    // both sessions stay open, and every write is visible to the other controller's next read
+    assert_eq!(a.put_json("/characteristics", &write(true)).0, 204);
+    assert_eq!(b.get_json(&read_path).1["characteristics"][0]["value"], true);
+    assert_eq!(b.put_json("/characteristics", &write(false)).0, 204);
+    assert_eq!(a.get_json(&read_path).1["characteristics"][0]["value"], false);
+
+    // a request arriving in parts doesn't hold up the other connection in the meantime
+    let request = format!("GET {} HTTP/1.1\r\nHost: hap\r\n\r\n", read_path).into_bytes();
+    let (head, tail) = request.split_at(10);
+    a.send(head);
+    assert_eq!(b.put_json("/characteristics", &write(true)).0, 204);
+    assert_eq!(b.get_json(&read_path).1["characteristics"][0]["value"], true);
+    a.send(tail);
+    let (status, body) = a.read_response();
+    assert_eq!(status, 200);
+    let body: JsonValue = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["characteristics"][0]["value"], true);
+}
+
+#[test]
+fn test_reset_connections_dont_stop_the_server() {
+    let server = TestServer::start();
+
+    // connections reset right away may be gone by the time they're accepted
+    for _ in 0..20 {
+        let stream = net::TcpStream::connect(server.addr).unwrap();
+        TcpStreamExt::set_linger(&stream, Some(Duration::from_secs(0))).unwrap();
+    }
+
+    let (status, body) = Connection::connect(server.addr).get_json("/.well-known/hap/health");
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "ok");
+}
+
+#[test]
+fn test_pair_verify_with_low_order_public_key() {
+    let server = TestServer::start();
+
+    let mut connection = Connection::connect(server.addr);
+    let res = connection.tlv_request("/pair-verify", vec![(Type::State, vec![1]), (Type::PublicKey, vec![0; 32])]);
+    assert_eq!(res.get(&(Type::Error as u8)), Some(&vec![tlv::Error::Authentication as u8]));
+}
+
+#[test]
+fn test_concurrent_pair_setup_is_refused() {
+    let server = TestServer::start();
+    let controller = Controller::new();
+    let mut first = Connection::connect(server.addr);
+    let mut second = Connection::connect(server.addr);
+    let m1 = || vec![(Type::State, vec![1]), (Type::Method, vec![0])];
+
+    assert_eq!(tlv_error(&first.tlv_request("/pair-setup", m1())), Ok(()));
+    assert_eq!(
+        tlv_error(&second.tlv_request("/pair-setup", m1())),
+        Err(tlv::Error::Busy as u8)
+    );
+
+    // a failed attempt releases the lock for the other connection
+    assert_eq!(
+        first.pair_setup(&controller, "111-22-334"),
+        Err(tlv::Error::Authentication as u8)
+    );
+    assert_eq!(second.pair_setup(&controller, SETUP_CODE), Ok(()));
+}
+
+/// Returns an Outlet counting the calls of its identify callback, which fails if `fail` is set.
+fn identifying_outlet(fail: bool) -> (Box<dyn AccessoryListMember + Send>, Arc<AtomicUsize>) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut outlet = outlet::new(Information::default()).unwrap();
+    let counted = calls.clone();
+    outlet.set_identify_callback(Box::new(move || {
+        counted.fetch_add(1, Ordering::SeqCst);
+        if fail {
+            return Err(Error::from_str("the LED is broken"));
+        }
+        Ok(())
+    }));
+    (Box::new(outlet), calls)
+}
+
+#[test]
+fn test_identify_calls_the_identify_callback() {
+    let (outlet, calls) = identifying_outlet(false);
+    let server = TestServer::start_with(outlet);
+
+    let (status, _) = Connection::connect(server.addr).request("POST", "/identify", "application/hap+json", &[]);
+    assert_eq!(status, 204);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_identify_reports_a_failed_identify_callback() {
+    let (outlet, calls) = identifying_outlet(true);
+    let server = TestServer::start_with(outlet);
+
+    let (status, body) = Connection::connect(server.addr).request("POST", "/identify", "application/hap+json", &[]);
+    assert_eq!(status, 500);
+    let body: JsonValue = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["status"], Status::ServiceCommunicationFailure as i32);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_identify_characteristic_write_calls_the_identify_callback() {
+    let (outlet, calls) = identifying_outlet(true);
+    let server = TestServer::start_with(outlet);
+    let controller = Controller::new();
+    Connection::connect(server.addr).pair_setup(&controller, SETUP_CODE).unwrap();
+    let mut connection = Connection::connect(server.addr);
+    connection.pair_verify(&controller, &controller.private_key).unwrap();
+
+    // the Identify Characteristic comes first in the Accessory Information Service
+    let (_, body) = connection.get_json("/accessories");
+    let identify = &body["accessories"][0]["services"][0]["characteristics"][0];
+    assert_eq!(identify["type"], "14");
+    let write = serde_json::json!({ "characteristics": [{ "aid": 1, "iid": identify["iid"], "value": true }] });
+    let (status, body) = connection.put_json("/characteristics", &write);
+
+    assert_eq!(status, 207);
+    assert_eq!(body["characteristics"][0]["status"], Status::ServiceCommunicationFailure as i32);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
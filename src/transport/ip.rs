@@ -1,24 +1,47 @@
 use std::{
+    collections::HashMap,
+    fmt,
     net::SocketAddr,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
+use log::{error, warn};
+
 use crate::{
+    accessory::{bridge, Information},
+    characteristic::HapCharacteristic,
     config::{Config, ConfigPtr},
     db::{AccessoryList, AccessoryListMember, AccessoryListPtr, Database, DatabasePtr, FileStorage, Storage},
     event::{Event, EventEmitter, EventEmitterPtr},
     pin,
-    protocol::Device,
+    protocol::{ControllerInfo, Device},
     transport::{
         bonjour::StatusFlag,
-        http,
-        mdns::{Responder, ResponderPtr},
+        http::{self, ConnectionInfo, ResourceHandlerPtr, ResourceRequest, ShutdownHandle},
+        mdns::{self, MdnsResponder, Responder, ResponderPtr},
         Transport,
     },
+    Error,
     Result,
 };
 
-/// Transport via TCP/IP.
+/// Storage key of the persisted Characteristic values.
+const CHARACTERISTIC_VALUES_KEY: &str = "characteristic_values";
+/// Interval in which the addresses of the host are checked for changes while serving.
+const HOST_ADDRESS_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Transport via TCP/IP. Cloning an `IpTransport` is shallow, the clones share the same config,
+/// database and Accessories.
+///
+/// The values of the readable and writable Characteristics, e.g. the On and Brightness of a
+/// Lightbulb, are persisted whenever they change and restored when an Accessory with the same
+/// serial number is added after a restart. Accessories should therefore have unique serial numbers.
 #[derive(Clone)]
 pub struct IpTransport<S: Storage> {
     config: ConfigPtr,
@@ -27,6 +50,34 @@ pub struct IpTransport<S: Storage> {
     accessories: AccessoryList,
     event_emitter: EventEmitterPtr,
     mdns_responder: ResponderPtr,
+    resource_handler: ResourceHandlerPtr,
+    local_addr: Arc<Mutex<Option<SocketAddr>>>,
+    shutdown_handle: ShutdownHandle,
+    characteristic_values: Arc<Mutex<CharacteristicValues>>,
+}
+
+/// Persisted values of Characteristics and the keys they are persisted under, by the Accessory and
+/// instance IDs of the Characteristics of the added Accessories.
+#[derive(Debug, Default)]
+struct CharacteristicValues {
+    keys: HashMap<(u64, u64), String>,
+    values: serde_json::Map<String, serde_json::Value>,
+}
+
+impl<S: Storage + fmt::Debug> fmt::Debug for IpTransport<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IpTransport")
+            .field("config", &self.config)
+            .field("storage", &self.storage)
+            .field("database", &self.database)
+            .field("accessories", &self.accessories)
+            .field("event_emitter", &self.event_emitter)
+            .field("mdns_responder", &self.mdns_responder)
+            .field("local_addr", &self.local_addr)
+            .field("shutdown_handle", &self.shutdown_handle)
+            .field("characteristic_values", &self.characteristic_values)
+            .finish_non_exhaustive()
+    }
 }
 
 impl IpTransport<FileStorage> {
@@ -72,7 +123,34 @@ impl IpTransport<FileStorage> {
     ///
     /// //ip_transport.start().unwrap();
     /// ```
-    pub fn new(mut config: Config) -> Result<IpTransport<FileStorage>> {
+    pub fn new(config: Config) -> Result<IpTransport<FileStorage>> {
+        let responder = Responder::new(&config.name, config.port, config.txt_records());
+        IpTransport::new_with_responder(config, responder)
+    }
+
+    /// Creates a new `IpTransport` announcing the Accessory via the given mDNS responder instead of
+    /// the built-in one, e.g. one registering the service with a responder already running on the
+    /// host. The name, port and TXT records of the responder are set from the `Config`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::{
+    ///     transport::{mdns::Responder, IpTransport},
+    ///     Config,
+    /// };
+    ///
+    /// let config = Config {
+    ///     name: "Acme Outlet".into(),
+    ///     ..Default::default()
+    /// };
+    /// let responder = Responder::new("", 0, Default::default());
+    /// let ip_transport = IpTransport::new_with_responder(config, responder).unwrap();
+    /// ```
+    pub fn new_with_responder(
+        mut config: Config,
+        mut responder: impl MdnsResponder + 'static,
+    ) -> Result<IpTransport<FileStorage>> {
         let storage = FileStorage::new(&config.storage_path)?;
         let database = Database::new_with_file_storage(&config.storage_path)?;
 
@@ -83,11 +161,37 @@ impl IpTransport<FileStorage> {
         let pin = pin::new(&config.pin)?;
         let device = Device::load_or_new(config.device_id.to_hex_string(), pin, &database)?;
         let event_emitter = Arc::new(Mutex::new(EventEmitter::new()));
-        let mdns_responder = Arc::new(Mutex::new(Responder::new(
-            &config.name,
-            config.port,
-            config.txt_records(),
-        )));
+        responder.set_name(config.effective_name());
+        responder.set_port(config.port);
+        responder.update_txt_records(config.txt_records())?;
+        let mdns_responder: ResponderPtr = Arc::new(Mutex::new(Box::new(responder)));
+        let characteristic_values = Arc::new(Mutex::new(CharacteristicValues {
+            keys: HashMap::new(),
+            values: match storage.get_bytes(CHARACTERISTIC_VALUES_KEY) {
+                Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                    warn!("couldn't load the persisted characteristic values: {}", e);
+                    serde_json::Map::new()
+                }),
+                // nothing persisted yet
+                Err(_) => serde_json::Map::new(),
+            },
+        }));
+        {
+            let characteristic_values = characteristic_values.clone();
+            let storage = storage.clone();
+            event_emitter
+                .lock()
+                .expect("couldn't access event_emitter")
+                .add_listener(Box::new(move |event| {
+                    if let Event::CharacteristicValueChanged { aid, iid, ref value, .. } = *event {
+                        if let Err(e) =
+                            persist_characteristic_value(&characteristic_values, &storage, aid, iid, value)
+                        {
+                            error!("couldn't persist the value of characteristic {}.{}: {}", aid, iid, e);
+                        }
+                    }
+                }));
+        }
 
         let ip_transport = IpTransport {
             config: Arc::new(Mutex::new(config)),
@@ -96,84 +200,985 @@ impl IpTransport<FileStorage> {
             accessories: AccessoryList::new(event_emitter.clone()),
             event_emitter,
             mdns_responder,
+            resource_handler: Arc::new(Mutex::new(None)),
+            local_addr: Arc::new(Mutex::new(None)),
+            shutdown_handle: ShutdownHandle::default(),
+            characteristic_values,
         };
         device.save_to(&ip_transport.database)?;
 
         Ok(ip_transport)
     }
-}
 
-impl Transport for IpTransport<FileStorage> {
-    fn start(&mut self) -> Result<()> {
+    /// Returns the socket address the transport is bound to once it is started, e.g. to find out
+    /// the port assigned by the OS when the configured port is `0`. As `start` blocks while
+    /// serving, this is called on a clone of the `IpTransport`.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.local_addr
+            .lock()
+            .expect("couldn't access local_addr")
+            .ok_or_else(|| Error::from_str("transport isn't started"))
+    }
+
+    /// Starts the transport on a background thread named `hap-server` and returns right away. The
+    /// returned `JoinHandle` yields the result of `start` once the server has terminated, e.g. after
+    /// `stop` was called.
+    pub fn start_background(&self) -> Result<JoinHandle<Result<()>>> {
+        let mut ip_transport = self.clone();
+        let handle = thread::Builder::new()
+            .name("hap-server".into())
+            .spawn(move || ip_transport.start())?;
+        Ok(handle)
+    }
+
+    /// Starts the mDNS announcement again after `unannounce`, e.g. at the end of a maintenance
+    /// window. Does nothing if the Accessory is already announced. Fails if the transport isn't
+    /// started.
+    pub fn announce(&self) -> Result<()> {
+        if !self.shutdown_handle.is_serving() {
+            return Err(Error::from_str("transport isn't started"));
+        }
+        let mut mdns_responder = self.mdns_responder.lock().expect("couldn't access mDNS responder");
+        if !mdns_responder.is_announcing() {
+            mdns_responder.start()?;
+        }
+        Ok(())
+    }
+
+    /// Stops the mDNS announcement while the transport keeps serving, e.g. during maintenance, so
+    /// controllers don't discover the Accessory. Established connections aren't affected. Updates
+    /// of the TXT records, e.g. of the configuration number, are announced once `announce` is
+    /// called.
+    pub fn unannounce(&self) -> Result<()> {
         self.mdns_responder
+            .lock()
+            .expect("couldn't access mDNS responder")
+            .stop()
+    }
+
+    /// Restarts the mDNS announcement with the current addresses of the host, e.g. after a new DHCP
+    /// lease changed its IP address. An unannounced Accessory is announced again. Fails if the
+    /// transport isn't started.
+    ///
+    /// While the transport is serving, changes of the host addresses are checked for every 10
+    /// seconds and a running announcement is restarted automatically.
+    pub fn reannounce(&self) -> Result<()> {
+        if !self.shutdown_handle.is_serving() {
+            return Err(Error::from_str("transport isn't started"));
+        }
+        let mdns_interface = self.config.lock().expect("couldn't access config").mdns_interface()?;
+        let mut mdns_responder = self.mdns_responder.lock().expect("couldn't access mDNS responder");
+        mdns_responder.stop()?;
+        mdns_responder.set_interface(mdns_interface)?;
+        mdns_responder.start()
+    }
+
+    /// Restarts a running mDNS announcement whenever the addresses of the host change, until
+    /// `watching` is unset.
+    fn watch_host_addresses(&self, watching: &AtomicBool) {
+        let mut addresses = mdns::host_addresses();
+        let mut since_check = Duration::from_secs(0);
+        while watching.load(Ordering::SeqCst) {
+            // sleeping in short steps, so the watcher exits soon after the transport is stopped
+            thread::sleep(Duration::from_secs(1));
+            since_check += Duration::from_secs(1);
+            if since_check < HOST_ADDRESS_CHECK_INTERVAL {
+                continue;
+            }
+            since_check = Duration::from_secs(0);
+            let current_addresses = mdns::host_addresses();
+            if current_addresses == addresses {
+                continue;
+            }
+            addresses = current_addresses;
+            let announcing = self
+                .mdns_responder
+                .lock()
+                .expect("couldn't access mDNS responder")
+                .is_announcing();
+            if announcing {
+                if let Err(e) = self.reannounce() {
+                    error!("couldn't reannounce after the host addresses changed: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Returns a `ShutdownHandle` to stop the transport from another thread while `start` is
+    /// blocking.
+    pub fn shutdown_handle(&self) -> ShutdownHandle { self.shutdown_handle.clone() }
+
+    /// Returns a snapshot of the `Config` of the transport. Once the transport is started, `port`
+    /// holds the port served on, i.e. the one assigned by the OS if `0` was configured.
+    pub fn config(&self) -> Config { self.config.lock().expect("couldn't access config").clone() }
+
+    /// Returns the number of currently open controller connections.
+    pub fn connection_count(&self) -> usize { self.shutdown_handle.connection_count() }
+
+    /// Returns information about the currently open controller connections, in the order they were
+    /// opened.
+    pub fn active_connections(&self) -> Vec<ConnectionInfo> { self.shutdown_handle.connections() }
+
+    /// Returns information about all paired controllers.
+    pub fn paired_controllers(&self) -> Result<Vec<ControllerInfo>> {
+        let pairings = self.database.lock().expect("couldn't access database").list_pairings()?;
+        Ok(pairings.into_iter().map(ControllerInfo::from).collect())
+    }
+
+    /// Sets the handler answering the resource requests of controllers, e.g. for the snapshots shown
+    /// on the tiles of camera and video doorbell Accessories. The handler returns the requested
+    /// image JPEG encoded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::{
+    ///     accessory::{ip_camera, Category, Information},
+    ///     transport::{IpTransport, ResourceRequest},
+    ///     Config,
+    /// };
+    ///
+    /// # fn take_snapshot(width: u32, height: u32) -> hap::Result<Vec<u8>> { Ok(vec![]) }
+    /// let config = Config {
+    ///     name: "Acme Camera".into(),
+    ///     category: Category::IPCamera,
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let mut ip_transport = IpTransport::new(config).unwrap();
+    /// ip_transport.set_resource_handler(|request: ResourceRequest| {
+    ///     take_snapshot(request.image_width.unwrap_or(640), request.image_height.unwrap_or(480))
+    /// });
+    /// ```
+    pub fn set_resource_handler(
+        &mut self,
+        resource_handler: impl Fn(ResourceRequest) -> Result<Vec<u8>> + Send + 'static,
+    ) {
+        *self.resource_handler.lock().expect("couldn't access resource_handler") = Some(Box::new(resource_handler));
+    }
+
+    /// Adds a listener that is called with every `Event` emitted by the transport. Listeners are
+    /// called synchronously and mustn't block.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hap::{transport::IpTransport, Config, Event};
+    ///
+    /// let mut ip_transport = IpTransport::new(Config::default()).unwrap();
+    /// ip_transport.add_event_listener(|event: &Event| match event {
+    ///     Event::PairSetupFailed { attempts } => println!("pair setup failed {} times", attempts),
+    ///     Event::ConnectionIdleTimeout { remote_addr } => println!("closed idle connection of {}", remote_addr),
+    ///     Event::StatusFlagChanged { status_flag } => println!("status flag is now {:?}", status_flag),
+    ///     _ => {},
+    /// });
+    /// ```
+    pub fn add_event_listener(&mut self, listener: impl Fn(&Event) + Send + 'static) {
+        self.event_emitter
             .lock()
             .expect("couldn't access event_emitter")
-            .start();
+            .add_listener(Box::new(listener));
+    }
+
+    /// Adds an Accessory bridged by this transport and returns a pointer to the added Accessory.
+    ///
+    /// If no Accessory has been added yet, a Bridge Accessory named after `Config::name` is added
+    /// first, so the Bridge gets the Accessory ID `1` and the bridged Accessories get IDs from `2`
+    /// on, as HAP requires. If Accessories have been added before, the first of them is expected to
+    /// be the Bridge. `Config::category` should be set to `Category::Bridge`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use hap::{
+    ///     accessory::{lightbulb, outlet, Category, Information},
+    ///     transport::{IpTransport, Transport},
+    ///     Config,
+    /// };
+    ///
+    /// let mut ip_transport = IpTransport::new(Config {
+    ///     name: "Acme Bridge".into(),
+    ///     category: Category::Bridge,
+    ///     ..Default::default()
+    /// })
+    /// .unwrap();
+    ///
+    /// let outlet = outlet::new(Information {
+    ///     name: "Outlet".into(),
+    ///     ..Default::default()
+    /// })
+    /// .unwrap();
+    /// let lightbulb = lightbulb::new(Information {
+    ///     name: "Lightbulb".into(),
+    ///     ..Default::default()
+    /// })
+    /// .unwrap();
+    ///
+    /// // the Bridge Accessory gets AID 1, the outlet AID 2 and the lightbulb AID 3
+    /// ip_transport.add_bridged_accessory(outlet).unwrap();
+    /// ip_transport.add_bridged_accessory(lightbulb).unwrap();
+    ///
+    /// ip_transport.start().unwrap();
+    /// ```
+    pub fn add_bridged_accessory<A: 'static + AccessoryListMember + Send>(
+        &mut self,
+        accessory: A,
+    ) -> Result<AccessoryListPtr> {
+        let has_bridge = !self
+            .accessories
+            .accessories
+            .lock()
+            .expect("couldn't access accessories")
+            .is_empty();
+        if !has_bridge {
+            let name = self.config.lock().expect("couldn't access config").name.clone();
+            let bridge = bridge::new(Information {
+                name,
+                ..Default::default()
+            })?;
+            self.accessories.add_accessory(Box::new(bridge))?;
+        }
+        let accessory = self.accessories.add_accessory(Box::new(accessory))?;
+        self.topology_changed()?;
+        Ok(accessory)
+    }
+
+    /// Returns the number of unsuccessful pair setup attempts, i.e. attempts with a wrong setup code.
+    /// The counter is persisted and reset on a successful pairing or a factory reset. Once it
+    /// reaches 100, pair setup is refused until the accessory is factory reset.
+    pub fn unsuccessful_setup_attempts(&self) -> Result<u64> {
+        let (attempts, _) = self
+            .database
+            .lock()
+            .expect("couldn't access database")
+            .get_unsuccessful_setup_attempts()?;
+        Ok(attempts)
+    }
+
+    /// Performs a factory reset. All pairings are removed, the long-term key pair of the device is
+    /// regenerated, the counter of unsuccessful pair setup attempts is cleared, the configuration
+    /// number is incremented and the accessory is announced as not paired again. A
+    /// `ControllerUnpaired` event is emitted for every removed pairing, followed by a
+    /// `DeviceUnpaired` event if there were any.
+    pub fn factory_reset(&mut self) -> Result<()> {
+        let mut device = Device::load_from(&self.database)?;
+        let removed = device.rotate_keypair(&self.database)?;
+        self.database
+            .lock()
+            .expect("couldn't access database")
+            .set_unsuccessful_setup_attempts(0, 0)?;
+
+        let events = {
+            let mut c = self.config.lock().expect("couldn't access config");
+            let previous_status_flag = c.status_flag;
+            c.increment_configuration_number();
+            c.status_flag = StatusFlag::NotPaired;
+            c.update_hash();
+            c.save_to(&self.storage)?;
+            self.mdns_responder
+                .lock()
+                .expect("couldn't access mDNS responder")
+                .update_txt_records(c.txt_records())?;
+            bonjour_events(previous_status_flag, &c)
+        };
+
+        let event_emitter = self.event_emitter.lock().expect("couldn't access event_emitter");
+        for &id in &removed {
+            event_emitter.emit(&Event::ControllerUnpaired { id });
+        }
+        if !removed.is_empty() {
+            event_emitter.emit(&Event::DeviceUnpaired);
+        }
+        for event in &events {
+            event_emitter.emit(event);
+        }
+        event_emitter.emit(&Event::FactoryReset);
 
-        let (ip, port) = {
+        Ok(())
+    }
+
+    /// Increments the configuration number if Accessories, Services or Characteristics were added
+    /// or removed since it was last persisted, so controllers fetch the attribute database again.
+    /// The new number is persisted and announced right away.
+    fn update_configuration_number(&self) -> Result<()> {
+        let topology_hash = self.accessories.topology_hash()?;
+        let changed = match self.storage.get_u64("topology_hash") {
+            Ok(previous_hash) => previous_hash != topology_hash,
+            // nothing to compare to on the very first start
+            Err(_) => false,
+        };
+        self.storage.set_u64("topology_hash", topology_hash)?;
+        if changed {
+            let events = {
+                let mut c = self.config.lock().expect("couldn't access config");
+                c.increment_configuration_number();
+                c.update_hash();
+                c.save_to(&self.storage)?;
+                self.mdns_responder
+                    .lock()
+                    .expect("couldn't access mDNS responder")
+                    .update_txt_records(c.txt_records())?;
+                bonjour_events(c.status_flag, &c)
+            };
+            let event_emitter = self.event_emitter.lock().expect("couldn't access event_emitter");
+            for event in &events {
+                event_emitter.emit(event);
+            }
+        }
+        Ok(())
+    }
+
+    /// Probes the mDNS instance name with the mDNS responder and picks one with a numeric suffix if
+    /// another device on the network already announces it. The chosen name is persisted and `Event::MdnsNameChanged` is
+    /// emitted if it differs from the one announced before.
+    fn update_mdns_name(&self) -> Result<()> {
+        let (name, previous) = {
             let c = self.config.lock().expect("couldn't access config");
-            (c.ip, c.port)
+            (c.name.clone(), c.mdns_name.clone())
         };
+        let mut mdns_responder = self.mdns_responder.lock().expect("couldn't access mDNS responder");
+        let mdns_name = mdns::resolve_name(&name, previous.as_deref(), |n, deadline| {
+            mdns_responder.name_in_use(n, deadline)
+        });
+        let changed = mdns_name != previous.as_deref().unwrap_or(&name);
+        {
+            let mut c = self.config.lock().expect("couldn't access config");
+            c.mdns_name = Some(mdns_name.clone());
+            c.save_to(&self.storage)?;
+        }
+        mdns_responder.set_name(&mdns_name);
+        drop(mdns_responder);
+        if changed {
+            self.event_emitter
+                .lock()
+                .expect("couldn't access event_emitter")
+                .emit(&Event::MdnsNameChanged { name: mdns_name });
+        }
+        Ok(())
+    }
+
+    /// Updates the configuration number after a change of the Accessories, Services or
+    /// Characteristics while the transport is serving. Changes made before it is started are
+    /// accounted for by `start`.
+    fn topology_changed(&self) -> Result<()> {
+        if self.shutdown_handle.is_serving() {
+            self.update_configuration_number()?;
+        }
+        Ok(())
+    }
+}
+
+impl Transport for IpTransport<FileStorage> {
+    fn start(&mut self) -> Result<()> {
+        // serves again after a previous `stop`
+        self.shutdown_handle.reset();
+        let (addresses, mdns_interface) = {
+            let c = self.config.lock().expect("couldn't access config");
+            (c.serve_addresses()?, c.mdns_interface()?)
+        };
+
+        // the listeners are bound before the announcement, so a port assigned by the OS for port
+        // `0` is the one announced
+        let mut listeners = vec![http::server::bind(&addresses[0])?];
+        let local_addr = listeners[0].local_addr()?;
+        for mut address in addresses.into_iter().skip(1) {
+            address.set_port(local_addr.port());
+            listeners.push(http::server::bind(&address)?);
+        }
+        *self.local_addr.lock().expect("couldn't access local_addr") = Some(local_addr);
+        self.config.lock().expect("couldn't access config").port = local_addr.port();
+
+        self.update_configuration_number()?;
+        self.update_mdns_name()?;
+        {
+            let mut mdns_responder = self.mdns_responder.lock().expect("couldn't access mDNS responder");
+            mdns_responder.set_port(local_addr.port());
+            mdns_responder.set_interface(mdns_interface)?;
+            mdns_responder.start()?;
+        }
 
         let config = self.config.clone();
         let database = self.database.clone();
         let mdns_responder = self.mdns_responder.clone();
-        self.event_emitter
+        // a weak pointer, so the listener doesn't keep the emitter holding it alive
+        let event_emitter = Arc::downgrade(&self.event_emitter);
+        // removed once the server terminates, so a later `start` doesn't add a second one
+        let status_flag_listener_id = self
+            .event_emitter
             .lock()
             .expect("couldn't access event_emitter")
-            .add_listener(Box::new(move |event| match *event {
-                Event::DevicePaired => {
-                    if let Ok(count) = database.lock().expect("couldn't access database").count_pairings() {
-                        if count > 0 {
-                            let mut c = config.lock().expect("couldn't access config");
-                            c.status_flag = StatusFlag::Zero;
-                            mdns_responder
-                                .lock()
-                                .expect("couldn't access mDNS responder")
-                                .update_txt_records(c.txt_records())
-                                .expect("couldn't update mDNS TXT records");
-                        }
-                    }
-                },
-                Event::DeviceUnpaired => {
-                    if let Ok(count) = database.lock().expect("couldn't access database").count_pairings() {
-                        if count == 0 {
-                            let mut c = config.lock().expect("couldn't access config");
-                            c.status_flag = StatusFlag::NotPaired;
-                            mdns_responder
-                                .lock()
-                                .expect("couldn't access mDNS responder")
-                                .update_txt_records(c.txt_records())
-                                .expect("couldn't update mDNS TXT records");
-                        }
-                    }
-                },
-                _ => {},
+            .add_listener(Box::new(move |event| {
+                let paired = match *event {
+                    Event::DevicePaired => true,
+                    Event::DeviceUnpaired => false,
+                    _ => return,
+                };
+                match update_status_flag(&config, &database, &mdns_responder, paired) {
+                    Ok(events) if !events.is_empty() => {
+                        // listeners are called while the emitter is locked, so the events are
+                        // emitted from another thread
+                        let event_emitter = event_emitter.clone();
+                        thread::spawn(move || {
+                            if let Some(event_emitter) = event_emitter.upgrade() {
+                                match event_emitter.lock() {
+                                    Ok(event_emitter) => {
+                                        for event in &events {
+                                            event_emitter.emit(event);
+                                        }
+                                    },
+                                    Err(_) => error!("couldn't access event_emitter"),
+                                }
+                            }
+                        });
+                    },
+                    Ok(_) => {},
+                    Err(e) => error!("couldn't update the pairing status flag: {}", e),
+                }
             }));
 
-        http::server::serve(
-            &SocketAddr::new(ip, port),
+        self.shutdown_handle.set_serving(true);
+        let watching = Arc::new(AtomicBool::new(true));
+        {
+            let ip_transport = self.clone();
+            let watching = watching.clone();
+            thread::spawn(move || ip_transport.watch_host_addresses(&watching));
+        }
+        let res = http::server::serve(
+            listeners,
             &self.config,
             &self.database,
             &self.accessories,
             &self.event_emitter,
-        )?;
-        Ok(())
+            &self.resource_handler,
+            &self.shutdown_handle,
+        )
+        .and_then(|_| {
+            self.mdns_responder
+                .lock()
+                .expect("couldn't access mDNS responder")
+                .stop()?;
+            self.config
+                .lock()
+                .expect("couldn't access config")
+                .save_to(&self.storage)
+        });
+        watching.store(false, Ordering::SeqCst);
+        self.event_emitter
+            .lock()
+            .expect("couldn't access event_emitter")
+            .remove_listener(status_flag_listener_id);
+        self.shutdown_handle.set_serving(false);
+        res
     }
 
+    /// Shuts the server down, stops the mDNS announcement and returns once `start` has returned.
+    /// As it waits for the server, it must not be called from an event listener or a
+    /// Characteristic callback.
     fn stop(&self) -> Result<()> {
+        let serving = self.shutdown_handle.is_serving();
+        self.shutdown_handle.shutdown();
         self.mdns_responder
             .lock()
             .expect("couldn't access mDNS responder")
             .stop()?;
+        self.shutdown_handle.wait();
+        if !serving {
+            // there was nothing to shut down, so the signal mustn't stop a later `start` right away
+            self.shutdown_handle.reset();
+        }
         Ok(())
     }
 
     fn add_accessory<A: 'static + AccessoryListMember + Send>(&mut self, accessory: A) -> Result<AccessoryListPtr> {
-        self.accessories.add_accessory(Box::new(accessory))
+        let accessory = self.accessories.add_accessory(Box::new(accessory))?;
+        // cloned, as restoring the values emits events handled by the persisting listener
+        let values = self
+            .characteristic_values
+            .lock()
+            .expect("couldn't access characteristic_values")
+            .values
+            .clone();
+        let keys = self.accessories.restore_characteristic_values(&accessory, None, &values)?;
+        self.characteristic_values
+            .lock()
+            .expect("couldn't access characteristic_values")
+            .keys
+            .extend(keys);
+        self.topology_changed()?;
+        Ok(accessory)
     }
 
     fn remove_accessory(&mut self, accessory: &AccessoryListPtr) -> Result<()> {
-        self.accessories.remove_accessory(accessory)
+        let accessory_id = accessory.lock().expect("couldn't access accessory").get_id();
+        self.accessories.remove_accessory(accessory)?;
+        self.characteristic_values
+            .lock()
+            .expect("couldn't access characteristic_values")
+            .keys
+            .retain(|&(aid, _), _| aid != accessory_id);
+        self.topology_changed()
+    }
+
+    fn add_characteristic(
+        &mut self,
+        accessory: &AccessoryListPtr,
+        service_id: u64,
+        characteristic: Box<dyn HapCharacteristic + Send>,
+    ) -> Result<u64> {
+        let characteristic_id = self.accessories.add_characteristic(accessory, service_id, characteristic)?;
+        // cloned, as restoring the value emits an event handled by the persisting listener
+        let values = self
+            .characteristic_values
+            .lock()
+            .expect("couldn't access characteristic_values")
+            .values
+            .clone();
+        let keys = self
+            .accessories
+            .restore_characteristic_values(accessory, Some(characteristic_id), &values)?;
+        self.characteristic_values
+            .lock()
+            .expect("couldn't access characteristic_values")
+            .keys
+            .extend(keys);
+        self.topology_changed()?;
+        Ok(characteristic_id)
+    }
+
+    fn remove_characteristic(&mut self, accessory: &AccessoryListPtr, characteristic_id: u64) -> Result<()> {
+        let accessory_id = accessory.lock().expect("couldn't access accessory").get_id();
+        self.accessories.remove_characteristic(accessory, characteristic_id)?;
+        // the instance ID may be assigned to a Characteristic added later on
+        self.characteristic_values
+            .lock()
+            .expect("couldn't access characteristic_values")
+            .keys
+            .remove(&(accessory_id, characteristic_id));
+        self.topology_changed()
+    }
+}
+
+/// Persists the changed value of a Characteristic, if the Characteristic is persisted at all.
+fn persist_characteristic_value(
+    characteristic_values: &Mutex<CharacteristicValues>,
+    storage: &impl Storage,
+    aid: u64,
+    iid: u64,
+    value: &serde_json::Value,
+) -> Result<()> {
+    let mut c = characteristic_values
+        .lock()
+        .map_err(|_| Error::from_str("couldn't access characteristic_values"))?;
+    if let Some(key) = c.keys.get(&(aid, iid)).cloned() {
+        c.values.insert(key, value.clone());
+        storage.set_bytes(CHARACTERISTIC_VALUES_KEY, serde_json::to_vec(&c.values)?)?;
+    }
+    Ok(())
+}
+
+/// Updates the status flag in the mDNS TXT records after a controller was paired or unpaired and
+/// returns the events to emit for it.
+fn update_status_flag(
+    config: &ConfigPtr,
+    database: &DatabasePtr,
+    mdns_responder: &ResponderPtr,
+    paired: bool,
+) -> Result<Vec<Event>> {
+    let count = database
+        .lock()
+        .map_err(|_| Error::from_str("couldn't access database"))?
+        .count_pairings()?;
+    let status_flag = match (paired, count) {
+        (true, c) if c > 0 => StatusFlag::Zero,
+        (false, 0) => StatusFlag::NotPaired,
+        _ => return Ok(vec![]),
+    };
+    let mut c = config.lock().map_err(|_| Error::from_str("couldn't access config"))?;
+    if c.status_flag == status_flag {
+        return Ok(vec![]);
+    }
+    let previous_status_flag = c.status_flag;
+    c.status_flag = status_flag;
+    mdns_responder
+        .lock()
+        .map_err(|_| Error::from_str("couldn't access mDNS responder"))?
+        .update_txt_records(c.txt_records())?;
+    Ok(bonjour_events(previous_status_flag, &c))
+}
+
+/// Returns the events to emit after the TXT records were updated from `c`.
+fn bonjour_events(previous_status_flag: StatusFlag, c: &Config) -> Vec<Event> {
+    let mut events = Vec::new();
+    if c.status_flag != previous_status_flag {
+        events.push(Event::StatusFlagChanged { status_flag: c.status_flag });
+    }
+    events.push(Event::TxtRecordsUpdated { txt_records: c.txt_records() });
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{IpAddr, Ipv4Addr, Ipv6Addr},
+        time::Instant,
+    };
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        accessory::outlet,
+        characteristic::brightness,
+        protocol::{Pairing, Permissions},
+        test_util::TempStorage,
+        transport::{
+            http::tests::{Connection, Controller, SETUP_CODE},
+            mdns::MdnsInterface,
+        },
+        HapType,
+    };
+
+    /// An mDNS responder recording the TXT records it is updated with instead of announcing them.
+    /// Probing reports the `names_in_use` as announced by other devices.
+    #[derive(Debug, Default)]
+    struct MockResponder {
+        name: String,
+        port: u16,
+        announcing: bool,
+        txt_records: Arc<Mutex<Vec<[String; 9]>>>,
+        names_in_use: Vec<String>,
+    }
+
+    impl MdnsResponder for MockResponder {
+        fn start(&mut self) -> Result<()> {
+            self.announcing = true;
+            Ok(())
+        }
+
+        fn stop(&mut self) -> Result<()> {
+            self.announcing = false;
+            Ok(())
+        }
+
+        fn update_txt_records(&mut self, txt_records: [String; 9]) -> Result<()> {
+            self.txt_records.lock().unwrap().push(txt_records);
+            Ok(())
+        }
+
+        fn name(&self) -> &str { &self.name }
+
+        fn set_name(&mut self, name: &str) { self.name = name.into(); }
+
+        fn port(&self) -> u16 { self.port }
+
+        fn set_port(&mut self, port: u16) { self.port = port; }
+
+        fn is_announcing(&self) -> bool { self.announcing }
+
+        fn set_interface(&mut self, _: Option<MdnsInterface>) -> Result<()> { Ok(()) }
+
+        fn name_in_use(&self, instance_name: &str, _: Instant) -> bool {
+            self.names_in_use.iter().any(|n| n == instance_name)
+        }
+    }
+
+    fn config(storage: &TempStorage) -> Config {
+        Config {
+            storage_path: storage.path().into(),
+            ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: 0,
+            pin: "11122333".into(),
+            name: "Outlet".into(),
+            ..Default::default()
+        }
+    }
+
+    fn outlet(name: &str) -> outlet::Outlet {
+        outlet::new(Information {
+            name: name.into(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    /// Starts the transport in the background and returns once it is bound.
+    fn start(ip_transport: &IpTransport<FileStorage>) -> JoinHandle<Result<()>> {
+        let handle = ip_transport.start_background().unwrap();
+        while !ip_transport.shutdown_handle().is_serving() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        handle
+    }
+
+    #[test]
+    fn test_get_accessories_on_ipv6_loopback_address() {
+        let storage = TempStorage::new();
+        let config = Config {
+            bind_ipv6: true,
+            ..config(&storage)
+        };
+        let mut ip_transport = IpTransport::new_with_responder(config, MockResponder::default()).unwrap();
+        ip_transport.add_accessory(outlet("Outlet")).unwrap();
+        let handle = start(&ip_transport);
+        let port = ip_transport.local_addr().unwrap().port();
+        let addr = SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), port);
+
+        let controller = Controller::new();
+        Connection::connect(addr).pair_setup(&controller, SETUP_CODE).unwrap();
+        let mut connection = Connection::connect(addr);
+        connection.pair_verify(&controller, &controller.private_key).unwrap();
+        let (status, body) = connection.get_json("/accessories");
+        assert_eq!(status, 200);
+        assert_eq!(body["accessories"][0]["aid"], 1);
+
+        drop(connection);
+        ip_transport.stop().unwrap();
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_health_endpoint() {
+        let storage = TempStorage::new();
+        let mut ip_transport = IpTransport::new_with_responder(config(&storage), MockResponder::default()).unwrap();
+        ip_transport.add_accessory(outlet("Outlet")).unwrap();
+        let handle = start(&ip_transport);
+        let addr = ip_transport.local_addr().unwrap();
+
+        // the health endpoint is served without a verified session
+        let (status, body) = Connection::connect(addr).get_json("/.well-known/hap/health");
+        assert_eq!(status, 200);
+        assert_eq!(body, serde_json::json!({ "status": "ok", "paired": false, "accessories": 1 }));
+
+        Connection::connect(addr).pair_setup(&Controller::new(), SETUP_CODE).unwrap();
+        let (status, body) = Connection::connect(addr).get_json("/.well-known/hap/health");
+        assert_eq!(status, 200);
+        assert_eq!(body["paired"], true);
+
+        ip_transport.stop().unwrap();
+        handle.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_status_flag_listener_is_removed_on_stop() {
+        let storage = TempStorage::new();
+        let txt_records = Arc::new(Mutex::new(Vec::new()));
+        let responder = MockResponder {
+            txt_records: txt_records.clone(),
+            ..Default::default()
+        };
+        let ip_transport = IpTransport::new_with_responder(config(&storage), responder).unwrap();
+        for _ in 0..2 {
+            let handle = start(&ip_transport);
+            ip_transport.stop().unwrap();
+            handle.join().unwrap().unwrap();
+        }
+        txt_records.lock().unwrap().clear();
+
+        Pairing::new(Uuid::new_v4(), Permissions::Admin, [0; 32])
+            .save_to(&ip_transport.database)
+            .unwrap();
+        ip_transport.event_emitter.lock().unwrap().emit(&Event::DevicePaired);
+
+        assert_eq!(ip_transport.config().status_flag, StatusFlag::NotPaired);
+        assert!(txt_records.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_start_renames_an_mdns_name_in_use() {
+        let storage = TempStorage::new();
+        let responder = MockResponder {
+            names_in_use: vec!["Outlet".into()],
+            ..Default::default()
+        };
+        let mut ip_transport = IpTransport::new_with_responder(config(&storage), responder).unwrap();
+        let events = record_events(&mut ip_transport);
+
+        let handle = start(&ip_transport);
+        ip_transport.stop().unwrap();
+        handle.join().unwrap().unwrap();
+
+        assert_eq!(ip_transport.mdns_responder.lock().unwrap().name(), "Outlet (2)");
+        assert_eq!(ip_transport.config().mdns_name.as_deref(), Some("Outlet (2)"));
+        assert!(events
+            .lock()
+            .unwrap()
+            .contains(&format!("{:?}", Event::MdnsNameChanged { name: "Outlet (2)".into() })));
+    }
+
+    /// Records the events emitted by the transport from now on, formatted with `Debug`.
+    fn record_events(ip_transport: &mut IpTransport<FileStorage>) -> Arc<Mutex<Vec<String>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        ip_transport.add_event_listener(move |event| recorded.lock().unwrap().push(format!("{:?}", event)));
+        events
+    }
+
+    #[test]
+    fn test_factory_reset_emits_unpaired_events() {
+        let storage = TempStorage::new();
+        let mut ip_transport = IpTransport::new_with_responder(config(&storage), MockResponder::default()).unwrap();
+        let ids = vec![Uuid::new_v4(), Uuid::new_v4()];
+        for (&id, permissions) in ids.iter().zip(vec![Permissions::Admin, Permissions::User]) {
+            Pairing::new(id, permissions, [0; 32]).save_to(&ip_transport.database).unwrap();
+        }
+        let events = record_events(&mut ip_transport);
+
+        ip_transport.factory_reset().unwrap();
+
+        assert!(ip_transport.paired_controllers().unwrap().is_empty());
+        let events = events.lock().unwrap();
+        let position = |event: &str| events.iter().position(|e| e == event).unwrap();
+        let unpaired = position("DeviceUnpaired");
+        for &id in &ids {
+            assert!(position(&format!("{:?}", Event::ControllerUnpaired { id })) < unpaired);
+        }
+        assert!(unpaired < position("FactoryReset"));
+    }
+
+    #[test]
+    fn test_factory_reset_without_pairings() {
+        let storage = TempStorage::new();
+        let mut ip_transport = IpTransport::new_with_responder(config(&storage), MockResponder::default()).unwrap();
+        let events = record_events(&mut ip_transport);
+
+        ip_transport.factory_reset().unwrap();
+
+        let events = events.lock().unwrap();
+        assert!(events.iter().any(|e| e == "FactoryReset"));
+        assert!(!events.iter().any(|e| e.starts_with("ControllerUnpaired") || e == "DeviceUnpaired"));
+    }
+
+    #[test]
+    fn test_update_status_flag() {
+        let storage = TempStorage::new();
+        let txt_records = Arc::new(Mutex::new(Vec::new()));
+        let responder = MockResponder {
+            txt_records: txt_records.clone(),
+            ..Default::default()
+        };
+        let ip_transport = IpTransport::new_with_responder(config(&storage), responder).unwrap();
+        let (config, database, mdns_responder) =
+            (&ip_transport.config, &ip_transport.database, &ip_transport.mdns_responder);
+        txt_records.lock().unwrap().clear();
+
+        // a pairing event without a stored pairing doesn't change anything
+        assert!(update_status_flag(config, database, mdns_responder, true).unwrap().is_empty());
+
+        let id = Uuid::new_v4();
+        Pairing::new(id, Permissions::Admin, [0; 32]).save_to(database).unwrap();
+        let events = update_status_flag(config, database, mdns_responder, true).unwrap();
+        assert!(matches!(events[0], Event::StatusFlagChanged { status_flag: StatusFlag::Zero }));
+        assert_eq!(config.lock().unwrap().status_flag, StatusFlag::Zero);
+        assert_eq!(txt_records.lock().unwrap().len(), 1);
+
+        database.lock().unwrap().delete_pairing(&id).unwrap();
+        let events = update_status_flag(config, database, mdns_responder, false).unwrap();
+        assert!(matches!(events[0], Event::StatusFlagChanged { status_flag: StatusFlag::NotPaired }));
+        assert_eq!(config.lock().unwrap().status_flag, StatusFlag::NotPaired);
+        assert_eq!(txt_records.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_update_status_flag_with_poisoned_lock() {
+        let storage = TempStorage::new();
+        let ip_transport = IpTransport::new_with_responder(config(&storage), MockResponder::default()).unwrap();
+        Pairing::new(Uuid::new_v4(), Permissions::Admin, [0; 32])
+            .save_to(&ip_transport.database)
+            .unwrap();
+        let config = ip_transport.config.clone();
+        let _ = thread::spawn(move || {
+            let _c = config.lock().unwrap();
+            panic!("poisoning the config");
+        })
+        .join();
+
+        // the status flag listener logs the error instead of panicking in the emitting thread
+        let res = update_status_flag(&ip_transport.config, &ip_transport.database, &ip_transport.mdns_responder, true);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_adding_an_accessory_while_serving_increments_the_configuration_number() {
+        let storage = TempStorage::new();
+        let txt_records = Arc::new(Mutex::new(Vec::new()));
+        let responder = MockResponder {
+            txt_records: txt_records.clone(),
+            ..Default::default()
+        };
+        let mut ip_transport = IpTransport::new_with_responder(config(&storage), responder).unwrap();
+        ip_transport.add_accessory(outlet("Outlet")).unwrap();
+        let handle = start(&ip_transport);
+        let configuration_number = ip_transport.config().configuration_number;
+        txt_records.lock().unwrap().clear();
+
+        ip_transport.add_accessory(outlet("Second Outlet")).unwrap();
+
+        assert_eq!(ip_transport.config().configuration_number, configuration_number + 1);
+        {
+            let txt_records = txt_records.lock().unwrap();
+            assert_eq!(txt_records.len(), 1);
+            assert_eq!(txt_records[0][2], format!("c#={}", configuration_number + 1));
+        }
+
+        ip_transport.stop().unwrap();
+        handle.join().unwrap().unwrap();
+    }
+
+    /// Adds an Outlet with an additional Brightness Characteristic and returns the Characteristic.
+    fn add_outlet_with_brightness(ip_transport: &mut IpTransport<FileStorage>) -> brightness::Brightness {
+        let accessory = ip_transport.add_accessory(outlet("Outlet")).unwrap();
+        let service_id = accessory
+            .lock()
+            .unwrap()
+            .get_services()
+            .into_iter()
+            .find(|s| s.get_type() == HapType::Outlet)
+            .unwrap()
+            .get_id();
+        let brightness = brightness::new();
+        ip_transport
+            .add_characteristic(&accessory, service_id, Box::new(brightness.clone()))
+            .unwrap();
+        brightness
+    }
+
+    #[test]
+    fn test_value_of_added_characteristic_is_persisted() {
+        let storage = TempStorage::new();
+        let config = config(&storage);
+        let mut ip_transport = IpTransport::new_with_responder(config.clone(), MockResponder::default()).unwrap();
+        add_outlet_with_brightness(&mut ip_transport).set_value(50).unwrap();
+        drop(ip_transport);
+
+        // restored once the Characteristic is added again after a restart
+        let mut ip_transport = IpTransport::new_with_responder(config, MockResponder::default()).unwrap();
+        assert_eq!(add_outlet_with_brightness(&mut ip_transport).get_value().unwrap(), 50);
+    }
+
+    #[test]
+    fn test_debug_doesnt_print_the_setup_code() {
+        let storage = TempStorage::new();
+        let ip_transport = IpTransport::new_with_responder(config(&storage), MockResponder::default()).unwrap();
+        let debug = format!("{:?}", ip_transport);
+        assert!(!debug.contains("11122333"));
+        assert!(!debug.contains(SETUP_CODE));
+    }
+
+    #[test]
+    fn test_start_after_stop_without_start() {
+        let storage = TempStorage::new();
+        let mut ip_transport = IpTransport::new_with_responder(config(&storage), MockResponder::default()).unwrap();
+        ip_transport.add_accessory(outlet("Outlet")).unwrap();
+        ip_transport.stop().unwrap();
+
+        let handle = start(&ip_transport);
+        // the server polls the shutdown signal every 100 ms
+        thread::sleep(Duration::from_millis(300));
+        assert!(ip_transport.shutdown_handle().is_serving());
+        let (status, _) = Connection::connect(ip_transport.local_addr().unwrap()).get_json("/.well-known/hap/health");
+        assert_eq!(status, 200);
+
+        ip_transport.stop().unwrap();
+        handle.join().unwrap().unwrap();
     }
 }
@@ -1,39 +1,109 @@
 use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, UdpSocket},
     sync::{
         mpsc::{self, TryRecvError},
         Arc,
         Mutex,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use libmdns;
+use pnet::datalink;
 
 use crate::Result;
 
-/// An mDNS Responder. Used to announce the Accessory's name and HAP TXT records to potential
-/// controllers.
+#[cfg(feature = "avahi")]
+mod avahi;
+mod interface;
+
+#[cfg(feature = "avahi")]
+pub use self::avahi::AvahiResponder;
+
+/// A network interface the mDNS announcement is restricted to. See `Config::mdns_interface`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MdnsInterface {
+    /// Name of the interface, e.g. `"eth0"`.
+    pub name: String,
+    /// IPv4 address of the interface the mDNS multicast group is joined on.
+    pub multicast_ip: Ipv4Addr,
+    /// Addresses announced in the A and AAAA records.
+    pub addresses: Vec<IpAddr>,
+}
+
+/// `MdnsResponder` is implemented by mDNS responders announcing the Accessory's name, port and HAP
+/// TXT records to potential controllers. `Responder` is the built-in one, and with the `avahi`
+/// feature, `AvahiResponder` registers the service with the Avahi daemon of the host. Other
+/// implementations, e.g. one registering via the `dns-sd` daemon on macOS, can be passed to
+/// `IpTransport::new_with_responder`.
+///
+/// The transport sets the name, port and TXT records before starting the announcement and updates
+/// the TXT records on changes of the pairing status or the configuration number.
+pub trait MdnsResponder: fmt::Debug + Send {
+    /// Starts the mDNS announcement.
+    fn start(&mut self) -> Result<()>;
+    /// Stops the mDNS announcement.
+    fn stop(&mut self) -> Result<()>;
+    /// Updates the announced TXT records. A running mDNS announcement has to be updated right
+    /// away, otherwise the TXT records are announced from the next start on.
+    fn update_txt_records(&mut self, txt_records: [String; 9]) -> Result<()>;
+    /// Returns the announced instance name.
+    fn name(&self) -> &str;
+    /// Sets the instance name announced from the next start of the mDNS announcement on.
+    fn set_name(&mut self, name: &str);
+    /// Returns the announced port.
+    fn port(&self) -> u16;
+    /// Sets the port announced from the next start of the mDNS announcement on.
+    fn set_port(&mut self, port: u16);
+    /// Returns whether the mDNS announcement is running.
+    fn is_announcing(&self) -> bool;
+    /// Restricts the mDNS announcement to a network interface from the next start on, or lifts the
+    /// restriction for `None`. Fails if the responder can't be restricted to an interface.
+    fn set_interface(&mut self, interface: Option<MdnsInterface>) -> Result<()>;
+    /// Returns whether another device on the network already announces the instance name
+    /// `instance_name`, probing no longer than until `deadline`. The transport probes names before
+    /// starting the announcement and appends a numeric suffix to names in use. The default never
+    /// probes and suits responders resolving name conflicts on their own, like `AvahiResponder`.
+    fn name_in_use(&self, _instance_name: &str, _deadline: Instant) -> bool { false }
+}
+
+/// The built-in mDNS Responder. Announces the service in a separate thread, on all interfaces or
+/// on the one set via `set_interface`.
+#[derive(Debug)]
 pub struct Responder {
     name: String,
     port: u16,
-    txt_records: [String; 8],
+    txt_records: [String; 9],
+    interface: Option<MdnsInterface>,
     stop: Option<mpsc::Sender<()>>,
 }
 
 impl Responder {
     /// Creates a new mDNS Responder.
-    pub fn new(name: &str, port: u16, txt_records: [String; 8]) -> Self {
+    pub fn new(name: &str, port: u16, txt_records: [String; 9]) -> Self {
         Responder {
             name: name.to_string(),
             port,
             txt_records,
+            interface: None,
             stop: None,
         }
     }
+}
+
+impl MdnsResponder for Responder {
+    fn start(&mut self) -> Result<()> {
+        if let Some(ref iface) = self.interface {
+            // libmdns always announces on all interfaces
+            let responder = interface::InterfaceResponder::bind(iface, &self.name, self.port, &self.txt_records)?;
+            let (tx, rx) = mpsc::channel();
+            thread::spawn(move || responder.serve(&rx));
+            self.stop = Some(tx);
+            return Ok(());
+        }
 
-    /// Starts mDNS announcement in a separate thread.
-    pub fn start(&mut self) {
         let (tx, rx) = mpsc::channel();
         let name = self.name.clone();
         let port = self.port;
@@ -41,7 +111,7 @@ impl Responder {
         thread::spawn(move || {
             let responder = libmdns::Responder::new().expect("couldn't create mDNS responder");
             let _svc = responder.register("_hap._tcp".into(), name, port, &[
-                &tr[0], &tr[1], &tr[2], &tr[3], &tr[4], &tr[5], &tr[6], &tr[7],
+                &tr[0], &tr[1], &tr[2], &tr[3], &tr[4], &tr[5], &tr[6], &tr[7], &tr[8],
             ]);
             loop {
                 thread::sleep(Duration::from_secs(2));
@@ -54,24 +124,284 @@ impl Responder {
             }
         });
         self.stop = Some(tx);
+        Ok(())
     }
 
-    /// Stops mDNS announcement.
-    pub fn stop(&self) -> Result<()> {
-        if let Some(stop) = self.stop.clone() {
-            stop.send(())?;
+    /// Stops mDNS announcement. The service is deregistered as the announcement thread exits.
+    fn stop(&mut self) -> Result<()> {
+        if let Some(stop) = self.stop.take() {
+            // a failed send means the announcement thread has already exited
+            let _ = stop.send(());
         }
         Ok(())
     }
 
-    /// Stops mDNS announcement and restarts it with updated TXT records.
-    pub fn update_txt_records(&mut self, txt_records: [String; 8]) -> Result<()> {
+    /// Updates the announced TXT records. A running mDNS announcement is restarted with the updated
+    /// TXT records, otherwise they are announced from the next start on.
+    fn update_txt_records(&mut self, txt_records: [String; 9]) -> Result<()> {
+        let running = self.stop.is_some();
         self.stop()?;
         self.txt_records = txt_records;
-        self.start();
+        if running {
+            self.start()?;
+        }
         Ok(())
     }
+
+    fn name(&self) -> &str { &self.name }
+
+    fn set_name(&mut self, name: &str) { self.name = name.to_string(); }
+
+    fn port(&self) -> u16 { self.port }
+
+    fn set_port(&mut self, port: u16) { self.port = port; }
+
+    fn is_announcing(&self) -> bool { self.stop.is_some() }
+
+    fn set_interface(&mut self, interface: Option<MdnsInterface>) -> Result<()> {
+        self.interface = interface;
+        Ok(())
+    }
+
+    fn name_in_use(&self, instance_name: &str, deadline: Instant) -> bool { name_in_use(instance_name, deadline) }
+}
+
+/// Pointer to an `MdnsResponder`.
+pub type ResponderPtr = Arc<Mutex<Box<dyn MdnsResponder>>>;
+
+/// Returns the addresses of all non-loopback interfaces of the host, e.g. to notice a changed IP
+/// address after a new DHCP lease.
+pub(crate) fn host_addresses() -> Vec<IpAddr> {
+    let mut addresses: Vec<IpAddr> = datalink::interfaces()
+        .into_iter()
+        .flat_map(|iface| iface.ips.into_iter().map(|n| n.ip()))
+        .filter(|ip| !ip.is_loopback())
+        .collect();
+    addresses.sort();
+    addresses
+}
+
+/// Service type of HAP accessories.
+const SERVICE_TYPE_LABELS: [&str; 3] = ["_hap", "_tcp", "local"];
+/// Multicast address and port of mDNS.
+const MDNS_ADDR: &str = "224.0.0.251:5353";
+/// Number of probe queries sent for an instance name.
+const PROBE_COUNT: usize = 3;
+/// Time to wait for answers to a probe query.
+const PROBE_WAIT: Duration = Duration::from_millis(250);
+/// Upper bound of the total time spent probing instance names on a start of the transport.
+const MAX_PROBE_TIME: Duration = Duration::from_secs(5);
+/// Highest numeric suffix tried when renaming an instance, e.g. `Acme Outlet (99)`.
+const MAX_NAME_SUFFIX: usize = 99;
+/// DNS query type `ANY`.
+const QTYPE_ANY: u16 = 255;
+/// DNS class `IN`.
+const QCLASS_IN: u16 = 1;
+
+/// Returns the mDNS instance name to announce for the accessory `name`. If another device on the
+/// network already announces the name, a numeric suffix is appended the way Bonjour does it, i.e.
+/// `Acme Outlet (2)`, `Acme Outlet (3)` and so on. A `previous` instance name chosen for `name`
+/// is tried first, so the announced name stays the same across restarts.
+///
+/// Names are checked with `in_use`, which is given the deadline of the probing. Once it passes,
+/// or if every suffix is in use, the `previous` instance name or `name` is announced unprobed.
+pub(crate) fn resolve_name(
+    name: &str,
+    previous: Option<&str>,
+    mut in_use: impl FnMut(&str, Instant) -> bool,
+) -> String {
+    let previous = previous.filter(|p| is_variant_of(p, name));
+    let fallback = previous.unwrap_or(name).to_string();
+    let deadline = Instant::now() + MAX_PROBE_TIME;
+    let suffixed = (1..=MAX_NAME_SUFFIX)
+        .map(|n| match n {
+            1 => name.to_string(),
+            n => format!("{} ({})", name, n),
+        })
+        .filter(|c| Some(c.as_str()) != previous);
+    let candidates = previous.map(str::to_string).into_iter().chain(suffixed);
+    for candidate in candidates {
+        if Instant::now() >= deadline {
+            break;
+        }
+        if !in_use(&candidate, deadline) {
+            return candidate;
+        }
+    }
+    fallback
+}
+
+/// Whether `instance_name` is `name` or `name` with a numeric suffix.
+fn is_variant_of(instance_name: &str, name: &str) -> bool {
+    if instance_name == name {
+        return true;
+    }
+    instance_name
+        .get(name.len()..)
+        .filter(|_| instance_name.starts_with(name))
+        .and_then(|suffix| suffix.strip_prefix(" ("))
+        .and_then(|suffix| suffix.strip_suffix(')'))
+        .map_or(false, |n| n.parse::<usize>().is_ok())
+}
+
+/// Whether another device on the network answers for the HAP service instance `instance_name`
+/// before `deadline`. The query is sent from an ephemeral port, so responders answer it via unicast
+/// (RFC 6762, section 6.7) and our own announcement isn't involved. Network errors count as not in
+/// use.
+fn name_in_use(instance_name: &str, deadline: Instant) -> bool {
+    let query = match encode_query(instance_name) {
+        Some(query) => query,
+        None => return false,
+    };
+    let socket = match UdpSocket::bind("0.0.0.0:0") {
+        Ok(socket) => socket,
+        Err(_) => return false,
+    };
+    let mut buf = [0; 9000];
+    for _ in 0..PROBE_COUNT {
+        if socket.send_to(&query, MDNS_ADDR).is_err() {
+            return false;
+        }
+        // read answers until the probe's wait, or the whole probing, is over
+        let wait_until = deadline.min(Instant::now() + PROBE_WAIT);
+        loop {
+            let timeout = wait_until.saturating_duration_since(Instant::now());
+            if timeout == Duration::from_secs(0) || socket.set_read_timeout(Some(timeout)).is_err() {
+                break;
+            }
+            match socket.recv_from(&mut buf) {
+                Ok((len, _)) if answers_for(&buf[..len], instance_name) => return true,
+                Ok(_) => {},
+                Err(_) => break,
+            }
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+    }
+    false
+}
+
+/// Returns the labels of the full service instance name of `instance_name`.
+fn instance_labels(instance_name: &str) -> Vec<&[u8]> {
+    let mut labels = vec![instance_name.as_bytes()];
+    labels.extend(SERVICE_TYPE_LABELS.iter().map(|l| l.as_bytes()));
+    labels
+}
+
+/// Encodes a DNS query of the type `ANY` for `instance_name`. Returns `None` for names that don't
+/// fit in a DNS label.
+fn encode_query(instance_name: &str) -> Option<Vec<u8>> {
+    let labels = instance_labels(instance_name);
+    if labels.iter().any(|l| l.is_empty() || l.len() > 63) {
+        return None;
+    }
+    // ID, flags, 1 question, no answer, authority or additional records
+    let mut query = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in labels {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label);
+    }
+    query.push(0);
+    query.extend_from_slice(&QTYPE_ANY.to_be_bytes());
+    query.extend_from_slice(&QCLASS_IN.to_be_bytes());
+    Some(query)
 }
 
-/// Pointer to a `Responder`.
-pub type ResponderPtr = Arc<Mutex<Responder>>;
+/// Whether the DNS response `packet` contains a record of `instance_name`.
+fn answers_for(packet: &[u8], instance_name: &str) -> bool {
+    if packet.len() < 12 || packet[2] & 0x80 == 0 {
+        return false;
+    }
+    let count = |i: usize| u16::from_be_bytes([packet[i], packet[i + 1]]) as usize;
+    let (questions, records) = (count(4), count(6) + count(8) + count(10));
+    let labels = instance_labels(instance_name);
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        match read_name(packet, pos) {
+            Some((_, end)) => pos = end + 4,
+            None => return false,
+        }
+    }
+    for _ in 0..records {
+        let (name, end) = match read_name(packet, pos) {
+            Some(name) => name,
+            None => return false,
+        };
+        if name.len() == labels.len() && name.iter().zip(&labels).all(|(a, b)| a.eq_ignore_ascii_case(b)) {
+            return true;
+        }
+        // type, class, TTL and data length precede the record data
+        if packet.len() < end + 10 {
+            return false;
+        }
+        pos = end + 10 + count(end + 8);
+    }
+    false
+}
+
+/// Reads a possibly compressed DNS name at `pos` of `packet`. Returns its labels and the position
+/// after the name.
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(Vec<&[u8]>, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // bounds the number of compression pointers followed, guarding against pointer loops
+    for _ in 0..128 {
+        let len = *packet.get(pos)? as usize;
+        if len == 0 {
+            return Some((labels, end.unwrap_or(pos + 1)));
+        }
+        if len & 0xc0 == 0xc0 {
+            let pointer = (len & 0x3f) << 8 | *packet.get(pos + 1)? as usize;
+            end = end.or(Some(pos + 2));
+            pos = pointer;
+        } else {
+            labels.push(packet.get(pos + 1..pos + 1 + len)?);
+            pos += 1 + len;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_name_appends_a_suffix_to_names_in_use() {
+        let in_use = ["Outlet", "Outlet (2)"];
+        let resolved = resolve_name("Outlet", None, |n, _| in_use.contains(&n));
+        assert_eq!(resolved, "Outlet (3)");
+    }
+
+    #[test]
+    fn test_resolve_name_keeps_the_previous_name() {
+        let mut probed = Vec::new();
+        let resolved = resolve_name("Outlet", Some("Outlet (2)"), |n, _| {
+            probed.push(n.to_string());
+            false
+        });
+        assert_eq!(resolved, "Outlet (2)");
+        assert_eq!(probed, vec!["Outlet (2)"]);
+
+        // a previous name chosen for another name isn't tried
+        let resolved = resolve_name("Outlet", Some("Lamp (2)"), |_, _| false);
+        assert_eq!(resolved, "Outlet");
+    }
+
+    #[test]
+    fn test_resolve_name_stops_probing_at_the_deadline() {
+        let start = Instant::now();
+        let mut probed = 0;
+        let resolved = resolve_name("Outlet", Some("Outlet (2)"), |_, deadline| {
+            assert!(deadline <= start + MAX_PROBE_TIME + Duration::from_secs(1));
+            probed += 1;
+            // the probe of a name in use runs up to the deadline
+            thread::sleep(deadline.saturating_duration_since(Instant::now()));
+            true
+        });
+        assert_eq!(probed, 1);
+        assert_eq!(resolved, "Outlet (2)");
+    }
+}
@@ -0,0 +1,97 @@
+use std::process::{Child, Command, Stdio};
+
+use crate::{
+    transport::mdns::{MdnsInterface, MdnsResponder},
+    Error,
+    Result,
+};
+
+/// An mDNS Responder registering the service with the Avahi daemon of the host, for hosts already
+/// running Avahi, where a second responder on the mDNS port doesn't play well with it. The service
+/// is published via the `avahi-publish-service` command of the `avahi-utils` package, which has to
+/// be installed.
+///
+/// Avahi resolves name conflicts on its own, so the announced name may differ from `name`.
+#[derive(Debug)]
+pub struct AvahiResponder {
+    name: String,
+    port: u16,
+    txt_records: [String; 9],
+    process: Option<Child>,
+}
+
+impl AvahiResponder {
+    /// Creates a new Avahi mDNS Responder.
+    pub fn new(name: &str, port: u16, txt_records: [String; 9]) -> Self {
+        AvahiResponder {
+            name: name.to_string(),
+            port,
+            txt_records,
+            process: None,
+        }
+    }
+}
+
+impl MdnsResponder for AvahiResponder {
+    fn start(&mut self) -> Result<()> {
+        self.stop()?;
+        let process = Command::new("avahi-publish-service")
+            .arg(&self.name)
+            .arg("_hap._tcp")
+            .arg(self.port.to_string())
+            .args(&self.txt_records)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        self.process = Some(process);
+        Ok(())
+    }
+
+    /// Stops mDNS announcement. The service is deregistered as the `avahi-publish-service` process
+    /// exits.
+    fn stop(&mut self) -> Result<()> {
+        if let Some(mut process) = self.process.take() {
+            // the process may have exited already, e.g. if the Avahi daemon went away
+            let _ = process.kill();
+            process.wait()?;
+        }
+        Ok(())
+    }
+
+    /// Updates the announced TXT records. A running mDNS announcement is restarted with the updated
+    /// TXT records, otherwise they are announced from the next start on.
+    fn update_txt_records(&mut self, txt_records: [String; 9]) -> Result<()> {
+        let running = self.process.is_some();
+        self.stop()?;
+        self.txt_records = txt_records;
+        if running {
+            self.start()?;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str { &self.name }
+
+    fn set_name(&mut self, name: &str) { self.name = name.to_string(); }
+
+    fn port(&self) -> u16 { self.port }
+
+    fn set_port(&mut self, port: u16) { self.port = port; }
+
+    fn is_announcing(&self) -> bool { self.process.is_some() }
+
+    /// Fails for `Some`. The interfaces Avahi announces on are set via `allow-interfaces` in the
+    /// `avahi-daemon.conf` of the host.
+    fn set_interface(&mut self, interface: Option<MdnsInterface>) -> Result<()> {
+        match interface {
+            Some(_) => Err(Error::from_str(
+                "the interfaces of the Avahi responder are set via allow-interfaces in avahi-daemon.conf",
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for AvahiResponder {
+    fn drop(&mut self) { let _ = self.stop(); }
+}
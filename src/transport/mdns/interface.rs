@@ -0,0 +1,295 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    sync::mpsc::{Receiver, TryRecvError},
+    time::Duration,
+};
+
+#[cfg(unix)]
+use net2::unix::UnixUdpBuilderExt;
+use net2::{UdpBuilder, UdpSocketExt};
+use pnet::datalink::{self, NetworkInterface};
+
+use crate::{
+    transport::mdns::{instance_labels, read_name, MdnsInterface, QCLASS_IN, SERVICE_TYPE_LABELS},
+    Error,
+    Result,
+};
+
+/// Multicast group of mDNS.
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+/// Port of mDNS.
+const MDNS_PORT: u16 = 5353;
+/// Labels of the name used to enumerate the service types on a network.
+const SERVICES_LABELS: [&str; 4] = ["_services", "_dns-sd", "_udp", "local"];
+/// Number of unsolicited announcements sent on start.
+const ANNOUNCEMENT_COUNT: usize = 2;
+/// TTL of records containing a host name, in seconds.
+const TTL_HOST: u32 = 120;
+/// TTL of the other records, in seconds.
+const TTL_OTHER: u32 = 4500;
+/// Bit of the record class telling caches to replace previous records of the same name and type.
+const CACHE_FLUSH: u16 = 0x8000;
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+
+/// An mDNS responder announcing the service on a single network interface. libmdns always joins
+/// the multicast group on and announces the addresses of all interfaces, so the built-in
+/// `Responder` uses this one if it is restricted to an interface.
+pub(crate) struct InterfaceResponder {
+    socket: UdpSocket,
+    interface: NetworkInterface,
+    addresses: Vec<IpAddr>,
+    instance_name: String,
+    host_name: String,
+    port: u16,
+    txt_records: Vec<String>,
+}
+
+impl InterfaceResponder {
+    /// Binds the mDNS port and joins the multicast group on the given interface.
+    pub(crate) fn bind(
+        iface: &MdnsInterface,
+        instance_name: &str,
+        port: u16,
+        txt_records: &[String],
+    ) -> Result<InterfaceResponder> {
+        let interface = datalink::interfaces()
+            .into_iter()
+            .find(|i| i.name == iface.name)
+            .ok_or_else(|| Error::from_str("couldn't find the configured mDNS interface"))?;
+        Ok(InterfaceResponder {
+            socket: bind_socket(&iface.multicast_ip)?,
+            interface,
+            addresses: iface.addresses.clone(),
+            instance_name: instance_name.to_string(),
+            host_name: host_name(instance_name),
+            port,
+            txt_records: txt_records.to_vec(),
+        })
+    }
+
+    /// Answers queries for the service until a message is received on `stop` or its sender is
+    /// dropped. The service is announced on start and deregistered on stop.
+    pub(crate) fn serve(&self, stop: &Receiver<()>) {
+        let group = SocketAddr::new(IpAddr::V4(MDNS_GROUP), MDNS_PORT);
+        let mut announcements = 0;
+        let mut buf = [0; 9000];
+        loop {
+            if announcements < ANNOUNCEMENT_COUNT {
+                let _ = self.socket.send_to(&self.response(0, &[], 0, true), group);
+                announcements += 1;
+            }
+            match stop.try_recv() {
+                Ok(_) | Err(TryRecvError::Disconnected) => {
+                    // a response with a TTL of 0 deregisters the service
+                    let _ = self.socket.send_to(&self.response(0, &[], 0, false), group);
+                    return;
+                },
+                Err(TryRecvError::Empty) => {},
+            }
+            // the socket times out every second, so stopping is noticed in time
+            if let Ok((len, src)) = self.socket.recv_from(&mut buf) {
+                // other interfaces' traffic reaches the socket if their group membership is shared
+                if !self.interface.ips.iter().any(|n| n.contains(src.ip())) {
+                    continue;
+                }
+                if let Some(questions) = self.matching_questions(&buf[..len]) {
+                    let _ = if src.port() == MDNS_PORT {
+                        self.socket.send_to(&self.response(0, &[], 0, true), group)
+                    } else {
+                        // legacy unicast queries are answered directly, echoing the ID and questions
+                        let id = u16::from_be_bytes([buf[0], buf[1]]);
+                        self.socket
+                            .send_to(&self.response(id, &buf[12..questions.1], questions.0, true), src)
+                    };
+                }
+            }
+        }
+    }
+
+    /// Returns the number of questions and the end of the question section if the query `packet`
+    /// asks for any of the names of the service.
+    fn matching_questions(&self, packet: &[u8]) -> Option<(u16, usize)> {
+        if packet.len() < 12 || packet[2] & 0x80 != 0 {
+            return None;
+        }
+        let count = u16::from_be_bytes([packet[4], packet[5]]);
+        let names: Vec<Vec<&[u8]>> = vec![
+            SERVICE_TYPE_LABELS.iter().map(|l| l.as_bytes()).collect(),
+            SERVICES_LABELS.iter().map(|l| l.as_bytes()).collect(),
+            instance_labels(&self.instance_name),
+            vec![self.host_name.as_bytes(), b"local"],
+        ];
+        let mut matching = false;
+        let mut pos = 12;
+        for _ in 0..count {
+            let (name, end) = read_name(packet, pos)?;
+            matching = matching
+                || names.iter().any(|n| {
+                    n.len() == name.len() && n.iter().zip(&name).all(|(a, b)| a.eq_ignore_ascii_case(b))
+                });
+            pos = end + 4;
+        }
+        if matching && pos <= packet.len() {
+            Some((count, pos))
+        } else {
+            None
+        }
+    }
+
+    /// Encodes a response containing all records of the service. `questions` holds the raw
+    /// question section echoed in answers to legacy unicast queries.
+    fn response(&self, id: u16, questions: &[u8], question_count: u16, alive: bool) -> Vec<u8> {
+        let ttl = |ttl: u32| if alive { ttl } else { 0 };
+        let service_type: Vec<&[u8]> = SERVICE_TYPE_LABELS.iter().map(|l| l.as_bytes()).collect();
+        let services: Vec<&[u8]> = SERVICES_LABELS.iter().map(|l| l.as_bytes()).collect();
+        let instance = instance_labels(&self.instance_name);
+        let host: Vec<&[u8]> = vec![self.host_name.as_bytes(), b"local"];
+
+        let mut records = Vec::new();
+        let mut record_count = 0;
+        let mut record = |name: &[&[u8]], rtype: u16, class: u16, ttl: u32, data: Vec<u8>| {
+            encode_name(&mut records, name);
+            records.extend_from_slice(&rtype.to_be_bytes());
+            records.extend_from_slice(&class.to_be_bytes());
+            records.extend_from_slice(&ttl.to_be_bytes());
+            records.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            records.extend_from_slice(&data);
+            record_count += 1;
+        };
+
+        let mut data = Vec::new();
+        encode_name(&mut data, &service_type);
+        record(&services, TYPE_PTR, QCLASS_IN, ttl(TTL_OTHER), data);
+
+        let mut data = Vec::new();
+        encode_name(&mut data, &instance);
+        record(&service_type, TYPE_PTR, QCLASS_IN, ttl(TTL_OTHER), data);
+
+        // priority and weight of 0, followed by the port and the target host
+        let mut data = vec![0, 0, 0, 0];
+        data.extend_from_slice(&self.port.to_be_bytes());
+        encode_name(&mut data, &host);
+        record(&instance, TYPE_SRV, QCLASS_IN | CACHE_FLUSH, ttl(TTL_HOST), data);
+
+        let mut data = Vec::new();
+        for txt_record in &self.txt_records {
+            let bytes = &txt_record.as_bytes()[..txt_record.len().min(255)];
+            data.push(bytes.len() as u8);
+            data.extend_from_slice(bytes);
+        }
+        record(&instance, TYPE_TXT, QCLASS_IN | CACHE_FLUSH, ttl(TTL_OTHER), data);
+
+        for address in &self.addresses {
+            let (rtype, data) = match address {
+                IpAddr::V4(ip) => (TYPE_A, ip.octets().to_vec()),
+                IpAddr::V6(ip) => (TYPE_AAAA, ip.octets().to_vec()),
+            };
+            record(&host, rtype, QCLASS_IN | CACHE_FLUSH, ttl(TTL_HOST), data);
+        }
+
+        // ID, flags of an authoritative response, questions, answers, no authority or additional records
+        let mut response = id.to_be_bytes().to_vec();
+        response.extend_from_slice(&[0x84, 0]);
+        response.extend_from_slice(&question_count.to_be_bytes());
+        response.extend_from_slice(&(record_count as u16).to_be_bytes());
+        response.extend_from_slice(&[0, 0, 0, 0]);
+        response.extend_from_slice(questions);
+        response.extend_from_slice(&records);
+        response
+    }
+}
+
+/// Binds a socket to the mDNS port, sharing it with other responders on the host, and joins the
+/// multicast group on the interface with the given address.
+fn bind_socket(multicast_ip: &Ipv4Addr) -> io::Result<UdpSocket> {
+    let builder = UdpBuilder::new_v4()?;
+    builder.reuse_address(true)?;
+    #[cfg(unix)]
+    builder.reuse_port(true)?;
+    let socket = builder.bind((Ipv4Addr::UNSPECIFIED, MDNS_PORT))?;
+    socket.join_multicast_v4(&MDNS_GROUP, multicast_ip)?;
+    UdpSocketExt::set_multicast_if_v4(&socket, multicast_ip)?;
+    socket.set_multicast_ttl_v4(255)?;
+    socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+    Ok(socket)
+}
+
+/// Derives the host name the service points to from the instance name, e.g. `Acme-Outlet-2` for
+/// `Acme Outlet (2)`.
+fn host_name(instance_name: &str) -> String {
+    let host_name = instance_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    match host_name.len() {
+        0 => "hap".into(),
+        len => host_name[..len.min(63)].into(),
+    }
+}
+
+/// Encodes an uncompressed DNS name.
+fn encode_name(buf: &mut Vec<u8>, labels: &[&[u8]]) {
+    for label in labels {
+        let label = &label[..label.len().min(63)];
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label);
+    }
+    buf.push(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::*;
+
+    /// Returns the bytes of an address record of `host` in a response.
+    fn address_record(host: &str, rtype: u16, address: &[u8]) -> Vec<u8> {
+        let mut record = Vec::new();
+        encode_name(&mut record, &[host.as_bytes(), b"local"]);
+        record.extend_from_slice(&rtype.to_be_bytes());
+        record.extend_from_slice(&(QCLASS_IN | CACHE_FLUSH).to_be_bytes());
+        record.extend_from_slice(&TTL_HOST.to_be_bytes());
+        record.extend_from_slice(&(address.len() as u16).to_be_bytes());
+        record.extend_from_slice(address);
+        record
+    }
+
+    #[test]
+    fn test_response_contains_a_and_aaaa_records() {
+        // skipped on hosts without a loopback interface
+        let interface = match datalink::interfaces().into_iter().find(|i| i.is_loopback()) {
+            Some(interface) => interface,
+            None => return,
+        };
+        let responder = InterfaceResponder {
+            socket: UdpSocket::bind("127.0.0.1:0").unwrap(),
+            interface,
+            addresses: vec![IpAddr::V4(Ipv4Addr::LOCALHOST), IpAddr::V6(Ipv6Addr::LOCALHOST)],
+            instance_name: "Acme Outlet".into(),
+            host_name: host_name("Acme Outlet"),
+            port: 32000,
+            txt_records: vec!["c#=1".into()],
+        };
+        let response = responder.response(0, &[], 0, true);
+        let contains = |record: &[u8]| response.windows(record.len()).any(|w| w == record);
+
+        // PTR, PTR, SRV, TXT, A and AAAA records
+        assert_eq!(u16::from_be_bytes([response[6], response[7]]), 6);
+        assert!(contains(&address_record("Acme-Outlet", TYPE_A, &[127, 0, 0, 1])));
+        assert!(contains(&address_record(
+            "Acme-Outlet",
+            TYPE_AAAA,
+            &Ipv6Addr::LOCALHOST.octets()
+        )));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    characteristic::HapCharacteristic,
     db::{AccessoryListMember, AccessoryListPtr},
     Result,
 };
@@ -11,17 +12,30 @@ pub(crate) mod tcp;
 
 mod ip;
 
-pub use self::ip::IpTransport;
+pub use self::{
+    http::{ConnectionInfo, ResourceRequest, ShutdownHandle},
+    ip::IpTransport,
+};
 
 /// `Transport` is implemented by the transport methods HAP supports. Currently, that's just
 /// `IpTransport`.
 pub trait Transport {
     /// Starts the transport.
     fn start(&mut self) -> Result<()>;
-    /// Stops the transport.
+    /// Stops the transport and returns once it is down.
     fn stop(&self) -> Result<()>;
     /// Adds an Accessory to the transport and returns a pointer to the added Accessory.
     fn add_accessory<A: 'static + AccessoryListMember + Send>(&mut self, accessory: A) -> Result<AccessoryListPtr>;
     /// Takes a pointer to an Accessory and removes the Accessory from the transport.
     fn remove_accessory(&mut self, accessory: &AccessoryListPtr) -> Result<()>;
+    /// Adds a Characteristic to the Service with the given ID of an Accessory and returns the
+    /// instance ID assigned to the Characteristic.
+    fn add_characteristic(
+        &mut self,
+        accessory: &AccessoryListPtr,
+        service_id: u64,
+        characteristic: Box<dyn HapCharacteristic + Send>,
+    ) -> Result<u64>;
+    /// Removes the Characteristic with the given ID from an Accessory.
+    fn remove_characteristic(&mut self, accessory: &AccessoryListPtr, characteristic_id: u64) -> Result<()>;
 }
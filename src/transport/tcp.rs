@@ -1,7 +1,9 @@
 use std::{
     cmp::min,
     io::{self, ErrorKind, Read, Write},
+    mem,
     sync::{Arc, Mutex},
+    time::Instant,
 };
 
 use byteorder::{ByteOrder, LittleEndian};
@@ -12,37 +14,52 @@ use futures::{
         mpsc::{self, UnboundedReceiver, UnboundedSender},
         oneshot,
     },
-    try_ready,
-    Async::{self, NotReady, Ready},
+    Async::{NotReady, Ready},
     Future,
     Poll,
     Sink,
     Stream,
 };
-use ring::{digest, hkdf, hmac};
 use tokio::{
     io::{AsyncRead, AsyncWrite},
     net::TcpStream,
 };
 use uuid::Uuid;
 
-use crate::{protocol::IdPtr, Result};
+use crate::{crypto::hkdf, protocol::IdPtr, Result};
 
+/// Data sent on a connection.
+pub enum Outgoing {
+    /// Data to be written, encrypted once the session is verified.
+    Data(Vec<u8>),
+    /// The response completing Pair Verify. It's written unencrypted, after which all further data
+    /// on the connection is encrypted with the key material of the session.
+    Upgrade(Vec<u8>, Session),
+}
+
+/// The I/O type the HTTP server reads and writes. Written data is buffered and sent to the
+/// `EncryptedStream` on flush, so every response is sent as a whole and event notifications can't
+/// end up in the middle of one.
 pub struct StreamWrapper {
     incoming_receiver: UnboundedReceiver<Vec<u8>>,
-    outgoing_sender: UnboundedSender<Vec<u8>>,
+    outgoing_sender: UnboundedSender<Outgoing>,
+    session_receiver: Option<oneshot::Receiver<Session>>,
     incoming_buf: BytesMut,
+    outgoing_buf: Vec<u8>,
 }
 
 impl StreamWrapper {
-    pub fn new(
+    fn new(
         incoming_receiver: UnboundedReceiver<Vec<u8>>,
-        outgoing_sender: UnboundedSender<Vec<u8>>,
+        outgoing_sender: UnboundedSender<Outgoing>,
+        session_receiver: oneshot::Receiver<Session>,
     ) -> StreamWrapper {
         StreamWrapper {
             incoming_receiver,
             outgoing_sender,
+            session_receiver: Some(session_receiver),
             incoming_buf: BytesMut::new(),
+            outgoing_buf: Vec::new(),
         }
     }
 
@@ -58,6 +75,18 @@ impl StreamWrapper {
             Err(_) => Err(io::Error::new(io::ErrorKind::Other, "couldn't poll receiver")),
         }
     }
+
+    /// Returns the session established by Pair Verify or Pair Resume, once the handler completing
+    /// either of them sent it. The response written by that handler is the last unencrypted one.
+    fn poll_session(&mut self) -> Option<Session> {
+        let session = match self.session_receiver.as_mut().map(|r| r.poll()) {
+            Some(Ok(Ready(session))) => Some(session),
+            Some(Ok(NotReady)) | None => return None,
+            Some(Err(_)) => None,
+        };
+        self.session_receiver = None;
+        session
+    }
 }
 
 impl Read for StreamWrapper {
@@ -72,13 +101,21 @@ impl Read for StreamWrapper {
 
 impl Write for StreamWrapper {
     fn write(&mut self, buf: &[u8]) -> std::result::Result<usize, io::Error> {
-        self.outgoing_sender.unbounded_send(buf.to_vec())
-            // .map_err(|_| Error::from_str("couldn't write").into())?;
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "couldn't write"))?;
+        self.outgoing_buf.extend_from_slice(buf);
         Ok(buf.len())
     }
 
     fn flush(&mut self) -> std::result::Result<(), io::Error> {
+        if !self.outgoing_buf.is_empty() {
+            let data = mem::replace(&mut self.outgoing_buf, Vec::new());
+            let outgoing = match self.poll_session() {
+                Some(session) => Outgoing::Upgrade(data, session),
+                None => Outgoing::Data(data),
+            };
+            self.outgoing_sender
+                .unbounded_send(outgoing)
+                .map_err(|_| io::Error::new(io::ErrorKind::Other, "couldn't write"))?;
+        }
         self.outgoing_sender.poll_complete()
             .map(|_| ())
             // .map_err(|_| Error::from_str("couldn't flush").into())
@@ -90,7 +127,7 @@ impl AsyncRead for StreamWrapper {}
 
 impl AsyncWrite for StreamWrapper {
     fn shutdown(&mut self) -> Poll<(), io::Error> {
-        // TODO - maybe do something useful here
+        self.flush()?;
         Ok(Ready(()))
     }
 }
@@ -103,9 +140,11 @@ pub struct Session {
 pub struct EncryptedStream {
     stream: TcpStream,
     incoming_sender: UnboundedSender<Vec<u8>>,
-    outgoing_receiver: UnboundedReceiver<Vec<u8>>,
-    session_receiver: oneshot::Receiver<Session>,
+    outgoing_receiver: UnboundedReceiver<Outgoing>,
+    outgoing_buf: BytesMut,
     pub controller_id: IdPtr,
+    /// Time data was last read from or written to the connection.
+    pub last_activity: Arc<Mutex<Instant>>,
     shared_secret: Option<[u8; 32]>,
     decrypt_count: u64,
     encrypt_count: u64,
@@ -120,15 +159,13 @@ pub struct EncryptedStream {
 }
 
 impl EncryptedStream {
+    /// Creates a new `EncryptedStream` for a connection. Returns the `StreamWrapper` to serve HTTP
+    /// on, a sender for event notifications and the sender the session of the connection is to be
+    /// sent on once it's verified.
     pub fn new(
         stream: TcpStream,
-    ) -> (
-        EncryptedStream,
-        UnboundedReceiver<Vec<u8>>,
-        UnboundedSender<Vec<u8>>,
-        oneshot::Sender<Session>,
-    ) {
-        let (sender, receiver) = oneshot::channel();
+    ) -> (EncryptedStream, StreamWrapper, UnboundedSender<Outgoing>, oneshot::Sender<Session>) {
+        let (session_sender, session_receiver) = oneshot::channel();
         let (incoming_sender, incoming_receiver) = mpsc::unbounded();
         let (outgoing_sender, outgoing_receiver) = mpsc::unbounded();
         (
@@ -136,8 +173,9 @@ impl EncryptedStream {
                 stream,
                 incoming_sender,
                 outgoing_receiver,
-                session_receiver: receiver,
+                outgoing_buf: BytesMut::new(),
                 controller_id: Arc::new(Mutex::new(None)),
+                last_activity: Arc::new(Mutex::new(Instant::now())),
                 shared_secret: None,
                 decrypt_count: 0,
                 encrypt_count: 0,
@@ -150,18 +188,19 @@ impl EncryptedStream {
                 missing_data_for_decrypted_buf: false,
                 missing_data_for_encrypted_buf: false,
             },
-            incoming_receiver,
+            StreamWrapper::new(incoming_receiver, outgoing_sender.clone(), session_receiver),
             outgoing_sender,
-            sender,
+            session_sender,
         )
     }
 
     fn read_decrypted(&mut self, buf: &mut [u8]) -> std::result::Result<usize, io::Error> {
         if self.decrypted_ready {
-            let len = min(buf.len(), self.packet_len - 16);
-            buf[..len].copy_from_slice(&self.decrypted_buf[..len]);
-            self.already_copied = len;
-            if self.already_copied == (self.packet_len - 16) {
+            let decrypted_len = self.packet_len - 16;
+            let len = min(buf.len(), decrypted_len - self.already_copied);
+            buf[..len].copy_from_slice(&self.decrypted_buf[self.already_copied..self.already_copied + len]);
+            self.already_copied += len;
+            if self.already_copied == decrypted_len {
                 self.already_copied = 0;
                 self.decrypted_ready = false;
             }
@@ -181,7 +220,7 @@ impl EncryptedStream {
                 &self.encrypted_buf[(self.packet_len - 14)..(self.packet_len + 2)],
                 &mut self.decrypt_count,
             )
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "decryption failed"))?;
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "decryption failed"))?;
             self.decrypted_buf[..decrypted.len()].copy_from_slice(&decrypted);
             self.missing_data_for_decrypted_buf = false;
             self.decrypted_ready = true;
@@ -193,37 +232,41 @@ impl EncryptedStream {
     }
 
     fn read_stream(&mut self, buf: &mut [u8]) -> std::result::Result<usize, io::Error> {
-        if self.missing_data_for_encrypted_buf {
-            let r_len = self.stream.read(&mut self.encrypted_buf[self.already_read..])?;
+        // keep reading until a whole frame is buffered or the underlying stream would block, so the
+        // task is woken up again once more data arrives
+        loop {
+            if !self.missing_data_for_encrypted_buf {
+                let r_len = self.stream.read(&mut self.encrypted_buf[self.already_read..2])?;
+                if r_len == 0 {
+                    return Ok(0);
+                }
+                self.already_read += r_len;
+                if self.already_read < 2 {
+                    continue;
+                }
+
+                let len = LittleEndian::read_u16(&self.encrypted_buf) as usize;
+                if len > 1024 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "frame exceeds 1024 bytes"));
+                }
+                self.packet_len = len + 16;
+                self.missing_data_for_encrypted_buf = true;
+            }
 
-            if self.already_read + r_len == self.packet_len {
+            // the frame consists of the 2 byte length, the encrypted data and the 16 byte auth tag
+            let frame_len = self.packet_len + 2;
+            let r_len = self.stream.read(&mut self.encrypted_buf[self.already_read..frame_len])?;
+            if r_len == 0 {
+                return Ok(0);
+            }
+            self.already_read += r_len;
+
+            if self.already_read == frame_len {
                 self.already_read = 0;
                 self.missing_data_for_encrypted_buf = false;
                 self.missing_data_for_decrypted_buf = true;
                 return self.read_encrypted(buf);
             }
-            Err(ErrorKind::WouldBlock.into())
-        } else {
-            let r_len = self.stream.read(&mut self.encrypted_buf[self.already_read..2])?;
-            self.already_read += r_len;
-
-            if self.already_read == 2 {
-                self.packet_len = LittleEndian::read_u16(&self.encrypted_buf) as usize + 16;
-
-                self.missing_data_for_encrypted_buf = true;
-                let r_len = self.stream.read(&mut self.encrypted_buf[self.already_read..])?;
-                if r_len == self.packet_len {
-                    self.already_read = 0;
-                    self.missing_data_for_encrypted_buf = false;
-                    self.missing_data_for_decrypted_buf = true;
-                    self.read_encrypted(buf)
-                } else {
-                    self.already_read += r_len;
-                    Err(ErrorKind::WouldBlock.into())
-                }
-            } else {
-                Err(ErrorKind::WouldBlock.into())
-            }
         }
     }
 
@@ -243,6 +286,7 @@ impl EncryptedStream {
                     if r_len == 0 {
                         return Ok(Ready(()));
                     }
+                    self.touch();
                     self.incoming_sender.unbounded_send(data[..r_len].to_vec())
                         // .map_err(|_| Error::from_str("couldn't send incoming data").into())?;
                         .map_err(|_| io::Error::new(io::ErrorKind::Other, "couldn't send incoming data"))?;
@@ -251,17 +295,57 @@ impl EncryptedStream {
         }
     }
 
-    fn poll_outgoing(&mut self) -> Poll<(), ()> {
+    fn poll_outgoing(&mut self) -> Poll<(), io::Error> {
         loop {
-            match try_ready!(self.outgoing_receiver.poll()) {
-                None => {
-                    return Ok(Ready(()));
+            match self.outgoing_receiver.poll() {
+                Ok(Ready(Some(Outgoing::Data(data)))) => {
+                    self.buffer_outgoing(&data)?;
+                },
+                Ok(Ready(Some(Outgoing::Upgrade(data, session)))) => {
+                    self.buffer_outgoing(&data)?;
+                    *self.controller_id.lock().expect("couldn't access controller_id") = Some(session.controller_id);
+                    self.shared_secret = Some(session.shared_secret);
                 },
-                Some(data) => {
-                    self.write(&data).map_err(|_| ())?;
+                Ok(Ready(None)) | Err(_) | Ok(NotReady) => break,
+            }
+        }
+        self.write_outgoing_buf()
+    }
+
+    /// Buffers data to be written to the socket, split into encrypted frames once the session is
+    /// verified. The buffer is written by `poll_outgoing`.
+    fn buffer_outgoing(&mut self, buf: &[u8]) -> std::result::Result<(), io::Error> {
+        if let Some(shared_secret) = self.shared_secret {
+            for chunk in buf.chunks(1024) {
+                let (aad, chunk, auth_tag) = encrypt_chunk(&shared_secret, chunk, &mut self.encrypt_count)
+                    .map_err(|_| io::Error::new(io::ErrorKind::Other, "encryption failed"))?;
+                self.outgoing_buf.extend_from_slice(&aad);
+                self.outgoing_buf.extend_from_slice(&chunk);
+                self.outgoing_buf.extend_from_slice(&auth_tag);
+            }
+        } else {
+            self.outgoing_buf.extend_from_slice(buf);
+        }
+        Ok(())
+    }
+
+    fn touch(&mut self) { *self.last_activity.lock().expect("couldn't access last_activity") = Instant::now(); }
+
+    /// Writes the buffered outgoing data to the socket until it's drained or the socket would
+    /// block, in which case the task is woken up again once the socket is writable.
+    fn write_outgoing_buf(&mut self) -> Poll<(), io::Error> {
+        while !self.outgoing_buf.is_empty() {
+            match self.stream.write(&self.outgoing_buf) {
+                Ok(0) => return Err(ErrorKind::WriteZero.into()),
+                Ok(w_len) => {
+                    self.outgoing_buf.advance(w_len);
+                    self.touch();
                 },
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(NotReady),
+                Err(e) => return Err(e),
             }
         }
+        Ok(Ready(()))
     }
 }
 
@@ -270,10 +354,7 @@ impl Future for EncryptedStream {
     type Item = ();
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        self.poll_outgoing()
-            // .map_err(|_| Error::from_str("couldn't receive outgoing data").into())?;
-            .map_err(|_| io::Error::new(io::ErrorKind::Other, "couldn't receive incoming data"))?;
-
+        self.poll_outgoing()?;
         self.poll_incoming()
     }
 }
@@ -281,50 +362,26 @@ impl Future for EncryptedStream {
 impl Read for EncryptedStream {
     fn read(&mut self, buf: &mut [u8]) -> std::result::Result<usize, io::Error> {
         if self.shared_secret.is_none() {
-            match self.session_receiver.poll() {
-                Ok(Async::Ready(session)) => {
-                    *self.controller_id.lock().expect("couldn't access controller_id") = Some(session.controller_id);
-                    self.shared_secret = Some(session.shared_secret);
-                },
-                _ => {
-                    return self.stream.read(buf);
-                },
-            }
+            return self.stream.read(buf);
         }
 
+        // only fall through to the next stage if there's nothing buffered, any other error, e.g. a
+        // frame failing authentication, closes the connection
         match self.read_decrypted(buf) {
-            Ok(size) => Ok(size),
-            Err(_) => match self.read_encrypted(buf) {
-                Ok(size) => Ok(size),
-                Err(_) => self.read_stream(buf),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => match self.read_encrypted(buf) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => self.read_stream(buf),
+                res => res,
             },
+            res => res,
         }
     }
 }
 
 impl Write for EncryptedStream {
+    /// Buffers data to be written to the socket, see `buffer_outgoing`.
     fn write(&mut self, buf: &[u8]) -> std::result::Result<usize, io::Error> {
-        if let Some(shared_secret) = self.shared_secret {
-            let mut write_buf = BytesMut::from_buf(buf);
-
-            while write_buf.len() > 1024 {
-                let (aad, chunk, auth_tag) = encrypt_chunk(&shared_secret, &write_buf[..1024], &mut self.encrypt_count)
-                    .map_err(|_| io::Error::new(io::ErrorKind::Other, "encryption failed"))?;
-                self.stream.write_all(&aad)?;
-                self.stream.write_all(&chunk)?;
-                self.stream.write_all(&auth_tag)?;
-                write_buf.advance(1024);
-            }
-
-            let (aad, chunk, auth_tag) = encrypt_chunk(&shared_secret, &write_buf, &mut self.encrypt_count)
-                .map_err(|_| io::Error::new(io::ErrorKind::Other, "encryption failed"))?; // TODO - make this better
-            self.stream.write_all(&aad)?;
-            self.stream.write_all(&chunk)?;
-            self.stream.write_all(&auth_tag)?;
-            Ok(buf.len())
-        } else {
-            self.stream.write(buf)
-        }
+        self.buffer_outgoing(buf)?;
+        Ok(buf.len())
     }
 
     fn flush(&mut self) -> std::result::Result<(), io::Error> { self.stream.flush() }
@@ -376,16 +433,363 @@ fn encrypt_chunk(shared_secret: &[u8; 32], data: &[u8], count: &mut u64) -> Resu
 }
 
 fn compute_read_key(shared_secret: &[u8; 32]) -> [u8; 32] {
-    compute_key(shared_secret, b"Control-Write-Encryption-Key")
+    hkdf::derive_key(shared_secret, hkdf::CONTROL_SALT, hkdf::CONTROL_WRITE_ENCRYPTION_KEY_INFO)
 }
 
 fn compute_write_key(shared_secret: &[u8; 32]) -> [u8; 32] {
-    compute_key(shared_secret, b"Control-Read-Encryption-Key")
+    hkdf::derive_key(shared_secret, hkdf::CONTROL_SALT, hkdf::CONTROL_READ_ENCRYPTION_KEY_INFO)
 }
 
-fn compute_key(shared_secret: &[u8; 32], info: &[u8]) -> [u8; 32] {
-    let mut key = [0; 32];
-    let salt = hmac::SigningKey::new(&digest::SHA512, b"Control-Salt");
-    hkdf::extract_and_expand(&salt, shared_secret, &info, &mut key);
-    key
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{self, SocketAddr},
+        sync::Mutex,
+        thread,
+        time::Duration,
+    };
+
+    use futures::{future, Future, Stream};
+    use hyper::{server::conn::Http, service::service_fn, Body, Request, Response};
+    use tokio::{reactor::Handle, runtime::Runtime};
+
+    use super::*;
+    use crate::{test_util::from_hex, transport::http::server};
+
+    const SHARED_SECRET: [u8; 32] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0x11,
+        0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f,
+    ];
+
+    const RESPONSE: &[u8] = b"HTTP/1.1 204 No Content\r\n\r\n";
+    const REQUEST: &[u8] = b"GET /accessories HTTP/1.1\r\n\r\n";
+
+    /// The frame of `REQUEST` as the first frame a Controller sends.
+    const REQUEST_FRAME: (&str, &str, &str) = (
+        "1d00",
+        "6ecb4b84f15aa0dd68624751c345dd91735ee85d6c76f499d7d3602cf1",
+        "976e3f8d9a11058445d1139c2fbea152",
+    );
+
+    fn request_frame() -> Vec<u8> {
+        let (aad, data, auth_tag) = REQUEST_FRAME;
+        [from_hex(aad), from_hex(data), from_hex(auth_tag)].concat()
+    }
+
+    /// Writes `chunks` to an `EncryptedStream` with an established session, one TCP write at a time,
+    /// and returns the result of the stream along with everything it decrypted.
+    fn receive(chunks: Vec<Vec<u8>>) -> (std::result::Result<(), io::Error>, Vec<u8>) {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut controller = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        let stream = TcpStream::from_std(stream, &Handle::default()).unwrap();
+
+        let (encrypted_stream, stream_wrapper, outgoing_sender, _) = EncryptedStream::new(stream);
+        // as if Pair Verify had just been completed
+        let session = Session {
+            controller_id: Uuid::new_v4(),
+            shared_secret: SHARED_SECRET,
+        };
+        outgoing_sender.unbounded_send(Outgoing::Upgrade(Vec::new(), session)).unwrap();
+        drop(outgoing_sender);
+
+        let writer = thread::spawn(move || {
+            // the stream may close the connection before everything is written
+            for chunk in chunks {
+                if controller.write_all(&chunk).and_then(|_| controller.flush()).is_err() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+            let _ = controller.shutdown(net::Shutdown::Write);
+            // keep the connection open until the stream is done reading
+            let _ = controller.read(&mut [0; 1]);
+        });
+
+        let res = encrypted_stream.wait();
+        writer.join().unwrap();
+        let (_, decrypted) = tokio::io::read_to_end(stream_wrapper, Vec::new()).wait().unwrap();
+
+        (res, decrypted)
+    }
+
+    /// Serves a single connection like the HTTP server does, answering every request with its path.
+    /// A request to `/verify` establishes the session, like Pair Verify does.
+    fn serve(runtime: &mut Runtime) -> SocketAddr {
+        let listener = server::bind(&"127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = listener.local_addr().unwrap();
+        runtime.spawn(
+            listener
+                .incoming()
+                .take(1)
+                .for_each(|stream| {
+                    let (encrypted_stream, stream_wrapper, _, session_sender) = EncryptedStream::new(stream);
+                    let session_sender = Mutex::new(Some(session_sender));
+                    let service = service_fn(move |req: Request<Body>| {
+                        if req.uri().path() == "/verify" {
+                            if let Some(session_sender) = session_sender.lock().unwrap().take() {
+                                let _ = session_sender.send(Session {
+                                    controller_id: Uuid::nil(),
+                                    shared_secret: SHARED_SECRET,
+                                });
+                            }
+                        }
+                        future::ok::<_, io::Error>(Response::new(Body::from(req.uri().path().to_string())))
+                    });
+                    let mut http = Http::new();
+                    http.keep_alive(true).pipeline_flush(true);
+                    tokio::spawn(
+                        encrypted_stream
+                            .map_err(|_| ())
+                            .join(http.serve_connection(stream_wrapper, service).map_err(|_| ()))
+                            .map(|_| ()),
+                    );
+                    Ok(())
+                })
+                .map_err(|_| ()),
+        );
+        addr
+    }
+
+    fn nonce(count: &mut u64) -> Vec<u8> {
+        let mut nonce = vec![0; 4];
+        let mut suffix = vec![0; 8];
+        LittleEndian::write_u64(&mut suffix, *count);
+        nonce.extend(suffix);
+        *count += 1;
+        nonce
+    }
+
+    /// Encrypts data sent by the controller, i.e. with the key the accessory decrypts with.
+    fn encrypt(data: &[u8], count: &mut u64) -> Vec<u8> {
+        let mut aad = [0; 2];
+        LittleEndian::write_u16(&mut aad, data.len() as u16);
+        let mut encrypted = Vec::new();
+        let auth_tag = chacha20_poly1305_aead::encrypt(
+            &compute_read_key(&SHARED_SECRET),
+            &nonce(count),
+            &aad,
+            data,
+            &mut encrypted,
+        )
+        .unwrap();
+        let mut frame = aad.to_vec();
+        frame.extend(encrypted);
+        frame.extend_from_slice(&auth_tag);
+        frame
+    }
+
+    /// Decrypts the complete frames sent by the accessory and removes them from `frames`.
+    fn decrypt(frames: &mut Vec<u8>, count: &mut u64) -> Vec<u8> {
+        let mut decrypted = Vec::new();
+        while frames.len() >= 2 {
+            let len = LittleEndian::read_u16(frames) as usize;
+            if frames.len() < len + 18 {
+                break;
+            }
+            chacha20_poly1305_aead::decrypt(
+                &compute_write_key(&SHARED_SECRET),
+                &nonce(count),
+                &frames[..2],
+                &frames[2..len + 2],
+                &frames[len + 2..len + 18],
+                &mut decrypted,
+            )
+            .unwrap();
+            frames.drain(..len + 18);
+        }
+        decrypted
+    }
+
+    /// Reads from the client until `done` returns true for the (decrypted) data read so far.
+    fn read_until(
+        client: &mut net::TcpStream,
+        mut decrypt_count: Option<&mut u64>,
+        done: impl Fn(&str) -> bool,
+    ) -> String {
+        let mut frames = Vec::new();
+        let mut data = Vec::new();
+        let mut buf = [0; 1024];
+        while !done(&String::from_utf8_lossy(&data)) {
+            let len = client.read(&mut buf).unwrap();
+            assert!(len > 0, "connection closed");
+            match decrypt_count {
+                Some(ref mut count) => {
+                    frames.extend_from_slice(&buf[..len]);
+                    data.extend(decrypt(&mut frames, count));
+                },
+                None => data.extend_from_slice(&buf[..len]),
+            }
+        }
+        String::from_utf8(data).unwrap()
+    }
+
+    #[test]
+    fn test_compute_keys() {
+        assert_eq!(
+            compute_read_key(&SHARED_SECRET).to_vec(),
+            from_hex("c3ca130c7033dbe5e7ff7f91d117ead869bac476994c7a48ca170c111136ed96"),
+        );
+        assert_eq!(
+            compute_write_key(&SHARED_SECRET).to_vec(),
+            from_hex("c09403ef8aa6c5045cbd8cf9bf3e665b2caed623af2be0e87c8f80f519914d3d"),
+        );
+    }
+
+    #[test]
+    fn test_encrypt_chunk() {
+        let mut count = 0;
+
+        let (aad, data, auth_tag) = encrypt_chunk(&SHARED_SECRET, RESPONSE, &mut count).unwrap();
+        assert_eq!(aad.to_vec(), from_hex("1b00"));
+        assert_eq!(data, from_hex("0c1e1407a8a5a0c3a290852aca8bd237957656ee663f04141bfdde"));
+        assert_eq!(auth_tag.to_vec(), from_hex("c191b102a429bbf5d24d456ac6fce543"));
+        assert_eq!(count, 1);
+
+        // the counter is part of the nonce, so the same data encrypts differently
+        let (aad, data, auth_tag) = encrypt_chunk(&SHARED_SECRET, RESPONSE, &mut count).unwrap();
+        assert_eq!(aad.to_vec(), from_hex("1b00"));
+        assert_eq!(data, from_hex("5a7c4d62c9d54fd9b6557020e1d1f088a3173c5d3eab704b811da2"));
+        assert_eq!(auth_tag.to_vec(), from_hex("86a7c91c7734c0ebda1bb5ca9cc0f408"));
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_decrypt_chunk() {
+        let (aad, data, auth_tag) = REQUEST_FRAME;
+        let mut count = 0;
+
+        let decrypted =
+            decrypt_chunk(&SHARED_SECRET, &from_hex(aad), &from_hex(data), &from_hex(auth_tag), &mut count).unwrap();
+        assert_eq!(decrypted, REQUEST.to_vec());
+        assert_eq!(count, 1);
+
+        // a replayed frame doesn't match the next nonce
+        let replayed = decrypt_chunk(&SHARED_SECRET, &from_hex(aad), &from_hex(data), &from_hex(auth_tag), &mut count);
+        assert!(replayed.is_err());
+    }
+
+    #[test]
+    fn test_decrypt_chunk_with_tampered_data() {
+        let (aad, data, auth_tag) = REQUEST_FRAME;
+
+        let mut tampered_aad = from_hex(aad);
+        tampered_aad[0] ^= 0x01;
+        assert!(decrypt_chunk(&SHARED_SECRET, &tampered_aad, &from_hex(data), &from_hex(auth_tag), &mut 0).is_err());
+
+        let mut tampered_data = from_hex(data);
+        tampered_data[0] ^= 0x01;
+        assert!(decrypt_chunk(&SHARED_SECRET, &from_hex(aad), &tampered_data, &from_hex(auth_tag), &mut 0).is_err());
+
+        let mut tampered_auth_tag = from_hex(auth_tag);
+        tampered_auth_tag[15] ^= 0x01;
+        assert!(decrypt_chunk(&SHARED_SECRET, &from_hex(aad), &from_hex(data), &tampered_auth_tag, &mut 0).is_err());
+    }
+
+    #[test]
+    fn test_receive_frame() {
+        let (res, decrypted) = receive(vec![request_frame()]);
+        assert!(res.is_ok());
+        assert_eq!(decrypted, REQUEST.to_vec());
+    }
+
+    #[test]
+    fn test_receive_frame_split_across_reads() {
+        let frame = request_frame();
+        // the length, part of the data, the rest of the data and part of the tag, the rest of the tag
+        let chunks = vec![
+            frame[..1].to_vec(),
+            frame[1..10].to_vec(),
+            frame[10..frame.len() - 8].to_vec(),
+            frame[frame.len() - 8..].to_vec(),
+        ];
+
+        let (res, decrypted) = receive(chunks);
+        assert!(res.is_ok());
+        assert_eq!(decrypted, REQUEST.to_vec());
+    }
+
+    #[test]
+    fn test_receive_multiple_frames_in_one_read() {
+        let mut count = 0;
+        let frames = [
+            encrypt(b"GET /a HTTP/1.1\r\n", &mut count),
+            encrypt(b"Host: hap\r\n", &mut count),
+            encrypt(b"\r\n", &mut count),
+        ]
+        .concat();
+
+        let (res, decrypted) = receive(vec![frames]);
+        assert!(res.is_ok());
+        assert_eq!(decrypted, b"GET /a HTTP/1.1\r\nHost: hap\r\n\r\n".to_vec());
+    }
+
+    #[test]
+    fn test_receive_frame_of_maximum_length() {
+        let data = (0..1024).map(|i| i as u8).collect::<Vec<u8>>();
+        let frame = encrypt(&data, &mut 0);
+        assert_eq!(frame.len(), 2 + 1024 + 16);
+
+        let (res, decrypted) = receive(vec![frame.clone()]);
+        assert!(res.is_ok());
+        assert_eq!(decrypted, data);
+
+        let (res, decrypted) = receive(vec![frame[..600].to_vec(), frame[600..].to_vec()]);
+        assert!(res.is_ok());
+        assert_eq!(decrypted, data);
+    }
+
+    #[test]
+    fn test_frame_exceeding_maximum_length_closes_the_connection() {
+        let frame = encrypt(&[0; 1025], &mut 0);
+
+        let (res, decrypted) = receive(vec![frame]);
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(decrypted.is_empty());
+    }
+
+    #[test]
+    fn test_tampered_frame_closes_the_connection() {
+        let mut frame = request_frame();
+        let last = frame.len() - 1;
+        frame[last] ^= 0x01;
+
+        let (res, decrypted) = receive(vec![frame]);
+        assert_eq!(res.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(decrypted.is_empty());
+    }
+
+    #[test]
+    fn test_pipelined_requests_and_keep_alive_across_upgrade() {
+        let mut runtime = Runtime::new().unwrap();
+        let addr = serve(&mut runtime);
+        let mut client = net::TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        // pipelined requests before the session is verified are answered in order, unencrypted
+        client
+            .write_all(b"GET /a HTTP/1.1\r\nHost: hap\r\n\r\nGET /b HTTP/1.1\r\nHost: hap\r\n\r\n")
+            .unwrap();
+        let responses = read_until(&mut client, None, |r| r.ends_with("\r\n\r\n/b"));
+        assert_eq!(responses.matches("HTTP/1.1 200 OK").count(), 2);
+        assert!(responses.find("\r\n\r\n/a").unwrap() < responses.find("\r\n\r\n/b").unwrap());
+
+        // the response completing the session is the last unencrypted one
+        client
+            .write_all(b"POST /verify HTTP/1.1\r\nHost: hap\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        let response = read_until(&mut client, None, |r| r.ends_with("\r\n\r\n/verify"));
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+        // the same connection is kept alive and pipelined requests are answered in order, encrypted
+        let (mut encrypt_count, mut decrypt_count) = (0, 0);
+        let requests = b"GET /c HTTP/1.1\r\nHost: hap\r\n\r\nGET /d HTTP/1.1\r\nHost: hap\r\n\r\n";
+        client.write_all(&encrypt(requests, &mut encrypt_count)).unwrap();
+        let responses = read_until(&mut client, Some(&mut decrypt_count), |r| r.ends_with("\r\n\r\n/d"));
+        assert_eq!(responses.matches("HTTP/1.1 200 OK").count(), 2);
+        assert!(responses.find("\r\n\r\n/c").unwrap() < responses.find("\r\n\r\n/d").unwrap());
+
+        runtime.shutdown_now().wait().unwrap();
+    }
 }